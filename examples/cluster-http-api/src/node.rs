@@ -3,7 +3,7 @@ use std::sync::atomic::{AtomicU64, Ordering};
 use log::{info, warn};
 
 use ephemera::ephemera_api::{
-    ApiBlock, ApiBroadcastInfo, ApiCertificate, ApiEphemeraConfig, ApiEphemeraMessage, Client,
+    ApiBlock, ApiBlockCertificate, ApiBroadcastInfo, ApiEphemeraConfig, ApiEphemeraMessage, Client,
 };
 
 pub(crate) struct Node {
@@ -60,7 +60,7 @@ impl Node {
     pub(crate) fn process_block_with_next_height(
         &mut self,
         block: ApiBlock,
-        certificates: Vec<ApiCertificate>,
+        certificates: Vec<ApiBlockCertificate>,
     ) {
         let hash = block.header.hash.clone();
         let height = block.header.height;
@@ -122,7 +122,7 @@ impl Node {
         &self,
         client: &Client,
         hash: &str,
-    ) -> anyhow::Result<Option<Vec<ApiCertificate>>> {
+    ) -> anyhow::Result<Option<Vec<ApiBlockCertificate>>> {
         client
             .get_block_certificates(hash)
             .await
@@ -133,7 +133,7 @@ impl Node {
         &self,
         client: &Client,
         height: u64,
-    ) -> anyhow::Result<Option<(ApiBlock, Vec<ApiCertificate>)>> {
+    ) -> anyhow::Result<Option<(ApiBlock, Vec<ApiBlockCertificate>)>> {
         match client.get_block_by_height(height).await {
             Ok(Some(block)) => {
                 let certificates = self
@@ -159,7 +159,7 @@ impl Node {
         &self,
         client: &Client,
         message: ApiEphemeraMessage,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<String> {
         client.submit_message(message).await.map_err(|e| e.into())
     }
 }
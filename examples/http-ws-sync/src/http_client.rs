@@ -34,7 +34,7 @@ impl SignedMessageClient {
     pub(crate) async fn block_certificates(
         &self,
         hash: &str,
-    ) -> Option<Vec<ephemera_api::ApiCertificate>> {
+    ) -> Option<Vec<ephemera_api::ApiBlockCertificate>> {
         match self.client.get_block_certificates(hash).await {
             Ok(certificates) => certificates,
             Err(err) => {
@@ -4,10 +4,12 @@ use std::thread;
 
 use clap::Parser;
 
-use ephemera::peer::ToPeerId;
 use ephemera::{
     crypto::{EphemeraKeypair, EphemeraPublicKey, Keypair},
-    ephemera_api::{ApiBlock, ApiCertificate, ApiEphemeraMessage, RawApiEphemeraMessage},
+    ephemera_api::{
+        ApiBlock, ApiBlockBroadcastInfo, ApiBlockCertificate, ApiEphemeraMessage, BlockVerifier,
+        RawApiEphemeraMessage,
+    },
 };
 
 use crate::http_client::SignedMessageClient;
@@ -155,10 +157,11 @@ async fn compare_ws_http_blocks(shared_data: Arc<Mutex<Data>>, args: Args) -> !
                                 hash,
                                 certificates.len()
                             );
-                            verify_block_certificates(&block, &certificates).unwrap();
+                            let broadcast_info = client.block_broadcast_info(&hash).await.unwrap();
+                            verify_block_certificates(&block, &certificates, &broadcast_info)
+                                .unwrap();
 
                             println!("Checking if all group members signed block");
-                            let broadcast_info = client.block_broadcast_info(&hash).await.unwrap();
                             if broadcast_info.broadcast_group.len() == certificates.len() {
                                 println!("All group members signed block");
                             } else {
@@ -208,28 +211,17 @@ async fn verify_messages(block: &ApiBlock, client: &SignedMessageClient) -> anyh
 
 fn verify_block_certificates(
     block: &ApiBlock,
-    certificates: &[ApiCertificate],
+    certificates: &[ApiBlockCertificate],
+    broadcast_info: &ApiBlockBroadcastInfo,
 ) -> anyhow::Result<()> {
     println!("Verifying block certificates: {:?}\n", certificates.len());
-    for certificate in certificates {
-        match block.verify(certificate) {
-            Ok(valid) => {
-                if valid {
-                    println!(
-                        "Certificate from peer {} is valid",
-                        certificate.public_key.peer_id()
-                    );
-                } else {
-                    println!(
-                        "Certificate from peer {} is invalid",
-                        certificate.public_key.peer_id()
-                    );
-                }
-            }
-            Err(err) => {
-                println!("Certificate verification failed: {err:?}",);
-            }
-        }
+    //The example takes the broadcast group from the same node
+    let verdict = BlockVerifier::new(broadcast_info).verify(block, certificates)?;
+    for peer_id in &verdict.signers {
+        println!("Certificate from peer {peer_id} is valid");
+    }
+    for peer_id in &verdict.invalid_signatures {
+        println!("Certificate from peer {peer_id} is invalid");
     }
     Ok(())
 }
@@ -163,6 +163,7 @@ impl PeersStatus {
                 name: setting.name,
                 address: setting.address,
                 public_key: setting.public_key,
+                weight: setting.weight,
             });
         });
         println!("Read {:?} peers from config", peers.len());
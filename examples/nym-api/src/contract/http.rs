@@ -90,6 +90,7 @@ pub(crate) async fn get_nym_apis(contract: web::Data<Arc<Mutex<SmartContract>>>)
             name: peer_id.to_string(),
             address: peer.address,
             public_key: peer.public_key.to_string(),
+            weight: None,
         });
     }
 
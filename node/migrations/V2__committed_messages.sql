@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS committed_messages (
+    id              INTEGER      NOT NULL PRIMARY KEY AUTOINCREMENT,
+    message_hash    TEXT         NOT NULL UNIQUE,
+    block_hash      TEXT         NOT NULL,
+    timestamp       INTEGER      NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS committed_messages_timestamp ON committed_messages (timestamp);
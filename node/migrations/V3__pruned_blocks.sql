@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS pruned_blocks (
+    id          INTEGER      NOT NULL PRIMARY KEY AUTOINCREMENT,
+    block_hash  TEXT         NOT NULL UNIQUE,
+    height      INTEGER      NOT NULL UNIQUE,
+    header      BLOB,
+    pruned_at   INTEGER      NOT NULL
+);
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS block_quorum_certificates (
+    id                  INTEGER      NOT NULL PRIMARY KEY AUTOINCREMENT,
+    block_hash          TEXT         NOT NULL UNIQUE,
+    quorum_certificate  BLOB         NOT NULL
+);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS equivocation_evidence (
+    id           INTEGER      NOT NULL PRIMARY KEY AUTOINCREMENT,
+    offender     TEXT         NOT NULL,
+    height       INTEGER      NOT NULL,
+    evidence     BLOB         NOT NULL,
+    detected_at  INTEGER      NOT NULL
+);
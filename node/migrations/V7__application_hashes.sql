@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS application_hashes (
+    height       INTEGER      NOT NULL PRIMARY KEY,
+    app_hash     TEXT         NOT NULL
+);
@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS published_commitments (
+    id           INTEGER      NOT NULL PRIMARY KEY CHECK (id = 0),
+    height       INTEGER      NOT NULL
+);
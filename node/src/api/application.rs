@@ -1,7 +1,8 @@
+use async_trait::async_trait;
 use log::trace;
 use thiserror::Error;
 
-use crate::api::types::{ApiBlock, ApiEphemeraMessage};
+use crate::api::types::{ApiBlock, ApiEphemeraMessage, ApiEquivocationEvidence};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum RemoveMessages {
@@ -27,19 +28,53 @@ pub struct CheckBlockResponse {
     pub reason: Option<String>,
 }
 
+/// Application state reported to Ephemera at startup.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ApplicationInfo {
+    /// Height of the last block the application has processed. Committed blocks after it are delivered again.
+    ///
+    /// If `None`, the application doesn't keep track of blocks and nothing is replayed.
+    pub last_block_height: Option<u64>,
+    /// Application state hash after `last_block_height`, see [`Application::commit`].
+    pub last_app_hash: [u8; 32],
+}
+
 #[derive(Error, Debug)]
 pub enum Error {
     //Just a placeholder for now
     #[error("ApplicationError: {0}")]
     Application(#[from] anyhow::Error),
+    /// Application didn't respond within configured timeout
+    #[error("Application call timed out")]
+    Timeout,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// Cosmos style ABCI application hook
 ///
-/// These functions should be relatively fast, as they are called synchronously by Ephemera main loop.
+/// These functions should be relatively fast. Except for `prepare_proposal`, Ephemera calls them outside its main
+/// loop, but in async tasks, so a blocking call holds up a runtime thread. If the application needs I/O, like
+/// database lookups or HTTP calls, implement [`AsyncApplication`] instead.
 pub trait Application {
+    /// Similar to ABCI `Info`. It's called once at startup, before Ephemera starts processing blocks.
+    ///
+    /// Ephemera delivers again all committed blocks after `last_block_height`, in order. Blocks are stored
+    /// before delivery, so no block is lost if the process stops or delivery fails. A block can be delivered
+    /// again if it was processed but `last_block_height` was not updated yet, so application should
+    /// skip blocks it has already seen.
+    ///
+    /// It's also called before a failed or timed out `deliver_block` is retried, a block at or below
+    /// `last_block_height` is not delivered again.
+    ///
+    /// Default implementation returns `ApplicationInfo::default()`, nothing is replayed.
+    ///
+    /// # Errors
+    /// * `Error::General` - if there was an error, then nothing is replayed
+    fn info(&self) -> Result<ApplicationInfo> {
+        Ok(ApplicationInfo::default())
+    }
+
     /// It's called when receiving a new message from network before adding it to the mempool.
     /// It's up to the application to decide whether the message is valid or not.
     /// Basic check could be for example signature verification.
@@ -71,14 +106,222 @@ pub trait Application {
     /// * `Error::General` - if there was an error during validation
     fn check_block(&self, block: &ApiBlock) -> Result<CheckBlockResult>;
 
+    /// Similar to ABCI `PrepareProposal`. It's called before Ephemera creates a new block.
+    /// Application can choose which messages to include and in which order.
+    ///
+    /// Messages are included in the returned order. Messages exceeding `max_messages` or `max_bytes`
+    /// are left out, as well as messages which are not among the candidates. Messages which are not included
+    /// stay in the mempool.
+    ///
+    /// Default implementation returns candidates as they are.
+    ///
+    /// # Arguments
+    /// * `candidates` - pending messages from the mempool, oldest first
+    /// * `max_messages` - maximum number of messages in a block
+    /// * `max_bytes` - maximum total size of block messages in bytes
+    ///
+    /// # Errors
+    /// * `Error::General` - if there was an error, then candidates are used as they are
+    fn prepare_proposal(
+        &self,
+        candidates: Vec<ApiEphemeraMessage>,
+        max_messages: usize,
+        max_bytes: usize,
+    ) -> Result<Vec<ApiEphemeraMessage>> {
+        trace!("prepare_proposal: {max_messages} {max_bytes}");
+        Ok(candidates)
+    }
+
     /// Deliver Block is called after block is confirmed by Ephemera and persisted to the storage.
     ///
+    /// Delivery is at-least-once. If the call fails or times out, the block is delivered again later, unless
+    /// [`Application::info`] reports a `last_block_height` which already includes it. Application should keep
+    /// `last_block_height` up to date and skip blocks at or below it.
+    ///
     /// # Arguments
     /// * `block` - block to be delivered
     ///
     /// # Errors
     /// * `Error::General` - if there was an error during validation
     fn deliver_block(&self, block: ApiBlock) -> Result<()>;
+
+    /// Similar to ABCI `Commit`. It's called after `deliver_block` succeeded.
+    ///
+    /// Returns the hash of application state after the block. Ephemera includes it in the next block header,
+    /// which every node signs. If applications of different nodes diverge, they don't agree on the next block
+    /// and it doesn't get committed.
+    ///
+    /// Default implementation returns the zero hash.
+    ///
+    /// # Errors
+    /// * `Error::General` - if there was an error, then the node doesn't accept the next block
+    fn commit(&self) -> Result<[u8; 32]> {
+        Ok([0; 32])
+    }
+
+    /// Similar to ABCI `Query`. Lets clients read application state through Ephemera HTTP API,
+    /// `/ephemera/application/query/{path}`.
+    ///
+    /// Default implementation doesn't know any paths.
+    ///
+    /// # Arguments
+    /// * `path` - application specific path, for example `rewards`
+    /// * `data` - application specific query parameters
+    ///
+    /// # Returns
+    /// * `Some(value)` - query result
+    /// * `None` - if nothing was found
+    ///
+    /// # Errors
+    /// * `Error::General` - if the query is invalid or there was an error answering it
+    fn query(&self, path: &str, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        trace!("query: {path} {data:?}");
+        Ok(None)
+    }
+
+    /// Similar to ABCI evidence. It's called when the node detects that a peer signed two different blocks
+    /// of the same creator at the same height. The evidence is also stored and available through Ephemera HTTP API,
+    /// `/ephemera/broadcast/evidence`.
+    ///
+    /// Leaders re-propose blocks which are not committed in time, so the evidence is not necessarily malicious.
+    /// Default implementation ignores it.
+    ///
+    /// # Arguments
+    /// * `evidence` - both conflicting headers and their signatures by the offender
+    ///
+    /// # Errors
+    /// * `Error::General` - if there was an error, it's logged and the evidence is not delivered again
+    fn deliver_evidence(&self, evidence: ApiEquivocationEvidence) -> Result<()> {
+        trace!("deliver_evidence: {evidence:?}");
+        Ok(())
+    }
+}
+
+/// Async variant of [`Application`] for applications which need I/O to make their decisions.
+///
+/// Ephemera awaits each call outside its main loop with a configured timeout, so a slow application doesn't stall
+/// the node. `prepare_proposal` is the exception, see [`AsyncApplication::prepare_proposal`].
+/// A call which times out is treated as failed:
+/// * `check_tx` - message is rejected
+/// * `check_block` - block is not broadcast
+/// * `deliver_block` - delivery is retried later, blocks are delivered in order
+/// * `commit` - the node doesn't accept the next block
+/// * `query` - query fails
+/// * `deliver_evidence` - evidence is not delivered again
+///
+/// Calls can be dropped at a timeout, so they should be cancellation safe.
+///
+/// Every [`Application`] is also an [`AsyncApplication`].
+#[async_trait]
+pub trait AsyncApplication: Send + Sync {
+    /// See [`Application::info`].
+    ///
+    /// # Errors
+    /// * `Error::General` - if there was an error, then nothing is replayed
+    async fn info(&self) -> Result<ApplicationInfo> {
+        Ok(ApplicationInfo::default())
+    }
+
+    /// See [`Application::check_tx`].
+    ///
+    /// # Errors
+    /// * `Error::General` - if there was an error during validation
+    async fn check_tx(&self, message: ApiEphemeraMessage) -> Result<bool>;
+
+    /// See [`Application::check_block`].
+    ///
+    /// # Errors
+    /// * `Error::General` - if there was an error during validation
+    async fn check_block(&self, block: &ApiBlock) -> Result<CheckBlockResult>;
+
+    /// See [`Application::prepare_proposal`].
+    ///
+    /// Unlike the other calls, it's synchronous and called inside the main loop while producing a block. It's not
+    /// covered by the call timeout, so it must not block and should be fast.
+    ///
+    /// # Errors
+    /// * `Error::General` - if there was an error, then candidates are used as they are
+    fn prepare_proposal(
+        &self,
+        candidates: Vec<ApiEphemeraMessage>,
+        max_messages: usize,
+        max_bytes: usize,
+    ) -> Result<Vec<ApiEphemeraMessage>> {
+        trace!("prepare_proposal: {max_messages} {max_bytes}");
+        Ok(candidates)
+    }
+
+    /// See [`Application::deliver_block`].
+    ///
+    /// # Errors
+    /// * `Error::General` - if there was an error, then delivery is retried
+    async fn deliver_block(&self, block: ApiBlock) -> Result<()>;
+
+    /// See [`Application::commit`].
+    ///
+    /// # Errors
+    /// * `Error::General` - if there was an error, then the node doesn't accept the next block
+    async fn commit(&self) -> Result<[u8; 32]> {
+        Ok([0; 32])
+    }
+
+    /// See [`Application::query`].
+    ///
+    /// # Errors
+    /// * `Error::General` - if the query is invalid or there was an error answering it
+    async fn query(&self, path: &str, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        trace!("query: {path} {data:?}");
+        Ok(None)
+    }
+
+    /// See [`Application::deliver_evidence`].
+    ///
+    /// # Errors
+    /// * `Error::General` - if there was an error, it's logged and the evidence is not delivered again
+    async fn deliver_evidence(&self, evidence: ApiEquivocationEvidence) -> Result<()> {
+        trace!("deliver_evidence: {evidence:?}");
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<A: Application + Send + Sync> AsyncApplication for A {
+    async fn info(&self) -> Result<ApplicationInfo> {
+        Application::info(self)
+    }
+
+    async fn check_tx(&self, message: ApiEphemeraMessage) -> Result<bool> {
+        Application::check_tx(self, message)
+    }
+
+    async fn check_block(&self, block: &ApiBlock) -> Result<CheckBlockResult> {
+        Application::check_block(self, block)
+    }
+
+    fn prepare_proposal(
+        &self,
+        candidates: Vec<ApiEphemeraMessage>,
+        max_messages: usize,
+        max_bytes: usize,
+    ) -> Result<Vec<ApiEphemeraMessage>> {
+        Application::prepare_proposal(self, candidates, max_messages, max_bytes)
+    }
+
+    async fn deliver_block(&self, block: ApiBlock) -> Result<()> {
+        Application::deliver_block(self, block)
+    }
+
+    async fn commit(&self) -> Result<[u8; 32]> {
+        Application::commit(self)
+    }
+
+    async fn query(&self, path: &str, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        Application::query(self, path, data)
+    }
+
+    async fn deliver_evidence(&self, evidence: ApiEquivocationEvidence) -> Result<()> {
+        Application::deliver_evidence(self, evidence)
+    }
 }
 
 /// Dummy application which doesn't do any validation.
@@ -2,10 +2,16 @@ use std::time::Duration;
 
 use thiserror::Error;
 
-use crate::api::types::{ApiBlockBroadcastInfo, ApiBroadcastInfo, ApiHealth};
+use crate::api::types::{
+    ApiBlockBroadcastInfo, ApiBroadcastInfo, ApiEquivocationEvidence, ApiHealth, ApiMempoolStats,
+    ApiPrunedBlock, ApiPruningStats, ApiQuorumCertificate, ApiSubmitMessageResult, ApiSyncStatus,
+};
+use crate::api::verifier::{BlockVerdict, BlockVerifier};
 use crate::ephemera_api::{
-    ApiBlock, ApiCertificate, ApiDhtQueryRequest, ApiDhtQueryResponse, ApiDhtStoreRequest,
-    ApiEphemeraConfig, ApiEphemeraMessage, ApiVerifyMessageInBlock,
+    ApiApplicationHash, ApiApplicationQueryRequest, ApiApplicationQueryResponse, ApiBlock,
+    ApiBlockCertificate, ApiDhtQueryRequest, ApiDhtQueryResponse, ApiDhtStoreRequest,
+    ApiEphemeraConfig, ApiEphemeraMessage, ApiError, ApiMessageProof, ApiMessageStatus,
+    ApiVerifyMessageInBlock,
 };
 
 #[derive(Error, Debug)]
@@ -17,6 +23,11 @@ pub enum Error {
         status: reqwest::StatusCode,
         body: String,
     },
+    #[error(transparent)]
+    Api(#[from] ApiError),
+    /// Block served by the node failed local verification.
+    #[error("Block {} failed verification", .0.block_hash)]
+    Unverified(Box<BlockVerdict>),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -96,7 +107,7 @@ impl Client {
     /// * Option<[`ApiBlock`]> - The block.
     ///
     /// # Errors
-    /// If the request fails.
+    /// If the request fails or `ApiError::BlockPruned` if the block was pruned.
     pub async fn get_block_by_hash(&self, hash: &str) -> Result<Option<ApiBlock>> {
         let url = format!("ephemera/broadcast/block/{hash}",);
         self.query_optional(&url).await
@@ -106,7 +117,7 @@ impl Client {
     ///
     /// # Example
     /// ```no_run
-    /// use ephemera::ephemera_api::{ApiCertificate, Client};
+    /// use ephemera::ephemera_api::{ApiBlockCertificate, Client};
     ///
     /// #[tokio::main]
     /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -120,15 +131,51 @@ impl Client {
     /// * `hash` - The hash of the block.
     ///
     /// # Returns
-    /// * Option<Vec<[`ApiCertificate`]>> - The block certificates.
+    /// * Option<Vec<[`ApiBlockCertificate`]>> - The block certificates.
     ///
     /// # Errors
-    /// If the request fails.
-    pub async fn get_block_certificates(&self, hash: &str) -> Result<Option<Vec<ApiCertificate>>> {
+    /// If the request fails or `ApiError::BlockPruned` if the block was pruned.
+    pub async fn get_block_certificates(
+        &self,
+        hash: &str,
+    ) -> Result<Option<Vec<ApiBlockCertificate>>> {
         let url = format!("ephemera/broadcast/block/certificates/{hash}",);
         self.query_optional(&url).await
     }
 
+    /// Get the compact quorum certificate of the block by hash.
+    ///
+    /// It can be verified against the block header and the block broadcast group, see
+    /// [`ApiQuorumCertificate::verify`] and [`Client::get_block_broadcast_info`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use ephemera::ephemera_api::{ApiQuorumCertificate, Client};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///    let client = Client::new("http://localhost:7000".to_string());
+    ///    let certificate = client.get_block_quorum_certificate("9D2LaY17rbnxfgKUbvcsJ5cB2BRHEd8fPJwsBnDHNGBX").await?;
+    ///    Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Arguments
+    /// * `hash` - The hash of the block.
+    ///
+    /// # Returns
+    /// * Option<[`ApiQuorumCertificate`]> - The block quorum certificate.
+    ///
+    /// # Errors
+    /// If the request fails or `ApiError::BlockPruned` if the block was pruned.
+    pub async fn get_block_quorum_certificate(
+        &self,
+        hash: &str,
+    ) -> Result<Option<ApiQuorumCertificate>> {
+        let url = format!("ephemera/broadcast/block/certificates/{hash}?format=quorum");
+        self.query_optional(&url).await
+    }
+
     /// Get the block by height.
     ///
     /// # Example
@@ -150,12 +197,83 @@ impl Client {
     /// * Option<[`ApiBlock`]> - The block.
     ///
     /// # Errors
-    /// If the request fails.
+    /// If the request fails or `ApiError::BlockPruned` if the block was pruned.
     pub async fn get_block_by_height(&self, height: u64) -> Result<Option<ApiBlock>> {
         let url = format!("ephemera/broadcast/block/height/{height}",);
         self.query_optional(&url).await
     }
 
+    /// Get blocks by height range. The node returns at most 100 blocks per request.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use ephemera::ephemera_api::{ApiBlock, Client};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///    let client = Client::new("http://localhost:7000/".to_string());
+    ///    let blocks = client.get_blocks_by_height_range(1, 10).await?;
+    ///    Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Arguments
+    /// * `from_height` - The height of the first block.
+    /// * `to_height` - The height of the last block.
+    ///
+    /// # Returns
+    /// * `Vec<ApiBlock>` - Blocks ordered by height.
+    ///
+    /// # Errors
+    /// If the request fails.
+    pub async fn get_blocks_by_height_range(
+        &self,
+        from_height: u64,
+        to_height: u64,
+    ) -> Result<Vec<ApiBlock>> {
+        let url = format!("ephemera/broadcast/blocks/range/{from_height}/{to_height}");
+        self.query(&url).await
+    }
+
+    /// Walks blocks from `from_height` to `to_height` and verifies that they form a hash-linked chain.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use ephemera::ephemera_api::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///    let client = Client::new("http://localhost:7000/".to_string());
+    ///    let valid = client.verify_chain_segment(1, 1000).await?;
+    ///    Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Arguments
+    /// * `from_height` - The height of the first block.
+    /// * `to_height` - The height of the last block.
+    ///
+    /// # Returns
+    /// * bool - True if all blocks exist and are linked, false otherwise.
+    ///
+    /// # Errors
+    /// If the request fails or blocks can't be verified.
+    pub async fn verify_chain_segment(&self, from_height: u64, to_height: u64) -> Result<bool> {
+        let mut blocks: Vec<ApiBlock> = vec![];
+        let mut next_height = from_height;
+        while next_height <= to_height {
+            let page = self
+                .get_blocks_by_height_range(next_height, to_height)
+                .await?;
+            match page.last() {
+                Some(last) => next_height = last.header.height + 1,
+                None => return Ok(false),
+            }
+            blocks.extend(page);
+        }
+        Ok(ApiBlock::verify_chain(&blocks)?)
+    }
+
     /// Get the last block.
     ///
     /// # Example
@@ -202,6 +320,97 @@ impl Client {
         self.query("ephemera/node/config").await
     }
 
+    /// Get the node block synchronization status.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use ephemera::ephemera_api::{ApiSyncStatus, Client};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///    let client = Client::new("http://localhost:7000/".to_string());
+    ///    let status = client.get_sync_status().await?;
+    ///    Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Returns
+    /// * [`ApiSyncStatus`] - The synchronization status.
+    ///
+    /// # Errors
+    /// If the request fails.
+    pub async fn get_sync_status(&self) -> Result<ApiSyncStatus> {
+        self.query("ephemera/node/sync_status").await
+    }
+
+    /// Get the node's message pool size, limits and counters.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use ephemera::ephemera_api::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///   let client = Client::new("http://localhost:7000/".to_string());
+    ///   let stats = client.get_mempool_stats().await?;
+    ///   Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    /// If the request fails.
+    pub async fn get_mempool_stats(&self) -> Result<ApiMempoolStats> {
+        self.query("ephemera/node/mempool").await
+    }
+
+    /// Get the node's block pruning counters.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use ephemera::ephemera_api::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///   let client = Client::new("http://localhost:7000/".to_string());
+    ///   let stats = client.get_pruning_stats().await?;
+    ///   Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    /// If the request fails.
+    pub async fn get_pruning_stats(&self) -> Result<ApiPruningStats> {
+        self.query("ephemera/node/pruning").await
+    }
+
+    /// Get the most recent equivocation evidence detected by the node, newest first.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use ephemera::ephemera_api::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///   let client = Client::new("http://localhost:7000/".to_string());
+    ///   for evidence in client.get_evidence(Some(10)).await? {
+    ///       assert!(evidence.verify()?);
+    ///   }
+    ///   Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    /// If the request fails.
+    pub async fn get_evidence(&self, limit: Option<usize>) -> Result<Vec<ApiEquivocationEvidence>> {
+        match limit {
+            Some(limit) => {
+                self.query(&format!("ephemera/broadcast/evidence?limit={limit}"))
+                    .await
+            }
+            None => self.query("ephemera/broadcast/evidence").await,
+        }
+    }
+
     /// Submit a message to the node.
     ///
     /// # Example
@@ -212,7 +421,7 @@ impl Client {
     /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
     ///   let client = Client::new("http://localhost:7000/".to_string());
     ///   let message = unimplemented!("See how to create a ApiEphemeraMessage");
-    ///   client.submit_message(message).await?;
+    ///   let message_hash = client.submit_message(message).await?;
     ///   Ok(())
     /// }
     ///
@@ -221,13 +430,67 @@ impl Client {
     /// # Arguments
     /// * `message` - The message to submit.
     ///
+    /// # Returns
+    /// * `String` - The message hash, see [`Client::get_message_status`].
+    ///
     /// # Errors
     /// If the request fails.
-    pub async fn submit_message(&self, message: ApiEphemeraMessage) -> Result<()> {
+    /// If the node's message pool is full, returns `Error::Api(ApiError::MempoolFull)`.
+    /// Then the message can be submitted again later.
+    pub async fn submit_message(&self, message: ApiEphemeraMessage) -> Result<String> {
         let url = format!("{}/{}", self.url, "ephemera/broadcast/submit_message");
         let response = self.client.post(&url).json(&message).send().await?;
         if response.status().is_success() {
-            Ok(())
+            let message_hash = response.json::<String>().await?;
+            Ok(message_hash)
+        } else if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            Err(ApiError::MempoolFull(response.text().await?).into())
+        } else {
+            Err(Error::UnexpectedResponse {
+                status: response.status(),
+                body: response.text().await?,
+            })
+        }
+    }
+
+    /// Submit a batch of messages to the node in a single request.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use ephemera::ephemera_api::{ApiEphemeraMessage, Client};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///   let client = Client::new("http://localhost:7000/".to_string());
+    ///   let messages: Vec<ApiEphemeraMessage> = unimplemented!("See how to create a ApiEphemeraMessage");
+    ///   for result in client.submit_messages(messages).await? {
+    ///       if !result.accepted {
+    ///           println!("Message {} rejected: {:?}", result.message_hash, result.error);
+    ///       } else if let Some(error) = result.error {
+    ///           println!("Message {} accepted, but not gossiped: {error}", result.message_hash);
+    ///       }
+    ///   }
+    ///   Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Arguments
+    /// * `messages` - The messages to submit, at most 1000.
+    ///
+    /// # Returns
+    /// * Vec<[`ApiSubmitMessageResult`]> - Result of each message, in the same order.
+    ///
+    /// # Errors
+    /// If the request fails or the batch has too many messages.
+    pub async fn submit_messages(
+        &self,
+        messages: Vec<ApiEphemeraMessage>,
+    ) -> Result<Vec<ApiSubmitMessageResult>> {
+        let url = format!("{}/{}", self.url, "ephemera/broadcast/submit_messages");
+        let response = self.client.post(&url).json(&messages).send().await?;
+        if response.status().is_success() {
+            let results = response.json::<Vec<ApiSubmitMessageResult>>().await?;
+            Ok(results)
         } else {
             Err(Error::UnexpectedResponse {
                 status: response.status(),
@@ -351,6 +614,68 @@ impl Client {
         self.query_dht(request).await
     }
 
+    /// Query application state.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use ephemera::ephemera_api::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///   let client = Client::new("http://localhost:7000/".to_string());
+    ///   let rewards = client.query_application("rewards", &[]).await?;
+    ///   Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Arguments
+    /// * `path` - Application specific path.
+    /// * `data` - Application specific query parameters.
+    ///
+    /// # Returns
+    /// * `None` - If the application didn't find anything.
+    ///
+    /// # Errors
+    /// If the request fails or the application failed to answer the query.
+    pub async fn query_application(&self, path: &str, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        let request = ApiApplicationQueryRequest::new(data);
+        let url = match request.data_encoded() {
+            Some(data) => format!("ephemera/application/query/{path}?data={data}"),
+            None => format!("ephemera/application/query/{path}"),
+        };
+        let response: Option<ApiApplicationQueryResponse> = self.query_optional(&url).await?;
+        Ok(response.map(|response| response.value()))
+    }
+
+    /// Get the local application state hash after the block at given height.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use ephemera::ephemera_api::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///   let client = Client::new("http://localhost:7000/".to_string());
+    ///   if let Some(app_hash) = client.get_application_hash(10).await? {
+    ///       assert!(!app_hash.is_mismatch());
+    ///   }
+    ///   Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Arguments
+    /// * `height` - The height of the block.
+    ///
+    /// # Returns
+    /// * `None` - If the application hasn't committed the block.
+    ///
+    /// # Errors
+    /// If the request fails.
+    pub async fn get_application_hash(&self, height: u64) -> Result<Option<ApiApplicationHash>> {
+        let url = format!("ephemera/application/app_hash/{height}");
+        self.query_optional(&url).await
+    }
+
     /// Get broadcast group info.
     ///
     /// # Example
@@ -454,6 +779,174 @@ impl Client {
         }
     }
 
+    /// Returns Merkle inclusion proof of a message in a block.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use ephemera::ephemera_api::Client;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = Client::new("http://localhost:7000/".to_string());
+    /// let proof = client.get_message_proof("block_hash", "message_hash").await?;
+    /// Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Arguments
+    /// * `block_hash` - Hash of the block to query.
+    /// * `message_hash` - Hash of the message to query.
+    ///
+    /// # Returns
+    /// * `None` - If the block doesn't exist or doesn't include the message.
+    ///
+    /// # Errors
+    /// If the request fails or `ApiError::BlockPruned` if the block was pruned.
+    pub async fn get_message_proof(
+        &self,
+        block_hash: &str,
+        message_hash: &str,
+    ) -> Result<Option<ApiMessageProof>> {
+        let url = format!("ephemera/messages/proof/{block_hash}/{message_hash}");
+        self.query_optional(&url).await
+    }
+
+    /// Returns what happened to a submitted message.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use ephemera::ephemera_api::{ApiMessageStatus, Client};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = Client::new("http://localhost:7000/".to_string());
+    /// if let Some(ApiMessageStatus::Included { block_hash, height }) =
+    ///     client.get_message_status("message_hash").await?
+    /// {
+    ///     println!("Message is in block {block_hash} at height {height}");
+    /// }
+    /// Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Arguments
+    /// * `message_hash` - Hash of the message, returned by [`Client::submit_message`].
+    ///
+    /// # Returns
+    /// * Some([`ApiMessageStatus`]) - Pending, proposed or included in a block, rejected, expired or evicted.
+    /// * None - If the node doesn't know the message, or doesn't remember its status anymore.
+    ///
+    /// # Errors
+    /// If the request fails.
+    pub async fn get_message_status(&self, message_hash: &str) -> Result<Option<ApiMessageStatus>> {
+        let url = format!("ephemera/messages/status/{message_hash}");
+        self.query_optional(&url).await
+    }
+
+    /// Verifies locally that the message is in the block.
+    ///
+    /// Unlike [`Client::verify_message_in_block`], it doesn't trust the node's answer. It fetches
+    /// the message proof and the block certificates and checks them with [`ApiMessageProof::verify`].
+    ///
+    /// The verifier has the block broadcast group. Obtain it independently of this node, for example from
+    /// the membership provider, the node could sign the proof itself otherwise.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use ephemera::ephemera_api::{BlockVerifier, Client};
+    /// use ephemera::peer::PeerId;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = Client::new("http://localhost:7000/".to_string());
+    /// let members: Vec<PeerId> = vec![]; //From the membership provider
+    /// let verifier = BlockVerifier::with_group(members);
+    /// let is_in_block = client.verify_message_proof("block_hash", "message_hash", &verifier).await?;
+    /// Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Arguments
+    /// * `block_hash` - The hash of the block.
+    /// * `message_hash` - The hash of the message.
+    /// * `verifier` - Verifier with the block broadcast group.
+    ///
+    /// # Returns
+    /// * bool - True if the proof is valid, false otherwise or if the block or message doesn't exist.
+    ///
+    /// # Errors
+    /// If the request fails or the proof contains invalid hashes.
+    pub async fn verify_message_proof(
+        &self,
+        block_hash: &str,
+        message_hash: &str,
+        verifier: &BlockVerifier,
+    ) -> Result<bool> {
+        let Some(proof) = self.get_message_proof(block_hash, message_hash).await? else {
+            return Ok(false);
+        };
+        let Some(certificates) = self.get_block_certificates(block_hash).await? else {
+            return Ok(false);
+        };
+        if proof.header.hash != block_hash || proof.message_hash != message_hash {
+            return Ok(false);
+        }
+        Ok(proof.verify(&certificates, verifier)?)
+    }
+
+    /// Get the block by hash and verify it locally.
+    ///
+    /// Unlike [`Client::get_block_by_hash`], it doesn't trust the node. It fetches the block and its certificates
+    /// and checks them with the given [`BlockVerifier`]. The verifier has the block broadcast group, obtain it
+    /// independently of this node, for example from the membership provider.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use ephemera::ephemera_api::{BlockVerifier, Client};
+    /// use ephemera::peer::PeerId;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = Client::new("http://localhost:7000/".to_string());
+    /// let members: Vec<PeerId> = vec![]; //From the membership provider
+    /// let verifier = BlockVerifier::with_group(members);
+    /// let block = client.get_verified_block("9D2LaY17rbnxfgKUbvcsJ5cB2BRHEd8fPJwsBnDHNGBX", &verifier).await?;
+    /// Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Arguments
+    /// * `hash` - The hash of the block.
+    /// * `verifier` - Verifier with the block broadcast group.
+    ///
+    /// # Returns
+    /// * Some([`ApiBlock`]) - If the block was found and verified.
+    /// * None - If the block or its certificates are not found.
+    ///
+    /// # Errors
+    /// * `Error::Unverified` with the [`BlockVerdict`] if the block failed verification.
+    /// * If the request fails or `ApiError::BlockPruned` if the block was pruned.
+    pub async fn get_verified_block(
+        &self,
+        hash: &str,
+        verifier: &BlockVerifier,
+    ) -> Result<Option<ApiBlock>> {
+        let Some(block) = self.get_block_by_hash(hash).await? else {
+            return Ok(None);
+        };
+        let Some(certificates) = self.get_block_certificates(hash).await? else {
+            return Ok(None);
+        };
+        let mut verdict = verifier.verify(&block, &certificates)?;
+        //The node could have served a valid block, just not the one asked for
+        verdict.hash_valid &= block.hash() == hash;
+        if verdict.is_valid() {
+            Ok(Some(block))
+        } else {
+            Err(Error::Unverified(Box::new(verdict)))
+        }
+    }
+
     async fn query_optional<T: for<'de> serde::Deserialize<'de>>(
         &self,
         path: &str,
@@ -466,6 +959,9 @@ impl Client {
                     Ok(Some(body))
                 } else if response.status() == reqwest::StatusCode::NOT_FOUND {
                     Ok(None)
+                } else if response.status() == reqwest::StatusCode::GONE {
+                    let pruned = response.json::<ApiPrunedBlock>().await?;
+                    Err(ApiError::BlockPruned(Box::new(pruned)).into())
                 } else {
                     return Err(Error::UnexpectedResponse {
                         status: response.status(),
@@ -21,12 +21,22 @@ pub(crate) fn init(node_info: &NodeInfo, api: CommandExecutor) -> anyhow::Result
             .service(query::block_by_hash)
             .service(query::block_certificates)
             .service(query::block_by_height)
+            .service(query::blocks_by_height_range)
             .service(query::block_broadcast_group)
             .service(query::last_block)
             .service(query::node_config)
+            .service(query::sync_status)
+            .service(query::mempool_stats)
+            .service(query::pruning_stats)
+            .service(query::evidence)
             .service(query::query_dht)
             .service(query::broadcast_info)
+            .service(query::message_proof)
+            .service(query::message_status)
+            .service(query::query_application)
+            .service(query::application_hash)
             .service(submit::submit_message)
+            .service(submit::submit_messages)
             .service(submit::store_in_dht)
             .service(submit::verify_message_in_block)
             .service(swagger_ui())
@@ -49,19 +59,31 @@ fn swagger_ui() -> SwaggerUi {
             query::block_by_hash,
             query::block_certificates,
             query::block_by_height,
+            query::blocks_by_height_range,
             query::last_block,
             query::block_broadcast_group,
             query::node_config,
+            query::sync_status,
+            query::mempool_stats,
+            query::pruning_stats,
+            query::evidence,
             query::query_dht,
             query::broadcast_info,
+            query::message_proof,
+            query::message_status,
+            query::query_application,
+            query::application_hash,
             submit::submit_message,
+            submit::submit_messages,
             submit::store_in_dht,
             submit::verify_message_in_block
         ),
         components(schemas(
             types::ApiBlock,
+            types::ApiBlockHeader,
             types::ApiEphemeraMessage,
             types::ApiCertificate,
+            types::ApiBlockCertificate,
             types::ApiSignature,
             types::ApiPublicKey,
             types::ApiHealth,
@@ -72,6 +94,21 @@ fn swagger_ui() -> SwaggerUi {
             types::ApiDhtQueryResponse,
             types::ApiBroadcastInfo,
             types::ApiVerifyMessageInBlock,
+            types::ApiSyncStatus,
+            types::ApiMessageProof,
+            types::ApiMempoolStats,
+            types::ApiApplicationQueryRequest,
+            types::ApiApplicationQueryResponse,
+            types::ApiApplicationHash,
+            types::ApiPrunedBlock,
+            types::ApiPruningStats,
+            types::ApiEquivocationEvidence,
+            types::ApiEquivocationKind,
+            types::ApiSignedHeader,
+            types::ApiQuorumCertificate,
+            types::ApiBroadcastPhase,
+            types::ApiMessageStatus,
+            types::ApiSubmitMessageResult,
         ))
     )]
     struct ApiDoc;
@@ -1,9 +1,15 @@
 use actix_web::{get, web, HttpResponse, Responder};
-use log::error;
+use log::{debug, error};
+use serde::Deserialize;
 
 use crate::{
-    api::{types::ApiHealth, types::HealthStatus::Healthy, CommandExecutor},
-    ephemera_api::{ApiDhtQueryRequest, ApiDhtQueryResponse},
+    api::{
+        types::ApiHealth, types::HealthStatus::Healthy, CommandExecutor, MAX_EVIDENCE_PER_QUERY,
+    },
+    ephemera_api::{
+        ApiApplicationQueryRequest, ApiApplicationQueryResponse, ApiDhtQueryRequest,
+        ApiDhtQueryResponse, ApiError,
+    },
 };
 
 #[utoipa::path(
@@ -36,6 +42,7 @@ pub(crate) async fn broadcast_info(api: web::Data<CommandExecutor>) -> impl Resp
 responses(
 (status = 200, description = "GET block by hash"),
 (status = 404, description = "Block not found"),
+(status = 410, description = "Block is pruned", body = ApiPrunedBlock),
 (status = 500, description = "Server failed to process request")),
 params(("hash", description = "Block hash")),
 )]
@@ -47,6 +54,7 @@ pub(crate) async fn block_by_hash(
     match api.get_block_by_id(hash.into_inner()).await {
         Ok(Some(block)) => HttpResponse::Ok().json(block),
         Ok(_) => HttpResponse::NotFound().json("Block not found"),
+        Err(ApiError::BlockPruned(pruned)) => HttpResponse::Gone().json(pruned),
         Err(err) => {
             error!("Failed to get block by hash: {err}",);
             HttpResponse::InternalServerError().json("Server failed to process request")
@@ -54,22 +62,53 @@ pub(crate) async fn block_by_hash(
     }
 }
 
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CertificatesFormat {
+    /// Certificate(signature and public key) per signer
+    #[default]
+    Individual,
+    /// Bitmap of signers over the broadcast group and their signatures
+    Quorum,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CertificatesQuery {
+    #[serde(default)]
+    format: CertificatesFormat,
+}
+
 #[utoipa::path(
 responses(
-(status = 200, description = "Get block signatures"),
+(status = 200, description = "Get block signatures, individual certificates or a compact quorum certificate"),
 (status = 404, description = "Certificates not found"),
+(status = 410, description = "Block is pruned without its certificates", body = ApiPrunedBlock),
 (status = 500, description = "Server failed to process request")),
-params(("hash", description = "Block hash")),
+params(("hash", description = "Block hash"),
+("format", Query, description = "`individual`(default) or `quorum`")),
 )]
 #[get("/ephemera/broadcast/block/certificates/{hash}")]
 pub(crate) async fn block_certificates(
     hash: web::Path<String>,
+    query: web::Query<CertificatesQuery>,
     api: web::Data<CommandExecutor>,
 ) -> impl Responder {
     let id = hash.into_inner();
+    if query.format == CertificatesFormat::Quorum {
+        return match api.get_block_quorum_certificate(id).await {
+            Ok(Some(quorum_certificate)) => HttpResponse::Ok().json(quorum_certificate),
+            Ok(_) => HttpResponse::NotFound().json("Certificates not found"),
+            Err(ApiError::BlockPruned(pruned)) => HttpResponse::Gone().json(pruned),
+            Err(err) => {
+                error!("Failed to get quorum certificate {err}",);
+                HttpResponse::InternalServerError().json("Server failed to process request")
+            }
+        };
+    }
     match api.get_block_certificates(id.clone()).await {
         Ok(Some(signatures)) => HttpResponse::Ok().json(signatures),
         Ok(_) => HttpResponse::NotFound().json("Certificates not found"),
+        Err(ApiError::BlockPruned(pruned)) => HttpResponse::Gone().json(pruned),
         Err(err) => {
             error!("Failed to get signatures {err}",);
             HttpResponse::InternalServerError().json("Server failed to process request")
@@ -81,6 +120,7 @@ pub(crate) async fn block_certificates(
 responses(
 (status = 200, description = "Get block by height"),
 (status = 404, description = "Block not found"),
+(status = 410, description = "Block is pruned", body = ApiPrunedBlock),
 (status = 500, description = "Server failed to process request")),
 params(("height", description = "Block height")),
 )]
@@ -92,6 +132,7 @@ pub(crate) async fn block_by_height(
     match api.get_block_by_height(height.into_inner()).await {
         Ok(Some(block)) => HttpResponse::Ok().json(block),
         Ok(_) => HttpResponse::NotFound().json("Block not found"),
+        Err(ApiError::BlockPruned(pruned)) => HttpResponse::Gone().json(pruned),
         Err(err) => {
             error!("Failed to get block {err}",);
             HttpResponse::InternalServerError().json("Server failed to process request")
@@ -99,6 +140,138 @@ pub(crate) async fn block_by_height(
     }
 }
 
+#[utoipa::path(
+responses(
+(status = 200, description = "Get blocks by height range, at most 100 blocks ordered by height. Pruned blocks are skipped"),
+(status = 500, description = "Server failed to process request")),
+params(("from_height", description = "First block height"),
+("to_height", description = "Last block height")),
+)]
+#[get("/ephemera/broadcast/blocks/range/{from_height}/{to_height}")]
+pub(crate) async fn blocks_by_height_range(
+    range: web::Path<(u64, u64)>,
+    api: web::Data<CommandExecutor>,
+) -> impl Responder {
+    let (from_height, to_height) = range.into_inner();
+    match api.get_blocks_by_height_range(from_height, to_height).await {
+        Ok(blocks) => HttpResponse::Ok().json(blocks),
+        Err(err) => {
+            error!("Failed to get blocks {err}",);
+            HttpResponse::InternalServerError().json("Server failed to process request")
+        }
+    }
+}
+
+#[utoipa::path(
+responses(
+(status = 200, description = "Get Merkle inclusion proof of a message in a block", body = ApiMessageProof),
+(status = 400, description = "Invalid message hash"),
+(status = 404, description = "Block not found or message is not in the block"),
+(status = 410, description = "Block is pruned", body = ApiPrunedBlock),
+(status = 500, description = "Server failed to process request")),
+params(("block_hash", description = "Block hash"),
+("message_hash", description = "Message hash")),
+)]
+#[get("/ephemera/messages/proof/{block_hash}/{message_hash}")]
+pub(crate) async fn message_proof(
+    path: web::Path<(String, String)>,
+    api: web::Data<CommandExecutor>,
+) -> impl Responder {
+    let (block_hash, message_hash) = path.into_inner();
+    match api.get_message_proof(block_hash, message_hash).await {
+        Ok(Some(proof)) => HttpResponse::Ok().json(proof),
+        Ok(_) => HttpResponse::NotFound().json("Message proof not found"),
+        Err(ApiError::InvalidHash(err)) => HttpResponse::BadRequest().json(err),
+        Err(ApiError::BlockPruned(pruned)) => HttpResponse::Gone().json(pruned),
+        Err(err) => {
+            error!("Failed to get message proof {err}",);
+            HttpResponse::InternalServerError().json("Server failed to process request")
+        }
+    }
+}
+
+#[utoipa::path(
+responses(
+(status = 200, description = "Get status of a submitted message", body = ApiMessageStatus),
+(status = 400, description = "Invalid message hash"),
+(status = 404, description = "Message is unknown"),
+(status = 500, description = "Server failed to process request")),
+params(("hash", description = "Message hash")),
+)]
+#[get("/ephemera/messages/status/{hash}")]
+pub(crate) async fn message_status(
+    hash: web::Path<String>,
+    api: web::Data<CommandExecutor>,
+) -> impl Responder {
+    match api.get_message_status(hash.into_inner()).await {
+        Ok(Some(status)) => HttpResponse::Ok().json(status),
+        Ok(_) => HttpResponse::NotFound().json("Message not found"),
+        Err(ApiError::InvalidHash(err)) => HttpResponse::BadRequest().json(err),
+        Err(err) => {
+            error!("Failed to get message status {err}",);
+            HttpResponse::InternalServerError().json("Server failed to process request")
+        }
+    }
+}
+
+#[utoipa::path(
+responses(
+(status = 200, description = "Get message pool size, limits and counters", body = ApiMempoolStats),
+(status = 500, description = "Server failed to process request")),
+)]
+#[get("/ephemera/node/mempool")]
+pub(crate) async fn mempool_stats(api: web::Data<CommandExecutor>) -> impl Responder {
+    match api.get_mempool_stats().await {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(err) => {
+            error!("Failed to get mempool stats: {err}",);
+            HttpResponse::InternalServerError().json("Server failed to process request")
+        }
+    }
+}
+
+#[utoipa::path(
+responses(
+(status = 200, description = "Get block pruning counters", body = ApiPruningStats),
+(status = 500, description = "Server failed to process request")),
+)]
+#[get("/ephemera/node/pruning")]
+pub(crate) async fn pruning_stats(api: web::Data<CommandExecutor>) -> impl Responder {
+    match api.get_pruning_stats().await {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(err) => {
+            error!("Failed to get pruning stats: {err}",);
+            HttpResponse::InternalServerError().json("Server failed to process request")
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct EvidenceQuery {
+    limit: Option<usize>,
+}
+
+#[utoipa::path(
+responses(
+(status = 200, description = "Get the most recent equivocation evidence, newest first", body = [ApiEquivocationEvidence]),
+(status = 500, description = "Server failed to process request")),
+params(("limit", Query, description = "Maximum number of records, at most 100(default)")),
+)]
+#[get("/ephemera/broadcast/evidence")]
+pub(crate) async fn evidence(
+    api: web::Data<CommandExecutor>,
+    query: web::Query<EvidenceQuery>,
+) -> impl Responder {
+    let limit = query.limit.unwrap_or(MAX_EVIDENCE_PER_QUERY);
+    match api.get_evidence(limit).await {
+        Ok(evidence) => HttpResponse::Ok().json(evidence),
+        Err(err) => {
+            error!("Failed to get evidence: {err}",);
+            HttpResponse::InternalServerError().json("Server failed to process request")
+        }
+    }
+}
+
 #[utoipa::path(
 responses(
 (status = 200, description = "Get last block"),
@@ -155,6 +328,22 @@ pub(crate) async fn node_config(api: web::Data<CommandExecutor>) -> impl Respond
     }
 }
 
+#[utoipa::path(
+responses(
+(status = 200, description = "Get node block synchronization status"),
+(status = 500, description = "Server failed to process request")),
+)]
+#[get("/ephemera/node/sync_status")]
+pub(crate) async fn sync_status(api: web::Data<CommandExecutor>) -> impl Responder {
+    match api.get_sync_status().await {
+        Ok(status) => HttpResponse::Ok().json(status),
+        Err(err) => {
+            error!("Failed to get sync status {err}",);
+            HttpResponse::InternalServerError().json("Server failed to process request")
+        }
+    }
+}
+
 #[utoipa::path(
 responses(
 (status = 200, description = "Query dht"),
@@ -180,3 +369,61 @@ pub(crate) async fn query_dht(
         }
     }
 }
+
+#[utoipa::path(
+responses(
+(status = 200, description = "Query application state", body = ApiApplicationQueryResponse),
+(status = 400, description = "Invalid query or application failed to answer it"),
+(status = 404, description = "Application didn't find anything"),
+(status = 500, description = "Server failed to process request")),
+params(
+("path", description = "Application specific path"),
+("data" = Option<String>, Query, description = "Application specific query parameters in hex format")
+),
+)]
+#[get("/ephemera/application/query/{path}")]
+pub(crate) async fn query_application(
+    api: web::Data<CommandExecutor>,
+    path: web::Path<String>,
+    request: web::Query<ApiApplicationQueryRequest>,
+) -> impl Responder {
+    let path = path.into_inner();
+    let Ok(data) = request.data() else {
+        return HttpResponse::BadRequest().json("Invalid query data");
+    };
+
+    match api.query_application(path.clone(), data).await {
+        Ok(Some(value)) => HttpResponse::Ok().json(ApiApplicationQueryResponse::new(path, &value)),
+        Ok(None) => HttpResponse::NotFound().json("Not found"),
+        Err(ApiError::Application(err)) => {
+            debug!("Application failed to answer query {path}: {err}");
+            HttpResponse::BadRequest().json(format!("Application failed to answer query: {err}"))
+        }
+        Err(err) => {
+            error!("Failed to query application {err}",);
+            HttpResponse::InternalServerError().json("Server failed to process request")
+        }
+    }
+}
+
+#[utoipa::path(
+responses(
+(status = 200, description = "Get the local application state hash after the block at given height", body = ApiApplicationHash),
+(status = 404, description = "Application hasn't committed the block"),
+(status = 500, description = "Server failed to process request")),
+params(("height", description = "Block height")),
+)]
+#[get("/ephemera/application/app_hash/{height}")]
+pub(crate) async fn application_hash(
+    height: web::Path<u64>,
+    api: web::Data<CommandExecutor>,
+) -> impl Responder {
+    match api.get_application_hash(height.into_inner()).await {
+        Ok(Some(app_hash)) => HttpResponse::Ok().json(app_hash),
+        Ok(None) => HttpResponse::NotFound().json("Application hash not found"),
+        Err(err) => {
+            error!("Failed to get application hash {err}",);
+            HttpResponse::InternalServerError().json("Server failed to process request")
+        }
+    }
+}
@@ -10,7 +10,10 @@ use crate::api::{
 #[utoipa::path(
 request_body = ApiEphemeraMessage,
 responses(
-(status = 200, description = "Send a message to an Ephemera node which will be broadcast to the network"),
+(status = 200, description = "Send a message to an Ephemera node which will be broadcast to the network. \
+Returns the message hash"),
+(status = 400, description = "Message already submitted or expired"),
+(status = 429, description = "Message pool is full, try again later"),
 (status = 500, description = "Server failed to process request")),
 params(("message", description = "Message to send"))
 )]
@@ -20,15 +23,49 @@ pub(crate) async fn submit_message(
     api: web::Data<CommandExecutor>,
 ) -> HttpResponse {
     match api.send_ephemera_message(message.into_inner()).await {
-        Ok(_) => HttpResponse::Ok().json("Message submitted"),
+        Ok(message_hash) => HttpResponse::Ok().json(message_hash),
+        Err(ApiError::DuplicateMessage) => {
+            debug!("Message already submitted");
+            HttpResponse::BadRequest().json("Message already submitted")
+        }
+        Err(ApiError::MessageExpired) => {
+            debug!("Message expired");
+            HttpResponse::BadRequest().json("Message expired")
+        }
+        Err(ApiError::MempoolFull(err)) => {
+            debug!("Message pool is full: {err}");
+            HttpResponse::TooManyRequests().json(format!("Message pool is full: {err}"))
+        }
         Err(err) => {
-            if let ApiError::DuplicateMessage = err {
-                debug!("Message already submitted {err:?}");
-                HttpResponse::BadRequest().json("Message already submitted")
-            } else {
-                error!("Error submitting message: {}", err);
-                HttpResponse::InternalServerError().json("Server failed to process request")
-            }
+            error!("Error submitting message: {}", err);
+            HttpResponse::InternalServerError().json("Server failed to process request")
+        }
+    }
+}
+
+#[utoipa::path(
+request_body = Vec<ApiEphemeraMessage>,
+responses(
+(status = 200, description = "Send a batch of messages to an Ephemera node. Returns result of each message in the same order",
+body = Vec<ApiSubmitMessageResult>),
+(status = 400, description = "Batch has too many messages"),
+(status = 500, description = "Server failed to process request")),
+params(("messages", description = "Messages to send"))
+)]
+#[post("/ephemera/broadcast/submit_messages")]
+pub(crate) async fn submit_messages(
+    messages: web::Json<Vec<ApiEphemeraMessage>>,
+    api: web::Data<CommandExecutor>,
+) -> HttpResponse {
+    match api.send_ephemera_messages(messages.into_inner()).await {
+        Ok(results) => HttpResponse::Ok().json(results),
+        Err(err @ ApiError::BatchTooLarge(..)) => {
+            debug!("{err}");
+            HttpResponse::BadRequest().json(err.to_string())
+        }
+        Err(err) => {
+            error!("Error submitting messages: {}", err);
+            HttpResponse::InternalServerError().json("Server failed to process request")
         }
     }
 }
@@ -13,13 +13,16 @@ use tokio::sync::{
 };
 
 use crate::api::types::{
-    ApiBlock, ApiBlockBroadcastInfo, ApiBroadcastInfo, ApiCertificate, ApiEphemeraConfig,
-    ApiEphemeraMessage, ApiError, ApiVerifyMessageInBlock,
+    ApiApplicationHash, ApiBlock, ApiBlockBroadcastInfo, ApiBlockCertificate, ApiBroadcastInfo,
+    ApiEphemeraConfig, ApiEphemeraMessage, ApiEquivocationEvidence, ApiError, ApiMempoolStats,
+    ApiMessageProof, ApiMessageStatus, ApiPruningStats, ApiQuorumCertificate,
+    ApiSubmitMessageResult, ApiSyncStatus, ApiVerifyMessageInBlock,
 };
 
 pub(crate) mod application;
 pub(crate) mod http;
 pub(crate) mod types;
+pub(crate) mod verifier;
 
 /// Kademlia DHT key
 pub(crate) type DhtKey = Vec<u8>;
@@ -32,13 +35,35 @@ pub(crate) type DhtKV = (DhtKey, DhtValue);
 
 pub(crate) type Result<T> = std::result::Result<T, ApiError>;
 
+/// Maximum number of blocks returned by a single block range query.
+pub(crate) const MAX_BLOCKS_PER_RANGE_QUERY: u64 = 100;
+
+/// Maximum number of messages in a single batch submit.
+pub(crate) const MAX_MESSAGES_PER_BATCH: usize = 1000;
+
+/// Maximum number of equivocation evidence records returned by a single query.
+pub(crate) const MAX_EVIDENCE_PER_QUERY: usize = 100;
+
 #[derive(Debug)]
 pub(crate) enum ToEphemeraApiCmd {
-    SubmitEphemeraMessage(Box<ApiEphemeraMessage>, oneshot::Sender<Result<()>>),
+    SubmitEphemeraMessage(Box<ApiEphemeraMessage>, oneshot::Sender<Result<String>>),
+    SubmitEphemeraMessages(
+        Vec<ApiEphemeraMessage>,
+        oneshot::Sender<Result<Vec<ApiSubmitMessageResult>>>,
+    ),
+    QueryMessageStatus(String, oneshot::Sender<Result<Option<ApiMessageStatus>>>),
     QueryBlockByHeight(u64, oneshot::Sender<Result<Option<ApiBlock>>>),
+    QueryBlocksByHeightRange(u64, u64, oneshot::Sender<Result<Vec<ApiBlock>>>),
     QueryBlockByHash(String, oneshot::Sender<Result<Option<ApiBlock>>>),
     QueryLastBlock(oneshot::Sender<Result<ApiBlock>>),
-    QueryBlockCertificates(String, oneshot::Sender<Result<Option<Vec<ApiCertificate>>>>),
+    QueryBlockCertificates(
+        String,
+        oneshot::Sender<Result<Option<Vec<ApiBlockCertificate>>>>,
+    ),
+    QueryBlockQuorumCertificate(
+        String,
+        oneshot::Sender<Result<Option<ApiQuorumCertificate>>>,
+    ),
     QueryDht(DhtKey, oneshot::Sender<Result<Option<DhtKV>>>),
     StoreInDht(DhtKey, DhtValue, oneshot::Sender<Result<()>>),
     QueryEphemeraConfig(oneshot::Sender<Result<ApiEphemeraConfig>>),
@@ -48,6 +73,17 @@ pub(crate) enum ToEphemeraApiCmd {
         oneshot::Sender<Result<Option<ApiBlockBroadcastInfo>>>,
     ),
     VerifyMessageInBlock(String, String, usize, oneshot::Sender<Result<bool>>),
+    QueryMessageProof(
+        String,
+        String,
+        oneshot::Sender<Result<Option<ApiMessageProof>>>,
+    ),
+    QuerySyncStatus(oneshot::Sender<Result<ApiSyncStatus>>),
+    QueryMempoolStats(oneshot::Sender<Result<ApiMempoolStats>>),
+    QueryApplication(String, Vec<u8>, oneshot::Sender<Result<Option<Vec<u8>>>>),
+    QueryApplicationHash(u64, oneshot::Sender<Result<Option<ApiApplicationHash>>>),
+    QueryPruningStats(oneshot::Sender<Result<ApiPruningStats>>),
+    QueryEvidence(usize, oneshot::Sender<Result<Vec<ApiEquivocationEvidence>>>),
 }
 
 impl Display for ToEphemeraApiCmd {
@@ -59,8 +95,20 @@ impl Display for ToEphemeraApiCmd {
             ToEphemeraApiCmd::QueryBlockByHeight(height, _) => {
                 write!(f, "QueryBlockByHeight({height})",)
             }
+            ToEphemeraApiCmd::QueryBlocksByHeightRange(from, to, _) => {
+                write!(f, "QueryBlocksByHeightRange({from}, {to})")
+            }
             ToEphemeraApiCmd::QueryBlockByHash(hash, _) => write!(f, "QueryBlockByHash({hash})",),
             ToEphemeraApiCmd::QueryLastBlock(_) => write!(f, "QueryLastBlock"),
+            ToEphemeraApiCmd::SubmitEphemeraMessages(messages, _) => {
+                write!(f, "SubmitEphemeraMessages({})", messages.len())
+            }
+            ToEphemeraApiCmd::QueryMessageStatus(hash, _) => {
+                write!(f, "QueryMessageStatus({hash})")
+            }
+            ToEphemeraApiCmd::QueryBlockQuorumCertificate(id, _) => {
+                write!(f, "QueryBlockQuorumCertificate({id})")
+            }
             ToEphemeraApiCmd::QueryBlockCertificates(id, _) => {
                 write!(f, "QueryBlockSignatures{id}")
             }
@@ -85,6 +133,27 @@ impl Display for ToEphemeraApiCmd {
                     "VerifyMessageInBlock({block_id}, {message_id}, {height})",
                 )
             }
+            ToEphemeraApiCmd::QueryMessageProof(block_hash, message_hash, _) => {
+                write!(f, "QueryMessageProof({block_hash}, {message_hash})")
+            }
+            ToEphemeraApiCmd::QuerySyncStatus(_) => {
+                write!(f, "SyncStatus")
+            }
+            ToEphemeraApiCmd::QueryMempoolStats(_) => {
+                write!(f, "MempoolStats")
+            }
+            ToEphemeraApiCmd::QueryApplication(path, ..) => {
+                write!(f, "QueryApplication({path})")
+            }
+            ToEphemeraApiCmd::QueryApplicationHash(height, _) => {
+                write!(f, "QueryApplicationHash({height})")
+            }
+            ToEphemeraApiCmd::QueryPruningStats(_) => {
+                write!(f, "PruningStats")
+            }
+            ToEphemeraApiCmd::QueryEvidence(limit, _) => {
+                write!(f, "QueryEvidence({limit})")
+            }
         }
     }
 }
@@ -121,6 +190,7 @@ impl CommandExecutor {
     /// * `ApiBlock` - Block
     ///
     /// # Errors
+    /// * `ApiError::BlockPruned` - If the block was pruned
     /// * `ApiError::InternalError` - If there is an internal error
     pub async fn get_block_by_id(&self, block_id: String) -> Result<Option<ApiBlock>> {
         trace!("get_block_by_id({:?})", block_id);
@@ -137,6 +207,7 @@ impl CommandExecutor {
     /// * `ApiBlock` - Block
     ///
     /// # Errors
+    /// * `ApiError::BlockPruned` - If the block was pruned
     /// * `ApiError::InternalError` - If there is an internal error
     pub async fn get_block_by_height(&self, height: u64) -> Result<Option<ApiBlock>> {
         trace!("get_block_by_height({:?})", height);
@@ -144,6 +215,32 @@ impl CommandExecutor {
             .await
     }
 
+    /// Returns blocks with heights in range `[from_height, to_height]` ordered by height.
+    ///
+    /// At most `MAX_BLOCKS_PER_RANGE_QUERY`(100) blocks are returned. If a block in the range is missing,
+    /// blocks up to it are returned. Pruned blocks are skipped.
+    ///
+    /// # Arguments
+    /// * `from_height` - First block height
+    /// * `to_height` - Last block height
+    ///
+    /// # Returns
+    /// * `Vec<ApiBlock>` - Blocks
+    ///
+    /// # Errors
+    /// * `ApiError::InternalError` - If there is an internal error
+    pub async fn get_blocks_by_height_range(
+        &self,
+        from_height: u64,
+        to_height: u64,
+    ) -> Result<Vec<ApiBlock>> {
+        trace!("get_blocks_by_height_range({from_height}, {to_height})");
+        self.send_and_wait_response(|tx| {
+            ToEphemeraApiCmd::QueryBlocksByHeightRange(from_height, to_height, tx)
+        })
+        .await
+    }
+
     /// Returns last block. Which has maximum height and is stored in database
     ///
     /// # Returns
@@ -163,19 +260,42 @@ impl CommandExecutor {
     /// * `block_hash` - Block id
     ///
     /// # Returns
-    /// * `Vec<ApiCertificate>` - Certificates
+    /// * `Vec<ApiBlockCertificate>` - Certificates of the phase which delivered the block
     ///
     /// # Errors
+    /// * `ApiError::BlockPruned` - If the block was pruned without keeping its certificates
     /// * `ApiError::InternalError` - If there is an internal error
     pub async fn get_block_certificates(
         &self,
         block_hash: String,
-    ) -> Result<Option<Vec<ApiCertificate>>> {
+    ) -> Result<Option<Vec<ApiBlockCertificate>>> {
         trace!("get_block_certificates({block_hash:?})",);
         self.send_and_wait_response(|tx| ToEphemeraApiCmd::QueryBlockCertificates(block_hash, tx))
             .await
     }
 
+    /// Returns compact quorum certificate for given block id
+    ///
+    /// # Arguments
+    /// * `block_hash` - Block id
+    ///
+    /// # Returns
+    /// * `ApiQuorumCertificate` - Bitmap of signers over the block broadcast group and their signatures
+    ///
+    /// # Errors
+    /// * `ApiError::BlockPruned` - If the block was pruned without keeping its certificates
+    /// * `ApiError::InternalError` - If there is an internal error
+    pub async fn get_block_quorum_certificate(
+        &self,
+        block_hash: String,
+    ) -> Result<Option<ApiQuorumCertificate>> {
+        trace!("get_block_quorum_certificate({block_hash:?})",);
+        self.send_and_wait_response(|tx| {
+            ToEphemeraApiCmd::QueryBlockQuorumCertificate(block_hash, tx)
+        })
+        .await
+    }
+
     /// Queries DHT for given key
     ///
     /// # Arguments
@@ -253,14 +373,106 @@ impl CommandExecutor {
             .await
     }
 
+    /// Returns block synchronization status.
+    ///
+    /// Node which is behind its peers fetches missing blocks before it starts to produce new ones.
+    ///
+    /// # Returns
+    /// * `ApiSyncStatus` - Synchronization status
+    ///
+    /// # Errors
+    /// * `ApiError::InternalError` - If there is an internal error
+    pub async fn get_sync_status(&self) -> Result<ApiSyncStatus> {
+        trace!("get_sync_status()");
+        self.send_and_wait_response(ToEphemeraApiCmd::QuerySyncStatus)
+            .await
+    }
+
+    /// Returns message pool size, limits and counters
+    ///
+    /// # Errors
+    /// * `ApiError::InternalError` - If there is an internal error
+    pub async fn get_mempool_stats(&self) -> Result<ApiMempoolStats> {
+        trace!("get_mempool_stats()");
+        self.send_and_wait_response(ToEphemeraApiCmd::QueryMempoolStats)
+            .await
+    }
+
+    /// Returns block pruning counters
+    ///
+    /// # Errors
+    /// * `ApiError::InternalError` - If there is an internal error
+    pub async fn get_pruning_stats(&self) -> Result<ApiPruningStats> {
+        trace!("get_pruning_stats()");
+        self.send_and_wait_response(ToEphemeraApiCmd::QueryPruningStats)
+            .await
+    }
+
+    /// Returns the most recent equivocation evidence detected by the node, newest first
+    ///
+    /// # Arguments
+    /// * `limit` - Maximum number of records, at most [`MAX_EVIDENCE_PER_QUERY`]
+    ///
+    /// # Returns
+    /// * `Vec<ApiEquivocationEvidence>` - Evidence of peers which signed conflicting blocks
+    ///
+    /// # Errors
+    /// * `ApiError::InternalError` - If there is an internal error
+    pub async fn get_evidence(&self, limit: usize) -> Result<Vec<ApiEquivocationEvidence>> {
+        trace!("get_evidence({limit})");
+        let limit = limit.min(MAX_EVIDENCE_PER_QUERY);
+        self.send_and_wait_response(|tx| ToEphemeraApiCmd::QueryEvidence(limit, tx))
+            .await
+    }
+
+    /// Queries application state, see [`crate::ephemera_api::Application::query`].
+    ///
+    /// # Arguments
+    /// * `path` - Application specific path
+    /// * `data` - Application specific query parameters
+    ///
+    /// # Returns
+    /// * `None` - If the application didn't find anything
+    ///
+    /// # Errors
+    /// * `ApiError::Application` - If the application failed to answer the query or timed out
+    /// * `ApiError::InternalError` - If there is an internal error
+    pub async fn query_application(&self, path: String, data: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        trace!("query_application({path}, {data:?})");
+        self.send_and_wait_response(|tx| ToEphemeraApiCmd::QueryApplication(path, data, tx))
+            .await
+    }
+
+    /// Returns the local application state hash after the block at given height
+    ///
+    /// # Arguments
+    /// * `height` - Block height
+    ///
+    /// # Returns
+    /// * `None` - If the application hasn't committed the block yet
+    ///
+    /// # Errors
+    /// * `ApiError::InternalError` - If there is an internal error
+    pub async fn get_application_hash(&self, height: u64) -> Result<Option<ApiApplicationHash>> {
+        trace!("get_application_hash({height})");
+        self.send_and_wait_response(|tx| ToEphemeraApiCmd::QueryApplicationHash(height, tx))
+            .await
+    }
+
     /// Send a message to Ephemera which should then be included in mempool  and broadcast to all peers
     ///
     /// # Arguments
     /// * `message` - Message to be sent
     ///
+    /// # Returns
+    /// * `String` - Hash of the message, see [`CommandExecutor::get_message_status`]
+    ///
     /// # Errors
+    /// * `ApiError::DuplicateMessage` - If the message is already in mempool or was already committed
+    /// * `ApiError::MempoolFull` - If mempool has no room for the message, try again later
+    /// * `ApiError::MessageExpired` - If the message timestamp is older than mempool TTL or committed messages retention allows
     /// * `ApiError::InternalError` - If there is an internal error
-    pub async fn send_ephemera_message(&self, message: ApiEphemeraMessage) -> Result<()> {
+    pub async fn send_ephemera_message(&self, message: ApiEphemeraMessage) -> Result<String> {
         trace!("send_ephemera_message({message})",);
         self.send_and_wait_response(|tx| {
             ToEphemeraApiCmd::SubmitEphemeraMessage(message.into(), tx)
@@ -268,6 +480,54 @@ impl CommandExecutor {
         .await
     }
 
+    /// Send a batch of messages to Ephemera. Each message is checked by the application separately,
+    /// accepted messages are put into mempool and broadcast to all peers together
+    ///
+    /// # Arguments
+    /// * `messages` - Messages to be sent, at most [`MAX_MESSAGES_PER_BATCH`]
+    ///
+    /// # Returns
+    /// * `Vec<ApiSubmitMessageResult>` - Result of each message, in the same order
+    ///
+    /// # Errors
+    /// * `ApiError::BatchTooLarge` - If the batch has too many messages
+    /// * `ApiError::InternalError` - If there is an internal error
+    pub async fn send_ephemera_messages(
+        &self,
+        messages: Vec<ApiEphemeraMessage>,
+    ) -> Result<Vec<ApiSubmitMessageResult>> {
+        trace!("send_ephemera_messages({})", messages.len());
+        if messages.len() > MAX_MESSAGES_PER_BATCH {
+            return Err(ApiError::BatchTooLarge(
+                messages.len(),
+                MAX_MESSAGES_PER_BATCH,
+            ));
+        }
+        self.send_and_wait_response(|tx| ToEphemeraApiCmd::SubmitEphemeraMessages(messages, tx))
+            .await
+    }
+
+    /// Returns what happened to a submitted message
+    ///
+    /// # Arguments
+    /// * `message_hash` - Hash of the message, returned by [`CommandExecutor::send_ephemera_message`]
+    ///
+    /// # Returns
+    /// * `ApiMessageStatus` - Pending, included in a block, rejected, expired or evicted
+    /// * `None` - If the message is unknown, or its status is not remembered anymore
+    ///
+    /// # Errors
+    /// * `ApiError::InvalidHash` - If the message hash is invalid
+    /// * `ApiError::InternalError` - If there is an internal error
+    pub async fn get_message_status(
+        &self,
+        message_hash: String,
+    ) -> Result<Option<ApiMessageStatus>> {
+        trace!("get_message_status({message_hash})");
+        self.send_and_wait_response(|tx| ToEphemeraApiCmd::QueryMessageStatus(message_hash, tx))
+            .await
+    }
+
     /// Verifies if given message is in block identified by block hash
     /// Returns true if message is in block, false otherwise. False can also mean that block or message
     /// does not exist.
@@ -288,6 +548,34 @@ impl CommandExecutor {
         .await
     }
 
+    /// Returns Merkle inclusion proof of a message in a block.
+    ///
+    /// Unlike [`CommandExecutor::verify_message_in_block`], the proof can be verified by the caller
+    /// with [`ApiMessageProof::verify`] without trusting this node.
+    ///
+    /// # Arguments
+    /// * `block_hash` - Block hash
+    /// * `message_hash` - Message hash
+    ///
+    /// # Returns
+    /// * `None` - If the block doesn't exist or doesn't include the message
+    ///
+    /// # Errors
+    /// * `ApiError::InvalidHash` - If the message hash is invalid
+    /// * `ApiError::BlockPruned` - If the block was pruned
+    /// * `ApiError::InternalError` - If there is an internal error
+    pub async fn get_message_proof(
+        &self,
+        block_hash: String,
+        message_hash: String,
+    ) -> Result<Option<ApiMessageProof>> {
+        trace!("get_message_proof({block_hash}, {message_hash})");
+        self.send_and_wait_response(|tx| {
+            ToEphemeraApiCmd::QueryMessageProof(block_hash, message_hash, tx)
+        })
+        .await
+    }
+
     async fn send_and_wait_response<F, R>(&self, f: F) -> Result<R>
     where
         F: FnOnce(oneshot::Sender<Result<R>>) -> ToEphemeraApiCmd,
@@ -3,6 +3,7 @@
 //! - `ApiEphemeraMessage`
 //! - `RawApiEphemeraMessage`
 //! - `ApiBlock`
+//! - `ApiBlockHeader`
 //! - `ApiCertificate`
 //! - `Health`
 //! - `ApiError`
@@ -13,8 +14,17 @@
 //! - `ApiBroadcastInfo`
 //! - `ApiBlockBroadcastInfo`
 //! - `ApiVerifyMessageInBlock`
-
-use std::collections::HashSet;
+//! - `ApiSyncStatus`
+//! - `ApiMessageProof`
+//! - `ApiMessageStatus`
+//! - `ApiSubmitMessageResult`
+//! - `ApiEquivocationEvidence`
+//! - `ApiMempoolStats`
+//! - `ApiApplicationQueryRequest`
+//! - `ApiApplicationQueryResponse`
+//! - `ApiApplicationHash`
+
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 
 use array_bytes::{bytes2hex, hex2bytes};
@@ -23,15 +33,27 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use utoipa::ToSchema;
 
+use crate::api::verifier::BlockVerifier;
 use crate::peer::{PeerId, ToPeerId};
 use crate::utilities::codec::{Codec, DecodingError, EncodingError, EphemeraCodec};
 use crate::{
-    block::types::{block::Block, block::BlockHeader, message::EphemeraMessage},
+    block::manager::MessageStatus,
+    block::types::{
+        block::Block,
+        block::BlockHeader,
+        message::EphemeraMessage,
+        quorum_certificate::{BlockCertificate, QuorumCertificate},
+    },
+    broadcast::evidence::{EquivocationKind, Evidence, RawEvidence, SignedHeader},
+    broadcast::signing::Phase,
     codec::{Decode, Encode},
     crypto::{Keypair, PublicKey},
     ephemera_api,
+    storage::PrunedBlock,
     utilities::{
         crypto::{Certificate, Signature},
+        hash::Hash,
+        merkle::MerkleProof,
         time::EphemeraTime,
     },
 };
@@ -42,8 +64,20 @@ pub enum ApiError {
     ApplicationRejectedMessage,
     #[error("Duplicate message")]
     DuplicateMessage,
+    /// Message pool has no room for the message. Clients should retry later.
+    #[error("Message pool is full: {0}")]
+    MempoolFull(String),
+    /// Message timestamp is older than the message pool TTL allows.
+    #[error("Message is expired")]
+    MessageExpired,
     #[error("Invalid hash: {0}")]
     InvalidHash(String),
+    /// Batch has more messages than a single request allows.
+    #[error("Batch has {0} messages, at most {1} are allowed")]
+    BatchTooLarge(usize, usize),
+    /// Block messages were removed by pruning. The header is included if the node keeps commitments.
+    #[error("Block is pruned: {}", .0.hash)]
+    BlockPruned(Box<ApiPrunedBlock>),
     #[error("ApplicationError: {0}")]
     Application(#[from] ephemera_api::ApplicationError),
     #[error("Internal error: {0}")]
@@ -149,7 +183,7 @@ impl RawApiEphemeraMessage {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
 pub struct ApiBlockHeader {
     /// The timestamp of the block. It's initialized when the block is created.
     /// It uses UTC time.
@@ -158,6 +192,15 @@ pub struct ApiBlockHeader {
     pub creator: PeerId,
     /// The height of the block.
     pub height: u64,
+    /// The view in which the block was proposed. A leader proposes at most one block per height and view.
+    pub view: u64,
+    /// The hash of the previous block. Blocks at height 1 refer to the zero hash
+    /// because genesis block is local to each node.
+    pub previous_hash: String,
+    /// The Merkle root of the block messages hashes.
+    pub merkle_root: String,
+    /// The application state hash after the previous block was delivered.
+    pub app_hash: String,
     /// The hash of the current block.
     pub hash: String,
 }
@@ -180,6 +223,20 @@ pub struct ApiCertificate {
     pub public_key: ApiPublicKey,
 }
 
+/// Certificate of a committed block.
+///
+/// It signs the broadcast phase which delivered the block together with the block hash and the broadcast group id,
+/// see [`BlockVerifier`]. Certificates of blocks committed before phase signatures don't have a phase and sign
+/// the block header.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct ApiBlockCertificate {
+    /// The broadcast phase the signature agrees to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phase: Option<ApiBroadcastPhase>,
+    pub signature: ApiSignature,
+    pub public_key: ApiPublicKey,
+}
+
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
 pub struct ApiSignature(pub(crate) Signature);
 
@@ -228,6 +285,43 @@ pub struct ApiDhtQueryResponse {
     value: String,
 }
 
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct ApiApplicationQueryRequest {
+    /// Application specific query parameters in hex format.
+    data: Option<String>,
+}
+
+/// Application state hash of this node after the block at given height.
+///
+/// The next block header includes the application hash of its creator. If they differ, the application state of
+/// this node diverged from the creator's.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct ApiApplicationHash {
+    pub height: u64,
+    /// The hash the local application returned from commit after the block.
+    pub app_hash: String,
+    /// The hash in the header of the block at `height + 1`, if the node has it.
+    pub next_block_app_hash: Option<String>,
+}
+
+impl ApiApplicationHash {
+    /// Returns true if the next block is known and its header has a different application hash.
+    #[must_use]
+    pub fn is_mismatch(&self) -> bool {
+        self.next_block_app_hash
+            .as_ref()
+            .is_some_and(|next| next != &self.app_hash)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct ApiApplicationQueryResponse {
+    /// The application path that was queried.
+    path: String,
+    /// The query result in hex format.
+    value: String,
+}
+
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
 pub enum HealthStatus {
     Healthy,
@@ -259,6 +353,378 @@ pub struct ApiBroadcastInfo {
 pub struct ApiBlockBroadcastInfo {
     pub local_peer_id: PeerId,
     pub broadcast_group: Vec<PeerId>,
+    /// Weights of the broadcast group members at the time of the broadcast.
+    /// Members without a weight have weight 1, as do all members of blocks stored before weights were recorded.
+    #[serde(default)]
+    pub weights: HashMap<PeerId, u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct ApiSyncStatus {
+    /// True if the node has all blocks its peers have. Block production is paused until then.
+    pub synced: bool,
+    /// The height of the last block stored by the node.
+    pub last_block_height: u64,
+    /// The last block height reported by the peer the node is syncing from, if known.
+    pub target_height: Option<u64>,
+    /// The height of the block synchronization failed at, because no broadcast group the node knows committed it.
+    /// Synchronization resumes when the group changes.
+    #[serde(default)]
+    pub failed_height: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct ApiMempoolStats {
+    /// The number of pending messages.
+    pub message_count: usize,
+    /// The total size of pending messages in bytes.
+    pub total_bytes: usize,
+    /// The maximum number of pending messages.
+    pub max_messages: usize,
+    /// The maximum total size of pending messages in bytes.
+    pub max_bytes: usize,
+    /// The number of messages evicted to make room for newer messages since the node started.
+    pub evicted_messages: u64,
+    /// The number of messages removed because their TTL passed since the node started.
+    pub expired_messages: u64,
+    /// The number of messages rejected by the pool since the node started.
+    pub rejected_messages: u64,
+}
+
+/// Broadcast phase a quorum certificate signature agrees to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiBroadcastPhase {
+    /// The member echoed the block.
+    Echo,
+    /// The member voted for the block.
+    Vote,
+}
+
+/// Compact proof that a quorum of the block broadcast group signed the block.
+///
+/// Instead of a certificate per signer, it has a bitmap of signers over the group ordered by peer id and their
+/// signatures in the same order. Verify it with [`ApiQuorumCertificate::verify`] against the block broadcast group.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct ApiQuorumCertificate {
+    /// Phase which delivered the block. Signatures are of the block hash, the phase and the broadcast group.
+    /// Certificates of blocks committed before phase signatures don't have it, their signatures are of the block header
+    /// and don't verify.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phase: Option<ApiBroadcastPhase>,
+    /// Hex encoded bitmap of signers. Bit `i`, counted from the least significant bit of the first byte,
+    /// is set if `i`-th member of the group signed the block.
+    pub signers: String,
+    /// Signatures of the signers in the group order.
+    pub signatures: Vec<ApiSignature>,
+}
+
+impl ApiQuorumCertificate {
+    /// Returns members of the block broadcast group who signed the block.
+    ///
+    /// # Errors
+    /// - If the bitmap doesn't match the group.
+    pub fn signers(&self, members: &[PeerId]) -> Result<Vec<PeerId>, ApiError> {
+        let certificate: QuorumCertificate = self.clone().try_into()?;
+        certificate.signers(members).map_err(|e| {
+            error!("Invalid quorum certificate: {}", e);
+            ApiError::Internal("Invalid quorum certificate".to_string())
+        })
+    }
+
+    /// Verifies that members of the block broadcast group with enough weight signed the phase which delivered the block.
+    ///
+    /// # Arguments
+    /// * `header` - Block header
+    /// * `members` - Block broadcast group, see [`ApiBlockBroadcastInfo`]
+    /// * `weights` - Weights of the members, see [`ApiBlockBroadcastInfo`]
+    ///
+    /// # Errors
+    /// - If the certificate can't be decoded.
+    pub fn verify(
+        &self,
+        header: &ApiBlockHeader,
+        members: &[PeerId],
+        weights: &HashMap<PeerId, u64>,
+    ) -> Result<bool, ApiError> {
+        let certificate: QuorumCertificate = self.clone().try_into()?;
+        let header: BlockHeader = header.clone().try_into()?;
+        match certificate.verify(&header, members, weights) {
+            Ok(()) => Ok(true),
+            Err(err) => {
+                error!("Block {} quorum certificate is invalid: {err}", header.hash);
+                Ok(false)
+            }
+        }
+    }
+}
+
+/// What is left of a block after its messages were pruned.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct ApiPrunedBlock {
+    /// The hash of the pruned block.
+    pub hash: String,
+    /// The height of the pruned block.
+    pub height: u64,
+    /// The header of the pruned block, present if the node keeps commitments.
+    pub header: Option<ApiBlockHeader>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct ApiPruningStats {
+    /// True if any retention limit is configured.
+    pub enabled: bool,
+    /// The number of finished pruning runs since the node started.
+    pub runs: u64,
+    /// The number of failed pruning runs since the node started.
+    pub failed_runs: u64,
+    /// The number of blocks pruned since the node started.
+    pub pruned_blocks: u64,
+    /// The height of the last pruned block.
+    pub pruned_height: Option<u64>,
+    /// The database size in bytes, measured at the end of the last run.
+    pub database_size_bytes: u64,
+    /// The duration of the last run in milliseconds.
+    pub last_run_duration_ms: u64,
+}
+
+/// What happened to a submitted message.
+///
+/// Rejected, expired and evicted messages are remembered only for a while, statuses of committed messages
+/// as long as their hashes are kept for replay protection.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ApiMessageStatus {
+    /// The message is in the mempool, waiting to be included in a block.
+    Pending,
+    /// The message is in a block which is broadcast but not committed yet.
+    Proposed {
+        /// The hash of the block.
+        block_hash: String,
+        /// The height of the block.
+        height: u64,
+    },
+    /// The message is included in a committed block.
+    Included {
+        /// The hash of the block.
+        block_hash: String,
+        /// The height of the block.
+        height: u64,
+    },
+    /// The application rejected the message.
+    Rejected {
+        /// Why the message was rejected.
+        reason: String,
+    },
+    /// The message was dropped from the mempool after its TTL passed.
+    Expired,
+    /// The message was dropped from the full mempool to make room for newer messages.
+    Evicted,
+}
+
+impl From<MessageStatus> for ApiMessageStatus {
+    fn from(status: MessageStatus) -> Self {
+        match status {
+            MessageStatus::Pending => ApiMessageStatus::Pending,
+            MessageStatus::Proposed { block_hash, height } => ApiMessageStatus::Proposed {
+                block_hash: block_hash.to_string(),
+                height,
+            },
+            MessageStatus::Rejected(reason) => ApiMessageStatus::Rejected { reason },
+            MessageStatus::Expired => ApiMessageStatus::Expired,
+            MessageStatus::Evicted => ApiMessageStatus::Evicted,
+        }
+    }
+}
+
+/// Result of submitting one message of a batch.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+pub struct ApiSubmitMessageResult {
+    /// The hash of the message, see [`ApiMessageStatus`].
+    pub message_hash: String,
+    /// True if the message was put into the mempool. Submitting it again is rejected as a duplicate.
+    pub accepted: bool,
+    /// Why the message was not accepted. For an accepted message, why it wasn't gossiped to peers.
+    /// It's still included in blocks this node proposes.
+    pub error: Option<String>,
+}
+
+impl ApiSubmitMessageResult {
+    pub(crate) fn accepted(message_hash: String) -> Self {
+        Self {
+            message_hash,
+            accepted: true,
+            error: None,
+        }
+    }
+
+    pub(crate) fn rejected(message_hash: String, error: &ApiError) -> Self {
+        Self {
+            message_hash,
+            accepted: false,
+            error: Some(error.to_string()),
+        }
+    }
+
+    /// Accepted message is in the mempool, but it couldn't be gossiped to peers.
+    pub(crate) fn on_gossip_failed(&mut self) {
+        if self.accepted {
+            self.error =
+                Some("Message is in the mempool, but gossiping it to peers failed".to_string());
+        }
+    }
+}
+
+/// Kind of equivocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiEquivocationKind {
+    /// The block creator signed two blocks at the same height.
+    ConflictingProposal,
+    /// A peer signed two blocks of the same creator at the same height.
+    ConflictingVote,
+}
+
+/// Block header and its signature by the offender, as received in a broadcast message.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct ApiSignedHeader {
+    pub header: ApiBlockHeader,
+    pub certificate: ApiCertificate,
+}
+
+/// Proof that a peer signed two different blocks of the same creator at the same height.
+///
+/// A leader re-proposes a block at the same height if the previous one isn't committed in time, which looks the
+/// same as equivocation. It's up to the application how to treat the evidence.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct ApiEquivocationEvidence {
+    /// The peer which signed both headers.
+    pub offender: PeerId,
+    pub kind: ApiEquivocationKind,
+    /// The creator of both blocks.
+    pub creator: PeerId,
+    /// The height of both blocks.
+    pub height: u64,
+    /// The view of both blocks.
+    pub view: u64,
+    /// The header the offender signed first.
+    pub first: ApiSignedHeader,
+    /// The conflicting header.
+    pub second: ApiSignedHeader,
+    /// When the node detected the equivocation, UTC time in milliseconds.
+    pub detected_at: u64,
+    /// Signature of the evidence by the node which detected it.
+    pub reporter: ApiCertificate,
+}
+
+impl ApiEquivocationEvidence {
+    /// Peer which detected the equivocation.
+    #[must_use]
+    pub fn reporter(&self) -> PeerId {
+        self.reporter.public_key.0.peer_id()
+    }
+
+    /// Verifies that both headers are signed by the offender, conflict with each other,
+    /// and that the evidence is signed by the reporter.
+    ///
+    /// # Errors
+    /// - If the evidence can't be decoded.
+    pub fn verify(&self) -> Result<bool, ApiError> {
+        let evidence: Evidence = self.clone().try_into()?;
+        match evidence.verify() {
+            Ok(()) => Ok(true),
+            Err(err) => {
+                error!("Evidence against {} is invalid: {err}", self.offender);
+                Ok(false)
+            }
+        }
+    }
+}
+
+impl From<SignedHeader> for ApiSignedHeader {
+    fn from(signed: SignedHeader) -> Self {
+        Self {
+            header: signed.header.into(),
+            certificate: signed.certificate.into(),
+        }
+    }
+}
+
+impl TryFrom<ApiSignedHeader> for SignedHeader {
+    type Error = ApiError;
+
+    fn try_from(signed: ApiSignedHeader) -> Result<Self, ApiError> {
+        Ok(Self {
+            header: signed.header.try_into()?,
+            certificate: signed.certificate.into(),
+        })
+    }
+}
+
+impl From<Evidence> for ApiEquivocationEvidence {
+    fn from(evidence: Evidence) -> Self {
+        let Evidence {
+            evidence,
+            certificate,
+        } = evidence;
+        Self {
+            offender: evidence.offender,
+            kind: match evidence.kind {
+                EquivocationKind::ConflictingProposal => ApiEquivocationKind::ConflictingProposal,
+                EquivocationKind::ConflictingVote => ApiEquivocationKind::ConflictingVote,
+            },
+            creator: evidence.creator,
+            height: evidence.height,
+            view: evidence.view,
+            first: evidence.first.into(),
+            second: evidence.second.into(),
+            detected_at: evidence.detected_at,
+            reporter: certificate.into(),
+        }
+    }
+}
+
+impl TryFrom<ApiEquivocationEvidence> for Evidence {
+    type Error = ApiError;
+
+    fn try_from(evidence: ApiEquivocationEvidence) -> Result<Self, ApiError> {
+        Ok(Self {
+            evidence: RawEvidence {
+                offender: evidence.offender,
+                kind: match evidence.kind {
+                    ApiEquivocationKind::ConflictingProposal => {
+                        EquivocationKind::ConflictingProposal
+                    }
+                    ApiEquivocationKind::ConflictingVote => EquivocationKind::ConflictingVote,
+                },
+                creator: evidence.creator,
+                height: evidence.height,
+                view: evidence.view,
+                first: evidence.first.try_into()?,
+                second: evidence.second.try_into()?,
+                detected_at: evidence.detected_at,
+            },
+            certificate: evidence.reporter.into(),
+        })
+    }
+}
+
+/// Merkle inclusion proof of a message in a block.
+///
+/// It can be verified with [`ApiMessageProof::verify`] without downloading the block
+/// and without trusting the node which served it.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct ApiMessageProof {
+    /// The header of the block which includes the message. Its `merkle_root` is the proof root.
+    pub header: ApiBlockHeader,
+    /// The hash of the proven message.
+    pub message_hash: String,
+    /// The index of the message in the block.
+    pub leaf_index: usize,
+    /// The number of messages in the block.
+    pub leaf_count: usize,
+    /// Sibling hashes on the path from the message up to the Merkle root.
+    pub path: Vec<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
@@ -290,10 +756,15 @@ impl Display for ApiVerifyMessageInBlock {
 }
 
 impl ApiBlockBroadcastInfo {
-    pub(crate) fn new(local_peer_id: PeerId, broadcast_group: Vec<PeerId>) -> Self {
+    pub(crate) fn new(
+        local_peer_id: PeerId,
+        broadcast_group: Vec<PeerId>,
+        weights: HashMap<PeerId, u64>,
+    ) -> Self {
         Self {
             local_peer_id,
             broadcast_group,
+            weights,
         }
     }
 }
@@ -373,12 +844,130 @@ impl Display for ApiBlockHeader {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "ApiBlockHeader(timestamp: {}, creator: {}, height: {}, hash: {})",
-            self.timestamp, self.creator, self.height, self.hash,
+            "ApiBlockHeader(timestamp: {}, creator: {}, height: {}, view: {}, previous_hash: {}, merkle_root: {}, app_hash: {}, hash: {})",
+            self.timestamp, self.creator, self.height, self.view, self.previous_hash, self.merkle_root, self.app_hash, self.hash,
         )
     }
 }
 
+impl ApiBlockHeader {
+    /// Verifies that the header hash matches its content.
+    ///
+    /// # Errors
+    /// - If the header is invalid.
+    /// - If hashing fails.
+    pub fn verify_hash(&self) -> Result<bool, ApiError> {
+        let header: BlockHeader = self.clone().try_into()?;
+        header.verify_hash().map_err(|e| {
+            error!("Failed to hash block header: {}", e);
+            ApiError::Internal("Failed to hash block header".to_string())
+        })
+    }
+
+    /// Verifies that the certificate signs this header.
+    ///
+    /// # Errors
+    /// - If the header is invalid.
+    /// - If the certificate verification fails.
+    pub fn verify(&self, certificate: &ApiCertificate) -> Result<bool, ApiError> {
+        let header: BlockHeader = self.clone().try_into()?;
+        header.verify(&(certificate.clone()).into()).map_err(|e| {
+            error!("Failed to verify block header: {}", e);
+            ApiError::Internal("Failed to verify block header certificate".to_string())
+        })
+    }
+}
+
+impl ApiMessageProof {
+    pub(crate) fn new(header: BlockHeader, message_hash: Hash, proof: &MerkleProof) -> Self {
+        Self {
+            header: header.into(),
+            message_hash: message_hash.to_string(),
+            leaf_index: proof.leaf_index,
+            leaf_count: proof.leaf_count,
+            path: proof.path.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    /// Verifies that the message is included in the block.
+    ///
+    /// Checks that:
+    /// - the header hash matches the header
+    /// - members of the block broadcast group with at least `n - f` weight signed the header, see [`BlockVerifier`]
+    /// - the message hash and the path lead to the header Merkle root
+    ///
+    /// The verifier needs the broadcast group and weights obtained independently of the node which served
+    /// the proof, for example from the membership provider. Otherwise the node could sign the proof itself.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use ephemera::ephemera_api::{BlockVerifier, Client};
+    /// use ephemera::peer::PeerId;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///    let client = Client::new("http://localhost:7000/".to_string());
+    ///    let members: Vec<PeerId> = vec![]; //From the membership provider
+    ///    let verifier = BlockVerifier::with_group(members);
+    ///    let proof = client.get_message_proof("block_hash", "message_hash").await?;
+    ///    let certificates = client.get_block_certificates("block_hash").await?;
+    ///    if let (Some(proof), Some(certificates)) = (proof, certificates) {
+    ///        let included = proof.verify(&certificates, &verifier)?;
+    ///    }
+    ///    Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    /// - If the proof contains invalid hashes.
+    /// - If the certificate verification fails.
+    pub fn verify(
+        &self,
+        certificates: &[ApiBlockCertificate],
+        verifier: &BlockVerifier,
+    ) -> Result<bool, ApiError> {
+        let verdict = verifier.verify_header(&self.header, certificates)?;
+        if !verdict.hash_valid {
+            error!("Block {} header hash is invalid", self.header.hash);
+            return Ok(false);
+        }
+        if !verdict.quorum_reached() {
+            error!(
+                "Block {} has not enough signatures of its broadcast group: {} / {}",
+                self.header.hash, verdict.signed_weight, verdict.threshold
+            );
+            return Ok(false);
+        }
+
+        let proof: MerkleProof = self.clone().try_into()?;
+        let message_hash = parse_hash(&self.message_hash)?;
+        let merkle_root = parse_hash(&self.header.merkle_root)?;
+        Ok(proof.verify(message_hash, merkle_root))
+    }
+}
+
+impl TryFrom<ApiMessageProof> for MerkleProof {
+    type Error = ApiError;
+
+    fn try_from(proof: ApiMessageProof) -> Result<Self, ApiError> {
+        let path = proof
+            .path
+            .iter()
+            .map(|hash| parse_hash(hash))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            leaf_index: proof.leaf_index,
+            leaf_count: proof.leaf_count,
+            path,
+        })
+    }
+}
+
+fn parse_hash(hash: &str) -> Result<Hash, ApiError> {
+    hash.parse()
+        .map_err(|_| ApiError::InvalidHash(format!("Failed to parse hash {hash}")))
+}
+
 impl ApiBlock {
     #[must_use]
     pub fn as_raw_block(&self) -> ApiRawBlock {
@@ -410,6 +999,80 @@ impl ApiBlock {
         })?;
         Ok(valid)
     }
+
+    /// Verifies that the block hash matches its header and that the header Merkle root
+    /// matches block messages.
+    ///
+    /// # Errors
+    /// - If the block is invalid.
+    /// - If hashing fails.
+    pub fn verify_hash(&self) -> Result<bool, ApiError> {
+        let block: Block = self.clone().try_into()?;
+        let hash = block.hash_with_default_hasher().map_err(|e| {
+            error!("Failed to hash block: {}", e);
+            ApiError::Internal("Failed to hash block".to_string())
+        })?;
+        let merkle_root_valid = block.verify_merkle_root().map_err(|e| {
+            error!("Failed to build block Merkle tree: {}", e);
+            ApiError::Internal("Failed to build block Merkle tree".to_string())
+        })?;
+        Ok(hash == block.get_hash() && merkle_root_valid)
+    }
+
+    /// Verifies that blocks form a hash-linked chain.
+    ///
+    /// Blocks need to be ordered by height without gaps. Every block hash has to match its content
+    /// and every block has to refer to the previous block hash as its parent.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use ephemera::ephemera_api::{ApiBlock, Client};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///    let client = Client::new("http://localhost:7000/".to_string());
+    ///    let blocks = client.get_blocks_by_height_range(1, 10).await?;
+    ///    let valid = ApiBlock::verify_chain(&blocks)?;
+    ///    Ok(())
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    /// - If a block is invalid.
+    /// - If hashing fails.
+    pub fn verify_chain(blocks: &[ApiBlock]) -> Result<bool, ApiError> {
+        for block in blocks {
+            if !block.verify_hash()? {
+                error!("Block {} hash is invalid", block.hash());
+                return Ok(false);
+            }
+        }
+
+        for pair in blocks.windows(2) {
+            let (parent, child) = (&pair[0], &pair[1]);
+            if child.header.height != parent.header.height + 1 {
+                error!(
+                    "Block {} height {} doesn't follow block {} height {}",
+                    child.hash(),
+                    child.header.height,
+                    parent.hash(),
+                    parent.header.height
+                );
+                return Ok(false);
+            }
+            let parent: Block = parent.clone().try_into()?;
+            if child.header.previous_hash != parent.hash_as_parent().to_string() {
+                error!(
+                    "Block {} parent {} doesn't match block {}",
+                    child.hash(),
+                    child.header.previous_hash,
+                    parent.get_hash()
+                );
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
 }
 
 impl Display for ApiBlock {
@@ -477,6 +1140,28 @@ impl From<ApiCertificate> for Certificate {
     }
 }
 
+impl From<BlockCertificate> for ApiBlockCertificate {
+    fn from(certificate: BlockCertificate) -> Self {
+        Self {
+            phase: certificate.phase.map(Into::into),
+            signature: certificate.certificate.signature.into(),
+            public_key: certificate.certificate.public_key.into(),
+        }
+    }
+}
+
+impl From<ApiBlockCertificate> for BlockCertificate {
+    fn from(value: ApiBlockCertificate) -> Self {
+        BlockCertificate {
+            phase: value.phase.map(Into::into),
+            certificate: Certificate {
+                signature: value.signature.into(),
+                public_key: value.public_key.into(),
+            },
+        }
+    }
+}
+
 impl From<&Block> for &ApiBlock {
     fn from(block: &Block) -> Self {
         let api_block: ApiBlock = block.clone().into();
@@ -517,17 +1202,89 @@ impl From<ApiPublicKey> for PublicKey {
 impl From<Block> for ApiBlock {
     fn from(block: Block) -> Self {
         Self {
-            header: ApiBlockHeader {
-                timestamp: block.header.timestamp,
-                creator: block.header.creator,
-                height: block.header.height,
-                hash: block.header.hash.to_string(),
-            },
+            header: block.header.into(),
             messages: block.messages.into_iter().map(Into::into).collect(),
         }
     }
 }
 
+impl From<QuorumCertificate> for ApiQuorumCertificate {
+    fn from(certificate: QuorumCertificate) -> Self {
+        Self {
+            phase: certificate.phase.map(Into::into),
+            signers: bytes2hex("0x", &certificate.signers),
+            signatures: certificate
+                .signatures
+                .into_iter()
+                .map(ApiSignature)
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<ApiQuorumCertificate> for QuorumCertificate {
+    type Error = ApiError;
+
+    fn try_from(certificate: ApiQuorumCertificate) -> Result<Self, ApiError> {
+        let signers = hex2bytes(&certificate.signers).map_err(|e| {
+            error!("Failed to parse quorum certificate signers: {:?}", e);
+            ApiError::Internal("Failed to parse quorum certificate signers".to_string())
+        })?;
+        Ok(Self {
+            phase: certificate.phase.map(Into::into),
+            signers,
+            signatures: certificate
+                .signatures
+                .into_iter()
+                .map(|signature| signature.0)
+                .collect(),
+        })
+    }
+}
+
+impl From<Phase> for ApiBroadcastPhase {
+    fn from(phase: Phase) -> Self {
+        match phase {
+            Phase::Echo => ApiBroadcastPhase::Echo,
+            Phase::Vote => ApiBroadcastPhase::Vote,
+        }
+    }
+}
+
+impl From<ApiBroadcastPhase> for Phase {
+    fn from(phase: ApiBroadcastPhase) -> Self {
+        match phase {
+            ApiBroadcastPhase::Echo => Phase::Echo,
+            ApiBroadcastPhase::Vote => Phase::Vote,
+        }
+    }
+}
+
+impl From<PrunedBlock> for ApiPrunedBlock {
+    fn from(block: PrunedBlock) -> Self {
+        Self {
+            hash: block.hash,
+            height: block.height,
+            header: block.header.map(Into::into),
+        }
+    }
+}
+
+impl From<BlockHeader> for ApiBlockHeader {
+    fn from(header: BlockHeader) -> Self {
+        Self {
+            timestamp: header.timestamp,
+            creator: header.creator,
+            height: header.height,
+            view: header.view,
+            previous_hash: header.previous_hash.to_string(),
+            merkle_root: header.merkle_root.to_string(),
+            app_hash: header.app_hash.to_string(),
+            hash: header.hash.to_string(),
+        }
+    }
+}
+
 impl TryFrom<ApiBlock> for Block {
     type Error = ApiError;
 
@@ -538,20 +1295,41 @@ impl TryFrom<ApiBlock> for Block {
             .map(Into::into)
             .collect::<Vec<EphemeraMessage>>();
         Ok(Self {
-            header: BlockHeader {
-                timestamp: api_block.header.timestamp,
-                creator: api_block.header.creator,
-                height: api_block.header.height,
-                hash: api_block.header.hash.parse().map_err(|e| {
-                    error!("Failed to parse block hash: {}", e);
-                    ApiError::Internal("Failed to parse block hash".to_string())
-                })?,
-            },
+            header: api_block.header.try_into()?,
             messages,
         })
     }
 }
 
+impl TryFrom<ApiBlockHeader> for BlockHeader {
+    type Error = ApiError;
+
+    fn try_from(header: ApiBlockHeader) -> Result<Self, ApiError> {
+        Ok(Self {
+            timestamp: header.timestamp,
+            creator: header.creator,
+            height: header.height,
+            view: header.view,
+            previous_hash: header.previous_hash.parse().map_err(|e| {
+                error!("Failed to parse previous block hash: {}", e);
+                ApiError::Internal("Failed to parse previous block hash".to_string())
+            })?,
+            merkle_root: header.merkle_root.parse().map_err(|e| {
+                error!("Failed to parse block Merkle root: {}", e);
+                ApiError::Internal("Failed to parse block Merkle root".to_string())
+            })?,
+            app_hash: header.app_hash.parse().map_err(|e| {
+                error!("Failed to parse block application hash: {}", e);
+                ApiError::Internal("Failed to parse block application hash".to_string())
+            })?,
+            hash: header.hash.parse().map_err(|e| {
+                error!("Failed to parse block hash: {}", e);
+                ApiError::Internal("Failed to parse block hash".to_string())
+            })?,
+        })
+    }
+}
+
 impl ApiDhtStoreRequest {
     #[must_use]
     pub fn new(key: &[u8], value: &[u8]) -> Self {
@@ -621,13 +1399,80 @@ impl ApiDhtQueryResponse {
     }
 }
 
+impl ApiApplicationQueryRequest {
+    #[must_use]
+    pub fn new(data: &[u8]) -> Self {
+        let data = (!data.is_empty()).then(|| bytes2hex("0x", data));
+        Self { data }
+    }
+
+    /// Query parameters encoded for the request URL.
+    #[must_use]
+    pub fn data_encoded(&self) -> Option<String> {
+        self.data.clone()
+    }
+
+    /// Decoded query parameters, empty if none were given.
+    ///
+    /// # Errors
+    /// * `ApiError::Internal` - If the data is not valid hex
+    pub fn data(&self) -> Result<Vec<u8>, ApiError> {
+        match &self.data {
+            Some(data) => hex2bytes(data)
+                .map_err(|_| ApiError::Internal("Failed to parse query data".to_string())),
+            None => Ok(vec![]),
+        }
+    }
+}
+
+impl ApiApplicationQueryResponse {
+    pub(crate) fn new(path: String, value: &[u8]) -> Self {
+        let value = bytes2hex("0x", value);
+        Self { path, value }
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    #[allow(clippy::missing_panics_doc)]
+    #[must_use]
+    pub fn value(&self) -> Vec<u8> {
+        //We can unwrap here because the value is always valid.
+        hex2bytes(&self.value).unwrap()
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use crate::block::types::block::{merkle_tree, RawBlock};
+    use crate::broadcast::bracha::quorum::Quorum;
+    use crate::broadcast::signing::RawPhaseVote;
     use crate::crypto::EphemeraKeypair;
     use crate::crypto::Keypair;
+    use crate::utilities::test_utils::{block_with, raw_header};
 
     use super::*;
 
+    #[test]
+    fn test_submit_result_gossip_failed() {
+        let mut accepted = ApiSubmitMessageResult::accepted("accepted".to_string());
+        let mut rejected = ApiSubmitMessageResult::rejected(
+            "rejected".to_string(),
+            &ApiError::ApplicationRejectedMessage,
+        );
+        accepted.on_gossip_failed();
+        rejected.on_gossip_failed();
+
+        assert!(accepted.accepted);
+        assert!(accepted.error.is_some());
+        assert_eq!(
+            rejected.error,
+            Some(ApiError::ApplicationRejectedMessage.to_string())
+        );
+    }
+
     #[test]
     fn test_message_sign_ok() {
         let message_signing_keypair = Keypair::generate(None);
@@ -656,4 +1501,110 @@ mod test {
         let modified_message = RawApiEphemeraMessage::new("test2".to_string(), vec![1, 2, 3]);
         assert!(!certificate.verify(&modified_message).unwrap());
     }
+
+    #[test]
+    fn test_application_query_data() {
+        let request = ApiApplicationQueryRequest::new(&[1, 2, 3]);
+        assert_eq!(request.data_encoded(), Some("0x010203".to_string()));
+        assert_eq!(request.data().unwrap(), vec![1, 2, 3]);
+
+        let empty = ApiApplicationQueryRequest::new(&[]);
+        assert_eq!(empty.data_encoded(), None);
+        assert!(empty.data().unwrap().is_empty());
+
+        let invalid = ApiApplicationQueryRequest {
+            data: Some("0xzz".to_string()),
+        };
+        assert!(invalid.data().is_err());
+    }
+
+    #[test]
+    fn test_verify_chain() {
+        let genesis = Block::new_genesis_block(PeerId::random());
+        let first = child_block(&genesis);
+        let second = child_block(&first);
+
+        let chain: Vec<ApiBlock> = vec![genesis.into(), first.into(), second.clone().into()];
+        assert!(ApiBlock::verify_chain(&chain).unwrap());
+
+        let unlinked = child_block(&second);
+        let mut broken = chain.clone();
+        broken[2] = unlinked.into();
+        assert!(!ApiBlock::verify_chain(&broken).unwrap());
+
+        let mut tampered = chain;
+        tampered[1].header.timestamp += 1;
+        assert!(!ApiBlock::verify_chain(&tampered).unwrap());
+    }
+
+    #[test]
+    fn test_verify_message_proof() {
+        let keypair = Keypair::generate(None);
+        let messages = (0..5)
+            .map(|i| {
+                RawApiEphemeraMessage::new(format!("test {i}"), vec![i])
+                    .sign(&keypair)
+                    .unwrap()
+                    .into()
+            })
+            .collect::<Vec<EphemeraMessage>>();
+        let block = block_with(&keypair, 1, messages);
+        let certificates = vec![vote(&block, &keypair)];
+
+        let tree = block.merkle_tree().unwrap();
+        let message_hash = block.messages[3].hash_with_default_hasher().unwrap();
+        let index = tree.leaf_index(&message_hash).unwrap();
+        let proof = ApiMessageProof::new(
+            block.header.clone(),
+            message_hash,
+            &tree.proof(index).unwrap(),
+        );
+        let verifier = BlockVerifier::with_group(vec![keypair.peer_id()]);
+        assert!(proof.verify(&certificates, &verifier).unwrap());
+        assert!(!proof.verify(&[], &verifier).unwrap());
+
+        //Certificates of a group the caller doesn't know
+        let outsider = Keypair::generate(None);
+        let outsider_certificates = vec![vote(&block, &outsider)];
+        assert!(!proof.verify(&outsider_certificates, &verifier).unwrap());
+        let outsider_verifier = BlockVerifier::with_group(vec![outsider.peer_id()]);
+        assert!(!proof.verify(&certificates, &outsider_verifier).unwrap());
+
+        let mut wrong_message = proof.clone();
+        wrong_message.message_hash = block.messages[2]
+            .hash_with_default_hasher()
+            .unwrap()
+            .to_string();
+        assert!(!wrong_message.verify(&certificates, &verifier).unwrap());
+
+        let other = child_block(&block);
+        let other_certificates = vec![vote(&other, &keypair)];
+        assert!(!proof.verify(&other_certificates, &verifier).unwrap());
+
+        let mut tampered_header = proof;
+        tampered_header.header.height += 1;
+        assert!(!tampered_header.verify(&certificates, &verifier).unwrap());
+    }
+
+    //Vote of a single member group
+    fn vote(block: &Block, keypair: &Keypair) -> ApiBlockCertificate {
+        let group = HashSet::from([keypair.peer_id()]);
+        let group_id = Quorum::weighted(&group, &HashMap::new()).group_id;
+        BlockCertificate {
+            phase: Some(Phase::Vote),
+            certificate: RawPhaseVote::new(block.get_hash(), Phase::Vote, group_id)
+                .sign(keypair)
+                .unwrap(),
+        }
+        .into()
+    }
+
+    fn child_block(parent: &Block) -> Block {
+        let mut header = raw_header(PeerId::random(), parent.get_height() + 1);
+        header.previous_hash = parent.hash_as_parent();
+        header.merkle_root = merkle_tree(&[]).unwrap().root_hash();
+        let raw_block = RawBlock::new(header, vec![]);
+        let hash = raw_block.hash_with_default_hasher().unwrap();
+        Block::new(raw_block, hash)
+    }
 }
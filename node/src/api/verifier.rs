@@ -0,0 +1,387 @@
+//! # Block verifier
+//!
+//! Verifies a block offline, without trusting the node which served it. The block and its certificates come
+//! from the public API. The broadcast group has to come from somewhere else, for example the membership provider,
+//! a node could otherwise serve a group of its own which signed anything.
+//!
+//! A block is valid if:
+//! - its hash matches its content and the header Merkle root matches its messages
+//! - members of its broadcast group with at least `n - f` weight signed the same broadcast phase of it, where `n`
+//!   is the total weight of the group and `f` the maximum weight of faulty members. Without weights every member
+//!   has weight 1.
+//!
+//! Phase signatures sign the block hash, the phase and the group id, which covers the members and their weights.
+//! Certificates without a phase sign only the block header. Members sign the header of every proposal they see,
+//! so such certificates don't count towards the threshold.
+//!
+//! Certificates of peers outside the group and invalid signatures don't count towards the threshold either. They are
+//! reported in [`BlockVerdict`] but don't make the block invalid on their own.
+
+use std::collections::{HashMap, HashSet};
+
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::api::types::{
+    ApiBlock, ApiBlockBroadcastInfo, ApiBlockCertificate, ApiBlockHeader, ApiBroadcastPhase,
+    ApiError,
+};
+use crate::block::types::block::BlockHeader;
+use crate::block::types::quorum_certificate::BlockCertificate;
+use crate::broadcast::bracha::quorum::Quorum;
+use crate::broadcast::signing::{Phase, RawPhaseVote};
+use crate::peer::{PeerId, ToPeerId};
+
+/// Detailed result of block verification.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BlockVerdict {
+    /// The hash of the verified block.
+    pub block_hash: String,
+    /// True if the block hash matches its content and the Merkle root matches its messages.
+    pub hash_valid: bool,
+    /// The broadcast phase the signers agree to, the phase signed with the most weight.
+    pub phase: Option<ApiBroadcastPhase>,
+    /// Group members with a valid signature of the phase.
+    pub signers: Vec<PeerId>,
+    /// Group members who signed only the block header. It shows they saw the block, not that it was delivered.
+    pub header_signers: Vec<PeerId>,
+    /// Peers whose certificate doesn't sign the block.
+    pub invalid_signatures: Vec<PeerId>,
+    /// Peers with a valid signature who are not members of the broadcast group.
+    pub non_members: Vec<PeerId>,
+    /// The size of the broadcast group.
+    pub group_size: usize,
+    /// The total weight of the broadcast group, `n`.
+    pub total_weight: u64,
+    /// The weight of the signers.
+    pub signed_weight: u64,
+    /// The weight of member signatures needed, `n - f`.
+    pub threshold: u64,
+}
+
+impl BlockVerdict {
+    /// True if members with enough weight signed the block.
+    #[must_use]
+    pub fn quorum_reached(&self) -> bool {
+        self.total_weight > 0 && self.signed_weight >= self.threshold
+    }
+
+    /// True if the block content is intact and it was signed by a quorum of its group.
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        self.hash_valid && self.quorum_reached()
+    }
+}
+
+/// Verifies blocks against their broadcast group.
+///
+/// # Example
+/// ```no_run
+/// use ephemera::ephemera_api::{BlockVerifier, Client};
+/// use ephemera::peer::PeerId;
+///
+/// #[tokio::main]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///    let client = Client::new("http://localhost:7000/".to_string());
+///    let hash = "9D2LaY17rbnxfgKUbvcsJ5cB2BRHEd8fPJwsBnDHNGBX";
+///    let block = client.get_block_by_hash(hash).await?.unwrap();
+///    let certificates = client.get_block_certificates(hash).await?.unwrap();
+///    //From the membership provider, not from the node
+///    let members: Vec<PeerId> = vec![];
+///
+///    let verdict = BlockVerifier::with_group(members).verify(&block, &certificates)?;
+///    assert!(verdict.is_valid());
+///    Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct BlockVerifier {
+    group: HashSet<PeerId>,
+    weights: HashMap<PeerId, u64>,
+}
+
+impl BlockVerifier {
+    /// Creates verifier for blocks broadcast by the given group, with its member weights.
+    ///
+    /// Use it only with broadcast info of a node you trust, the info of the node being verified proves nothing.
+    #[must_use]
+    pub fn new(broadcast_info: &ApiBlockBroadcastInfo) -> Self {
+        Self::with_group(broadcast_info.broadcast_group.iter().copied())
+            .with_weights(broadcast_info.weights.clone())
+    }
+
+    /// Creates verifier for blocks broadcast by the given members, with equal weights.
+    pub fn with_group(members: impl IntoIterator<Item = PeerId>) -> Self {
+        Self {
+            group: members.into_iter().collect(),
+            weights: HashMap::new(),
+        }
+    }
+
+    /// Sets weights of the members. Members without a weight have weight 1.
+    #[must_use]
+    pub fn with_weights(mut self, weights: HashMap<PeerId, u64>) -> Self {
+        self.weights = weights;
+        self
+    }
+
+    /// Verifies the block and its certificates.
+    ///
+    /// # Arguments
+    /// * `block` - The block to verify
+    /// * `certificates` - Certificates of the block, see [`crate::ephemera_api::Client::get_block_certificates`]
+    ///
+    /// # Returns
+    /// * [`BlockVerdict`] - What was found valid and what not
+    ///
+    /// # Errors
+    /// * If the block can't be decoded or hashed.
+    pub fn verify(
+        &self,
+        block: &ApiBlock,
+        certificates: &[ApiBlockCertificate],
+    ) -> Result<BlockVerdict, ApiError> {
+        let mut verdict = self.verify_header(&block.header, certificates)?;
+        verdict.hash_valid &= block.verify_hash()?;
+        Ok(verdict)
+    }
+
+    /// Verifies the block header and its certificates, without the block messages.
+    ///
+    /// The header hash covers the messages Merkle root, so this is enough to verify Merkle proofs of messages.
+    ///
+    /// # Arguments
+    /// * `header` - The header of the block to verify
+    /// * `certificates` - Certificates of the block, see [`crate::ephemera_api::Client::get_block_certificates`]
+    ///
+    /// # Returns
+    /// * [`BlockVerdict`] - What was found valid and what not
+    ///
+    /// # Errors
+    /// * If the header can't be decoded or hashed.
+    pub fn verify_header(
+        &self,
+        header: &ApiBlockHeader,
+        certificates: &[ApiBlockCertificate],
+    ) -> Result<BlockVerdict, ApiError> {
+        let hash_valid = header.verify_hash()?;
+        let header: BlockHeader = header.clone().try_into()?;
+        let quorum = Quorum::weighted(&self.group, &self.weights);
+
+        let mut phase_signers: HashMap<Phase, Vec<PeerId>> = HashMap::new();
+        let mut header_signers = vec![];
+        let mut invalid_signatures = vec![];
+        let mut non_members = vec![];
+        let mut seen = HashSet::new();
+        for certificate in certificates {
+            let BlockCertificate { phase, certificate } = certificate.clone().into();
+            let peer_id = certificate.public_key.peer_id();
+            if !seen.insert((peer_id, phase)) {
+                continue;
+            }
+            let valid = match phase {
+                Some(phase) => {
+                    certificate.verify(&RawPhaseVote::new(header.hash, phase, quorum.group_id))
+                }
+                None => header.verify(&certificate),
+            }
+            .unwrap_or_else(|err| {
+                error!("Failed to verify certificate of {peer_id}: {err}");
+                false
+            });
+            if !valid {
+                invalid_signatures.push(peer_id);
+            } else if !self.group.contains(&peer_id) {
+                non_members.push(peer_id);
+            } else if let Some(phase) = phase {
+                phase_signers.entry(phase).or_default().push(peer_id);
+            } else {
+                header_signers.push(peer_id);
+            }
+        }
+
+        //Signatures of different phases don't add up
+        let (phase, signers) = phase_signers
+            .into_iter()
+            .max_by_key(|(_, signers)| quorum.weight_of(signers))
+            .map(|(phase, signers)| (Some(phase.into()), signers))
+            .unwrap_or_default();
+        Ok(BlockVerdict {
+            block_hash: header.hash.to_string(),
+            hash_valid,
+            phase,
+            signed_weight: quorum.weight_of(&signers),
+            signers,
+            header_signers,
+            invalid_signatures,
+            non_members,
+            group_size: self.group.len(),
+            total_weight: quorum.total_weight,
+            threshold: quorum.deliver_threshold(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::block::types::block::Block;
+    use crate::crypto::Keypair;
+    use crate::utilities::test_utils::{block, keypairs};
+
+    use super::*;
+
+    #[test]
+    fn test_verify_block() {
+        let keypairs = keypairs(4);
+        let block = block(&keypairs[0], 1);
+        let verifier = BlockVerifier::with_group(keypairs.iter().map(|kp| kp.peer_id()));
+
+        let certificates = vote(&block, &keypairs[..3], &verifier);
+        let verdict = verifier
+            .verify(&block.clone().into(), &certificates)
+            .unwrap();
+        assert!(verdict.is_valid());
+        assert_eq!(verdict.phase, Some(ApiBroadcastPhase::Vote));
+        assert_eq!(verdict.signers.len(), 3);
+        assert_eq!(verdict.threshold, 3);
+
+        let certificates = vote(&block, &keypairs[..2], &verifier);
+        let verdict = verifier.verify(&block.into(), &certificates).unwrap();
+        assert!(verdict.hash_valid);
+        assert!(!verdict.quorum_reached());
+    }
+
+    #[test]
+    fn test_verify_block_header_signatures() {
+        let keypairs = keypairs(4);
+        let block = block(&keypairs[0], 1);
+        let verifier = BlockVerifier::with_group(keypairs.iter().map(|kp| kp.peer_id()));
+
+        //Members sign the header of every proposal, it doesn't prove delivery
+        let certificates = keypairs
+            .iter()
+            .map(|kp| {
+                ApiBlockCertificate::from(BlockCertificate {
+                    phase: None,
+                    certificate: block.sign(kp).unwrap(),
+                })
+            })
+            .collect::<Vec<_>>();
+        let verdict = verifier.verify(&block.into(), &certificates).unwrap();
+        assert_eq!(verdict.header_signers.len(), 4);
+        assert!(verdict.signers.is_empty());
+        assert!(!verdict.is_valid());
+    }
+
+    #[test]
+    fn test_verify_block_phases_dont_add_up() {
+        let keypairs = keypairs(4);
+        let block = block(&keypairs[0], 1);
+        let verifier = BlockVerifier::with_group(keypairs.iter().map(|kp| kp.peer_id()));
+
+        let mut certificates = vote(&block, &keypairs[..2], &verifier);
+        certificates.extend(sign(&block, Phase::Echo, &keypairs[2..], &verifier));
+        let verdict = verifier.verify(&block.into(), &certificates).unwrap();
+        assert_eq!(verdict.signers.len(), 2);
+        assert!(!verdict.quorum_reached());
+    }
+
+    #[test]
+    fn test_verify_block_non_members_and_invalid_signatures() {
+        let keypairs = keypairs(4);
+        let other_block = block(&keypairs[1], 1);
+        let block = block(&keypairs[0], 1);
+        let verifier = BlockVerifier::with_group(keypairs.iter().take(3).map(|kp| kp.peer_id()));
+
+        let mut certificates = vote(&block, &keypairs[..2], &verifier);
+        certificates.extend(vote(&block, &keypairs[3..], &verifier));
+        certificates.extend(vote(&other_block, &keypairs[2..3], &verifier));
+
+        let verdict = verifier.verify(&block.into(), &certificates).unwrap();
+        assert_eq!(verdict.signers.len(), 2);
+        assert_eq!(verdict.non_members, vec![keypairs[3].peer_id()]);
+        assert_eq!(verdict.invalid_signatures, vec![keypairs[2].peer_id()]);
+        //2 of 3 members are enough, other certificates don't invalidate the block
+        assert_eq!(verdict.threshold, 2);
+        assert!(verdict.is_valid());
+    }
+
+    #[test]
+    fn test_verify_block_weighted() {
+        let keypairs = keypairs(4);
+        let block = block(&keypairs[0], 1);
+        let members = keypairs.iter().map(|kp| kp.peer_id()).collect::<Vec<_>>();
+        let verifier = BlockVerifier::with_group(members.clone())
+            .with_weights(HashMap::from([(members[3], 6)]));
+
+        //3 of 4 members, but 3 of 9 weight
+        let verdict = verifier
+            .verify(
+                &block.clone().into(),
+                &vote(&block, &keypairs[..3], &verifier),
+            )
+            .unwrap();
+        assert_eq!(verdict.total_weight, 9);
+        assert_eq!(verdict.signed_weight, 3);
+        assert_eq!(verdict.threshold, 6);
+        assert!(!verdict.quorum_reached());
+
+        let certificates = vote(&block, &keypairs[2..], &verifier);
+        let verdict = verifier
+            .verify(&block.clone().into(), &certificates)
+            .unwrap();
+        assert_eq!(verdict.signed_weight, 7);
+        assert!(verdict.is_valid());
+
+        //Votes of the same members with other weights are of another group
+        let equal_weights = BlockVerifier::with_group(members);
+        let verdict = equal_weights.verify(&block.into(), &certificates).unwrap();
+        assert_eq!(verdict.invalid_signatures.len(), 2);
+        assert!(!verdict.is_valid());
+    }
+
+    #[test]
+    fn test_verify_tampered_block() {
+        let keypairs = keypairs(1);
+        let block = block(&keypairs[0], 1);
+        let verifier = BlockVerifier::with_group(vec![keypairs[0].peer_id()]);
+        let certificates = vote(&block, &keypairs, &verifier);
+
+        let mut tampered: ApiBlock = block.into();
+        tampered.header.height += 1;
+        let verdict = verifier.verify(&tampered, &certificates).unwrap();
+        assert!(!verdict.hash_valid);
+        assert!(!verdict.is_valid());
+    }
+
+    fn vote(
+        block: &Block,
+        keypairs: &[Arc<Keypair>],
+        verifier: &BlockVerifier,
+    ) -> Vec<ApiBlockCertificate> {
+        sign(block, Phase::Vote, keypairs, verifier)
+    }
+
+    fn sign(
+        block: &Block,
+        phase: Phase,
+        keypairs: &[Arc<Keypair>],
+        verifier: &BlockVerifier,
+    ) -> Vec<ApiBlockCertificate> {
+        let group_id = Quorum::weighted(&verifier.group, &verifier.weights).group_id;
+        keypairs
+            .iter()
+            .map(|kp| {
+                BlockCertificate {
+                    phase: Some(phase),
+                    certificate: RawPhaseVote::new(block.get_hash(), phase, group_id)
+                        .sign(kp)
+                        .unwrap(),
+                }
+                .into()
+            })
+            .collect()
+    }
+}
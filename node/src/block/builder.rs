@@ -1,34 +1,45 @@
 use std::collections::HashSet;
+use std::num::NonZeroUsize;
 use std::{sync::Arc, time::Duration};
 
 use log::{debug, info};
+use lru::LruCache;
 
 use crate::block::manager::State;
+use crate::block::types::quorum_certificate::QuorumCertificate;
+use crate::broadcast::group::GroupSnapshot;
 use crate::peer::ToPeerId;
 use crate::{
     block::{
+        leader::ViewTimer,
         manager::{BlockChainState, BlockManager},
         message_pool::MessagePool,
         producer::BlockProducer,
         types::block::Block,
     },
     broadcast::signing::BlockSigner,
-    config::BlockManagerConfiguration,
+    config::{BlockManagerConfiguration, MempoolConfiguration},
     crypto::Keypair,
     storage::EphemeraDatabase,
 };
 
 pub(crate) struct BlockManagerBuilder {
     config: BlockManagerConfiguration,
+    mempool_config: MempoolConfiguration,
     block_producer: BlockProducer,
     keypair: Arc<Keypair>,
 }
 
 impl BlockManagerBuilder {
-    pub(crate) fn new(config: BlockManagerConfiguration, keypair: Arc<Keypair>) -> Self {
+    pub(crate) fn new(
+        config: BlockManagerConfiguration,
+        mempool_config: MempoolConfiguration,
+        keypair: Arc<Keypair>,
+    ) -> Self {
         let block_producer = BlockProducer::new(keypair.peer_id());
         Self {
             config,
+            mempool_config,
             block_producer,
             keypair,
         }
@@ -47,7 +58,12 @@ impl BlockManagerBuilder {
             info!("No last block found in database. Creating genesis block.");
 
             let genesis_block = Block::new_genesis_block(self.block_producer.peer_id);
-            storage.store_block(&genesis_block, HashSet::new(), HashSet::new())?;
+            storage.store_block(
+                &genesis_block,
+                &[],
+                &GroupSnapshot::default(),
+                &QuorumCertificate::default(),
+            )?;
             most_recent_block = Some(genesis_block);
         }
 
@@ -55,11 +71,13 @@ impl BlockManagerBuilder {
         debug!("Most recent block: {:?}", last_created_block);
 
         let block_signer = BlockSigner::new(self.keypair.clone());
-        let message_pool = MessagePool::new();
+        let message_pool = MessagePool::new(self.mempool_config);
         let block_chain_state = BlockChainState::new(last_created_block);
         let block_creation_interval =
             tokio::time::interval(Duration::from_secs(self.config.creation_interval_sec));
 
+        let view_timer = ViewTimer::new(Duration::from_secs(self.config.leader_timeout_sec));
+
         Ok(BlockManager {
             config: self.config,
             block_producer: self.block_producer,
@@ -69,6 +87,10 @@ impl BlockManagerBuilder {
             state: State::Paused,
             backoff: None,
             block_creation_interval,
+            prepare_proposal: None,
+            proposers: HashSet::new(),
+            view_timer,
+            rejected_messages: LruCache::new(NonZeroUsize::new(10_000).unwrap()),
         })
     }
 }
@@ -0,0 +1,158 @@
+//! # Leader rotation
+//!
+//! Only one peer, the leader, proposes a block at a given height. Leaders rotate round-robin over the
+//! broadcast group members sorted by their peer id. For block height `h` and view `v`, the leader is
+//! the member at index `(h + v) % group_size`.
+//!
+//! Each height starts with view 0. If a block at that height doesn't get committed within the leader timeout,
+//! peers move to the next view and the next member becomes the leader. Views are tracked by each peer locally,
+//! so blocks from leaders of one view ahead are accepted as well to tolerate small clock differences.
+//!
+//! Block headers carry the view they were proposed in, and a block is accepted only from the leader of that view.
+//! A leader proposes at most one block per height and view.
+
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::time::{Instant, Sleep};
+
+use crate::peer::PeerId;
+
+/// Returns the leader for given height and view.
+pub(crate) fn leader(members: &HashSet<PeerId>, height: u64, view: u64) -> Option<PeerId> {
+    if members.is_empty() {
+        return None;
+    }
+    let mut members = members.iter().copied().collect::<Vec<_>>();
+    members.sort();
+    let index = height.wrapping_add(view) % members.len() as u64;
+    members.get(usize::try_from(index).ok()?).copied()
+}
+
+/// Returns true if the peer is the leader for given height and view, and the view is not after `max_view`.
+pub(crate) fn is_leader(
+    members: &HashSet<PeerId>,
+    height: u64,
+    view: u64,
+    max_view: u64,
+    peer_id: &PeerId,
+) -> bool {
+    view <= max_view && leader(members, height, view).as_ref() == Some(peer_id)
+}
+
+/// Keeps track of the current view at the current height.
+pub(crate) struct ViewTimer {
+    /// How long a leader has to get its block committed
+    timeout: Duration,
+    /// When the current height started, i.e. when the previous block was committed
+    height_started: Instant,
+    /// Fires when the next view starts
+    next_view: Option<Pin<Box<Sleep>>>,
+}
+
+impl ViewTimer {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            height_started: Instant::now(),
+            next_view: None,
+        }
+    }
+
+    /// Starts view 0 for the next height.
+    pub(crate) fn reset(&mut self) {
+        self.height_started = Instant::now();
+    }
+
+    /// Ready once the current view is after `view`, otherwise registers the task to be woken when the next view starts.
+    pub(crate) fn poll_view_after(&mut self, view: u64, cx: &mut Context<'_>) -> Poll<()> {
+        if self.current_view() > view {
+            return Poll::Ready(());
+        }
+        if self.timeout.is_zero() {
+            return Poll::Pending;
+        }
+        let next_view = u32::try_from(view.saturating_add(1)).unwrap_or(u32::MAX);
+        let Some(deadline) = self
+            .height_started
+            .checked_add(self.timeout.saturating_mul(next_view))
+        else {
+            return Poll::Pending;
+        };
+        let sleep = self
+            .next_view
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(deadline)));
+        if sleep.deadline() != deadline {
+            sleep.as_mut().reset(deadline);
+        }
+        sleep.as_mut().poll(cx)
+    }
+
+    pub(crate) fn current_view(&self) -> u64 {
+        if self.timeout.is_zero() {
+            return 0;
+        }
+        let elapsed = self.height_started.elapsed().as_millis();
+        let view = elapsed / self.timeout.as_millis();
+        u64::try_from(view).unwrap_or(u64::MAX)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_leader_round_robin() {
+        let members = (0..4).map(|_| PeerId::random()).collect::<HashSet<_>>();
+        let mut sorted = members.iter().copied().collect::<Vec<_>>();
+        sorted.sort();
+
+        for height in 0..8 {
+            let expected = sorted[height % 4];
+            assert_eq!(leader(&members, height as u64, 0), Some(expected));
+            //Next view moves to the next member
+            let next = sorted[(height + 1) % 4];
+            assert_eq!(leader(&members, height as u64, 1), Some(next));
+        }
+        assert_eq!(leader(&HashSet::new(), 1, 0), None);
+    }
+
+    #[test]
+    fn test_is_leader_of_view() {
+        let members = (0..4).map(|_| PeerId::random()).collect::<HashSet<_>>();
+        let first = leader(&members, 1, 0).unwrap();
+        let second = leader(&members, 1, 1).unwrap();
+
+        assert!(is_leader(&members, 1, 0, 0, &first));
+        assert!(!is_leader(&members, 1, 0, 0, &second));
+        //Leader of a view we are not in yet
+        assert!(!is_leader(&members, 1, 1, 0, &second));
+        assert!(is_leader(&members, 1, 1, 1, &second));
+        //Leader of an earlier view, but not of the view it claims
+        assert!(!is_leader(&members, 1, 1, 1, &first));
+        assert!(!is_leader(&members, 1, 1, 1, &PeerId::random()));
+    }
+
+    #[test]
+    fn test_view_timer() {
+        let mut timer = ViewTimer::new(Duration::from_secs(10));
+        assert_eq!(timer.current_view(), 0);
+
+        timer.height_started = Instant::now() - Duration::from_secs(25);
+        assert_eq!(timer.current_view(), 2);
+
+        timer.reset();
+        assert_eq!(timer.current_view(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_next_view() {
+        let mut timer = ViewTimer::new(Duration::from_millis(50));
+        std::future::poll_fn(|cx| timer.poll_view_after(0, cx)).await;
+        assert_eq!(timer.current_view(), 1);
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
 use std::task::Poll;
 use std::time::Duration;
@@ -12,7 +12,7 @@ use std::{
 use anyhow::anyhow;
 use futures::Stream;
 use futures_util::FutureExt;
-use log::{debug, error, info, trace};
+use log::{debug, error, info, trace, warn};
 use lru::LruCache;
 use thiserror::Error;
 use tokio::time;
@@ -21,46 +21,109 @@ use tokio::time::{Instant, Interval};
 use crate::network::PeerId;
 use crate::peer::ToPeerId;
 use crate::{
-    api::application::RemoveMessages,
+    api::application::{RemoveMessages, Result as ApplicationResult},
+    api::types::ApiEphemeraMessage,
     block::{
-        message_pool::MessagePool,
+        leader::{self, ViewTimer},
+        message_pool::{DroppedMessage, MessagePool, MessagePoolError},
         producer::BlockProducer,
         types::{block::Block, message::EphemeraMessage},
     },
     broadcast::signing::BlockSigner,
     config::BlockManagerConfiguration,
-    utilities::{crypto::Certificate, hash::Hash},
+    utilities::{crypto::Certificate, hash::Hash, time::EphemeraTime},
 };
 
 pub(crate) type Result<T> = std::result::Result<T, BlockManagerError>;
 
+/// Lets the application choose and order block messages, see [`crate::ephemera_api::Application::prepare_proposal`].
+pub(crate) type PrepareProposal = Box<
+    dyn Fn(Vec<ApiEphemeraMessage>, usize, usize) -> ApplicationResult<Vec<ApiEphemeraMessage>>
+        + Send,
+>;
+
 #[derive(Error, Debug)]
 pub(crate) enum BlockManagerError {
     #[error("Message is already in pool: {0}")]
     DuplicateMessage(String),
+    #[error("Message rejected by pool: {0}")]
+    MessagePool(#[from] MessagePoolError),
     //Just a placeholder for now
     #[error("BlockManagerError: {0}")]
     BlockManager(#[from] anyhow::Error),
 }
 
+/// What happened to a message which is not in a committed block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum MessageStatus {
+    /// Waiting in the mempool to be included in a block
+    Pending,
+    /// In a block which is broadcast but not committed yet
+    Proposed { block_hash: Hash, height: u64 },
+    /// Rejected by the application, with the reason
+    Rejected(String),
+    /// Dropped from the mempool after its TTL passed
+    Expired,
+    /// Dropped from the full mempool to make room for newer messages
+    Evicted,
+}
+
 /// It helps to use atomic state management for new blocks.
 pub(crate) struct BlockChainState {
     pub(crate) last_blocks: LruCache<Hash, Block>,
+    /// Messages of recently seen blocks, with the block hash and height
+    proposed_messages: LruCache<Hash, (Hash, u64)>,
     /// Last block that we created.
     /// It's not Option because we always have genesis block
     last_produced_block: Option<Block>,
     /// Last block that we accepted
     /// It's not Option because we always have genesis block
     last_committed_block: Block,
+    /// Application state hash and the height of the block after which the application reported it
+    app_hash: Option<(u64, Hash)>,
 }
 
 impl BlockChainState {
     pub(crate) fn new(last_committed_block: Block) -> Self {
+        //Application state before the first block is the zero hash
+        let app_hash = (last_committed_block.get_height() == 0).then(|| (0, Hash::new([0; 32])));
         Self {
             //1000 is just a "big enough".
             last_blocks: LruCache::new(NonZeroUsize::new(1000).unwrap()),
+            proposed_messages: LruCache::new(NonZeroUsize::new(MAX_PROPOSED_MESSAGES).unwrap()),
             last_produced_block: None,
             last_committed_block,
+            app_hash,
+        }
+    }
+
+    /// Remembers a block we produced or accepted from a peer, together with its messages.
+    fn add_block(&mut self, block: &Block) -> anyhow::Result<()> {
+        let hash = block.get_hash();
+        let height = block.get_height();
+        self.last_blocks.put(hash, block.clone());
+        for message in &block.messages {
+            self.proposed_messages
+                .put(message.hash_with_default_hasher()?, (hash, height));
+        }
+        Ok(())
+    }
+
+    /// Block which contains the message, unless the block was superseded by another block at its height.
+    ///
+    /// Last committed block is included because its messages leave the mempool before they are stored.
+    fn proposed_block_of(&self, message_hash: &Hash) -> Option<(Hash, u64)> {
+        let (block_hash, height) = *self.proposed_messages.peek(message_hash)?;
+        let committed = &self.last_committed_block;
+        (height > committed.get_height() || block_hash == committed.get_hash())
+            .then_some((block_hash, height))
+    }
+
+    /// Application state hash after the last committed block, if the application has processed it.
+    fn app_hash(&self) -> Option<Hash> {
+        match self.app_hash {
+            Some((height, hash)) if height == self.last_committed_block.get_height() => Some(hash),
+            _ => None,
         }
     }
 
@@ -71,6 +134,34 @@ impl BlockChainState {
             .expect("Block should be present");
     }
 
+    /// Advances local chain view with a block committed by the group.
+    ///
+    /// The block can be our own last produced block or a block produced by another peer.
+    /// In latter case our pending block(if any) at the same or lower height becomes stale and is dropped.
+    /// Its messages stay in the mempool and will be included in the next block.
+    fn mark_block_committed(&mut self, block: &Block) {
+        if self.is_last_produced_block(block.get_hash()) {
+            self.mark_last_produced_block_as_committed();
+            return;
+        }
+
+        if let Some(produced) = self.last_produced_block.as_ref() {
+            if produced.get_height() <= block.get_height() {
+                debug!(
+                    "Dropping pending block {} because block {} was committed at height {}",
+                    produced.get_hash(),
+                    block.get_hash(),
+                    block.get_height()
+                );
+                self.last_produced_block = None;
+            }
+        }
+
+        if block.get_height() > self.last_committed_block.get_height() {
+            self.last_committed_block = block.clone();
+        }
+    }
+
     fn is_last_produced_block(&self, hash: Hash) -> bool {
         match self.last_produced_block.as_ref() {
             Some(block) => block.get_hash() == hash,
@@ -82,6 +173,13 @@ impl BlockChainState {
         self.last_produced_block.is_some()
     }
 
+    /// Returns true if our pending block was proposed in the given view.
+    fn is_produced_in_view(&self, view: u64) -> bool {
+        self.last_produced_block
+            .as_ref()
+            .is_some_and(|block| block.header.view == view)
+    }
+
     fn next_block_height(&self) -> u64 {
         self.last_committed_block.get_height() + 1
     }
@@ -98,6 +196,12 @@ pub(crate) enum State {
     Running,
 }
 
+/// Number of messages of recently seen blocks that are remembered for status queries.
+const MAX_PROPOSED_MESSAGES: usize = 100_000;
+
+/// Upper bound for the delay between backoff attempts, so that a high rate doesn't overflow.
+const MAX_BACKOFF_DELAY_SEC: u64 = 24 * 60 * 60;
+
 #[derive(Debug)]
 pub(crate) struct BackOffInterval {
     /// Maximum number of attempts before this backoff expires.
@@ -138,8 +242,12 @@ impl Future for BackOffInterval {
         match Pin::new(&mut self.delay).poll_tick(cx) {
             Ready(_) => {
                 self.nr_of_attempts += 1;
-                let next_tick = Instant::now()
-                    + self.delay.period() * self.backoff_rate.pow(self.nr_of_attempts);
+                let delay = self
+                    .delay
+                    .period()
+                    .saturating_mul(self.backoff_rate.saturating_pow(self.nr_of_attempts));
+                let next_tick =
+                    Instant::now() + delay.min(Duration::from_secs(MAX_BACKOFF_DELAY_SEC));
                 debug!("Backoff attempt: {}", self.nr_of_attempts);
                 self.delay = time::interval_at(next_tick, self.delay.period());
                 Ready(())
@@ -166,6 +274,14 @@ pub(crate) struct BlockManager {
     pub(crate) block_chain_state: BlockChainState,
     /// Current state of the block manager
     pub(crate) state: State,
+    /// Application hook which selects and orders messages for a new block
+    pub(crate) prepare_proposal: Option<PrepareProposal>,
+    /// Members of the current broadcast group, the leader is chosen among them
+    pub(crate) proposers: HashSet<PeerId>,
+    /// View at the current height, it changes when the leader fails to get its block committed in time
+    pub(crate) view_timer: ViewTimer,
+    /// Recently rejected messages and why
+    pub(crate) rejected_messages: LruCache<Hash, String>,
 }
 
 impl BlockManager {
@@ -180,9 +296,37 @@ impl BlockManager {
         }
 
         self.message_pool.add_message(msg)?;
+        self.rejected_messages.pop(&message_hash);
         Ok(())
     }
 
+    /// Remembers that the application rejected the message, so that its status can be queried.
+    pub(crate) fn on_message_rejected(&mut self, message_hash: Hash, reason: &str) {
+        trace!("Message {message_hash} rejected: {reason}");
+        self.rejected_messages.put(message_hash, reason.to_string());
+    }
+
+    /// Returns status of a message which is not committed.
+    ///
+    /// Only recently rejected and dropped messages are remembered, `None` if the message is unknown.
+    pub(crate) fn message_status(&self, message_hash: &Hash) -> Option<MessageStatus> {
+        if let Some((block_hash, height)) = self.block_chain_state.proposed_block_of(message_hash) {
+            return Some(MessageStatus::Proposed { block_hash, height });
+        }
+        if self.message_pool.contains(message_hash) {
+            return Some(MessageStatus::Pending);
+        }
+        if let Some(reason) = self.rejected_messages.peek(message_hash) {
+            return Some(MessageStatus::Rejected(reason.clone()));
+        }
+        self.message_pool
+            .dropped(message_hash)
+            .map(|dropped| match dropped {
+                DroppedMessage::Expired => MessageStatus::Expired,
+                DroppedMessage::Evicted => MessageStatus::Evicted,
+            })
+    }
+
     pub(crate) fn on_block(
         &mut self,
         sender: &PeerId,
@@ -201,6 +345,14 @@ impl BlockManager {
             return Err(anyhow!("Block hash is invalid: {} != {hash}", block.header.hash).into());
         }
 
+        //Reject blocks which messages don't match header Merkle root
+        if !block.verify_merkle_root()? {
+            return Err(anyhow!("Block {hash} Merkle root doesn't match its messages").into());
+        }
+
+        //Reject blocks exceeding our limits
+        self.check_block_limits(block)?;
+
         //Block signer should be also its sender
         let signer_peer_id = certificate.public_key.peer_id();
         if *sender != signer_peer_id {
@@ -211,20 +363,162 @@ impl BlockManager {
         }
 
         //Verify that block signature is valid
-        if self.block_signer.verify_block(block, certificate).is_err() {
+        if BlockSigner::verify_block(block, certificate).is_err() {
             return Err(anyhow!("Block signature is invalid: {hash}").into());
         }
 
-        self.block_chain_state.last_blocks.put(hash, block.clone());
+        //Block needs to extend our chain. We check it when we see the block first time,
+        //later messages of the same broadcast can arrive after the block got committed.
+        if self.block_chain_state.last_blocks.contains(&hash) {
+            return Ok(());
+        }
+        let next_height = self.block_chain_state.next_block_height();
+        if block.get_height() != next_height {
+            return Err(anyhow!(
+                "Block {hash} height {} doesn't follow local chain height {}",
+                block.get_height(),
+                next_height - 1
+            )
+            .into());
+        }
+        let expected_parent = self.block_chain_state.last_committed_block.hash_as_parent();
+        if block.header.previous_hash != expected_parent {
+            return Err(anyhow!(
+                "Block {hash} parent {} doesn't match local chain {expected_parent}",
+                block.header.previous_hash
+            )
+            .into());
+        }
+
+        //Block needs to carry the same application state as ours, otherwise our states have diverged
+        let Some(app_hash) = self.block_chain_state.app_hash() else {
+            return Err(anyhow!(
+                "Block {hash} can't be checked, application state at height {} is not known yet",
+                next_height - 1
+            )
+            .into());
+        };
+        if block.header.app_hash != app_hash {
+            return Err(anyhow!(
+                "Block {hash} app hash {} doesn't match local application state {app_hash}",
+                block.header.app_hash
+            )
+            .into());
+        }
+
+        self.block_chain_state.add_block(block)?;
         Ok(())
     }
 
-    pub(crate) fn sign_block(&mut self, block: &Block) -> Result<Certificate> {
-        let hash = block.hash_with_default_hasher()?;
+    fn check_block_limits(&self, block: &Block) -> Result<()> {
+        let hash = block.get_hash();
+        let max_messages = self.config.max_messages_per_block;
+        if block.messages.len() > max_messages {
+            return Err(anyhow!(
+                "Block {hash} has too many messages: {} > {max_messages}",
+                block.messages.len()
+            )
+            .into());
+        }
+
+        let mut size = 0;
+        for message in &block.messages {
+            size += message.encoded_size()?;
+        }
+        let max_bytes = self.config.max_block_size_bytes;
+        if size > max_bytes {
+            return Err(anyhow!("Block {hash} is too big: {size} > {max_bytes} bytes").into());
+        }
+        Ok(())
+    }
 
+    /// Selects messages for a new block from the mempool.
+    ///
+    /// Application can choose and order candidates, it can't add other messages. Messages are then taken in order as long as
+    /// they fit into block limits. Messages which don't fit stay in the mempool.
+    fn select_block_messages(&mut self) -> Vec<EphemeraMessage> {
+        self.message_pool.remove_expired(EphemeraTime::now());
+        let candidates = self.message_pool.get_messages();
+
+        let max_messages = self.config.max_messages_per_block;
+        let max_bytes = self.config.max_block_size_bytes;
+
+        let candidates = match &self.prepare_proposal {
+            Some(prepare_proposal) => {
+                let api_candidates = candidates.iter().cloned().map(Into::into).collect();
+                match prepare_proposal(api_candidates, max_messages, max_bytes) {
+                    Ok(selected) => Self::selected_candidates(candidates, selected),
+                    Err(err) => {
+                        error!(
+                            "Application failed to prepare proposal, using mempool order: {err:?}"
+                        );
+                        candidates
+                    }
+                }
+            }
+            None => candidates,
+        };
+
+        let mut messages = Vec::with_capacity(candidates.len().min(max_messages));
+        let mut size = 0;
+        for message in candidates {
+            if messages.len() >= max_messages {
+                break;
+            }
+            let message_size = match message.encoded_size() {
+                Ok(message_size) => message_size,
+                Err(err) => {
+                    error!("Failed to encode message: {err:?}");
+                    continue;
+                }
+            };
+            if size + message_size > max_bytes {
+                trace!("Message doesn't fit into block: {message_size} bytes");
+                continue;
+            }
+            size += message_size;
+            messages.push(message);
+        }
+        messages
+    }
+
+    /// Mempool candidates which the application selected, in its order.
+    ///
+    /// Messages which are not candidates are left out, so the application can't add messages that were not
+    /// checked or were already committed. A message selected more than once is included once.
+    fn selected_candidates(
+        candidates: Vec<EphemeraMessage>,
+        selected: Vec<ApiEphemeraMessage>,
+    ) -> Vec<EphemeraMessage> {
+        let mut candidates = candidates
+            .into_iter()
+            .filter_map(|message| {
+                let hash = message.hash_with_default_hasher().ok()?;
+                Some((hash, message))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut messages = Vec::with_capacity(selected.len());
+        for message in selected {
+            let message: EphemeraMessage = message.into();
+            match message.hash_with_default_hasher() {
+                Ok(hash) => {
+                    if let Some(candidate) = candidates.remove(&hash) {
+                        messages.push(candidate);
+                    } else {
+                        warn!("Application selected message {hash} which is not a mempool candidate, leaving it out");
+                    }
+                }
+                Err(err) => error!("Failed to hash selected message: {err:?}"),
+            }
+        }
+        messages
+    }
+
+    pub(crate) fn sign_block(&mut self, block: &Block) -> Result<Certificate> {
         trace!("Signing block: {block}");
 
-        let certificate = self.block_signer.sign_block(block, &hash)?;
+        let certificate = self.block_signer.sign_block(block)?;
 
         trace!("Block certificate: {certificate:?}",);
 
@@ -238,7 +532,7 @@ impl BlockManager {
         debug!("Application rejected last created block");
 
         let last_produced_block = self.block_chain_state.remove_last_produced_block();
-        match messages_to_remove {
+        let messages = match messages_to_remove {
             RemoveMessages::All => {
                 let messages = last_produced_block
                     .messages
@@ -248,39 +542,30 @@ impl BlockManager {
 
                 debug!("Removing block messages from pool: all: {messages:?}",);
                 self.message_pool.remove_messages(&messages)?;
+                messages
             }
             RemoveMessages::Selected(messages) => {
                 debug!("Removing block messages from pool: selected: {messages:?}",);
                 let messages = messages.into_iter().map(Into::into).collect::<Vec<_>>();
                 self.message_pool.remove_messages(messages.as_slice())?;
+                messages
             }
         };
+        for message in &messages {
+            let hash = message.hash_with_default_hasher()?;
+            self.on_message_rejected(hash, "Application rejected block");
+        }
         Ok(())
     }
 
-    /// After a block gets committed, clear up mempool from its messages
+    /// After a block gets committed, clear up mempool from its messages and advance local chain view.
     pub(crate) fn on_block_committed(&mut self, block: &Block) -> Result<()> {
         info!("Block committed: {}", block);
 
-        let hash = &block.header.hash;
-
-        if !self.block_chain_state.is_last_produced_block(*hash) {
-            let last_produced_block = self
-                .block_chain_state
-                .last_produced_block
-                .as_ref()
-                .expect("Last produced block should be present");
-            log::error!(
-                "Received unexpected committed block: {hash}, was expecting: {}",
-                last_produced_block.get_hash()
-            );
-            panic!("Received committed block which isn't last produced block, this is a bug!");
-        }
-
         match self.message_pool.remove_messages(&block.messages) {
             Ok(_) => {
-                self.block_chain_state
-                    .mark_last_produced_block_as_committed();
+                self.block_chain_state.mark_block_committed(block);
+                self.view_timer.reset();
             }
             Err(e) => {
                 return Err(anyhow!("Failed to remove messages from mempool: {}", e).into());
@@ -289,12 +574,53 @@ impl BlockManager {
         Ok(())
     }
 
-    pub(crate) fn get_block_by_hash(&mut self, block_id: &Hash) -> Option<Block> {
-        self.block_chain_state.last_blocks.get(block_id).cloned()
+    /// Application committed its state after delivering the block at given height.
+    pub(crate) fn on_app_hash(&mut self, height: u64, app_hash: Hash) {
+        trace!("Application state at height {height}: {app_hash}");
+        self.block_chain_state.app_hash = Some((height, app_hash));
+    }
+
+    /// Returns true if mempool has enough messages to produce a block before the next interval tick.
+    fn is_mempool_trigger_reached(&self) -> bool {
+        let stats = self.message_pool.stats();
+        let messages_reached = self
+            .config
+            .mempool_trigger_messages
+            .is_some_and(|trigger| stats.message_count >= trigger);
+        let bytes_reached = self
+            .config
+            .mempool_trigger_bytes
+            .is_some_and(|trigger| stats.total_bytes >= trigger);
+        messages_reached || bytes_reached
     }
 
-    pub(crate) fn get_block_certificates(&mut self, hash: &Hash) -> Option<&HashSet<Certificate>> {
-        self.block_signer.get_block_certificates(hash)
+    /// Broadcast group changed, leaders are chosen from the new members.
+    pub(crate) fn on_group_updated(&mut self, members: &HashSet<PeerId>) {
+        self.proposers.clone_from(members);
+    }
+
+    /// View at the current height.
+    pub(crate) fn current_view(&self) -> u64 {
+        self.view_timer.current_view()
+    }
+
+    /// Returns true if the local node is the leader at the next height in the current view.
+    pub(crate) fn is_leader(&self) -> bool {
+        let leader = leader::leader(
+            &self.proposers,
+            self.next_block_height(),
+            self.current_view(),
+        );
+        leader == Some(self.block_producer.peer_id)
+    }
+
+    /// Height of the block which the node expects to be committed next.
+    pub(crate) fn next_block_height(&self) -> u64 {
+        self.block_chain_state.next_block_height()
+    }
+
+    pub(crate) fn get_block_by_hash(&mut self, block_id: &Hash) -> Option<Block> {
+        self.block_chain_state.last_blocks.get(block_id).cloned()
     }
 
     pub(crate) fn stop(&mut self) {
@@ -315,6 +641,21 @@ impl BlockManager {
         self.block_creation_interval =
             tokio::time::interval(Duration::from_secs(self.config.creation_interval_sec));
     }
+
+    /// Registers the task to be woken at the next block creation tick or when the next view starts,
+    /// instead of waking it right away and spinning until then.
+    fn wait_for_next_tick_or_view(
+        &mut self,
+        cx: &mut task::Context,
+    ) -> Poll<Option<(Block, Certificate)>> {
+        let view = self.current_view();
+        let next_view = self.view_timer.poll_view_after(view, cx).is_ready();
+        let next_tick = self.block_creation_interval.poll_tick(cx).is_ready();
+        if next_view || next_tick {
+            cx.waker().wake_by_ref();
+        }
+        Pending
+    }
 }
 
 //Produces blocks at a predefined interval.
@@ -349,6 +690,11 @@ impl Stream for BlockManager {
                     return Pending;
                 }
                 self.backoff = Some(backoff);
+            } else if !is_previous_pending && self.is_leader() && self.is_mempool_trigger_reached()
+            {
+                //New messages arrive via the main loop which polls us again after each event
+                debug!("Mempool reached block creation threshold");
+                self.block_creation_interval.reset();
             } else {
                 return Pending;
             }
@@ -356,6 +702,34 @@ impl Stream for BlockManager {
             self.backoff = None;
         }
 
+        //Only the leader proposes a block. Try again at the next tick or when the view changes.
+        if !self.is_leader() {
+            trace!(
+                "Not the leader at height {} view {}",
+                self.next_block_height(),
+                self.current_view()
+            );
+            return self.wait_for_next_tick_or_view(cx);
+        }
+
+        //A leader proposes at most one block per height and view, a second one would be equivocation.
+        //If our block isn't committed in time, the leader of the next view proposes.
+        let view = self.current_view();
+        if self.block_chain_state.is_produced_in_view(view) {
+            trace!("Already proposed a block in view {view}");
+            return self.wait_for_next_tick_or_view(cx);
+        }
+
+        //Next block includes application state after the last committed block, wait until it's delivered.
+        //The main loop polls us again after the application commits.
+        let Some(app_hash) = self.block_chain_state.app_hash() else {
+            trace!(
+                "Waiting for application state at height {}",
+                self.next_block_height() - 1
+            );
+            return self.wait_for_next_tick_or_view(cx);
+        };
+
         //If backoff is expired and we still don't have previous block committed
         let repeat_previous = is_previous_pending && self.config.repeat_last_block_messages;
 
@@ -371,28 +745,44 @@ impl Stream for BlockManager {
             block.messages
         } else {
             debug!("Producing block with new messages");
-            self.message_pool.get_messages()
+            self.select_block_messages()
         };
 
+        if pending_messages.is_empty() && self.config.skip_empty_blocks {
+            trace!("No messages, skipping empty block");
+            return self.wait_for_next_tick_or_view(cx);
+        }
+
         let new_height = self.block_chain_state.next_block_height();
-        let created_block = self
-            .block_producer
-            .create_block(new_height, pending_messages);
+        let previous_hash = self.block_chain_state.last_committed_block.hash_as_parent();
+        let created_block = self.block_producer.create_block(
+            new_height,
+            view,
+            previous_hash,
+            app_hash,
+            pending_messages,
+        );
 
         if let Ok(block) = created_block {
             info!("Created block: {}", block);
 
             let hash = block.get_hash();
             self.block_chain_state.last_produced_block = Some(block.clone());
-            self.block_chain_state.last_blocks.put(hash, block.clone());
+            if let Err(err) = self.block_chain_state.add_block(&block) {
+                error!("Failed to index messages of block {hash}: {err}");
+            }
 
             let certificate = self
                 .block_signer
-                .sign_block(&block, &hash)
+                .sign_block(&block)
                 .expect("Failed to sign block");
 
             if self.backoff.is_none() {
-                let backoff = BackOffInterval::new(100, 2, Duration::from_secs(10));
+                let backoff = BackOffInterval::new(
+                    self.config.backoff_max_attempts,
+                    self.config.backoff_rate,
+                    Duration::from_secs(self.config.backoff_initial_delay_sec),
+                );
                 self.backoff = Some(backoff);
             }
 
@@ -406,14 +796,18 @@ impl Stream for BlockManager {
 
 #[cfg(test)]
 mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use std::sync::Arc;
     use std::time::Duration;
 
     use assert_matches::assert_matches;
+    use futures::task::ArcWake;
     use futures_util::StreamExt;
 
+    use crate::config::MempoolConfiguration;
     use crate::crypto::{EphemeraKeypair, Keypair};
     use crate::ephemera_api::RawApiEphemeraMessage;
+    use crate::utilities::test_utils;
 
     use super::*;
 
@@ -498,6 +892,80 @@ mod test {
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_reject_invalid_parent() {
+        let (mut manager, peer_id) = block_manager_with_defaults();
+
+        let block = manager
+            .block_producer
+            .create_block(1, 0, Hash::new([1; 32]), Hash::new([0; 32]), vec![])
+            .unwrap();
+        let certificate = manager.sign_block(&block).unwrap();
+
+        let result = manager.on_block(&peer_id, &block, &certificate);
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reject_block_not_following_local_chain() {
+        let (mut manager, peer_id) = block_manager_with_defaults();
+
+        let block = manager
+            .block_producer
+            .create_block(2, 0, Hash::new([0; 32]), Hash::new([0; 32]), vec![])
+            .unwrap();
+        let certificate = manager.sign_block(&block).unwrap();
+
+        let result = manager.on_block(&peer_id, &block, &certificate);
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_next_block_links_to_last_committed() {
+        let (mut manager, _) = block_manager_with_defaults();
+
+        let (first, _) = manager.next().await.unwrap();
+        assert_eq!(first.header.previous_hash, Hash::new([0; 32]));
+        manager.on_block_committed(&first).unwrap();
+        manager.on_app_hash(1, Hash::new([1; 32]));
+
+        let (second, _) = manager.next().await.unwrap();
+        assert_eq!(second.header.previous_hash, first.get_hash());
+        assert_eq!(second.header.app_hash, Hash::new([1; 32]));
+    }
+
+    #[tokio::test]
+    async fn test_reject_block_with_different_app_hash() {
+        let (mut manager, peer_id) = block_manager_with_defaults();
+
+        let block = manager
+            .block_producer
+            .create_block(1, 0, Hash::new([0; 32]), Hash::new([1; 32]), vec![])
+            .unwrap();
+        let certificate = manager.sign_block(&block).unwrap();
+
+        let result = manager.on_block(&peer_id, &block, &certificate);
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reject_block_before_app_hash_is_known() {
+        let (mut manager, peer_id) = block_manager_with_defaults();
+
+        let (first, _) = manager.next().await.unwrap();
+        manager.on_block_committed(&first).unwrap();
+
+        let block = manager
+            .block_producer
+            .create_block(2, 0, first.get_hash(), Hash::new([0; 32]), vec![])
+            .unwrap();
+        let certificate = manager.sign_block(&block).unwrap();
+        assert!(manager.on_block(&peer_id, &block, &certificate).is_err());
+
+        manager.on_app_hash(1, Hash::new([0; 32]));
+        assert!(manager.on_block(&peer_id, &block, &certificate).is_ok());
+    }
+
     #[tokio::test]
     async fn test_next_block_empty() {
         let (mut manager, _) = block_manager_with_defaults();
@@ -528,6 +996,7 @@ mod test {
     #[tokio::test]
     async fn test_next_block_previous_not_committed_repeat() {
         let (mut manager, _) = block_manager_with_defaults();
+        manager.view_timer = ViewTimer::new(Duration::from_millis(100));
 
         let signed_message = message("test");
         manager.on_new_message(signed_message).unwrap();
@@ -541,12 +1010,28 @@ mod test {
 
         assert_eq!(block1.messages.len(), block2.messages.len());
         assert_eq!(block1.header.height, block2.header.height);
+        assert!(block2.header.view > block1.header.view);
+    }
+
+    #[tokio::test]
+    async fn test_no_second_block_in_same_view() {
+        let (mut manager, _) = block_manager_with_defaults();
+        manager.view_timer = ViewTimer::new(Duration::from_secs(3600));
+
+        manager.on_new_message(message("test")).unwrap();
+        let (block, _) = manager.next().await.unwrap();
+        assert_eq!(block.header.view, 0);
+
+        //Backoff expires but the leader doesn't propose again at the same height and view
+        let next = tokio::time::timeout(Duration::from_millis(200), manager.next()).await;
+        assert!(next.is_err());
     }
 
     #[tokio::test]
     async fn test_next_block_previous_not_committed_repeat_false() {
         let config = BlockManagerConfiguration::new(true, 0, false);
         let (mut manager, _) = block_manager_with_config(config);
+        manager.view_timer = ViewTimer::new(Duration::from_millis(100));
 
         let signed_message = message("test");
         manager.on_new_message(signed_message).unwrap();
@@ -580,20 +1065,37 @@ mod test {
     }
 
     #[tokio::test]
-    #[should_panic]
-    async fn test_on_committed_with_invalid_pending_block() {
+    async fn test_on_committed_with_block_from_other_peer() {
         let (mut manager, _) = block_manager_with_defaults();
 
         let signed_message = message("test");
-        manager.on_new_message(signed_message).unwrap();
+        manager.on_new_message(signed_message.clone()).unwrap();
 
-        let _ = manager.next().await.unwrap();
+        let (own_block, _) = manager.next().await.unwrap();
 
-        //Create invalid block
-        let wrong_block = block();
+        //Block at the same height created by another peer, including the same message
+        let keypair: Arc<Keypair> = Keypair::generate(None).into();
+        let mut producer = BlockProducer::new(keypair.public_key().peer_id());
+        let other_block = producer
+            .create_block(
+                own_block.get_height(),
+                own_block.header.view,
+                own_block.header.previous_hash,
+                own_block.header.app_hash,
+                vec![signed_message],
+            )
+            .unwrap();
 
-        //This shouldn't remove messages from the pool
-        let _ = manager.on_block_committed(&wrong_block);
+        manager.on_block_committed(&other_block).unwrap();
+
+        assert!(manager.message_pool.get_messages().is_empty());
+        assert!(!manager
+            .block_chain_state
+            .is_last_produced_block_is_pending());
+        assert_eq!(
+            manager.block_chain_state.next_block_height(),
+            other_block.get_height() + 1
+        );
     }
 
     #[tokio::test]
@@ -618,6 +1120,74 @@ mod test {
         assert!(manager.message_pool.get_messages().is_empty());
     }
 
+    #[tokio::test]
+    async fn test_message_status() {
+        let (mut manager, _) = block_manager_with_defaults();
+
+        let pending = message("test");
+        let pending_hash = pending.hash_with_default_hasher().unwrap();
+        manager.on_new_message(pending).unwrap();
+        assert_eq!(
+            manager.message_status(&pending_hash),
+            Some(MessageStatus::Pending)
+        );
+
+        let rejected_hash = message("test").hash_with_default_hasher().unwrap();
+        manager.on_message_rejected(rejected_hash, "invalid");
+        assert_eq!(
+            manager.message_status(&rejected_hash),
+            Some(MessageStatus::Rejected("invalid".to_string()))
+        );
+
+        let unknown_hash = message("test").hash_with_default_hasher().unwrap();
+        assert_eq!(manager.message_status(&unknown_hash), None);
+    }
+
+    #[tokio::test]
+    async fn test_message_status_proposed() {
+        let (mut manager, peer_id) = block_manager_with_defaults();
+
+        let proposed = message("test");
+        let proposed_hash = proposed.hash_with_default_hasher().unwrap();
+        let block = manager
+            .block_producer
+            .create_block(1, 0, Hash::new([0; 32]), Hash::new([0; 32]), vec![proposed])
+            .unwrap();
+        let certificate = manager.sign_block(&block).unwrap();
+        manager.on_block(&peer_id, &block, &certificate).unwrap();
+
+        let status = Some(MessageStatus::Proposed {
+            block_hash: block.get_hash(),
+            height: 1,
+        });
+        assert_eq!(manager.message_status(&proposed_hash), status);
+
+        //Committed but maybe not stored yet
+        manager.on_block_committed(&block).unwrap();
+        assert_eq!(manager.message_status(&proposed_hash), status);
+    }
+
+    #[tokio::test]
+    async fn test_message_status_superseded_block() {
+        let (mut manager, peer_id) = block_manager_with_defaults();
+
+        let proposed = message("test");
+        let proposed_hash = proposed.hash_with_default_hasher().unwrap();
+        let block = manager
+            .block_producer
+            .create_block(1, 0, Hash::new([0; 32]), Hash::new([0; 32]), vec![proposed])
+            .unwrap();
+        let certificate = manager.sign_block(&block).unwrap();
+        manager.on_block(&peer_id, &block, &certificate).unwrap();
+
+        let other = manager
+            .block_producer
+            .create_block(1, 1, Hash::new([0; 32]), Hash::new([0; 32]), vec![])
+            .unwrap();
+        manager.on_block_committed(&other).unwrap();
+        assert_eq!(manager.message_status(&proposed_hash), None);
+    }
+
     #[tokio::test]
     async fn application_rejected_messages_selected() {
         let (mut manager, _) = block_manager_with_defaults();
@@ -647,6 +1217,194 @@ mod test {
         assert_eq!(message, signed_message1);
     }
 
+    #[tokio::test]
+    async fn test_next_block_respects_max_messages() {
+        let mut config = BlockManagerConfiguration::new(true, 0, false);
+        config.max_messages_per_block = 2;
+        let (mut manager, _) = block_manager_with_config(config);
+
+        for _ in 0..3 {
+            manager.on_new_message(message("test")).unwrap();
+        }
+
+        let (block, _) = manager.next().await.unwrap();
+        assert_eq!(block.messages.len(), 2);
+        assert_eq!(manager.message_pool.get_messages().len(), 3);
+
+        manager.on_block_committed(&block).unwrap();
+        assert_eq!(manager.message_pool.get_messages().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_next_block_respects_max_bytes() {
+        let first = message("test");
+        let size = first.encoded_size().unwrap();
+
+        let mut config = BlockManagerConfiguration::new(true, 0, false);
+        config.max_block_size_bytes = size * 3 / 2;
+        let (mut manager, _) = block_manager_with_config(config);
+
+        manager.on_new_message(first).unwrap();
+        manager.on_new_message(message("test")).unwrap();
+
+        let (block, _) = manager.next().await.unwrap();
+        assert_eq!(block.messages.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_next_block_with_prepare_proposal() {
+        let config = BlockManagerConfiguration::new(true, 0, false);
+        let (mut manager, _) = block_manager_with_config(config);
+        manager.prepare_proposal = Some(Box::new(|mut candidates, _, _| {
+            candidates.reverse();
+            Ok(candidates)
+        }));
+
+        let first = message("test");
+        let second = message("test");
+        manager.on_new_message(first.clone()).unwrap();
+        manager.on_new_message(second.clone()).unwrap();
+
+        let mempool_order = manager.message_pool.get_messages();
+        let (block, _) = manager.next().await.unwrap();
+        assert_eq!(block.messages.len(), 2);
+        assert_eq!(block.messages[0], mempool_order[1]);
+        assert_eq!(block.messages[1], mempool_order[0]);
+    }
+
+    #[tokio::test]
+    async fn test_prepare_proposal_selects_only_candidates() {
+        let config = BlockManagerConfiguration::new(true, 0, false);
+        let (mut manager, _) = block_manager_with_config(config);
+        let unknown: ApiEphemeraMessage = message("test").into();
+        manager.prepare_proposal = Some(Box::new(move |candidates, _, _| {
+            let mut selected = vec![unknown.clone()];
+            selected.extend(candidates.clone());
+            selected.extend(candidates);
+            Ok(selected)
+        }));
+
+        let candidate = message("test");
+        manager.on_new_message(candidate.clone()).unwrap();
+
+        let (block, _) = manager.next().await.unwrap();
+        assert_eq!(block.messages, vec![candidate]);
+    }
+
+    #[tokio::test]
+    async fn test_next_block_only_by_leader() {
+        let (mut manager, peer_id) = block_manager_with_defaults();
+        manager.view_timer = ViewTimer::new(Duration::from_millis(300));
+
+        //With two members, height 1 view 0 leader is the second one in order
+        let other = loop {
+            let other = Keypair::generate(None).public_key().peer_id();
+            if other > peer_id {
+                break other;
+            }
+        };
+        manager.on_group_updated(&HashSet::from([peer_id, other]));
+        assert!(!manager.is_leader());
+
+        let next = tokio::time::timeout(Duration::from_millis(50), manager.next()).await;
+        assert!(next.is_err());
+
+        //After view change local node becomes the leader
+        let (block, _) = manager.next().await.unwrap();
+        assert_eq!(block.header.height, 1);
+        assert_eq!(manager.current_view(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_waiting_does_not_spin() {
+        struct CountingWaker(AtomicUsize);
+        impl ArcWake for CountingWaker {
+            fn wake_by_ref(arc_self: &Arc<Self>) {
+                arc_self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let mut config = BlockManagerConfiguration::new(true, 3600, false);
+        config.mempool_trigger_messages = Some(1);
+        let (mut manager, peer_id) = block_manager_with_config(config);
+        manager.view_timer = ViewTimer::new(Duration::from_secs(3600));
+        manager.on_new_message(message("test")).unwrap();
+
+        let wakes = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = futures::task::waker(wakes.clone());
+        let mut cx = task::Context::from_waker(&waker);
+
+        //Not the leader while mempool trigger is reached
+        let other = loop {
+            let other = Keypair::generate(None).public_key().peer_id();
+            if other > peer_id {
+                break other;
+            }
+        };
+        manager.on_group_updated(&HashSet::from([peer_id, other]));
+        assert!(manager.poll_next_unpin(&mut cx).is_pending());
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 0);
+
+        //Leader waiting for the application state
+        manager.on_group_updated(&HashSet::from([peer_id]));
+        manager.block_chain_state.app_hash = None;
+        assert!(manager.poll_next_unpin(&mut cx).is_pending());
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_next_block_on_mempool_trigger() {
+        let mut config = BlockManagerConfiguration::new(true, 3600, false);
+        config.mempool_trigger_messages = Some(2);
+        let (mut manager, _) = block_manager_with_config(config);
+        let period = Duration::from_secs(3600);
+        manager.block_creation_interval = time::interval_at(Instant::now() + period, period);
+
+        manager.on_new_message(message("test")).unwrap();
+        let next = time::timeout(Duration::from_millis(50), manager.next()).await;
+        assert!(next.is_err());
+
+        manager.on_new_message(message("test")).unwrap();
+        let (block, _) = manager.next().await.unwrap();
+        assert_eq!(block.messages.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_skip_empty_blocks() {
+        let mut config = BlockManagerConfiguration::new(true, 0, false);
+        config.skip_empty_blocks = true;
+        let (mut manager, _) = block_manager_with_config(config);
+
+        let next = time::timeout(Duration::from_millis(50), manager.next()).await;
+        assert!(next.is_err());
+
+        manager.on_new_message(message("test")).unwrap();
+        let (block, _) = manager.next().await.unwrap();
+        assert_eq!(block.messages.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reject_block_exceeding_limits() {
+        let mut config = BlockManagerConfiguration::new(true, 0, true);
+        config.max_messages_per_block = 1;
+        let (mut manager, peer_id) = block_manager_with_config(config);
+
+        let block = manager
+            .block_producer
+            .create_block(
+                1,
+                0,
+                Hash::new([0; 32]),
+                Hash::new([0; 32]),
+                vec![message("test"), message("test")],
+            )
+            .unwrap();
+        let certificate = manager.sign_block(&block).unwrap();
+
+        let result = manager.on_block(&peer_id, &block, &certificate);
+        assert!(result.is_err());
+    }
+
     fn block_manager_with_defaults() -> (BlockManager, PeerId) {
         let config = BlockManagerConfiguration::new(true, 0, true);
         block_manager_with_config(config)
@@ -657,26 +1415,28 @@ mod test {
         let peer_id = keypair.public_key().peer_id();
         let genesis_block = Block::new_genesis_block(peer_id);
         let block_chain_state = BlockChainState::new(genesis_block);
+        let view_timer = ViewTimer::new(Duration::from_secs(config.leader_timeout_sec));
         (
             BlockManager {
                 config,
                 block_producer: BlockProducer::new(peer_id),
-                message_pool: MessagePool::new(),
+                message_pool: MessagePool::new(MempoolConfiguration::default()),
                 block_creation_interval: tokio::time::interval(Duration::from_millis(1)),
                 backoff: None,
                 block_signer: BlockSigner::new(keypair),
                 block_chain_state,
                 state: State::Running,
+                prepare_proposal: None,
+                proposers: HashSet::from([peer_id]),
+                view_timer,
+                rejected_messages: LruCache::new(NonZeroUsize::new(1000).unwrap()),
             },
             peer_id,
         )
     }
 
     fn block() -> Block {
-        let keypair: Arc<Keypair> = Keypair::generate(None).into();
-        let peer_id = keypair.public_key().peer_id();
-        let mut producer = BlockProducer::new(peer_id);
-        producer.create_block(1, vec![]).unwrap()
+        test_utils::block(&Keypair::generate(None), 1)
     }
 
     fn message(label: &str) -> EphemeraMessage {
@@ -1,25 +1,90 @@
 //! Message pool for Ephemera messages
 //!
 //! It stores pending Ephemera messages which will be added to a future block.
-//! It doesn't have any other logic than just storing messages.
+//!
+//! The pool is bounded by the number of messages and their total size, see [`MempoolConfiguration`].
+//! Messages expire after configured time counting from their timestamp. When the pool is full,
+//! [`EvictionPolicy`] decides if a new message is rejected or the oldest messages are evicted.
+//! Hashes of recently expired and evicted messages are remembered, so that clients can query what happened to them.
 //!
 //! It's up to the user provided [`crate::ephemera_api::Application::check_tx`] to decide which messages to include.
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+use std::num::NonZeroUsize;
 
-use log::{trace, warn};
+use log::{debug, trace, warn};
+use lru::LruCache;
+use thiserror::Error;
 
 use crate::block::types::message::EphemeraMessage;
+use crate::config::{EvictionPolicy, MempoolConfiguration};
 use crate::utilities::hash::Hash;
+use crate::utilities::time::EphemeraTime;
+
+/// How many recently dropped message hashes are remembered.
+const DROPPED_MESSAGES_CACHE_SIZE: usize = 10_000;
+
+#[derive(Error, Debug)]
+pub(crate) enum MessagePoolError {
+    #[error("Message pool is full: {0}")]
+    Full(String),
+    #[error("Message pool quota for label '{0}' is exceeded")]
+    LabelQuotaExceeded(String),
+    #[error("Message is expired: {0}")]
+    Expired(String),
+    #[error("MessagePoolError: {0}")]
+    Internal(#[from] anyhow::Error),
+}
+
+/// Message pool counters since the node started.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct MessagePoolStats {
+    pub(crate) message_count: usize,
+    pub(crate) total_bytes: usize,
+    pub(crate) evicted_messages: u64,
+    pub(crate) expired_messages: u64,
+    pub(crate) rejected_messages: u64,
+}
+
+/// Why a message left the pool without being included in a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DroppedMessage {
+    Expired,
+    Evicted,
+}
+
+struct PendingMessage {
+    message: EphemeraMessage,
+    /// Encoded size of the message
+    size: usize,
+}
 
 pub(crate) struct MessagePool {
-    pending_messages: HashMap<Hash, EphemeraMessage>,
+    config: MempoolConfiguration,
+    pending_messages: HashMap<Hash, PendingMessage>,
+    /// Pending messages ordered by their timestamp, oldest first
+    by_age: BTreeSet<(u64, Hash)>,
+    label_counts: HashMap<String, usize>,
+    total_bytes: usize,
+    evicted_messages: u64,
+    expired_messages: u64,
+    rejected_messages: u64,
+    /// Recently expired and evicted messages
+    dropped: LruCache<Hash, DroppedMessage>,
 }
 
 impl MessagePool {
-    pub(super) fn new() -> Self {
+    pub(super) fn new(config: MempoolConfiguration) -> Self {
         Self {
+            config,
             pending_messages: HashMap::default(),
+            by_age: BTreeSet::default(),
+            label_counts: HashMap::default(),
+            total_bytes: 0,
+            evicted_messages: 0,
+            expired_messages: 0,
+            rejected_messages: 0,
+            dropped: LruCache::new(NonZeroUsize::new(DROPPED_MESSAGES_CACHE_SIZE).unwrap()),
         }
     }
 
@@ -27,12 +92,46 @@ impl MessagePool {
         self.pending_messages.contains_key(hash)
     }
 
-    pub(super) fn add_message(&mut self, msg: EphemeraMessage) -> anyhow::Result<()> {
+    /// Returns why the message was dropped, if it was dropped recently.
+    pub(crate) fn dropped(&self, hash: &Hash) -> Option<DroppedMessage> {
+        self.dropped.peek(hash).copied()
+    }
+
+    pub(super) fn add_message(&mut self, msg: EphemeraMessage) -> Result<(), MessagePoolError> {
         trace!("Adding message to pool: {:?}", msg);
 
         let msg_hash = msg.hash_with_default_hasher()?;
+        let size = msg.encoded_size()?;
+
+        let now = EphemeraTime::now();
+        self.remove_expired(now);
+
+        if self.is_expired(&msg, now) {
+            self.rejected_messages += 1;
+            self.dropped.put(msg_hash, DroppedMessage::Expired);
+            return Err(MessagePoolError::Expired(msg_hash.to_string()));
+        }
 
-        self.pending_messages.insert(msg_hash, msg);
+        let evicted = match self.make_room(&msg, size) {
+            Ok(evicted) => evicted,
+            Err(err) => {
+                self.rejected_messages += 1;
+                return Err(err);
+            }
+        };
+        for hash in evicted {
+            debug!("Evicting message from pool: {hash}");
+            self.remove(&hash);
+            self.evicted_messages += 1;
+            self.dropped.put(hash, DroppedMessage::Evicted);
+        }
+        self.dropped.pop(&msg_hash);
+
+        self.by_age.insert((msg.timestamp, msg_hash));
+        *self.label_counts.entry(msg.label.clone()).or_default() += 1;
+        self.total_bytes += size;
+        self.pending_messages
+            .insert(msg_hash, PendingMessage { message: msg, size });
 
         trace!("Message pool size: {:?}", self.pending_messages.len());
         Ok(())
@@ -45,8 +144,9 @@ impl MessagePool {
         );
         for msg in messages {
             let hash = msg.hash_with_default_hasher()?;
-            if self.pending_messages.remove(&hash).is_none() {
-                warn!("Message not found in pool: {:?}", msg);
+            if self.remove(&hash).is_none() {
+                //Message can be also evicted or expired meanwhile
+                debug!("Message not found in pool: {:?}", msg);
             }
         }
         trace!(
@@ -56,19 +156,143 @@ impl MessagePool {
         Ok(())
     }
 
-    /// Returns a `Vec` of all `EphemeraMessage`s in the message pool.
+    /// Returns a `Vec` of all `EphemeraMessage`s in the message pool, oldest first.
     /// The message pool is not cleared.
     pub(super) fn get_messages(&self) -> Vec<EphemeraMessage> {
-        self.pending_messages.values().cloned().collect()
+        self.by_age
+            .iter()
+            .map(|(_, hash)| self.pending_messages[hash].message.clone())
+            .collect()
+    }
+
+    /// Removes messages whose TTL has passed.
+    pub(super) fn remove_expired(&mut self, now: u64) {
+        let expired = self
+            .by_age
+            .iter()
+            .take_while(|(timestamp, _)| self.is_expired_at(*timestamp, now))
+            .map(|(_, hash)| *hash)
+            .collect::<Vec<_>>();
+
+        if !expired.is_empty() {
+            debug!("Removing {} expired messages from pool", expired.len());
+        }
+        for hash in expired {
+            self.remove(&hash);
+            self.expired_messages += 1;
+            self.dropped.put(hash, DroppedMessage::Expired);
+        }
+    }
+
+    pub(crate) fn stats(&self) -> MessagePoolStats {
+        MessagePoolStats {
+            message_count: self.pending_messages.len(),
+            total_bytes: self.total_bytes,
+            evicted_messages: self.evicted_messages,
+            expired_messages: self.expired_messages,
+            rejected_messages: self.rejected_messages,
+        }
+    }
+
+    pub(crate) fn config(&self) -> &MempoolConfiguration {
+        &self.config
+    }
+
+    fn is_expired(&self, msg: &EphemeraMessage, now: u64) -> bool {
+        self.is_expired_at(msg.timestamp, now)
+    }
+
+    fn is_expired_at(&self, timestamp: u64, now: u64) -> bool {
+        if self.config.message_ttl_sec == 0 {
+            return false;
+        }
+        let ttl_millis = self.config.message_ttl_sec.saturating_mul(1000);
+        timestamp.saturating_add(ttl_millis) < now
+    }
+
+    /// Returns messages which need to be evicted so that the new message fits into the pool.
+    ///
+    /// Nothing is evicted if the new message can't be added anyway.
+    fn make_room(&self, msg: &EphemeraMessage, size: usize) -> Result<Vec<Hash>, MessagePoolError> {
+        if size > self.config.max_bytes {
+            return Err(MessagePoolError::Full(format!(
+                "message size {size} exceeds pool size limit {}",
+                self.config.max_bytes
+            )));
+        }
+
+        let evict_oldest = self.config.eviction_policy == EvictionPolicy::OldestFirst;
+        let mut evicted = vec![];
+        let mut count = self.pending_messages.len();
+        let mut bytes = self.total_bytes;
+
+        if let Some(quota) = self.config.max_messages_per_label {
+            let label_count = self
+                .label_counts
+                .get(&msg.label)
+                .copied()
+                .unwrap_or_default();
+            if label_count >= quota {
+                let oldest = self
+                    .by_age
+                    .iter()
+                    .find(|(_, hash)| self.pending_messages[hash].message.label == msg.label);
+                match oldest {
+                    Some((timestamp, hash)) if evict_oldest && *timestamp <= msg.timestamp => {
+                        count -= 1;
+                        bytes -= self.pending_messages[hash].size;
+                        evicted.push(*hash);
+                    }
+                    _ => return Err(MessagePoolError::LabelQuotaExceeded(msg.label.clone())),
+                }
+            }
+        }
+
+        let label_evicted = evicted.first().copied();
+        let mut oldest = self
+            .by_age
+            .iter()
+            .filter(move |(_, hash)| Some(*hash) != label_evicted);
+        while count + 1 > self.config.max_messages || bytes + size > self.config.max_bytes {
+            match oldest.next() {
+                Some((timestamp, hash)) if evict_oldest && *timestamp <= msg.timestamp => {
+                    count -= 1;
+                    bytes -= self.pending_messages[hash].size;
+                    evicted.push(*hash);
+                }
+                _ => {
+                    return Err(MessagePoolError::Full(format!(
+                        "{count} messages, {bytes} bytes pending"
+                    )))
+                }
+            }
+        }
+        Ok(evicted)
+    }
+
+    fn remove(&mut self, hash: &Hash) -> Option<EphemeraMessage> {
+        let pending = self.pending_messages.remove(hash)?;
+        self.by_age.remove(&(pending.message.timestamp, *hash));
+        self.total_bytes -= pending.size;
+        match self.label_counts.get_mut(&pending.message.label) {
+            Some(count) if *count > 1 => *count -= 1,
+            Some(_) => {
+                self.label_counts.remove(&pending.message.label);
+            }
+            None => warn!("Label count not found: {}", pending.message.label),
+        }
+        Some(pending.message)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::block::message_pool::MessagePool;
+    use crate::block::message_pool::{DroppedMessage, MessagePool, MessagePoolError};
     use crate::block::types::message::EphemeraMessage;
+    use crate::config::{EvictionPolicy, MempoolConfiguration};
     use crate::crypto::{EphemeraKeypair, Keypair};
     use crate::ephemera_api::RawApiEphemeraMessage;
+    use crate::utilities::time::EphemeraTime;
 
     #[test]
     fn test_add_remove() {
@@ -78,10 +302,131 @@ mod test {
         let signed_message = message.sign(&keypair).expect("Failed to sign message");
         let signed_message: EphemeraMessage = signed_message.into();
 
-        let mut pool = MessagePool::new();
+        let mut pool = MessagePool::new(MempoolConfiguration::default());
         pool.add_message(signed_message.clone()).unwrap();
         pool.remove_messages(&[signed_message]).unwrap();
 
         assert_eq!(pool.get_messages().len(), 0);
+        assert_eq!(pool.stats().total_bytes, 0);
+    }
+
+    #[test]
+    fn test_reject_new_when_full() {
+        let config = MempoolConfiguration {
+            max_messages: 2,
+            ..Default::default()
+        };
+        let mut pool = MessagePool::new(config);
+
+        pool.add_message(message("test", 1)).unwrap();
+        pool.add_message(message("test", 2)).unwrap();
+        assert!(matches!(
+            pool.add_message(message("test", 3)),
+            Err(MessagePoolError::Full(_))
+        ));
+
+        let stats = pool.stats();
+        assert_eq!(stats.message_count, 2);
+        assert_eq!(stats.rejected_messages, 1);
+    }
+
+    #[test]
+    fn test_evict_oldest_when_full() {
+        let config = MempoolConfiguration {
+            max_messages: 2,
+            eviction_policy: EvictionPolicy::OldestFirst,
+            ..Default::default()
+        };
+        let mut pool = MessagePool::new(config);
+
+        let now = EphemeraTime::now();
+        let oldest = message_at("test", now + 1);
+        pool.add_message(oldest.clone()).unwrap();
+        pool.add_message(message_at("test", now + 2)).unwrap();
+        pool.add_message(message_at("test", now + 3)).unwrap();
+
+        let hash = oldest.hash_with_default_hasher().unwrap();
+        assert!(!pool.contains(&hash));
+        assert_eq!(pool.dropped(&hash), Some(DroppedMessage::Evicted));
+        assert_eq!(pool.stats().message_count, 2);
+        assert_eq!(pool.stats().evicted_messages, 1);
+
+        //Older than everything in the pool
+        assert!(matches!(
+            pool.add_message(message_at("test", now)),
+            Err(MessagePoolError::Full(_))
+        ));
+    }
+
+    #[test]
+    fn test_max_bytes() {
+        let first = message("test", 1);
+        let config = MempoolConfiguration {
+            max_bytes: first.encoded_size().unwrap() * 3 / 2,
+            ..Default::default()
+        };
+        let mut pool = MessagePool::new(config);
+
+        pool.add_message(first).unwrap();
+        assert!(matches!(
+            pool.add_message(message("test", 2)),
+            Err(MessagePoolError::Full(_))
+        ));
+    }
+
+    #[test]
+    fn test_label_quota() {
+        let config = MempoolConfiguration {
+            max_messages_per_label: Some(1),
+            ..Default::default()
+        };
+        let mut pool = MessagePool::new(config);
+
+        pool.add_message(message("first", 1)).unwrap();
+        pool.add_message(message("second", 1)).unwrap();
+        assert!(matches!(
+            pool.add_message(message("first", 2)),
+            Err(MessagePoolError::LabelQuotaExceeded(_))
+        ));
+    }
+
+    #[test]
+    fn test_expired_messages() {
+        let config = MempoolConfiguration {
+            message_ttl_sec: 1,
+            ..Default::default()
+        };
+        let mut pool = MessagePool::new(config);
+
+        let now = EphemeraTime::now();
+        assert!(matches!(
+            pool.add_message(message_at("test", now - 2000)),
+            Err(MessagePoolError::Expired(_))
+        ));
+
+        let message = message_at("test", now);
+        pool.add_message(message.clone()).unwrap();
+        pool.remove_expired(now + 2000);
+
+        let hash = message.hash_with_default_hasher().unwrap();
+        assert_eq!(pool.dropped(&hash), Some(DroppedMessage::Expired));
+        let stats = pool.stats();
+        assert_eq!(stats.message_count, 0);
+        assert_eq!(stats.expired_messages, 1);
+    }
+
+    fn message(label: &str, seq: u8) -> EphemeraMessage {
+        message_at(label, EphemeraTime::now() + u64::from(seq))
+    }
+
+    fn message_at(label: &str, timestamp: u64) -> EphemeraMessage {
+        let keypair = Keypair::generate(None);
+        let mut message: EphemeraMessage =
+            RawApiEphemeraMessage::new(label.to_string(), vec![1, 2, 3])
+                .sign(&keypair)
+                .expect("Failed to sign message")
+                .into();
+        message.timestamp = timestamp;
+        message
     }
 }
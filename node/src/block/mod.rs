@@ -8,6 +8,8 @@
 //!
 //! When application shuts down, pending messages are lost.
 //!
+//! Only the leader of the current height proposes blocks, see [leader] for the rotation schedule.
+//!
 //! When a block gets accepted by reliable broadcast then Block Manager will remove all messages included in the block from the
 //! pending messages queue.
 //!
@@ -20,7 +22,9 @@
 //! by dropping previous blocks which get Finalised/Committed after a new block has been created.
 
 pub(crate) mod builder;
+pub(crate) mod leader;
 pub(crate) mod manager;
 pub(crate) mod message_pool;
 pub(crate) mod producer;
+pub(crate) mod sync;
 pub(crate) mod types;
@@ -1,8 +1,9 @@
 use crate::block::{
-    types::block::{Block, RawBlock, RawBlockHeader},
+    types::block::{merkle_tree, Block, RawBlock, RawBlockHeader},
     types::message::EphemeraMessage,
 };
 use crate::peer::PeerId;
+use crate::utilities::hash::Hash;
 use log::trace;
 
 pub(crate) struct BlockProducer {
@@ -17,21 +18,36 @@ impl BlockProducer {
     pub(super) fn create_block(
         &mut self,
         height: u64,
+        view: u64,
+        previous_hash: Hash,
+        app_hash: Hash,
         pending_messages: Vec<EphemeraMessage>,
     ) -> anyhow::Result<Block> {
         trace!("Pending messages for new block: {:?}", pending_messages);
-        let block = self.new_block(height, pending_messages)?;
+        let block = self.new_block(height, view, previous_hash, app_hash, pending_messages)?;
         Ok(block)
     }
 
-    fn new_block(&self, height: u64, mut messages: Vec<EphemeraMessage>) -> anyhow::Result<Block> {
-        //Ordering is fundamental for block hash. Simple sort is fine for now.
-        messages.sort();
-
-        let raw_header = RawBlockHeader::new(self.peer_id, height);
+    fn new_block(
+        &self,
+        height: u64,
+        view: u64,
+        previous_hash: Hash,
+        app_hash: Hash,
+        messages: Vec<EphemeraMessage>,
+    ) -> anyhow::Result<Block> {
+        //Messages are included in the given order, it's part of the block hash.
+        let merkle_root = merkle_tree(&messages)?.root_hash();
+        let raw_header = RawBlockHeader::new(
+            self.peer_id,
+            height,
+            view,
+            previous_hash,
+            merkle_root,
+            app_hash,
+        );
         let raw_block = RawBlock::new(raw_header, messages);
 
-        //Better idea is probably combine header hash with Merkle tree root hash
         let block_hash = raw_block.hash_with_default_hasher()?;
 
         let block = Block::new(raw_block, block_hash);
@@ -43,7 +59,6 @@ impl BlockProducer {
 mod test {
     use crate::crypto::{EphemeraKeypair, Keypair};
     use crate::ephemera_api::RawApiEphemeraMessage;
-    use std::cmp::Ordering;
 
     use super::*;
 
@@ -67,26 +82,24 @@ mod test {
 
         let messages = vec![signed_message1.clone(), signed_message2.clone()];
 
-        let block = block_producer.create_block(1, messages).unwrap();
+        let block = block_producer
+            .create_block(1, 0, Hash::new([0; 32]), Hash::new([0; 32]), messages)
+            .unwrap();
 
         assert_eq!(block.header.height, 1);
         assert_eq!(block.header.creator, peer_id);
         assert_eq!(block.messages.len(), 2);
 
-        //Nondeterministic because of timestamp
-        match signed_message1.cmp(&signed_message2) {
-            Ordering::Less => {
-                assert_eq!(block.messages[0], signed_message1);
-                assert_eq!(block.messages[1], signed_message2);
-            }
-            Ordering::Greater => {
-                assert_eq!(block.messages[0], signed_message2);
-                assert_eq!(block.messages[1], signed_message1);
-            }
-
-            Ordering::Equal => {
-                panic!("Messages are equal");
-            }
-        }
+        //Messages keep the order they were given in
+        assert_eq!(block.messages[0], signed_message1);
+        assert_eq!(block.messages[1], signed_message2);
+
+        let messages = vec![signed_message2.clone(), signed_message1.clone()];
+        let reversed = block_producer
+            .create_block(1, 0, Hash::new([0; 32]), Hash::new([0; 32]), messages)
+            .unwrap();
+        assert_eq!(reversed.messages[0], signed_message2);
+        assert_eq!(reversed.messages[1], signed_message1);
+        assert_ne!(block.get_hash(), reversed.get_hash());
     }
 }
@@ -0,0 +1,669 @@
+//! # Block synchronization
+//!
+//! A node which was offline or joined the group late is missing blocks which were committed meanwhile.
+//! Block synchronization asks peers for blocks by height range and stores them locally after verification.
+//!
+//! Each synced block comes together with its quorum certificate and the broadcast group which committed it.
+//! The group is only a claim of the peer, it needs to match a group we know ourselves: one the membership provider
+//! reported since the node started, or the group stored with our last block, which survives restarts.
+//! Member weights are taken from our own record of that group, never from the peer. If peers keep claiming groups
+//! we don't know, synchronization fails and resumes only after the group changes.
+//! Before a block is accepted, its hash is recomputed and its quorum certificate needs to be signed in the phase
+//! which delivered the block by members of that group, with enough weight to reach the deliver threshold.
+//! Blocks committed before phase signatures have only header signatures, they can't be synced.
+//!
+//! A peer which pruned requested blocks answers with the height of its last pruned block instead. Pruned blocks have
+//! no messages, so they can't be synced, the node asks another peer.
+//!
+//! Block production stays paused until the node is caught up with its peers.
+
+use std::collections::HashSet;
+use std::pin::Pin;
+use std::task;
+use std::task::Poll;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use futures::Stream;
+use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use tokio::time::{self, Instant, Interval};
+
+use crate::block::types::block::Block;
+use crate::block::types::quorum_certificate::QuorumCertificate;
+use crate::broadcast::group::{BroadcastGroup, GroupSnapshot};
+use crate::peer::PeerId;
+use crate::storage::{self, EphemeraDatabase};
+
+/// Maximum number of blocks requested from a peer at once.
+pub(crate) const MAX_BLOCKS_PER_REQUEST: u64 = 100;
+
+/// How long we wait for a response before asking again.
+const SYNC_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often we check if new sync request needs to be sent.
+const SYNC_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How many responses with blocks of a group we don't know we accept before synchronization fails.
+const MAX_UNTRUSTED_RESPONSES: u32 = 3;
+
+/// Request blocks in height range `[from_height, to_height]`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct BlockSyncRequest {
+    pub(crate) from_height: u64,
+    pub(crate) to_height: u64,
+}
+
+impl BlockSyncRequest {
+    pub(crate) fn new(from_height: u64, to_height: u64) -> Self {
+        Self {
+            from_height,
+            to_height,
+        }
+    }
+}
+
+/// Committed block together with the proof of its commitment.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct SyncBlock {
+    pub(crate) block: Block,
+    pub(crate) members: Vec<PeerId>,
+    /// Signatures of the phase which delivered the block
+    pub(crate) quorum_certificate: QuorumCertificate,
+}
+
+impl SyncBlock {
+    pub(crate) fn new(
+        block: Block,
+        members: Vec<PeerId>,
+        quorum_certificate: QuorumCertificate,
+    ) -> Self {
+        Self {
+            block,
+            members,
+            quorum_certificate,
+        }
+    }
+
+    /// Finds the group which committed the block among groups we know ourselves: groups the membership provider
+    /// reported since the node started, or the group which committed `parent`, our last stored block.
+    ///
+    /// Returns `None` if we don't know the group the peer claims.
+    pub(crate) fn trusted_group(
+        &self,
+        known_groups: &BroadcastGroup,
+        parent: &Block,
+        storage: &dyn EphemeraDatabase,
+    ) -> storage::Result<Option<GroupSnapshot>> {
+        let claimed = self.members.iter().copied().collect::<HashSet<_>>();
+        if let Some(group) = known_groups.find_snapshot(&claimed) {
+            return Ok(Some(group.clone()));
+        }
+
+        let parent_hash = parent.get_hash().to_string();
+        let Some(members) = storage.get_block_broadcast_group(&parent_hash)? else {
+            return Ok(None);
+        };
+        let members = members.into_iter().collect::<HashSet<_>>();
+        if members.is_empty() || members != claimed {
+            return Ok(None);
+        }
+        let weights = storage
+            .get_block_broadcast_weights(&parent_hash)?
+            .unwrap_or_default();
+        Ok(Some(GroupSnapshot { members, weights }))
+    }
+
+    /// Verifies that block hash and Merkle root are correct and that enough members of its broadcast group
+    /// signed the phase which delivered it.
+    ///
+    /// `group` is the group we recorded ourselves, the members sent by the peer need to be the same.
+    pub(crate) fn verify(&self, group: &GroupSnapshot) -> anyhow::Result<()> {
+        let hash = self.block.hash_with_default_hasher()?;
+        if hash != self.block.get_hash() {
+            return Err(anyhow!(
+                "Block hash is invalid: {} != {hash}",
+                self.block.get_hash()
+            ));
+        }
+        if !self.block.verify_merkle_root()? {
+            return Err(anyhow!(
+                "Block {hash} Merkle root doesn't match its messages"
+            ));
+        }
+
+        if self.members.iter().copied().collect::<HashSet<_>>() != group.members {
+            return Err(anyhow!(
+                "Block {hash} broadcast group doesn't match the local group"
+            ));
+        }
+        let members = group.members.iter().copied().collect::<Vec<_>>();
+        self.quorum_certificate
+            .verify(&self.block.header, &members, &group.weights)
+    }
+}
+
+/// Blocks starting from requested height and the height of the last block the peer has.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct BlockSyncResponse {
+    pub(crate) blocks: Vec<SyncBlock>,
+    pub(crate) last_height: u64,
+    /// Height of the last block the peer pruned, set if requested blocks are pruned
+    #[serde(default)]
+    pub(crate) pruned_height: Option<u64>,
+}
+
+impl BlockSyncResponse {
+    pub(crate) fn new(blocks: Vec<SyncBlock>, last_height: u64) -> Self {
+        Self {
+            blocks,
+            last_height,
+            pruned_height: None,
+        }
+    }
+
+    /// Requested blocks are pruned up to given height, the peer can't serve them.
+    pub(crate) fn pruned(last_height: u64, pruned_height: u64) -> Self {
+        Self {
+            blocks: vec![],
+            last_height,
+            pruned_height: Some(pruned_height),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SyncStatus {
+    /// Node is catching up with peers.
+    Syncing {
+        /// Last block height known by the peer we synced from.
+        target_height: Option<u64>,
+    },
+    /// Node has all blocks its peers have.
+    Synced,
+    /// No group we know committed the block at `height`, synchronization waits for the group to change.
+    Failed { height: u64 },
+}
+
+/// Keeps track of synchronization progress and produces requests for missing blocks.
+pub(crate) struct BlockSync {
+    /// Current synchronization status
+    pub(crate) status: SyncStatus,
+    /// Height of the next block we need
+    pub(crate) next_height: u64,
+    /// When the last unanswered request was sent
+    pending_request: Option<Instant>,
+    /// Interval to check if new request needs to be sent
+    poll_interval: Interval,
+    /// Responses since the last stored block which had blocks of a group we don't know
+    untrusted_responses: u32,
+}
+
+impl BlockSync {
+    pub(crate) fn new(last_height: u64) -> Self {
+        Self {
+            status: SyncStatus::Syncing {
+                target_height: None,
+            },
+            next_height: last_height + 1,
+            pending_request: None,
+            poll_interval: time::interval(SYNC_POLL_INTERVAL),
+            untrusted_responses: 0,
+        }
+    }
+
+    pub(crate) fn is_synced(&self) -> bool {
+        self.status == SyncStatus::Synced
+    }
+
+    /// Starts synchronization if it isn't already running or waiting for the group to change.
+    pub(crate) fn start(&mut self, target_height: Option<u64>) {
+        if !self.is_synced() {
+            return;
+        }
+        info!(
+            "Starting block synchronization from height {}",
+            self.next_height
+        );
+        self.status = SyncStatus::Syncing { target_height };
+        self.pending_request = None;
+    }
+
+    /// There is no one to sync from, we consider ourselves up-to-date.
+    pub(crate) fn mark_synced(&mut self) {
+        if !self.is_synced() {
+            info!(
+                "Block synchronization finished at height {}",
+                self.next_height - 1
+            );
+        }
+        self.status = SyncStatus::Synced;
+        self.pending_request = None;
+    }
+
+    /// Block with given height was stored locally.
+    ///
+    /// Blocks committed via broadcast while syncing can be ahead of `next_height`.
+    /// We advance only when there are no gaps. If the block synchronization failed at was committed via broadcast,
+    /// we continue syncing.
+    pub(crate) fn on_block_stored(&mut self, height: u64) {
+        if height == self.next_height {
+            self.next_height += 1;
+            self.untrusted_responses = 0;
+            if let SyncStatus::Failed { .. } = self.status {
+                self.status = SyncStatus::Syncing {
+                    target_height: None,
+                };
+            }
+        }
+    }
+
+    /// Response from a peer was processed.
+    ///
+    /// # Returns
+    /// `true` if the node is caught up with the peer.
+    pub(crate) fn on_response(&mut self, peer_last_height: u64) -> bool {
+        self.pending_request = None;
+        if self.next_height > peer_last_height {
+            self.mark_synced();
+            true
+        } else {
+            debug!(
+                "Synced up to height {}, peer last height {peer_last_height}",
+                self.next_height - 1
+            );
+            self.status = SyncStatus::Syncing {
+                target_height: Some(peer_last_height),
+            };
+            false
+        }
+    }
+
+    /// Request failed, try again with the next poll.
+    pub(crate) fn on_request_failed(&mut self) {
+        self.pending_request = None;
+    }
+
+    /// Peer sent the next block committed by a group we don't know. A single peer could lie about the group, so
+    /// we ask again. If it keeps happening, we don't know the group which committed the block, and asking again
+    /// won't help until the group changes.
+    pub(crate) fn on_untrusted_group(&mut self) {
+        self.untrusted_responses += 1;
+        self.pending_request = None;
+        if self.untrusted_responses < MAX_UNTRUSTED_RESPONSES {
+            return;
+        }
+        error!(
+            "Block {} was committed by a broadcast group we don't know, block synchronization failed",
+            self.next_height
+        );
+        self.status = SyncStatus::Failed {
+            height: self.next_height,
+        };
+    }
+
+    /// Group changed, the new group may prove the block which failed synchronization.
+    pub(crate) fn on_group_updated(&mut self) {
+        if let SyncStatus::Failed { height } = self.status {
+            info!("Group changed, retrying block synchronization from height {height}");
+            self.status = SyncStatus::Syncing {
+                target_height: None,
+            };
+            self.untrusted_responses = 0;
+        }
+    }
+
+    /// Peer pruned the blocks we need, ask another peer with the next poll.
+    ///
+    /// # Returns
+    /// `true` if the peer pruned the next block we need.
+    pub(crate) fn on_pruned_response(&mut self, peer_pruned_height: u64) -> bool {
+        if peer_pruned_height < self.next_height {
+            return false;
+        }
+        warn!(
+            "Peer pruned blocks up to height {peer_pruned_height}, block {} can't be synced from it",
+            self.next_height
+        );
+        self.on_request_failed();
+        true
+    }
+
+    fn next_request(&self) -> BlockSyncRequest {
+        BlockSyncRequest::new(
+            self.next_height,
+            self.next_height + MAX_BLOCKS_PER_REQUEST - 1,
+        )
+    }
+}
+
+//Produces block requests while node is syncing.
+impl Stream for BlockSync {
+    type Item = BlockSyncRequest;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut task::Context) -> Poll<Option<Self::Item>> {
+        if !matches!(self.status, SyncStatus::Syncing { .. }) {
+            return Poll::Pending;
+        }
+
+        if self.poll_interval.poll_tick(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        if let Some(sent) = self.pending_request {
+            if sent.elapsed() < SYNC_REQUEST_TIMEOUT {
+                return Poll::Pending;
+            }
+            debug!("Block sync request timed out");
+        }
+
+        let request = self.next_request();
+        trace!("Requesting blocks: {request:?}");
+        self.pending_request = Some(Instant::now());
+        Poll::Ready(Some(request))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use crate::broadcast::signing::{Phase, RawPhaseVote};
+    use crate::crypto::{EphemeraKeypair, Keypair};
+    use crate::peer::ToPeerId;
+    use crate::utilities::test_utils::{block, keypairs};
+
+    use super::*;
+
+    #[test]
+    fn test_verify_synced_block_ok() {
+        let keypairs = keypairs(4);
+        let block = block(&keypairs[0], 1);
+        let group = group(&keypairs);
+
+        let sync_block = SyncBlock::new(
+            block.clone(),
+            members(&keypairs),
+            votes(&block, &keypairs[..3], &group),
+        );
+        assert!(sync_block.verify(&group).is_ok());
+    }
+
+    #[test]
+    fn test_verify_synced_block_phase() {
+        let keypairs = keypairs(4);
+        let block = block(&keypairs[0], 1);
+        let group = group(&keypairs);
+        let votes = votes(&block, &keypairs, &group);
+
+        //Vote signatures claimed to be echoes
+        let echoes = QuorumCertificate {
+            phase: Some(Phase::Echo),
+            ..votes.clone()
+        };
+        let sync_block = SyncBlock::new(block.clone(), members(&keypairs), echoes);
+        assert!(sync_block.verify(&group).is_err());
+
+        //Header signatures don't prove delivery, a peer can't skip the phase check
+        let certificates = keypairs
+            .iter()
+            .map(|kp| block.sign(kp).unwrap())
+            .collect::<Vec<_>>();
+        let header_only = QuorumCertificate {
+            phase: None,
+            signers: votes.signers,
+            signatures: certificates
+                .into_iter()
+                .map(|certificate| certificate.signature)
+                .collect(),
+        };
+        let sync_block = SyncBlock::new(block, members(&keypairs), header_only);
+        assert!(sync_block.verify(&group).is_err());
+    }
+
+    #[test]
+    fn test_verify_synced_block_not_enough_signatures() {
+        let keypairs = keypairs(4);
+        let block = block(&keypairs[0], 1);
+        let group = group(&keypairs);
+
+        let sync_block = SyncBlock::new(
+            block.clone(),
+            members(&keypairs),
+            votes(&block, &keypairs[..2], &group),
+        );
+        assert!(sync_block.verify(&group).is_err());
+    }
+
+    #[test]
+    fn test_verify_synced_block_weighted() {
+        let keypairs = keypairs(4);
+        let block = block(&keypairs[0], 1);
+        let members = members(&keypairs);
+        let group = GroupSnapshot {
+            members: members.iter().copied().collect(),
+            weights: HashMap::from([(members[0], 4)]),
+        };
+
+        //5 of 7 weight
+        let sync_block = SyncBlock::new(
+            block.clone(),
+            members.clone(),
+            votes(&block, &keypairs[..2], &group),
+        );
+        assert!(sync_block.verify(&group).is_ok());
+
+        //3 of 4 members, but 3 of 7 weight
+        let sync_block = SyncBlock::new(
+            block.clone(),
+            members.clone(),
+            votes(&block, &keypairs[1..], &group),
+        );
+        assert!(sync_block.verify(&group).is_err());
+
+        //Weights claimed by the peer are not known to us, the local group has equal weights
+        let sync_block = SyncBlock::new(
+            block.clone(),
+            members,
+            votes(&block, &keypairs[..2], &group),
+        );
+        assert!(sync_block.verify(&group.members.clone().into()).is_err());
+    }
+
+    #[test]
+    fn test_verify_synced_block_signer_not_member() {
+        let keypairs = keypairs(4);
+        let block = block(&keypairs[0], 1);
+        let group = group(&keypairs);
+
+        //Signatures of the outsider are left out of the certificate
+        let mut signers = keypairs[..2].to_vec();
+        signers.push(Arc::new(Keypair::generate(None)));
+        let sync_block = SyncBlock::new(
+            block.clone(),
+            members(&keypairs),
+            votes(&block, &signers, &group),
+        );
+        assert!(sync_block.verify(&group).is_err());
+    }
+
+    #[test]
+    fn test_verify_synced_block_claimed_group() {
+        let keypairs = keypairs(4);
+        let block = block(&keypairs[0], 1);
+
+        //A peer claims that it is the whole group and signs the block alone
+        let attacker = vec![Arc::new(Keypair::generate(None))];
+        let sync_block = SyncBlock::new(
+            block.clone(),
+            members(&attacker),
+            votes(&block, &attacker, &group(&attacker)),
+        );
+        assert!(sync_block.verify(&group(&attacker)).is_ok());
+        assert!(sync_block.verify(&group(&keypairs)).is_err());
+    }
+
+    #[test]
+    fn test_verify_synced_block_invalid_hash() {
+        let keypairs = keypairs(1);
+        let mut block = block(&keypairs[0], 1);
+        let group = group(&keypairs);
+        let quorum_certificate = votes(&block, &keypairs, &group);
+        block.header.height += 1;
+
+        let sync_block = SyncBlock::new(block, members(&keypairs), quorum_certificate);
+        assert!(sync_block.verify(&group).is_err());
+    }
+
+    #[cfg(feature = "sqlite_storage")]
+    #[test]
+    fn test_trusted_group_after_restart() {
+        use crate::utilities::test_utils::sqlite_storage;
+
+        let keypairs = keypairs(4);
+        let old_group = group(&keypairs);
+        let new_group = group(&keypairs[..3]);
+
+        //Node stored block 1 committed by the old group and restarted, it knows only the new group
+        let (mut storage, _database) = sqlite_storage(None);
+        let parent = block(&keypairs[0], 1);
+        storage
+            .store_block(&parent, &[], &old_group, &QuorumCertificate::default())
+            .unwrap();
+        let mut known_groups = BroadcastGroup::new();
+        known_groups.add_snapshot(new_group.clone());
+
+        let block = block(&keypairs[0], 2);
+        let sync_block = SyncBlock::new(
+            block.clone(),
+            members(&keypairs),
+            votes(&block, &keypairs, &old_group),
+        );
+        let trusted = sync_block
+            .trusted_group(&known_groups, &parent, &storage)
+            .unwrap()
+            .unwrap();
+        assert_eq!(trusted.members, old_group.members);
+        assert!(sync_block.verify(&trusted).is_ok());
+
+        //Group the node knows from the membership provider
+        let sync_block = SyncBlock::new(
+            block.clone(),
+            members(&keypairs[..3]),
+            votes(&block, &keypairs[..3], &new_group),
+        );
+        let trusted = sync_block
+            .trusted_group(&known_groups, &parent, &storage)
+            .unwrap();
+        assert_eq!(trusted, Some(new_group));
+
+        //Group the node has never seen
+        let sync_block = SyncBlock::new(
+            block.clone(),
+            members(&keypairs[1..]),
+            votes(&block, &keypairs[1..], &group(&keypairs[1..])),
+        );
+        let trusted = sync_block
+            .trusted_group(&known_groups, &parent, &storage)
+            .unwrap();
+        assert!(trusted.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sync_fails_without_trusted_group() {
+        let mut sync = BlockSync::new(1);
+        sync.on_response(5);
+
+        for _ in 1..MAX_UNTRUSTED_RESPONSES {
+            sync.pending_request = Some(Instant::now());
+            sync.on_untrusted_group();
+            assert!(sync.pending_request.is_none());
+        }
+        assert!(matches!(sync.status, SyncStatus::Syncing { .. }));
+
+        sync.on_untrusted_group();
+        assert_eq!(sync.status, SyncStatus::Failed { height: 2 });
+        assert!(!sync.is_synced());
+
+        //No more requests until the group changes
+        sync.start(None);
+        assert_eq!(sync.status, SyncStatus::Failed { height: 2 });
+        sync.on_group_updated();
+        assert_eq!(
+            sync.status,
+            SyncStatus::Syncing {
+                target_height: None
+            }
+        );
+        assert_eq!(sync.untrusted_responses, 0);
+    }
+
+    #[tokio::test]
+    async fn test_sync_progress() {
+        let mut sync = BlockSync::new(0);
+        assert!(!sync.is_synced());
+
+        sync.on_block_stored(1);
+        sync.on_block_stored(2);
+        sync.on_block_stored(4);
+        assert_eq!(sync.next_height, 3);
+        assert!(!sync.on_response(5));
+        assert_eq!(
+            sync.status,
+            SyncStatus::Syncing {
+                target_height: Some(5)
+            }
+        );
+
+        sync.on_block_stored(3);
+        sync.on_block_stored(4);
+        sync.on_block_stored(5);
+        assert!(sync.on_response(5));
+        assert!(sync.is_synced());
+    }
+
+    #[tokio::test]
+    async fn test_sync_from_pruned_peer() {
+        let mut sync = BlockSync::new(2);
+        sync.pending_request = Some(Instant::now());
+
+        assert!(!sync.on_pruned_response(2));
+        assert!(sync.pending_request.is_some());
+
+        assert!(sync.on_pruned_response(3));
+        assert!(sync.pending_request.is_none());
+        assert!(!sync.is_synced());
+    }
+
+    #[test]
+    fn test_response_without_pruned_height() {
+        let response = BlockSyncResponse::pruned(10, 5);
+        let mut json = serde_json::to_value(&response).unwrap();
+        json.as_object_mut().unwrap().remove("pruned_height");
+
+        let response: BlockSyncResponse = serde_json::from_value(json).unwrap();
+        assert_eq!(response, BlockSyncResponse::new(vec![], 10));
+    }
+
+    fn members(keypairs: &[Arc<Keypair>]) -> Vec<PeerId> {
+        keypairs.iter().map(|kp| kp.peer_id()).collect()
+    }
+
+    fn group(keypairs: &[Arc<Keypair>]) -> GroupSnapshot {
+        members(keypairs).into_iter().collect::<HashSet<_>>().into()
+    }
+
+    fn votes(block: &Block, keypairs: &[Arc<Keypair>], group: &GroupSnapshot) -> QuorumCertificate {
+        let group_id = group.quorum().group_id;
+        let votes = keypairs
+            .iter()
+            .map(|kp| {
+                RawPhaseVote::new(block.get_hash(), Phase::Vote, group_id)
+                    .sign(kp)
+                    .unwrap()
+            })
+            .collect();
+        QuorumCertificate::for_phase(Phase::Vote, &votes, &group.members)
+    }
+}
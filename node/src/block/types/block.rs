@@ -22,6 +22,10 @@ pub(crate) struct BlockHeader {
     pub(crate) timestamp: u64,
     pub(crate) creator: PeerId,
     pub(crate) height: u64,
+    pub(crate) view: u64,
+    pub(crate) previous_hash: Hash,
+    pub(crate) merkle_root: Hash,
+    pub(crate) app_hash: Hash,
     pub(crate) hash: Hash,
 }
 
@@ -31,9 +35,25 @@ impl BlockHeader {
             timestamp: raw_header.timestamp,
             creator: raw_header.creator,
             height: raw_header.height,
+            view: raw_header.view,
+            previous_hash: raw_header.previous_hash,
+            merkle_root: raw_header.merkle_root,
+            app_hash: raw_header.app_hash,
             hash,
         }
     }
+
+    /// Checks that header hash matches its content.
+    pub(crate) fn verify_hash(&self) -> anyhow::Result<bool> {
+        let raw_header: RawBlockHeader = self.clone().into();
+        Ok(raw_header.hash_with_default_hasher()? == self.hash)
+    }
+
+    /// Checks that certificate signs this header.
+    pub(crate) fn verify(&self, certificate: &Certificate) -> anyhow::Result<bool> {
+        let raw_header: RawBlockHeader = self.clone().into();
+        certificate.verify(&raw_header)
+    }
 }
 
 impl Display for BlockHeader {
@@ -42,9 +62,13 @@ impl Display for BlockHeader {
         let time = self.timestamp;
         let creator = &self.creator;
         let height = self.height;
+        let view = self.view;
+        let previous_hash = &self.previous_hash;
+        let merkle_root = &self.merkle_root;
+        let app_hash = &self.app_hash;
         write!(
             f,
-            "hash: {hash}, timestamp: {time}, creator: {creator}, height: {height}",
+            "hash: {hash}, timestamp: {time}, creator: {creator}, height: {height}, view: {view}, previous_hash: {previous_hash}, merkle_root: {merkle_root}, app_hash: {app_hash}",
         )
     }
 }
@@ -76,14 +100,36 @@ pub(crate) struct RawBlockHeader {
     pub(crate) timestamp: u64,
     pub(crate) creator: PeerId,
     pub(crate) height: u64,
+    /// View in which the creator proposed the block. A leader proposes at most one block per height and view,
+    /// so two signed headers with the same creator, height and view are equivocation.
+    pub(crate) view: u64,
+    /// Hash of the block at previous height. It links blocks into a chain.
+    pub(crate) previous_hash: Hash,
+    /// Merkle root of block messages hashes. Because header hash is the block hash, block hash and
+    /// certificates cover the messages without including them.
+    pub(crate) merkle_root: Hash,
+    /// Application state hash after the previous block was delivered. Because nodes sign the header,
+    /// diverging application states prevent the block from being committed.
+    pub(crate) app_hash: Hash,
 }
 
 impl RawBlockHeader {
-    pub(crate) fn new(creator: PeerId, height: u64) -> Self {
+    pub(crate) fn new(
+        creator: PeerId,
+        height: u64,
+        view: u64,
+        previous_hash: Hash,
+        merkle_root: Hash,
+        app_hash: Hash,
+    ) -> Self {
         Self {
             timestamp: EphemeraTime::now(),
             creator,
             height,
+            view,
+            previous_hash,
+            merkle_root,
+            app_hash,
         }
     }
 
@@ -99,7 +145,14 @@ impl Display for RawBlockHeader {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let creator = &self.creator;
         let height = self.height;
-        write!(f, "creator: {creator}, height: {height}",)
+        let view = self.view;
+        let previous_hash = &self.previous_hash;
+        let merkle_root = &self.merkle_root;
+        let app_hash = &self.app_hash;
+        write!(
+            f,
+            "creator: {creator}, height: {height}, view: {view}, previous_hash: {previous_hash}, merkle_root: {merkle_root}, app_hash: {app_hash}",
+        )
     }
 }
 
@@ -109,6 +162,10 @@ impl From<BlockHeader> for RawBlockHeader {
             timestamp: block_header.timestamp,
             creator: block_header.creator,
             height: block_header.height,
+            view: block_header.view,
+            previous_hash: block_header.previous_hash,
+            merkle_root: block_header.merkle_root,
+            app_hash: block_header.app_hash,
         }
     }
 }
@@ -136,12 +193,28 @@ impl Block {
         self.header.height
     }
 
+    /// Hash which the next block refers to as its parent.
+    ///
+    /// Every node creates its own genesis block, so genesis hashes differ between nodes.
+    /// Therefore blocks at height 1 refer to the zero hash instead of the genesis block hash.
+    pub(crate) fn hash_as_parent(&self) -> Hash {
+        if self.header.height == 0 {
+            Hash::new([0; 32])
+        } else {
+            self.header.hash
+        }
+    }
+
     pub(crate) fn new_genesis_block(creator: PeerId) -> Self {
         let mut block = Self {
             header: BlockHeader {
                 timestamp: EphemeraTime::now(),
                 creator,
                 height: 0,
+                view: 0,
+                previous_hash: Hash::new([0; 32]),
+                merkle_root: MerkleTree::build_tree(&[]).root_hash(),
+                app_hash: Hash::new([0; 32]),
                 hash: Hash::new([0; 32]),
             },
             messages: Vec::new(),
@@ -154,15 +227,20 @@ impl Block {
         block
     }
 
+    /// Signs block header. Messages are covered by the header Merkle root.
     pub(crate) fn sign(&self, keypair: &Keypair) -> anyhow::Result<Certificate> {
-        let raw_block: RawBlock = self.clone().into();
-        let certificate = Certificate::prepare(keypair, &raw_block)?;
+        let raw_header: RawBlockHeader = self.header.clone().into();
+        let certificate = Certificate::prepare(keypair, &raw_header)?;
         Ok(certificate)
     }
 
     pub(crate) fn verify(&self, certificate: &Certificate) -> anyhow::Result<bool> {
-        let raw_block: RawBlock = self.clone().into();
-        certificate.verify(&raw_block)
+        self.header.verify(certificate)
+    }
+
+    /// Checks that header Merkle root matches block messages.
+    pub(crate) fn verify_merkle_root(&self) -> anyhow::Result<bool> {
+        Ok(self.merkle_tree()?.root_hash() == self.header.merkle_root)
     }
 
     pub(crate) fn hash_with_default_hasher(&self) -> anyhow::Result<Hash> {
@@ -207,11 +285,9 @@ impl RawBlock {
         Self { header, messages }
     }
 
+    /// Block hash is the hash of its header. Messages are committed to by the header Merkle root.
     pub(crate) fn hash_with_default_hasher(&self) -> anyhow::Result<Hash> {
-        let header_hash = self.header.hash_with_default_hasher()?;
-        let merkle_root = merkle_tree(&self.messages)?.root_hash();
-        let block_hash = Hasher::digest(&[header_hash.inner(), merkle_root.inner()].concat());
-        Ok(block_hash.into())
+        self.header.hash_with_default_hasher()
     }
 }
 
@@ -282,6 +358,7 @@ pub(crate) fn merkle_tree(messages: &[EphemeraMessage]) -> anyhow::Result<Merkle
 mod test {
     use crate::block::types::message::RawEphemeraMessage;
     use crate::crypto::EphemeraKeypair;
+    use crate::utilities::test_utils::raw_header;
 
     use super::*;
 
@@ -301,15 +378,81 @@ mod test {
             .collect::<anyhow::Result<Vec<Hash>>>()
             .unwrap();
 
-        let raw_block = RawBlock::new(RawBlockHeader::new(PeerId::random(), 0), messages);
+        let merkle_root = MerkleTree::build_tree(&message_hashes).root_hash();
+        let mut header = raw_header(PeerId::random(), 0);
+        header.merkle_root = merkle_root;
+        let raw_block = RawBlock::new(header, messages);
         let block_hash = raw_block.hash_with_default_hasher().unwrap();
 
         let header_hash = raw_block.header.hash_with_default_hasher().unwrap();
-        let merkle_root = MerkleTree::build_tree(&message_hashes).root_hash();
-        let expected_block_hash =
-            Hasher::digest(&[header_hash.inner(), merkle_root.inner()].concat());
+        assert_eq!(block_hash, header_hash);
+
+        let block = Block::new(raw_block, block_hash);
+        assert!(block.verify_merkle_root().unwrap());
+    }
+
+    #[test]
+    fn test_block_merkle_root_covers_messages() {
+        let messages = create_ephemera_messages(3);
+        let merkle_root = merkle_tree(&messages).unwrap().root_hash();
+        let mut header = raw_header(PeerId::random(), 1);
+        header.merkle_root = merkle_root;
+        let raw_block = RawBlock::new(header, messages);
+        let block_hash = raw_block.hash_with_default_hasher().unwrap();
+        let mut block = Block::new(raw_block, block_hash);
+
+        block.messages.pop();
+        assert!(!block.verify_merkle_root().unwrap());
+    }
+
+    #[test]
+    fn test_block_hash_covers_previous_hash() {
+        let mut raw_header = raw_header(PeerId::random(), 1);
+        raw_header.previous_hash = Hash::new([1; 32]);
+        let block_hash = RawBlock::new(raw_header.clone(), vec![])
+            .hash_with_default_hasher()
+            .unwrap();
+
+        let mut other_header = raw_header;
+        other_header.previous_hash = Hash::new([2; 32]);
+        let other_hash = RawBlock::new(other_header, vec![])
+            .hash_with_default_hasher()
+            .unwrap();
+
+        assert_ne!(block_hash, other_hash);
+    }
+
+    #[test]
+    fn test_block_hash_covers_app_hash() {
+        let mut raw_header = raw_header(PeerId::random(), 1);
+        raw_header.app_hash = Hash::new([1; 32]);
+        let block_hash = RawBlock::new(raw_header.clone(), vec![])
+            .hash_with_default_hasher()
+            .unwrap();
+
+        let mut other_header = raw_header;
+        other_header.app_hash = Hash::new([2; 32]);
+        let other_hash = RawBlock::new(other_header, vec![])
+            .hash_with_default_hasher()
+            .unwrap();
+
+        assert_ne!(block_hash, other_hash);
+    }
+
+    #[test]
+    fn test_block_hash_covers_view() {
+        let raw_header = raw_header(PeerId::random(), 1);
+        let block_hash = RawBlock::new(raw_header.clone(), vec![])
+            .hash_with_default_hasher()
+            .unwrap();
+
+        let mut other_header = raw_header;
+        other_header.view = 1;
+        let other_hash = RawBlock::new(other_header, vec![])
+            .hash_with_default_hasher()
+            .unwrap();
 
-        assert_eq!(block_hash, expected_block_hash.into());
+        assert_ne!(block_hash, other_hash);
     }
 
     fn create_ephemera_messages(n: usize) -> Vec<EphemeraMessage> {
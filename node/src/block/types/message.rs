@@ -39,6 +39,11 @@ impl EphemeraMessage {
         let hash = hasher.finish().into();
         Ok(hash)
     }
+
+    /// Size of the encoded message. Mempool and block size limits are based on it.
+    pub(crate) fn encoded_size(&self) -> anyhow::Result<usize> {
+        Ok(self.encode()?.len())
+    }
 }
 
 impl Encode for EphemeraMessage {
@@ -1,2 +1,3 @@
 pub(crate) mod block;
 pub(crate) mod message;
+pub(crate) mod quorum_certificate;
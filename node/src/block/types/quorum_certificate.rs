@@ -0,0 +1,358 @@
+//! # Quorum certificate
+//!
+//! Compact proof that a block was committed. Instead of a certificate(signature and public key) per signer,
+//! it has a bitmap of signers over the block broadcast group and their signatures.
+//!
+//! The group is ordered canonically, by peer id. Bit `i` is set if `i`-th member signed the block, bits are counted
+//! from the least significant bit of the first byte. Signatures follow the same order. Public keys are recovered
+//! from member peer ids, so the group is enough to verify the certificate.
+//!
+//! Signatures are of the broadcast phase which delivered the block, votes with Bracha and echoes with signed echo.
+//! They sign the block hash, the phase and the group id, see [`RawPhaseVote`]. Certificates of blocks committed
+//! before phase signatures don't have a phase and sign the block header. Every member signs the header of every
+//! proposal it sees, so such certificates don't prove that the block was delivered and don't verify.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::block::types::block::BlockHeader;
+use crate::broadcast::bracha::quorum::Quorum;
+use crate::broadcast::signing::{Phase, RawPhaseVote};
+use crate::crypto::PublicKey;
+use crate::peer::{PeerId, ToPeerId};
+use crate::utilities::crypto::{Certificate, Signature};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct QuorumCertificate {
+    /// Phase the signatures agree to, `None` if they sign the block header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) phase: Option<Phase>,
+    /// Bitmap of members who signed the block
+    pub(crate) signers: Vec<u8>,
+    /// Signatures of the signers in the group order
+    pub(crate) signatures: Vec<Signature>,
+}
+
+impl QuorumCertificate {
+    /// Builds certificate from individual block certificates. Certificates of non-members are ignored.
+    fn new(certificates: &HashSet<Certificate>, members: &HashSet<PeerId>) -> Self {
+        let members = Self::canonical_order(members.iter());
+        let mut signers = vec![0; members.len().div_ceil(8)];
+        let mut signatures = vec![];
+        for (i, member) in members.iter().enumerate() {
+            let signature = certificates
+                .iter()
+                .find(|certificate| certificate.public_key.peer_id() == *member)
+                .map(|certificate| certificate.signature.clone());
+            if let Some(signature) = signature {
+                signers[i / 8] |= 1 << (i % 8);
+                signatures.push(signature);
+            }
+        }
+        Self {
+            phase: None,
+            signers,
+            signatures,
+        }
+    }
+
+    /// Builds certificate from the signatures of a broadcast phase.
+    pub(crate) fn for_phase(
+        phase: Phase,
+        certificates: &HashSet<Certificate>,
+        members: &HashSet<PeerId>,
+    ) -> Self {
+        Self {
+            phase: Some(phase),
+            ..Self::new(certificates, members)
+        }
+    }
+
+    /// Members ordered the way the bitmap refers to them.
+    pub(crate) fn canonical_order<'a>(members: impl Iterator<Item = &'a PeerId>) -> Vec<PeerId> {
+        let mut members = members.copied().collect::<Vec<_>>();
+        members.sort();
+        members.dedup();
+        members
+    }
+
+    /// Returns members who signed the block, in the group order.
+    ///
+    /// # Errors
+    /// If the bitmap doesn't match the group or the number of signatures.
+    pub(crate) fn signers(&self, members: &[PeerId]) -> anyhow::Result<Vec<PeerId>> {
+        let members = Self::canonical_order(members.iter());
+        if self.signers.len() != members.len().div_ceil(8) {
+            return Err(anyhow!(
+                "Signers bitmap has {} bytes, group of {} members needs {}",
+                self.signers.len(),
+                members.len(),
+                members.len().div_ceil(8)
+            ));
+        }
+        let set_bits = (0..self.signers.len() * 8)
+            .filter(|&i| self.is_signer(i))
+            .count();
+        let signers = members
+            .into_iter()
+            .enumerate()
+            .filter(|&(i, _)| self.is_signer(i))
+            .map(|(_, member)| member)
+            .collect::<Vec<_>>();
+        if set_bits != signers.len() {
+            return Err(anyhow!("Signers bitmap refers to non-existing members"));
+        }
+        if signers.len() != self.signatures.len() {
+            return Err(anyhow!(
+                "Certificate has {} signers but {} signatures",
+                signers.len(),
+                self.signatures.len()
+            ));
+        }
+        Ok(signers)
+    }
+
+    fn is_signer(&self, index: usize) -> bool {
+        self.signers[index / 8] & (1 << (index % 8)) != 0
+    }
+
+    /// Expands the certificate back into individual certificates.
+    ///
+    /// # Errors
+    /// If the bitmap doesn't match the group or a public key can't be recovered from member peer id.
+    pub(crate) fn certificates(&self, members: &[PeerId]) -> anyhow::Result<Vec<Certificate>> {
+        self.signers(members)?
+            .into_iter()
+            .zip(self.signatures.iter())
+            .map(|(signer, signature)| {
+                let public_key = PublicKey::from_peer_id(&signer)
+                    .ok_or(anyhow!("Can't recover public key of {signer}"))?;
+                Ok(Certificate::new(signature.clone(), public_key))
+            })
+            .collect()
+    }
+
+    /// Expands the certificate into individual certificates together with its phase.
+    ///
+    /// # Errors
+    /// If the bitmap doesn't match the group or a public key can't be recovered from member peer id.
+    pub(crate) fn block_certificates(
+        &self,
+        members: &[PeerId],
+    ) -> anyhow::Result<Vec<BlockCertificate>> {
+        Ok(self
+            .certificates(members)?
+            .into_iter()
+            .map(|certificate| BlockCertificate {
+                phase: self.phase,
+                certificate,
+            })
+            .collect())
+    }
+
+    /// Verifies that members with enough weight signed the block in the certificate phase and the group.
+    ///
+    /// Members without a weight have the default weight.
+    ///
+    /// # Errors
+    /// If the certificate doesn't prove that the block was committed by the group.
+    pub(crate) fn verify(
+        &self,
+        header: &BlockHeader,
+        members: &[PeerId],
+        weights: &HashMap<PeerId, u64>,
+    ) -> anyhow::Result<()> {
+        let Some(phase) = self.phase else {
+            return Err(anyhow!(
+                "Block {} quorum certificate has no phase, header signatures don't prove delivery",
+                header.hash
+            ));
+        };
+        let certificates = self.certificates(members)?;
+        let quorum = Quorum::weighted(&members.iter().copied().collect(), weights);
+        let signers = certificates
+            .iter()
+            .map(|certificate| certificate.public_key.peer_id())
+            .collect::<Vec<_>>();
+        if !quorum.is_reached(&signers) {
+            return Err(anyhow!(
+                "Block {} has not enough signatures: {} / {}",
+                header.hash,
+                quorum.weight_of(&signers),
+                quorum.deliver_threshold()
+            ));
+        }
+        let vote = RawPhaseVote::new(header.hash, phase, quorum.group_id);
+        for certificate in &certificates {
+            if !certificate.verify(&vote)? {
+                return Err(anyhow!(
+                    "Block {} signature of {} is invalid",
+                    header.hash,
+                    certificate.public_key.peer_id()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Stored certificate of a committed block.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub(crate) struct BlockCertificate {
+    /// Phase the signature agrees to, `None` if it signs the block header
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) phase: Option<Phase>,
+    #[serde(flatten)]
+    pub(crate) certificate: Certificate,
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::block::types::block::Block;
+    use crate::crypto::{EphemeraKeypair, Keypair};
+    use crate::utilities::test_utils::{block, keypairs};
+
+    use super::*;
+
+    #[test]
+    fn test_quorum_certificate_roundtrip() {
+        let keypairs = keypairs(10);
+        let block = block(&keypairs[0], 1);
+        let members = keypairs.iter().map(|kp| kp.peer_id()).collect::<Vec<_>>();
+        let group = members.iter().copied().collect();
+        let certificates = votes(&block, &keypairs[..7], &group, &HashMap::new());
+
+        let qc = QuorumCertificate::for_phase(Phase::Vote, &certificates, &group);
+        assert_eq!(qc.signers.len(), 2);
+        assert_eq!(qc.signatures.len(), 7);
+        assert!(qc.verify(&block.header, &members, &HashMap::new()).is_ok());
+
+        let expanded = qc.certificates(&members).unwrap();
+        assert_eq!(expanded.into_iter().collect::<HashSet<_>>(), certificates);
+
+        let block_certificates = qc.block_certificates(&members).unwrap();
+        assert!(block_certificates
+            .iter()
+            .all(|certificate| certificate.phase == Some(Phase::Vote)));
+    }
+
+    #[test]
+    fn test_quorum_certificate_not_enough_signatures() {
+        let keypairs = keypairs(4);
+        let block = block(&keypairs[0], 1);
+        let members = keypairs.iter().map(|kp| kp.peer_id()).collect::<Vec<_>>();
+        let group = members.iter().copied().collect();
+        let certificates = votes(&block, &keypairs[..2], &group, &HashMap::new());
+
+        let qc = QuorumCertificate::for_phase(Phase::Vote, &certificates, &group);
+        assert!(qc.verify(&block.header, &members, &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_quorum_certificate_weighted() {
+        let keypairs = keypairs(4);
+        let block = block(&keypairs[0], 1);
+        let members = keypairs.iter().map(|kp| kp.peer_id()).collect::<Vec<_>>();
+        let group = members.iter().copied().collect();
+        let weights = HashMap::from([(members[0], 5)]);
+        let certificates = votes(&block, &keypairs[..2], &group, &weights);
+        let qc = QuorumCertificate::for_phase(Phase::Vote, &certificates, &group);
+
+        //Half of the members, but 6 of 8 weight
+        assert!(qc.verify(&block.header, &members, &weights).is_ok());
+        assert!(qc.verify(&block.header, &members, &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_quorum_certificate_wrong_group() {
+        let keypairs = keypairs(4);
+        let block = block(&keypairs[0], 1);
+        let members = keypairs.iter().map(|kp| kp.peer_id()).collect::<Vec<_>>();
+        let group = members.iter().copied().collect();
+        let certificates = votes(&block, &keypairs, &group, &HashMap::new());
+        let qc = QuorumCertificate::for_phase(Phase::Vote, &certificates, &group);
+
+        let mut other_members = members.clone();
+        other_members[0] = Keypair::generate(None).peer_id();
+        assert!(qc
+            .verify(&block.header, &other_members, &HashMap::new())
+            .is_err());
+
+        let mut forged = qc.clone();
+        forged.signatures.swap(0, 1);
+        assert!(forged
+            .verify(&block.header, &members, &HashMap::new())
+            .is_err());
+    }
+
+    #[test]
+    fn test_quorum_certificate_phase() {
+        let keypairs = keypairs(4);
+        let block = block(&keypairs[0], 1);
+        let members = keypairs.iter().map(|kp| kp.peer_id()).collect::<Vec<_>>();
+        let group = members.iter().copied().collect();
+        let votes = votes(&block, &keypairs[..3], &group, &HashMap::new());
+
+        let qc = QuorumCertificate::for_phase(Phase::Vote, &votes, &group);
+        assert!(qc.verify(&block.header, &members, &HashMap::new()).is_ok());
+
+        //Vote signatures don't prove an echo quorum
+        let echo_qc = QuorumCertificate::for_phase(Phase::Echo, &votes, &group);
+        assert!(echo_qc
+            .verify(&block.header, &members, &HashMap::new())
+            .is_err());
+
+        //Nor a quorum of the group with other weights
+        let weights = HashMap::from([(members[0], 2)]);
+        assert!(qc.verify(&block.header, &members, &weights).is_err());
+    }
+
+    #[test]
+    fn test_quorum_certificate_without_phase() {
+        let keypairs = keypairs(4);
+        let block = block(&keypairs[0], 1);
+        let members = keypairs.iter().map(|kp| kp.peer_id()).collect::<Vec<_>>();
+        let certificates = keypairs
+            .iter()
+            .map(|kp| block.sign(kp).unwrap())
+            .collect::<HashSet<_>>();
+
+        //Every member signs the header of every proposal, it doesn't prove delivery
+        let legacy = QuorumCertificate::new(&certificates, &members.iter().copied().collect());
+        assert!(legacy
+            .verify(&block.header, &members, &HashMap::new())
+            .is_err());
+    }
+
+    #[test]
+    fn test_block_certificate_legacy_format() {
+        let keypair = Keypair::generate(None);
+        let certificate = block(&keypair, 1).sign(&keypair).unwrap();
+
+        let legacy = serde_json::to_vec(&certificate).unwrap();
+        let parsed: BlockCertificate = serde_json::from_slice(&legacy).unwrap();
+        assert_eq!(parsed.phase, None);
+        assert_eq!(parsed.certificate, certificate);
+    }
+
+    fn votes(
+        block: &Block,
+        keypairs: &[Arc<Keypair>],
+        group: &HashSet<PeerId>,
+        weights: &HashMap<PeerId, u64>,
+    ) -> HashSet<Certificate> {
+        let group_id = Quorum::weighted(group, weights).group_id;
+        keypairs
+            .iter()
+            .map(|kp| {
+                RawPhaseVote::new(block.get_hash(), Phase::Vote, group_id)
+                    .sign(kp)
+                    .unwrap()
+            })
+            .collect()
+    }
+}
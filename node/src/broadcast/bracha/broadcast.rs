@@ -1,77 +1,45 @@
+use std::collections::HashSet;
 use std::num::NonZeroUsize;
+use std::sync::Arc;
 
-use log::{debug, trace};
+use log::{debug, error, trace};
 use lru::LruCache;
 
 use crate::broadcast::bracha::quorum::BrachaMessageType;
-use crate::peer::PeerId;
+use crate::broadcast::signing::Phase;
+use crate::crypto::Keypair;
+use crate::peer::{PeerId, ToPeerId};
 use crate::{
     block::types::block::Block,
     broadcast::{
         bracha::quorum::Quorum,
-        MessageType::{Echo, Vote},
+        BroadcastProtocol, BroadcastResponse,
+        MessageType::{self, Echo, Propose, Vote},
         ProtocolContext, RawRbMsg,
     },
-    utilities::hash::Hash,
+    utilities::{crypto::Certificate, hash::Hash},
 };
 
-#[allow(clippy::large_enum_variant)]
-#[derive(Debug)]
-pub(crate) enum BroadcastResponse {
-    Broadcast(RawRbMsg),
-    Deliver(Hash),
-    Drop(Hash),
-}
-
 pub(crate) struct Broadcaster {
     /// Local peer id
     local_peer_id: PeerId,
+    /// Signs our echoes and votes
+    keypair: Arc<Keypair>,
     /// We keep a context for each block we are processing.
     contexts: LruCache<Hash, ProtocolContext>,
-    /// Current cluster size
-    cluster_size: usize,
+    /// Quorum of the current group
+    quorum: Quorum,
 }
 
 impl Broadcaster {
-    pub fn new(peer_id: PeerId) -> Broadcaster {
+    pub fn new(keypair: Arc<Keypair>) -> Broadcaster {
         Broadcaster {
             //At any given time we are processing in parallel about n messages, where n is the number of peers in the group.
             //This is just large enough buffer.
             contexts: LruCache::new(NonZeroUsize::new(1000).unwrap()),
-            cluster_size: 0,
-            local_peer_id: peer_id,
-        }
-    }
-
-    pub(crate) fn new_broadcast(&mut self, block: Block) -> anyhow::Result<BroadcastResponse> {
-        debug!("Starting broadcast for new block {:?}", block.get_hash());
-        self.handle(&RawRbMsg::new(block, self.local_peer_id))
-    }
-
-    pub(crate) fn handle(&mut self, rb_msg: &RawRbMsg) -> anyhow::Result<BroadcastResponse> {
-        trace!("Processing new broadcast message: {:?}", rb_msg);
-
-        let block = rb_msg.block();
-        let hash = block.hash_with_default_hasher()?;
-
-        let ctx = self.contexts.get_or_insert(hash, || {
-            ProtocolContext::new(hash, self.local_peer_id, Quorum::new(self.cluster_size))
-        });
-
-        if ctx.delivered {
-            trace!("Block {hash:?} already delivered");
-            return Ok(BroadcastResponse::Drop(hash));
-        }
-
-        match rb_msg.message_type {
-            Echo(_) => {
-                trace!("Processing ECHO {:?}", rb_msg.id);
-                Ok(self.process_echo(rb_msg, hash))
-            }
-            Vote(_) => {
-                trace!("Processing VOTE {:?}", rb_msg.id);
-                Ok(self.process_vote(rb_msg, hash))
-            }
+            quorum: Quorum::new(0),
+            local_peer_id: keypair.peer_id(),
+            keypair,
         }
     }
 
@@ -80,16 +48,21 @@ impl Broadcaster {
 
         if self.local_peer_id != rb_msg.original_sender {
             trace!("Adding echo from {:?}", rb_msg.original_sender);
-            ctx.add_echo(rb_msg.original_sender);
+            ctx.add_echo(rb_msg.original_sender, rb_msg.signature.clone());
         }
 
         if !ctx.echoed() {
-            ctx.add_echo(self.local_peer_id);
+            //Our own proposal is our echo, it's the only message which carries the block
+            if self.local_peer_id == rb_msg.original_sender {
+                if let Propose(_) = rb_msg.message_type {
+                    ctx.add_echo(self.local_peer_id, rb_msg.signature.clone());
+                    trace!("Sending proposal for {hash:?}");
+                    return BroadcastResponse::Broadcast(rb_msg.clone());
+                }
+            }
 
             trace!("Sending echo reply for {hash:?}",);
-            return BroadcastResponse::Broadcast(
-                rb_msg.echo_reply(self.local_peer_id, rb_msg.block()),
-            );
+            return ctx.echo_reply(&self.keypair, rb_msg);
         }
 
         if !ctx.voted()
@@ -98,24 +71,19 @@ impl Broadcaster {
                 .check_threshold(ctx, BrachaMessageType::Echo)
                 .is_vote()
         {
-            ctx.add_vote(self.local_peer_id);
-
             trace!("Sending vote reply for {hash:?}",);
-            return BroadcastResponse::Broadcast(
-                rb_msg.vote_reply(self.local_peer_id, rb_msg.block()),
-            );
+            return ctx.vote_reply(&self.keypair, rb_msg);
         }
 
         BroadcastResponse::Drop(hash)
     }
 
     fn process_vote(&mut self, rb_msg: &RawRbMsg, hash: Hash) -> BroadcastResponse {
-        let block = rb_msg.block();
         let ctx = self.contexts.get_mut(&hash).expect("Context not found");
 
         if self.local_peer_id != rb_msg.original_sender {
             trace!("Adding vote from {:?}", rb_msg.original_sender);
-            ctx.add_vote(rb_msg.original_sender);
+            ctx.add_vote(rb_msg.original_sender, rb_msg.signature.clone());
         }
 
         if ctx
@@ -123,10 +91,8 @@ impl Broadcaster {
             .check_threshold(ctx, BrachaMessageType::Vote)
             .is_vote()
         {
-            ctx.add_vote(self.local_peer_id);
-
             trace!("Sending vote reply for {hash:?}",);
-            return BroadcastResponse::Broadcast(rb_msg.vote_reply(self.local_peer_id, block));
+            return ctx.vote_reply(&self.keypair, rb_msg);
         }
 
         if ctx
@@ -143,9 +109,80 @@ impl Broadcaster {
 
         BroadcastResponse::Drop(hash)
     }
+}
+
+impl BroadcastProtocol for Broadcaster {
+    fn new_broadcast(&mut self, block: Block) -> BroadcastResponse {
+        let hash = block.get_hash();
+        debug!("Starting broadcast for new block {hash:?}");
+        match RawRbMsg::new(block, &self.keypair, self.quorum.group_id) {
+            Ok(proposal) => self.handle(&proposal),
+            Err(err) => {
+                error!("Failed to sign proposal for block {hash:?}: {err:?}");
+                BroadcastResponse::Drop(hash)
+            }
+        }
+    }
+
+    fn handle(&mut self, rb_msg: &RawRbMsg) -> BroadcastResponse {
+        trace!("Processing new broadcast message: {:?}", rb_msg);
+
+        let hash = rb_msg.block_hash();
+
+        let ctx = self.contexts.get_or_insert(hash, || {
+            ProtocolContext::new(hash, self.local_peer_id, self.quorum.clone())
+        });
+
+        if ctx.delivered {
+            trace!("Block {hash:?} already delivered");
+            return BroadcastResponse::Drop(hash);
+        }
+
+        if !ctx.verify(rb_msg) {
+            debug!(
+                "Dropping {:?} from {}, invalid {:?} signature",
+                rb_msg.id,
+                rb_msg.original_sender,
+                rb_msg.phase()
+            );
+            return BroadcastResponse::Drop(hash);
+        }
+
+        match rb_msg.message_type {
+            Propose(_) | Echo(_) => {
+                trace!("Processing ECHO {:?}", rb_msg.id);
+                self.process_echo(rb_msg, hash)
+            }
+            Vote(_) => {
+                trace!("Processing VOTE {:?}", rb_msg.id);
+                self.process_vote(rb_msg, hash)
+            }
+            MessageType::Certificate(..) => {
+                debug!(
+                    "Ignoring CERTIFICATE {:?} from {}, Bracha delivers on votes",
+                    rb_msg.id, rb_msg.original_sender
+                );
+                BroadcastResponse::Drop(hash)
+            }
+        }
+    }
+
+    fn is_delivered(&self, hash: &Hash) -> bool {
+        self.contexts.peek(hash).is_some_and(|ctx| ctx.delivered)
+    }
+
+    fn delivery_certificates(&self, hash: &Hash) -> Option<(Phase, HashSet<Certificate>)> {
+        let ctx = self.contexts.peek(hash).filter(|ctx| ctx.delivered)?;
+        let certificates = ctx.certificates.get(&Phase::Vote)?;
+        Some((Phase::Vote, certificates.clone()))
+    }
 
-    pub(crate) fn group_updated(&mut self, size: usize) {
-        self.cluster_size = size;
+    fn group_updated(&mut self, quorum: Quorum) {
+        self.quorum = quorum;
+    }
+
+    fn is_group_active(&self) -> bool {
+        self.quorum.total_weight > 0
     }
 }
 
@@ -161,45 +198,52 @@ mod tests {
 
     //4. "Ideally" make sure that when group changes, the ongoing broadcast can deal with it
 
-    use std::iter;
+    use std::sync::Arc;
 
     use assert_matches::assert_matches;
 
-    use crate::broadcast::bracha::broadcast::BroadcastResponse;
-    use crate::peer::PeerId;
+    use crate::broadcast::{self, bracha::broadcast::Broadcaster, signing::Phase, RawRbMsg};
+    use crate::broadcast::{BroadcastProtocol, BroadcastResponse};
+    use crate::crypto::Keypair;
     use crate::utilities::hash::Hash;
-    use crate::{
-        block::types::block::{Block, RawBlock, RawBlockHeader},
-        broadcast::{self, bracha::broadcast::Broadcaster, RawRbMsg},
-    };
+    use crate::utilities::test_utils::{block, handle_double, keypairs, quorum};
 
     #[test]
     fn test_state_transitions_from_start_to_end() {
-        let peers: Vec<PeerId> = iter::repeat_with(PeerId::random).take(10).collect();
-        let local_peer_id = peers[0];
-        let block_creator_peer_id = peers[1];
+        let peers = keypairs(10);
+        let quorum = quorum(&peers);
+        let group_id = quorum.group_id;
+        let local = peers[0].clone();
+        let block_creator = peers[1].clone();
 
-        let mut broadcaster = Broadcaster::new(local_peer_id);
-        broadcaster.group_updated(peers.len());
+        let mut broadcaster = Broadcaster::new(local);
+        broadcaster.group_updated(quorum);
 
-        let (block_hash, block) = create_block(block_creator_peer_id);
+        let block = block(&block_creator, 0);
+        let block_hash = block.get_hash();
+        let proposal = RawRbMsg::new(block.clone(), &block_creator, group_id).unwrap();
 
         //After this echo set contains local and block creator(msg sender)
-        receive_echo_first_message(&mut broadcaster, &block, block_creator_peer_id);
+        receive_echo_first_message(&mut broadcaster, &proposal);
 
         let ctx = broadcaster.contexts.get(&block_hash).unwrap();
         assert_eq!(ctx.echo.len(), 2);
         assert!(ctx.echoed());
         assert!(!ctx.voted());
 
-        receive_nr_of_echo_messages_below_vote_threshold(&mut broadcaster, &block, &peers[2..6]);
+        receive_nr_of_echo_messages_below_vote_threshold(
+            &mut broadcaster,
+            &proposal,
+            &peers[2..6],
+            group_id,
+        );
 
         let ctx = broadcaster.contexts.get(&block_hash).unwrap();
         assert_eq!(ctx.echo.len(), 6);
         assert!(ctx.echoed());
         assert!(!ctx.voted());
 
-        receive_echo_threshold_message(&mut broadcaster, &block, *peers.get(7).unwrap());
+        receive_echo_threshold_message(&mut broadcaster, &proposal, &peers[7], group_id);
 
         let ctx = broadcaster.contexts.get(&block_hash).unwrap();
         assert_eq!(ctx.echo.len(), 7);
@@ -207,7 +251,12 @@ mod tests {
         assert!(ctx.echoed());
         assert!(ctx.voted());
 
-        receive_nr_of_vote_messages_below_deliver_threshold(&mut broadcaster, &block, &peers[2..7]);
+        receive_nr_of_vote_messages_below_deliver_threshold(
+            &mut broadcaster,
+            &proposal,
+            &peers[2..7],
+            group_id,
+        );
 
         let ctx = broadcaster.contexts.get(&block_hash).unwrap();
         assert_eq!(ctx.echo.len(), 7);
@@ -217,31 +266,89 @@ mod tests {
 
         receive_threshold_vote_message_for_deliver(
             &mut broadcaster,
-            &block,
-            *peers.get(8).unwrap(),
+            &proposal,
+            &peers[8],
+            group_id,
+        );
+
+        //Votes of the local node and the 6 peers prove the delivery
+        let (phase, certificates) = broadcaster.delivery_certificates(&block_hash).unwrap();
+        assert_eq!(phase, Phase::Vote);
+        assert_eq!(certificates.len(), 7);
+    }
+
+    #[test]
+    fn test_replayed_and_foreign_signatures_not_counted() {
+        let peers = keypairs(4);
+        let quorum = quorum(&peers);
+        let group_id = quorum.group_id;
+
+        let mut broadcaster = Broadcaster::new(peers[0].clone());
+        broadcaster.group_updated(quorum);
+
+        let block = block(&peers[1], 0);
+        let block_hash = block.get_hash();
+        let proposal = RawRbMsg::new(block, &peers[1], group_id).unwrap();
+        receive_echo_first_message(&mut broadcaster, &proposal);
+
+        //Echo signature replayed as a vote
+        let echo = proposal
+            .echo_reply(&peers[2], block_hash, group_id)
+            .unwrap();
+        let mut replayed = echo.clone();
+        replayed.message_type = broadcast::MessageType::Vote(block_hash);
+        assert_matches!(broadcaster.handle(&replayed), BroadcastResponse::Drop(_));
+
+        //Echo signed in another group
+        let foreign = proposal
+            .echo_reply(&peers[3], block_hash, Hash::new([1; 32]))
+            .unwrap();
+        assert_matches!(broadcaster.handle(&foreign), BroadcastResponse::Drop(_));
+
+        //Echo signed by someone else than the sender
+        let mut forged = foreign.clone();
+        forged.signature = echo.signature.clone();
+        assert_matches!(broadcaster.handle(&forged), BroadcastResponse::Drop(_));
+
+        let ctx = broadcaster.contexts.get(&block_hash).unwrap();
+        assert_eq!(ctx.echo.len(), 2);
+        assert!(ctx.vote.is_empty());
+
+        //The genuine echo reaches the vote threshold
+        assert_matches!(
+            broadcaster.handle(&echo),
+            BroadcastResponse::Broadcast(RawRbMsg {
+                message_type: broadcast::MessageType::Vote(_),
+                ..
+            })
         );
     }
 
     fn receive_threshold_vote_message_for_deliver(
         broadcaster: &mut Broadcaster,
-        block: &Block,
-        peer_id: PeerId,
+        proposal: &RawRbMsg,
+        peer: &Keypair,
+        group_id: Hash,
     ) {
-        let rb_msg = RawRbMsg::new(block.clone(), PeerId::random());
-        let rb_msg = rb_msg.vote_reply(peer_id, block.clone());
+        let hash = proposal.block_hash();
+        let rb_msg = proposal.vote_reply(peer, hash, group_id).unwrap();
 
         let response = handle_double(broadcaster, &rb_msg);
 
         assert_matches!(response, BroadcastResponse::Deliver(_));
+        assert!(broadcaster.is_delivered(&hash));
     }
 
     fn receive_nr_of_echo_messages_below_vote_threshold(
         broadcaster: &mut Broadcaster,
-        block: &Block,
-        peers: &[PeerId],
+        proposal: &RawRbMsg,
+        peers: &[Arc<Keypair>],
+        group_id: Hash,
     ) {
-        for peer_id in peers {
-            let rb_msg = RawRbMsg::new(block.clone(), *peer_id);
+        for peer in peers {
+            let rb_msg = proposal
+                .echo_reply(peer, proposal.block_hash(), group_id)
+                .unwrap();
 
             let response = handle_double(broadcaster, &rb_msg);
 
@@ -251,70 +358,49 @@ mod tests {
 
     fn receive_nr_of_vote_messages_below_deliver_threshold(
         broadcaster: &mut Broadcaster,
-        block: &Block,
-        peers: &[PeerId],
+        proposal: &RawRbMsg,
+        peers: &[Arc<Keypair>],
+        group_id: Hash,
     ) {
-        for peer_id in peers {
-            let rb_msg = RawRbMsg::new(block.clone(), PeerId::random());
-            let rb_msg = rb_msg.vote_reply(*peer_id, block.clone());
+        for peer in peers {
+            let rb_msg = proposal
+                .vote_reply(peer, proposal.block_hash(), group_id)
+                .unwrap();
 
             let response = handle_double(broadcaster, &rb_msg);
             assert_matches!(response, BroadcastResponse::Drop(_));
         }
     }
 
-    fn receive_echo_first_message(
-        broadcaster: &mut Broadcaster,
-        block: &Block,
-        block_creator: PeerId,
-    ) {
-        let rb_msg = RawRbMsg::new(block.clone(), block_creator);
-        let response = handle_double(broadcaster, &rb_msg);
+    fn receive_echo_first_message(broadcaster: &mut Broadcaster, proposal: &RawRbMsg) {
+        let response = handle_double(broadcaster, proposal);
 
         assert_matches!(
             response,
             BroadcastResponse::Broadcast(RawRbMsg {
-                id: _,
-                request_id: _,
-                original_sender: _,
-                timestamp: _,
                 message_type: broadcast::MessageType::Echo(_),
+                ..
             })
         );
     }
 
     fn receive_echo_threshold_message(
         broadcaster: &mut Broadcaster,
-        block: &Block,
-        peer_id: PeerId,
+        proposal: &RawRbMsg,
+        peer: &Keypair,
+        group_id: Hash,
     ) {
-        let rb_msg = RawRbMsg::new(block.clone(), peer_id);
+        let rb_msg = proposal
+            .echo_reply(peer, proposal.block_hash(), group_id)
+            .unwrap();
 
         let response = handle_double(broadcaster, &rb_msg);
         assert_matches!(
             response,
             BroadcastResponse::Broadcast(RawRbMsg {
-                id: _,
-                request_id: _,
-                original_sender: _,
-                timestamp: _,
                 message_type: broadcast::MessageType::Vote(_),
+                ..
             })
         );
     }
-
-    fn create_block(block_creator_peer_id: PeerId) -> (Hash, Block) {
-        let header = RawBlockHeader::new(block_creator_peer_id, 0);
-        let raw_block = RawBlock::new(header, vec![]);
-        let block_hash = raw_block.hash_with_default_hasher().unwrap();
-        let block = Block::new(raw_block, block_hash);
-        (block_hash, block)
-    }
-
-    //make sure that duplicate messages doesn't have impact
-    fn handle_double(broadcaster: &mut Broadcaster, rb_msg: &RawRbMsg) -> BroadcastResponse {
-        let response = broadcaster.handle(rb_msg).unwrap();
-        broadcaster.handle(rb_msg).unwrap();
-        response
-    }
 }
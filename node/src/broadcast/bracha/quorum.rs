@@ -1,6 +1,11 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
 use log::trace;
 
 use crate::broadcast::{MessageType, ProtocolContext};
+use crate::peer::PeerId;
+use crate::utilities::hash::{Blake2bHasher, EphemeraHasher, Hash};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum BrachaMessageType {
@@ -28,27 +33,98 @@ impl BrachaAction {
 impl From<MessageType> for BrachaMessageType {
     fn from(message_type: MessageType) -> Self {
         match message_type {
-            MessageType::Echo(_) => BrachaMessageType::Echo,
+            MessageType::Propose(_) | MessageType::Echo(_) | MessageType::Certificate(..) => {
+                BrachaMessageType::Echo
+            }
             MessageType::Vote(_) => BrachaMessageType::Vote,
         }
     }
 }
 
-const MAX_FAULTY_RATIO: f64 = 1.0 / 3.0;
+/// Weight of members which don't have one.
+pub(crate) const DEFAULT_WEIGHT: u64 = 1;
 
-#[derive(Debug, Clone, Copy)]
+/// Thresholds are sums of member weights. Without weights every member has weight 1,
+/// so `n` is the group size and `f` a third of it.
+#[derive(Debug, Clone)]
 pub(crate) struct Quorum {
     pub(crate) cluster_size: usize,
-    pub(crate) max_faulty_nodes: usize,
+    /// Sum of member weights, `n`
+    pub(crate) total_weight: u64,
+    /// Maximum weight of faulty members, `f`. A third of the total weight, rounded down.
+    pub(crate) max_faulty_weight: u64,
+    /// Weights of the members, members not in the map have [`DEFAULT_WEIGHT`]
+    weights: Arc<HashMap<PeerId, u64>>,
+    /// Members of the group, empty if they are not known
+    members: Arc<HashSet<PeerId>>,
+    /// Hash of the members and their weights, which echo and vote signatures are bound to
+    pub(crate) group_id: Hash,
 }
 
 impl Quorum {
+    /// Quorum of `cluster_size` members with equal weights. The members are not known, so the group id is zero.
     pub fn new(cluster_size: usize) -> Self {
-        let max_faulty_nodes = Quorum::max_faulty_nodes(cluster_size);
+        let total_weight = cluster_size as u64;
         Self {
             cluster_size,
-            max_faulty_nodes,
+            total_weight,
+            max_faulty_weight: Quorum::max_faulty_weight(total_weight),
+            weights: Arc::default(),
+            members: Arc::default(),
+            group_id: Hash::new([0; 32]),
+        }
+    }
+
+    /// Quorum of the members with given weights.
+    pub(crate) fn weighted(members: &HashSet<PeerId>, weights: &HashMap<PeerId, u64>) -> Self {
+        let weights = members
+            .iter()
+            .filter_map(|member| weights.get(member).map(|weight| (*member, *weight)))
+            .collect::<HashMap<_, _>>();
+        let mut quorum = Self {
+            cluster_size: members.len(),
+            total_weight: 0,
+            max_faulty_weight: 0,
+            weights: Arc::new(weights),
+            members: Arc::new(members.clone()),
+            group_id: Hash::new([0; 32]),
+        };
+        quorum.total_weight = quorum.weight_of(members);
+        quorum.max_faulty_weight = Quorum::max_faulty_weight(quorum.total_weight);
+        quorum.group_id = quorum.group_id(members);
+        quorum
+    }
+
+    /// All nodes with the same view of the group get the same id, members are hashed ordered by peer id.
+    fn group_id(&self, members: &HashSet<PeerId>) -> Hash {
+        let mut members = members.iter().collect::<Vec<_>>();
+        members.sort();
+        let mut hasher = Blake2bHasher::default();
+        for member in members {
+            hasher.update(&member.to_bytes());
+            hasher.update(&self.weight(member).to_be_bytes());
         }
+        hasher.finish().into()
+    }
+
+    pub(crate) fn weight(&self, peer_id: &PeerId) -> u64 {
+        self.weights.get(peer_id).copied().unwrap_or(DEFAULT_WEIGHT)
+    }
+
+    pub(crate) fn is_member(&self, peer_id: &PeerId) -> bool {
+        self.members.contains(peer_id)
+    }
+
+    /// Sum of the peers' weights.
+    pub(crate) fn weight_of<'a>(&self, peers: impl IntoIterator<Item = &'a PeerId>) -> u64 {
+        peers
+            .into_iter()
+            .fold(0, |sum, peer_id| sum.saturating_add(self.weight(peer_id)))
+    }
+
+    /// True if the peers' weights add up to the deliver threshold.
+    pub(crate) fn is_reached<'a>(&self, peers: impl IntoIterator<Item = &'a PeerId>) -> bool {
+        self.total_weight > 0 && self.weight_of(peers) >= self.deliver_threshold()
     }
 
     pub(crate) fn check_threshold(
@@ -56,38 +132,37 @@ impl Quorum {
         ctx: &ProtocolContext,
         phase: BrachaMessageType,
     ) -> BrachaAction {
-        if self.cluster_size == 0 {
+        if self.total_weight == 0 {
             return BrachaAction::Ignore;
         }
 
         match phase {
             BrachaMessageType::Echo => {
-                if ctx.echo.len() >= self.cluster_size - self.max_faulty_nodes {
+                let echoed = self.weight_of(&ctx.echo);
+                if echoed >= self.deliver_threshold() {
                     trace!(
-                        "Echo threshold reached: Echoed:{} / Threshold:{} for Block:{}",
-                        ctx.echo.len(),
-                        self.cluster_size - self.max_faulty_nodes,
+                        "Echo threshold reached: Echoed:{echoed} / Threshold:{} for Block:{}",
+                        self.deliver_threshold(),
                         ctx.hash
                     );
                     BrachaAction::Vote
                 } else {
                     trace!(
-                        "Echo threshold not reached: Echoed:{} / Threshold:{} for Block:{}",
-                        ctx.echo.len(),
-                        self.cluster_size - self.max_faulty_nodes,
+                        "Echo threshold not reached: Echoed:{echoed} / Threshold:{} for Block:{}",
+                        self.deliver_threshold(),
                         ctx.hash
                     );
                     BrachaAction::Ignore
                 }
             }
             BrachaMessageType::Vote => {
+                let voted = self.weight_of(&ctx.vote);
                 if !ctx.voted() {
                     // f + 1 votes are enough to send our vote
-                    if ctx.vote.len() >= self.max_faulty_nodes {
+                    if voted > self.max_faulty_weight {
                         trace!(
-                            "Vote send threshold reached: Voted:{} / Threshold:{} for Block:{}",
-                            ctx.vote.len(),
-                            self.max_faulty_nodes + 1,
+                            "Vote send threshold reached: Voted:{voted} / Threshold:{} for Block:{}",
+                            self.max_faulty_weight + 1,
                             ctx.hash
                         );
                         return BrachaAction::Vote;
@@ -96,11 +171,10 @@ impl Quorum {
 
                 if ctx.voted() {
                     // n-f votes are enough to deliver the value
-                    if ctx.vote.len() >= self.cluster_size - self.max_faulty_nodes {
+                    if voted >= self.deliver_threshold() {
                         trace!(
-                            "Deliver threshold reached: Voted:{} / Threshold:{} for Block:{}",
-                            ctx.vote.len(),
-                            self.cluster_size - self.max_faulty_nodes,
+                            "Deliver threshold reached: Voted:{voted} / Threshold:{} for Block:{}",
+                            self.deliver_threshold(),
                             ctx.hash
                         );
                         return BrachaAction::Deliver;
@@ -108,9 +182,8 @@ impl Quorum {
                 }
 
                 trace!(
-                    "Vote threshold not reached: Voted:{} / Threshold:{} for Block:{}",
-                    ctx.vote.len(),
-                    self.max_faulty_nodes + 1,
+                    "Vote threshold not reached: Voted:{voted} / Threshold:{} for Block:{}",
+                    self.max_faulty_weight + 1,
                     ctx.hash
                 );
                 BrachaAction::Ignore
@@ -118,24 +191,27 @@ impl Quorum {
         }
     }
 
-    pub(crate) fn cluster_size_info(cluster_size: usize) -> String {
-        let max_faulty_nodes = Quorum::max_faulty_nodes(cluster_size);
-        format!("Cluster size: {cluster_size} / Max faulty nodes: {max_faulty_nodes}",)
+    /// Weight of votes(n-f) needed to deliver a value.
+    pub(crate) fn deliver_threshold(&self) -> u64 {
+        self.total_weight - self.max_faulty_weight
+    }
+
+    pub(crate) fn info(&self) -> String {
+        format!(
+            "Cluster size: {} / Total weight: {} / Max faulty weight: {}",
+            self.cluster_size, self.total_weight, self.max_faulty_weight
+        )
     }
 
-    #[allow(
-        clippy::cast_precision_loss,
-        clippy::cast_sign_loss,
-        clippy::cast_possible_truncation
-    )]
-    fn max_faulty_nodes(cluster_size: usize) -> usize {
-        (cluster_size as f64 * MAX_FAULTY_RATIO).floor() as usize
+    fn max_faulty_weight(total_weight: u64) -> u64 {
+        total_weight / 3
     }
 }
 
 #[cfg(test)]
 mod test {
-    use std::collections::HashSet;
+    use std::collections::{HashMap, HashSet};
+    use std::iter;
 
     use crate::broadcast::{
         bracha::quorum::{BrachaAction, BrachaMessageType, Quorum},
@@ -146,7 +222,35 @@ mod test {
     #[test]
     fn test_max_faulty_nodes() {
         let quorum = Quorum::new(10);
-        assert_eq!(quorum.max_faulty_nodes, 3);
+        assert_eq!(quorum.max_faulty_weight, 3);
+    }
+
+    #[test]
+    fn test_group_id() {
+        let members = iter::repeat_with(PeerId::random)
+            .take(4)
+            .collect::<HashSet<_>>();
+        let group_id = Quorum::weighted(&members, &HashMap::new()).group_id;
+
+        //Explicit default weights are the same group
+        let default_weights = members.iter().map(|member| (*member, 1)).collect();
+        assert_eq!(
+            Quorum::weighted(&members, &default_weights).group_id,
+            group_id
+        );
+
+        let other_weights = HashMap::from([(*members.iter().next().unwrap(), 2)]);
+        assert_ne!(
+            Quorum::weighted(&members, &other_weights).group_id,
+            group_id
+        );
+
+        let mut other_members = members.clone();
+        other_members.insert(PeerId::random());
+        assert_ne!(
+            Quorum::weighted(&other_members, &HashMap::new()).group_id,
+            group_id
+        );
     }
 
     #[test]
@@ -219,12 +323,86 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_weighted_thresholds() {
+        let validator = PeerId::random();
+        let others = [PeerId::random(), PeerId::random(), PeerId::random()];
+        let members = others
+            .iter()
+            .copied()
+            .chain([validator])
+            .collect::<HashSet<_>>();
+        let weights = HashMap::from([(validator, 5), (PeerId::random(), 100)]);
+        let quorum = Quorum::weighted(&members, &weights);
+        //Non-members don't count, members without a weight have the default
+        assert_eq!(quorum.total_weight, 8);
+        assert_eq!(quorum.max_faulty_weight, 2);
+        assert_eq!(quorum.deliver_threshold(), 6);
+
+        let mut ctx = ProtocolContext::new([0; 32].into(), others[0], quorum.clone());
+        ctx.echo.extend(others);
+        assert_eq!(
+            quorum.check_threshold(&ctx, BrachaMessageType::Echo),
+            BrachaAction::Ignore
+        );
+        ctx.echo.insert(validator);
+        assert_eq!(
+            quorum.check_threshold(&ctx, BrachaMessageType::Echo),
+            BrachaAction::Vote
+        );
+
+        //Validator's vote alone is more than f
+        ctx.vote.insert(validator);
+        assert_eq!(
+            quorum.check_threshold(&ctx, BrachaMessageType::Vote),
+            BrachaAction::Vote
+        );
+        ctx.vote.insert(others[0]);
+        assert_eq!(
+            quorum.check_threshold(&ctx, BrachaMessageType::Vote),
+            BrachaAction::Deliver
+        );
+        assert!(quorum.is_reached(&ctx.vote));
+        assert!(!quorum.is_reached(&others));
+    }
+
+    #[test]
+    fn test_weighted_vote_threshold_above_f() {
+        let heavy = PeerId::random();
+        let others = iter::repeat_with(PeerId::random)
+            .take(6)
+            .collect::<Vec<_>>();
+        let members = others
+            .iter()
+            .copied()
+            .chain([heavy])
+            .collect::<HashSet<_>>();
+        let quorum = Quorum::weighted(&members, &HashMap::from([(heavy, 3)]));
+        assert_eq!(quorum.total_weight, 9);
+        assert_eq!(quorum.max_faulty_weight, 3);
+
+        //Votes holding exactly f could all be faulty
+        let mut ctx = ProtocolContext::new([0; 32].into(), others[0], quorum.clone());
+        ctx.vote.insert(heavy);
+        assert_eq!(
+            quorum.check_threshold(&ctx, BrachaMessageType::Vote),
+            BrachaAction::Ignore
+        );
+
+        ctx.vote.insert(others[1]);
+        assert_eq!(
+            quorum.check_threshold(&ctx, BrachaMessageType::Vote),
+            BrachaAction::Vote
+        );
+    }
+
     fn ctx_with_nr_echoes(n: usize) -> ProtocolContext {
         let mut ctx = ProtocolContext {
             local_peer_id: PeerId::random(),
             hash: [0; 32].into(),
             echo: HashSet::default(),
             vote: HashSet::default(),
+            certificates: HashMap::default(),
             quorum: Quorum::new(10),
             delivered: false,
         };
@@ -240,6 +418,7 @@ mod test {
             hash: [0; 32].into(),
             echo: HashSet::default(),
             vote: HashSet::default(),
+            certificates: HashMap::default(),
             quorum: Quorum::new(10),
             delivered: false,
         };
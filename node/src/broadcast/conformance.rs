@@ -0,0 +1,360 @@
+//! Conformance suite which every [`BroadcastProtocol`] has to pass.
+//!
+//! Runs a broadcast in a simulated group where every protocol message reaches every other member, or the one
+//! member it's sent to. Scenarios reuse the helpers of the Bracha tests:
+//! 1. all members deliver the block exactly once
+//! 2. the group makes progress with `f` crashed members, but not with more
+//! 3. duplicate and reordered messages don't have impact
+//! 4. only the proposal carries the block
+//! 5. thresholds are sums of member weights
+//! 6. echoes and votes count only with the sender's signature for their phase and group
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::iter;
+use std::sync::Arc;
+
+use assert_matches::assert_matches;
+
+use crate::broadcast::bracha::broadcast::Broadcaster;
+use crate::broadcast::bracha::quorum::Quorum;
+use crate::broadcast::signed_echo::SignedEchoBroadcaster;
+use crate::broadcast::{BroadcastProtocol, BroadcastResponse, MessageType, RawRbMsg};
+use crate::crypto::{EphemeraKeypair, Keypair};
+use crate::peer::ToPeerId;
+use crate::utilities::hash::Hash;
+use crate::utilities::test_utils::{block, handle_double, keypairs};
+
+macro_rules! conformance_suite {
+    ($name:ident, $new:expr) => {
+        mod $name {
+            #[test]
+            fn test_all_members_deliver() {
+                super::all_members_deliver($new);
+            }
+
+            #[test]
+            fn test_tolerates_f_crashed_members() {
+                super::tolerates_f_crashed_members($new);
+            }
+
+            #[test]
+            fn test_no_delivery_without_quorum() {
+                super::no_delivery_without_quorum($new);
+            }
+
+            #[test]
+            fn test_duplicate_and_reordered_messages() {
+                super::duplicate_and_reordered_messages($new);
+            }
+
+            #[test]
+            fn test_only_proposal_carries_block() {
+                super::only_proposal_carries_block($new);
+            }
+
+            #[test]
+            fn test_weighted_quorum() {
+                super::weighted_quorum($new);
+            }
+
+            #[test]
+            fn test_invalid_signatures_not_counted() {
+                super::invalid_signatures_not_counted($new);
+            }
+
+            #[test]
+            fn test_inactive_group() {
+                super::inactive_group($new);
+            }
+        }
+    };
+}
+
+conformance_suite!(bracha, super::Broadcaster::new);
+conformance_suite!(signed_echo, super::SignedEchoBroadcaster::new);
+
+#[derive(Clone, Copy)]
+struct Network {
+    /// Every message is received twice, the second time it has to be dropped
+    duplicate: bool,
+    /// Messages are received newest first
+    reverse: bool,
+}
+
+const RELIABLE: Network = Network {
+    duplicate: false,
+    reverse: false,
+};
+
+struct Group<P> {
+    members: Vec<(Arc<Keypair>, P)>,
+    group_id: Hash,
+    /// Members which don't receive or send anything
+    crashed: HashSet<usize>,
+    delivered: Vec<Vec<Hash>>,
+    /// All messages sent during the broadcast
+    sent: Vec<RawRbMsg>,
+}
+
+impl<P: BroadcastProtocol> Group<P> {
+    fn new(size: usize, crashed: usize, new: fn(Arc<Keypair>) -> P) -> Self {
+        Self::weighted(&vec![1; size], crashed, new)
+    }
+
+    /// Group with a member per weight, the last `crashed` members are crashed.
+    fn weighted(weights: &[u64], crashed: usize, new: fn(Arc<Keypair>) -> P) -> Self {
+        let size = weights.len();
+        let keypairs = keypairs(size);
+        let peer_ids = keypairs.iter().map(|kp| kp.peer_id()).collect::<Vec<_>>();
+        let weights = peer_ids
+            .iter()
+            .copied()
+            .zip(weights.iter().copied())
+            .collect::<HashMap<_, _>>();
+        let quorum = Quorum::weighted(&peer_ids.iter().copied().collect(), &weights);
+        let group_id = quorum.group_id;
+        let members = keypairs
+            .into_iter()
+            .map(|keypair| {
+                let mut protocol = new(keypair.clone());
+                protocol.group_updated(quorum.clone());
+                (keypair, protocol)
+            })
+            .collect();
+        Self {
+            members,
+            group_id,
+            crashed: (size - crashed..size).collect(),
+            delivered: vec![vec![]; size],
+            sent: vec![],
+        }
+    }
+
+    /// Member 0 broadcasts a new block.
+    fn broadcast(&mut self, network: Network) -> Hash {
+        let block = block(&self.members[0].0, 0);
+        let hash = block.get_hash();
+
+        let mut queue = VecDeque::new();
+        let response = self.members[0].1.new_broadcast(block);
+        self.on_response(0, response, &mut queue);
+
+        while let Some((from, to, msg)) = if network.reverse {
+            queue.pop_back()
+        } else {
+            queue.pop_front()
+        } {
+            let recipients = match to {
+                Some(to) => vec![to],
+                None => (0..self.members.len()).filter(|to| *to != from).collect(),
+            };
+            for to in recipients {
+                if self.crashed.contains(&to) {
+                    continue;
+                }
+                let protocol = &mut self.members[to].1;
+                let response = if network.duplicate {
+                    handle_double(protocol, &msg)
+                } else {
+                    protocol.handle(&msg)
+                };
+                self.on_response(to, response, &mut queue);
+            }
+        }
+        hash
+    }
+
+    fn on_response(
+        &mut self,
+        member: usize,
+        response: BroadcastResponse,
+        queue: &mut VecDeque<(usize, Option<usize>, RawRbMsg)>,
+    ) {
+        match response {
+            BroadcastResponse::Broadcast(msg) => {
+                self.sent.push(msg.clone());
+                queue.push_back((member, None, msg));
+            }
+            BroadcastResponse::SendTo(msg, peer) => {
+                let to = self
+                    .members
+                    .iter()
+                    .position(|(keypair, _)| keypair.peer_id() == peer)
+                    .expect("Message sent to a member");
+                self.sent.push(msg.clone());
+                queue.push_back((member, Some(to), msg));
+            }
+            BroadcastResponse::BroadcastAndDeliver(msg) => {
+                self.delivered[member].push(msg.block_hash());
+                self.sent.push(msg.clone());
+                queue.push_back((member, None, msg));
+            }
+            BroadcastResponse::Deliver(hash) => self.delivered[member].push(hash),
+            BroadcastResponse::Drop(_) => {}
+        }
+    }
+
+    fn assert_all_delivered(&self, hash: Hash) {
+        for (i, (_, protocol)) in self.members.iter().enumerate() {
+            if self.crashed.contains(&i) {
+                assert!(self.delivered[i].is_empty());
+                continue;
+            }
+            assert_eq!(self.delivered[i], vec![hash], "member {i} didn't deliver");
+            assert!(protocol.is_delivered(&hash));
+        }
+    }
+}
+
+fn all_members_deliver<P: BroadcastProtocol>(new: fn(Arc<Keypair>) -> P) {
+    for size in [4, 7, 10] {
+        let mut group = Group::new(size, 0, new);
+        let hash = group.broadcast(RELIABLE);
+        group.assert_all_delivered(hash);
+    }
+}
+
+fn tolerates_f_crashed_members<P: BroadcastProtocol>(new: fn(Arc<Keypair>) -> P) {
+    for (size, faulty) in [(4, 1), (7, 2), (10, 3)] {
+        let mut group = Group::new(size, faulty, new);
+        let hash = group.broadcast(RELIABLE);
+        group.assert_all_delivered(hash);
+    }
+}
+
+fn no_delivery_without_quorum<P: BroadcastProtocol>(new: fn(Arc<Keypair>) -> P) {
+    for (size, crashed) in [(4, 2), (10, 4)] {
+        let mut group = Group::new(size, crashed, new);
+        let hash = group.broadcast(RELIABLE);
+        assert!(group.delivered.iter().all(Vec::is_empty));
+        assert!(!group.members[0].1.is_delivered(&hash));
+    }
+}
+
+fn duplicate_and_reordered_messages<P: BroadcastProtocol>(new: fn(Arc<Keypair>) -> P) {
+    for network in [
+        Network {
+            duplicate: true,
+            reverse: false,
+        },
+        Network {
+            duplicate: false,
+            reverse: true,
+        },
+        Network {
+            duplicate: true,
+            reverse: true,
+        },
+    ] {
+        let mut group = Group::new(10, 3, new);
+        let hash = group.broadcast(network);
+        group.assert_all_delivered(hash);
+    }
+}
+
+fn weighted_quorum<P: BroadcastProtocol>(new: fn(Arc<Keypair>) -> P) {
+    //Total weight 8, f = 2. Two crashed members out of six, but their weight is f
+    let mut group = Group::weighted(&[3, 1, 1, 1, 1, 1], 2, new);
+    let hash = group.broadcast(RELIABLE);
+    group.assert_all_delivered(hash);
+
+    //Total weight 7, f = 2. A single crashed member out of five, but its weight is more than f
+    let mut group = Group::weighted(&[1, 1, 1, 1, 3], 1, new);
+    let hash = group.broadcast(RELIABLE);
+    assert!(group.delivered.iter().all(Vec::is_empty));
+    assert!(!group.members[0].1.is_delivered(&hash));
+}
+
+fn only_proposal_carries_block<P: BroadcastProtocol>(new: fn(Arc<Keypair>) -> P) {
+    let mut group = Group::new(4, 0, new);
+    let hash = group.broadcast(RELIABLE);
+
+    let (proposal, replies) = group.sent.split_first().unwrap();
+    assert_matches!(&proposal.message_type, MessageType::Propose(block) if block.get_hash() == hash);
+    assert_eq!(proposal.original_sender, group.members[0].0.peer_id());
+    for reply in replies {
+        assert_matches!(
+            reply.message_type,
+            MessageType::Echo(h) | MessageType::Vote(h) | MessageType::Certificate(h, _) if h == hash
+        );
+        assert_eq!(reply.id, proposal.id);
+    }
+
+    //Messages after delivery are dropped
+    let late = proposal
+        .echo_reply(&Keypair::generate(None), hash, group.group_id)
+        .unwrap();
+    assert_matches!(group.members[1].1.handle(&late), BroadcastResponse::Drop(h) if h == hash);
+
+    //The delivering phase signatures are the proof of delivery
+    for (_, protocol) in &group.members {
+        let (_, certificates) = protocol.delivery_certificates(&hash).unwrap();
+        assert!(certificates.len() >= 3);
+    }
+}
+
+fn invalid_signatures_not_counted<P: BroadcastProtocol>(new: fn(Arc<Keypair>) -> P) {
+    let mut group = Group::new(4, 0, new);
+    let group_id = group.group_id;
+    let keypairs = group
+        .members
+        .iter()
+        .map(|(keypair, _)| keypair.clone())
+        .collect::<Vec<_>>();
+    let block = block(&keypairs[0], 0);
+    let hash = block.get_hash();
+
+    //Every protocol counts echoes at the creator
+    let creator = &mut group.members[0].1;
+    let BroadcastResponse::Broadcast(proposal) = creator.new_broadcast(block) else {
+        panic!("Creator didn't send its proposal");
+    };
+
+    let mut replayed_vote = proposal.vote_reply(&keypairs[1], hash, group_id).unwrap();
+    replayed_vote.message_type = MessageType::Echo(hash);
+    let mut other_sender = proposal.echo_reply(&keypairs[1], hash, group_id).unwrap();
+    other_sender.original_sender = keypairs[3].peer_id();
+    let invalid = [
+        //Signed for another group
+        proposal
+            .echo_reply(&keypairs[1], hash, Hash::new([1; 32]))
+            .unwrap(),
+        //Vote signature replayed as an echo
+        replayed_vote,
+        //Signed by another member than the sender
+        other_sender,
+    ];
+    for msg in &invalid {
+        assert_matches!(creator.handle(msg), BroadcastResponse::Drop(h) if h == hash);
+    }
+
+    //Creator and two more members reach the threshold of 3
+    let echo = proposal.echo_reply(&keypairs[1], hash, group_id).unwrap();
+    assert_matches!(creator.handle(&echo), BroadcastResponse::Drop(h) if h == hash);
+    let echo = proposal.echo_reply(&keypairs[2], hash, group_id).unwrap();
+    assert!(!matches!(creator.handle(&echo), BroadcastResponse::Drop(_)));
+}
+
+fn inactive_group<P: BroadcastProtocol>(new: fn(Arc<Keypair>) -> P) {
+    let mut protocol = new(Arc::new(Keypair::generate(None)));
+    assert!(!protocol.is_group_active());
+
+    let block = block(&Keypair::generate(None), 0);
+    let hash = block.get_hash();
+    let group_id = Quorum::new(0).group_id;
+    let proposal = RawRbMsg::new(block, &Keypair::generate(None), group_id).unwrap();
+    let responses = iter::once(protocol.handle(&proposal))
+        .chain(
+            iter::repeat_with(|| Keypair::generate(None))
+                .take(10)
+                .map(|peer| protocol.handle(&proposal.echo_reply(&peer, hash, group_id).unwrap())),
+        )
+        .collect::<Vec<_>>();
+    assert!(responses.iter().all(|response| !matches!(
+        response,
+        BroadcastResponse::Deliver(_) | BroadcastResponse::BroadcastAndDeliver(_)
+    )));
+
+    protocol.group_updated(Quorum::new(4));
+    assert!(protocol.is_group_active());
+}
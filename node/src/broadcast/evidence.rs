@@ -0,0 +1,417 @@
+//! # Equivocation evidence
+//!
+//! A peer equivocates when it signs two different blocks of the same creator at the same height and view.
+//! Every broadcast message carries the sender's signature of the block header, so two such messages
+//! are a proof which anyone can verify without trusting the node which reported it:
+//! - the creator signing two blocks is a conflicting proposal
+//! - any other peer signing two blocks is a conflicting vote
+//!
+//! [`EquivocationDetector`] remembers the first header each peer signed per creator, height and view. When the same peer
+//! signs a different header, it creates an [`Evidence`] record with both headers and certificates, signed by the
+//! local node. Only one record is created per peer, creator, height and view.
+//!
+//! A leader proposes at most one block per height and view. If its block isn't committed in time, the leader of the
+//! next view proposes in a new view, so it's not equivocation. Honest nodes take part only in the first proposal
+//! they see from a creator at a height and view, see [`EquivocationDetector::is_first_proposal`], so they don't
+//! sign conflicting blocks of an equivocating creator.
+
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use log::{debug, warn};
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block::types::block::BlockHeader,
+    codec::Encode,
+    crypto::Keypair,
+    peer::{PeerId, ToPeerId},
+    utilities::{
+        codec::{Codec, EncodingError, EphemeraCodec},
+        crypto::Certificate,
+        hash::Hash,
+        time::EphemeraTime,
+    },
+};
+
+/// How many (signer, creator, height, view) entries the detector remembers.
+const SIGNED_HEADERS_CACHE_SIZE: usize = 10_000;
+
+/// How many (creator, height, view) first proposals the detector remembers.
+const PROPOSALS_CACHE_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) enum EquivocationKind {
+    /// The creator signed two blocks at the same height and view
+    ConflictingProposal,
+    /// A peer signed two blocks of the same creator at the same height and view
+    ConflictingVote,
+}
+
+/// Block header and the offender's signature of it, as received in a broadcast message.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct SignedHeader {
+    pub(crate) header: BlockHeader,
+    pub(crate) certificate: Certificate,
+}
+
+/// Evidence content which the reporting node signs.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct RawEvidence {
+    pub(crate) offender: PeerId,
+    pub(crate) kind: EquivocationKind,
+    pub(crate) creator: PeerId,
+    pub(crate) height: u64,
+    pub(crate) view: u64,
+    /// The header the offender signed first
+    pub(crate) first: SignedHeader,
+    /// The conflicting header
+    pub(crate) second: SignedHeader,
+    pub(crate) detected_at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct Evidence {
+    pub(crate) evidence: RawEvidence,
+    /// Signature of the reporting node
+    pub(crate) certificate: Certificate,
+}
+
+impl Evidence {
+    pub(crate) fn new(evidence: RawEvidence, keypair: &Keypair) -> anyhow::Result<Self> {
+        let certificate = Certificate::prepare(keypair, &evidence)?;
+        Ok(Self {
+            evidence,
+            certificate,
+        })
+    }
+
+    /// Checks that the evidence proves equivocation and is signed by the reporter.
+    ///
+    /// # Errors
+    /// If the evidence is invalid.
+    pub(crate) fn verify(&self) -> anyhow::Result<()> {
+        let RawEvidence {
+            offender,
+            kind,
+            creator,
+            height,
+            view,
+            first,
+            second,
+            ..
+        } = &self.evidence;
+
+        for signed in [first, second] {
+            if signed.header.creator != *creator
+                || signed.header.height != *height
+                || signed.header.view != *view
+            {
+                return Err(anyhow!(
+                    "Header {} is not from creator {creator} at height {height} view {view}",
+                    signed.header.hash
+                ));
+            }
+            if !signed.header.verify_hash()? {
+                return Err(anyhow!("Header {} hash is invalid", signed.header.hash));
+            }
+            if signed.certificate.public_key.peer_id() != *offender
+                || !signed.header.verify(&signed.certificate)?
+            {
+                return Err(anyhow!(
+                    "Header {} is not signed by {offender}",
+                    signed.header.hash
+                ));
+            }
+        }
+        if first.header.hash == second.header.hash {
+            return Err(anyhow!("Headers are the same"));
+        }
+        let expected_kind = if offender == creator {
+            EquivocationKind::ConflictingProposal
+        } else {
+            EquivocationKind::ConflictingVote
+        };
+        if *kind != expected_kind {
+            return Err(anyhow!(
+                "Evidence kind {kind:?}, expected {expected_kind:?}"
+            ));
+        }
+        if !self.certificate.verify(&self.evidence)? {
+            return Err(anyhow!("Reporter signature is invalid"));
+        }
+        Ok(())
+    }
+}
+
+impl Encode for RawEvidence {
+    fn encode(&self) -> Result<Vec<u8>, EncodingError> {
+        Codec::encode(&self)
+    }
+}
+
+struct SignedEntry {
+    first: SignedHeader,
+    reported: bool,
+}
+
+pub(crate) struct EquivocationDetector {
+    /// Signs evidence records
+    keypair: Arc<Keypair>,
+    /// First header each peer signed, keyed by (signer, creator, height, view)
+    signed: LruCache<(PeerId, PeerId, u64, u64), SignedEntry>,
+    /// First block seen from each creator, keyed by (creator, height, view)
+    proposals: LruCache<(PeerId, u64, u64), Hash>,
+}
+
+impl EquivocationDetector {
+    pub(crate) fn new(keypair: Arc<Keypair>) -> Self {
+        Self {
+            keypair,
+            signed: LruCache::new(NonZeroUsize::new(SIGNED_HEADERS_CACHE_SIZE).unwrap()),
+            proposals: LruCache::new(NonZeroUsize::new(PROPOSALS_CACHE_SIZE).unwrap()),
+        }
+    }
+
+    /// Returns true if the header is the first block we saw from its creator at its height and view.
+    ///
+    /// A creator proposes only one block per height and view. Nodes take part in the broadcast of the first one
+    /// only, so they don't sign a second proposal of an equivocating creator and don't become offenders themselves.
+    pub(crate) fn is_first_proposal(&mut self, header: &BlockHeader) -> bool {
+        let key = (header.creator, header.height, header.view);
+        if let Some(first) = self.proposals.get(&key) {
+            return *first == header.hash;
+        }
+        self.proposals.put(key, header.hash);
+        true
+    }
+
+    /// Checks a header signed by a broadcast message sender.
+    ///
+    /// Headers with an invalid hash or signature don't prove anything and are ignored.
+    ///
+    /// # Returns
+    /// Evidence if the signer already signed a different header of the same creator at the same height and view.
+    ///
+    /// # Errors
+    /// If the header can't be verified or the evidence can't be signed.
+    pub(crate) fn on_signed_header(
+        &mut self,
+        header: &BlockHeader,
+        certificate: &Certificate,
+    ) -> anyhow::Result<Option<Evidence>> {
+        if !header.verify_hash()? || !header.verify(certificate)? {
+            debug!("Ignoring invalid header {} signature", header.hash);
+            return Ok(None);
+        }
+
+        let signer = certificate.public_key.peer_id();
+        let key = (signer, header.creator, header.height, header.view);
+        let Some(entry) = self.signed.get_mut(&key) else {
+            let first = SignedHeader {
+                header: header.clone(),
+                certificate: certificate.clone(),
+            };
+            self.signed.put(
+                key,
+                SignedEntry {
+                    first,
+                    reported: false,
+                },
+            );
+            return Ok(None);
+        };
+
+        if entry.first.header.hash == header.hash || entry.reported {
+            return Ok(None);
+        }
+        entry.reported = true;
+
+        let kind = if signer == header.creator {
+            EquivocationKind::ConflictingProposal
+        } else {
+            EquivocationKind::ConflictingVote
+        };
+        warn!(
+            "Peer {signer} signed blocks {} and {} of {} at height {} view {}",
+            entry.first.header.hash, header.hash, header.creator, header.height, header.view
+        );
+
+        let evidence = RawEvidence {
+            offender: signer,
+            kind,
+            creator: header.creator,
+            height: header.height,
+            view: header.view,
+            first: entry.first.clone(),
+            second: SignedHeader {
+                header: header.clone(),
+                certificate: certificate.clone(),
+            },
+            detected_at: EphemeraTime::now(),
+        };
+        Evidence::new(evidence, &self.keypair).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::block::types::block::RawBlockHeader;
+    use crate::crypto::EphemeraKeypair;
+    use crate::utilities::test_utils::raw_header;
+
+    use super::*;
+
+    #[test]
+    fn test_conflicting_proposal() {
+        let creator = Arc::new(Keypair::generate(None));
+        let mut detector = EquivocationDetector::new(Arc::new(Keypair::generate(None)));
+
+        let first = header(&creator, 1, 0, 1);
+        let second = header(&creator, 1, 0, 2);
+        assert!(detector
+            .on_signed_header(&first, &sign(&first, &creator))
+            .unwrap()
+            .is_none());
+        //Same header again is not equivocation
+        assert!(detector
+            .on_signed_header(&first, &sign(&first, &creator))
+            .unwrap()
+            .is_none());
+
+        let evidence = detector
+            .on_signed_header(&second, &sign(&second, &creator))
+            .unwrap()
+            .unwrap();
+        evidence.verify().unwrap();
+        assert_eq!(evidence.evidence.offender, creator.peer_id());
+        assert_eq!(
+            evidence.evidence.kind,
+            EquivocationKind::ConflictingProposal
+        );
+        assert_eq!(evidence.evidence.first.header, first);
+        assert_eq!(evidence.evidence.second.header, second);
+
+        //Reported only once
+        let third = header(&creator, 1, 0, 3);
+        assert!(detector
+            .on_signed_header(&third, &sign(&third, &creator))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_conflicting_vote() {
+        let creator = Arc::new(Keypair::generate(None));
+        let voter = Arc::new(Keypair::generate(None));
+        let mut detector = EquivocationDetector::new(Arc::new(Keypair::generate(None)));
+
+        let first = header(&creator, 1, 0, 1);
+        let second = header(&creator, 1, 0, 2);
+        let next_height = header(&creator, 2, 0, 3);
+        for header in [&first, &next_height] {
+            assert!(detector
+                .on_signed_header(header, &sign(header, &voter))
+                .unwrap()
+                .is_none());
+        }
+
+        let evidence = detector
+            .on_signed_header(&second, &sign(&second, &voter))
+            .unwrap()
+            .unwrap();
+        evidence.verify().unwrap();
+        assert_eq!(evidence.evidence.offender, voter.peer_id());
+        assert_eq!(evidence.evidence.kind, EquivocationKind::ConflictingVote);
+    }
+
+    #[test]
+    fn test_proposals_in_different_views() {
+        let creator = Arc::new(Keypair::generate(None));
+        let voter = Arc::new(Keypair::generate(None));
+        let mut detector = EquivocationDetector::new(Arc::new(Keypair::generate(None)));
+
+        //Leader of the next view proposes again at the same height
+        let first = header(&creator, 1, 0, 1);
+        let second = header(&creator, 1, 1, 2);
+        for header in [&first, &second] {
+            for signer in [&creator, &voter] {
+                assert!(detector
+                    .on_signed_header(header, &sign(header, signer))
+                    .unwrap()
+                    .is_none());
+            }
+        }
+
+        //Evidence headers need to be from the same view
+        let conflicting = header(&creator, 1, 1, 3);
+        let mut evidence = detector
+            .on_signed_header(&conflicting, &sign(&conflicting, &creator))
+            .unwrap()
+            .unwrap();
+        evidence.verify().unwrap();
+        assert_eq!(evidence.evidence.view, 1);
+        evidence.evidence.first = SignedHeader {
+            header: first.clone(),
+            certificate: sign(&first, &creator),
+        };
+        evidence.certificate = Certificate::prepare(&detector.keypair, &evidence.evidence).unwrap();
+        assert!(evidence.verify().is_err());
+    }
+
+    #[test]
+    fn test_first_proposal() {
+        let creator = Arc::new(Keypair::generate(None));
+        let mut detector = EquivocationDetector::new(Arc::new(Keypair::generate(None)));
+
+        let first = header(&creator, 1, 0, 1);
+        let second = header(&creator, 1, 0, 2);
+        assert!(detector.is_first_proposal(&first));
+        assert!(detector.is_first_proposal(&first));
+        assert!(!detector.is_first_proposal(&second));
+
+        //A new view is a new proposal
+        assert!(detector.is_first_proposal(&header(&creator, 1, 1, 3)));
+    }
+
+    #[test]
+    fn test_invalid_signatures_and_evidence() {
+        let creator = Arc::new(Keypair::generate(None));
+        let other = Arc::new(Keypair::generate(None));
+        let mut detector = EquivocationDetector::new(Arc::new(Keypair::generate(None)));
+
+        let first = header(&creator, 1, 0, 1);
+        let second = header(&creator, 1, 0, 2);
+        assert!(detector
+            .on_signed_header(&first, &sign(&first, &creator))
+            .unwrap()
+            .is_none());
+        //Certificate doesn't sign the header
+        assert!(detector
+            .on_signed_header(&second, &sign(&first, &creator))
+            .unwrap()
+            .is_none());
+
+        let mut evidence = detector
+            .on_signed_header(&second, &sign(&second, &creator))
+            .unwrap()
+            .unwrap();
+        evidence.evidence.second.certificate = sign(&second, &other);
+        assert!(evidence.verify().is_err());
+    }
+
+    fn header(creator: &Keypair, height: u64, view: u64, timestamp: u64) -> BlockHeader {
+        let mut raw = raw_header(creator.peer_id(), height);
+        raw.view = view;
+        raw.timestamp = timestamp;
+        let hash = raw.hash_with_default_hasher().unwrap();
+        BlockHeader::new(&raw, hash)
+    }
+
+    fn sign(header: &BlockHeader, keypair: &Keypair) -> Certificate {
+        let raw: RawBlockHeader = header.clone().into();
+        Certificate::prepare(keypair, &raw).unwrap()
+    }
+}
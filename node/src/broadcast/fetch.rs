@@ -0,0 +1,276 @@
+//! # Block fetching
+//!
+//! Only the proposal of a broadcast carries the block, echo and vote messages carry just its hash.
+//! Messages can arrive out of order, so a node can see echoes or votes before the proposal. Such messages
+//! are kept aside until the block is known and the block is requested from the peer which sent the message.
+//!
+//! A peer which echoed or voted for a block has it, so any sender is a good source. If the block doesn't
+//! arrive in time, it is requested again from the sender of the next message for the same block.
+//!
+//! A block we don't know yet is broadcast in the current group. Only messages from its members with a valid phase
+//! signature are kept aside, and each sender can have only a limited number of them waiting.
+
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use log::{debug, error, trace};
+use lru::LruCache;
+
+use crate::{
+    block::types::block::Block,
+    broadcast::{group::GroupSnapshot, signing::RawPhaseVote, RbMsg},
+    peer::PeerId,
+    utilities::hash::Hash,
+};
+
+/// How many blocks and block requests we keep track of.
+const BLOCKS_CACHE_SIZE: usize = 1000;
+
+/// Maximum number of messages kept aside for a single block.
+/// Every peer sends an echo and a vote, so it's more than enough for any reasonable group.
+const MAX_PENDING_PER_BLOCK: usize = 1000;
+
+/// Maximum number of messages kept aside from a single sender.
+/// An honest peer sends an echo and a vote per block and only a few blocks are broadcast at once.
+const MAX_PENDING_PER_SENDER: usize = 100;
+
+/// How long to wait for a requested block before asking another peer.
+const REQUEST_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+pub(crate) struct BlockFetcher {
+    /// Blocks from proposals and fetched blocks, with verified hash
+    blocks: LruCache<Hash, Block>,
+    /// Messages waiting for their block
+    pending: LruCache<Hash, Vec<RbMsg>>,
+    /// Number of messages waiting for their block by sender
+    pending_per_sender: HashMap<PeerId, usize>,
+    /// When we last requested a block
+    requested: LruCache<Hash, Instant>,
+}
+
+impl BlockFetcher {
+    pub(crate) fn new() -> Self {
+        let cache_size = NonZeroUsize::new(BLOCKS_CACHE_SIZE).unwrap();
+        Self {
+            blocks: LruCache::new(cache_size),
+            pending: LruCache::new(cache_size),
+            pending_per_sender: HashMap::new(),
+            requested: LruCache::new(cache_size),
+        }
+    }
+
+    /// Block from a proposal or a fetch response.
+    ///
+    /// # Returns
+    /// Messages which were waiting for the block.
+    ///
+    /// # Errors
+    /// If the block hash doesn't match its content.
+    pub(crate) fn on_block(&mut self, block: &Block) -> anyhow::Result<Vec<RbMsg>> {
+        let hash = block.hash_with_default_hasher()?;
+        if block.header.hash != hash {
+            return Err(anyhow!(
+                "Block hash is invalid: {} != {hash}",
+                block.header.hash
+            ));
+        }
+
+        self.requested.pop(&hash);
+        self.blocks.put(hash, block.clone());
+        let pending = self.pending.pop(&hash).unwrap_or_default();
+        self.release(&pending);
+        trace!("Block {hash} is known, {} messages pending", pending.len());
+        Ok(pending)
+    }
+
+    pub(crate) fn get(&mut self, hash: &Hash) -> Option<Block> {
+        self.blocks.get(hash).cloned()
+    }
+
+    /// Keeps aside a message whose block we don't have. The message is dropped if its sender isn't a member
+    /// of `group`, its phase signature is invalid or the sender has too many messages waiting.
+    ///
+    /// # Returns
+    /// True if the block should be requested from the message sender.
+    pub(crate) fn on_missing_block(&mut self, msg: RbMsg, group: &GroupSnapshot) -> bool {
+        let hash = msg.block_hash();
+        let sender = msg.original_sender;
+        if !group.members.contains(&sender) {
+            debug!("Sender of {msg} is not a member of the current group, dropping it");
+            return false;
+        }
+        if !Self::verify_phase_signature(&msg, group) {
+            debug!("Invalid phase signature of {msg}, dropping it");
+            return false;
+        }
+        if self.pending_per_sender.get(&sender).copied().unwrap_or(0) >= MAX_PENDING_PER_SENDER {
+            debug!("Too many messages waiting from {sender}, dropping {msg}");
+            return false;
+        }
+
+        match self.pending.get_mut(&hash) {
+            Some(pending) if pending.len() >= MAX_PENDING_PER_BLOCK => {
+                debug!("Too many messages waiting for block {hash}, dropping {msg}");
+                return false;
+            }
+            Some(pending) => pending.push(msg),
+            None => {
+                if let Some((_, evicted)) = self.pending.push(hash, vec![msg]) {
+                    self.release(&evicted);
+                }
+            }
+        }
+        *self.pending_per_sender.entry(sender).or_default() += 1;
+
+        let now = Instant::now();
+        match self.requested.get(&hash) {
+            Some(last) if now.duration_since(*last) < REQUEST_RETRY_INTERVAL => false,
+            _ => {
+                self.requested.put(hash, now);
+                true
+            }
+        }
+    }
+
+    fn verify_phase_signature(msg: &RbMsg, group: &GroupSnapshot) -> bool {
+        let vote = RawPhaseVote::new(msg.block_hash(), msg.phase.phase(), group.quorum().group_id);
+        match vote.verify(&msg.phase_certificate, &msg.original_sender) {
+            Ok(valid) => valid,
+            Err(err) => {
+                error!("Failed to verify {:?} signature: {err:?}", msg.id);
+                false
+            }
+        }
+    }
+
+    /// Messages stopped waiting for their block.
+    fn release(&mut self, msgs: &[RbMsg]) {
+        for msg in msgs {
+            if let Some(count) = self.pending_per_sender.get_mut(&msg.original_sender) {
+                *count -= 1;
+                if *count == 0 {
+                    self.pending_per_sender.remove(&msg.original_sender);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use crate::broadcast::RawRbMsg;
+    use crate::crypto::{EphemeraKeypair, Keypair};
+    use crate::peer::ToPeerId;
+    use crate::utilities::test_utils::block;
+
+    use super::*;
+
+    #[test]
+    fn test_pending_messages_replayed_on_block() {
+        let keypair = Keypair::generate(None);
+        let group = group(&keypair);
+        let block = block(&keypair, 1);
+        let hash = block.get_hash();
+        let mut fetcher = BlockFetcher::new();
+
+        let group_id = group.quorum().group_id;
+        let proposal = RawRbMsg::new(block.clone(), &keypair, group_id).unwrap();
+        let echo = proposal.echo_reply(&keypair, hash, group_id).unwrap();
+        let vote = proposal.vote_reply(&keypair, hash, group_id).unwrap();
+
+        //Block is requested once per retry interval
+        assert!(fetcher.on_missing_block(sign(echo, &keypair), &group));
+        assert!(!fetcher.on_missing_block(sign(vote, &keypair), &group));
+        assert!(fetcher.get(&hash).is_none());
+
+        let pending = fetcher.on_block(&block).unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(fetcher.get(&hash), Some(block.clone()));
+        assert!(fetcher.on_block(&block).unwrap().is_empty());
+        assert!(fetcher.pending_per_sender.is_empty());
+    }
+
+    #[test]
+    fn test_unverified_messages_not_kept() {
+        let keypair = Keypair::generate(None);
+        let outsider = Keypair::generate(None);
+        let group = group(&keypair);
+        let block = block(&keypair, 1);
+        let hash = block.get_hash();
+        let mut fetcher = BlockFetcher::new();
+
+        //Sender is not a member of the group
+        let group_id = group.quorum().group_id;
+        let echo = RawRbMsg::new(block.clone(), &outsider, group_id)
+            .unwrap()
+            .echo_reply(&outsider, hash, group_id)
+            .unwrap();
+        assert!(!fetcher.on_missing_block(sign(echo, &outsider), &group));
+
+        //Signed for another group
+        let other_group_id = Hash::new([1; 32]);
+        let echo = RawRbMsg::new(block.clone(), &keypair, other_group_id)
+            .unwrap()
+            .echo_reply(&keypair, hash, other_group_id)
+            .unwrap();
+        assert!(!fetcher.on_missing_block(sign(echo, &keypair), &group));
+
+        assert!(fetcher.on_block(&block).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_pending_messages_limited_per_sender() {
+        let keypair = Keypair::generate(None);
+        let group = group(&keypair);
+        let group_id = group.quorum().group_id;
+        let mut fetcher = BlockFetcher::new();
+
+        let blocks = (1..=MAX_PENDING_PER_SENDER as u64 + 1)
+            .map(|height| block(&keypair, height))
+            .collect::<Vec<_>>();
+        for block in &blocks {
+            let echo = RawRbMsg::new(block.clone(), &keypair, group_id)
+                .unwrap()
+                .echo_reply(&keypair, block.get_hash(), group_id)
+                .unwrap();
+            fetcher.on_missing_block(sign(echo, &keypair), &group);
+        }
+        assert_eq!(
+            fetcher.pending_per_sender.get(&keypair.peer_id()),
+            Some(&MAX_PENDING_PER_SENDER)
+        );
+        assert!(fetcher.on_block(blocks.last().unwrap()).unwrap().is_empty());
+
+        //Sender can park messages again once its blocks arrive
+        assert_eq!(fetcher.on_block(&blocks[0]).unwrap().len(), 1);
+        assert_eq!(
+            fetcher.pending_per_sender.get(&keypair.peer_id()),
+            Some(&(MAX_PENDING_PER_SENDER - 1))
+        );
+    }
+
+    #[test]
+    fn test_invalid_block_rejected() {
+        let keypair = Keypair::generate(None);
+        let other_hash = block(&keypair, 2).get_hash();
+        let mut block = block(&keypair, 1);
+        block.header.hash = other_hash;
+        let mut fetcher = BlockFetcher::new();
+
+        assert!(fetcher.on_block(&block).is_err());
+        assert!(fetcher.get(&block.get_hash()).is_none());
+    }
+
+    fn group(keypair: &Keypair) -> GroupSnapshot {
+        HashSet::from([keypair.peer_id()]).into()
+    }
+
+    fn sign(raw: RawRbMsg, keypair: &Keypair) -> RbMsg {
+        let certificate = block(keypair, 1).sign(keypair).unwrap();
+        RbMsg::new(raw, certificate)
+    }
+}
@@ -1,17 +1,52 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::num::NonZeroUsize;
 
 use log::warn;
 use lru::LruCache;
 
+use crate::block::leader;
+use crate::broadcast::bracha::quorum::Quorum;
 use crate::peer::PeerId;
 use crate::utilities::hash::Hash;
 
+/// Members of the group and their weights, as the membership provider reported them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct GroupSnapshot {
+    pub(crate) members: HashSet<PeerId>,
+    /// Members without a weight have the default weight
+    pub(crate) weights: HashMap<PeerId, u64>,
+}
+
+impl GroupSnapshot {
+    pub(crate) fn quorum(&self) -> Quorum {
+        Quorum::weighted(&self.members, &self.weights)
+    }
+}
+
+/// Members with the default weight.
+impl From<HashSet<PeerId>> for GroupSnapshot {
+    fn from(members: HashSet<PeerId>) -> Self {
+        Self {
+            members,
+            weights: HashMap::new(),
+        }
+    }
+}
+
+impl From<HashMap<PeerId, u64>> for GroupSnapshot {
+    fn from(weights: HashMap<PeerId, u64>) -> Self {
+        Self {
+            members: weights.keys().copied().collect(),
+            weights,
+        }
+    }
+}
+
 pub(crate) struct BroadcastGroup {
     /// The id of current group. Incremented every time a new snapshot is added.
     pub(crate) current_id: u64,
     /// A cache of the group snapshots.
-    pub(crate) snapshots: LruCache<u64, HashSet<PeerId>>,
+    pub(crate) snapshots: LruCache<u64, GroupSnapshot>,
     /// A cache of the groups for each block.
     pub(crate) broadcast_groups: LruCache<Hash, u64>,
 }
@@ -19,7 +54,7 @@ pub(crate) struct BroadcastGroup {
 impl BroadcastGroup {
     pub(crate) fn new() -> BroadcastGroup {
         let mut snapshots = LruCache::new(NonZeroUsize::new(100).unwrap());
-        snapshots.put(0, HashSet::new());
+        snapshots.put(0, GroupSnapshot::default());
         BroadcastGroup {
             current_id: 0,
             snapshots,
@@ -27,25 +62,29 @@ impl BroadcastGroup {
         }
     }
 
-    pub(crate) fn add_snapshot(&mut self, snapshot: HashSet<PeerId>) {
+    pub(crate) fn add_snapshot(&mut self, snapshot: impl Into<GroupSnapshot>) {
         self.current_id += 1;
-        self.snapshots.put(self.current_id, snapshot);
+        self.snapshots.put(self.current_id, snapshot.into());
     }
 
     pub(crate) fn is_member(&mut self, id: u64, peer_id: &PeerId) -> bool {
         self.snapshots
             .get(&id)
-            .map_or(false, |s| s.contains(peer_id))
+            .map_or(false, |s| s.members.contains(peer_id))
     }
 
     pub(crate) fn is_empty(&mut self) -> bool {
         self.snapshots
             .get(&self.current_id)
-            .map_or(true, HashSet::is_empty)
+            .map_or(true, |s| s.members.is_empty())
     }
 
     // Returns empty snapshots(inserted in 'new' fn) if we haven't received any yet.
     pub(crate) fn current(&mut self) -> &HashSet<PeerId> {
+        &self.current_snapshot().members
+    }
+
+    pub(crate) fn current_snapshot(&mut self) -> &GroupSnapshot {
         self.snapshots
             .get(&self.current_id)
             .expect("Current group should always exist")
@@ -54,9 +93,14 @@ impl BroadcastGroup {
     // Checks if creator and sender are part of the expected group.
     // If we see hash first time, it checks against the current group. And if check passes, it
     // associates the hash with the current group.
+    // New blocks are also checked that their creator is the leader at block height and view, and that the view
+    // is not after `max_view`.
     pub(crate) fn check_membership(
         &mut self,
         hash: Hash,
+        height: u64,
+        view: u64,
+        max_view: u64,
         block_creator: &PeerId,
         message_sender: &PeerId,
     ) -> bool {
@@ -70,6 +114,14 @@ impl BroadcastGroup {
                 );
                 return false;
             }
+
+            //Only the leader proposes blocks
+            if !leader::is_leader(self.current(), height, view, max_view, block_creator) {
+                warn!(
+                    "Received new block {hash} but creator {block_creator} is not the leader at height {height} view {view}"
+                );
+                return false;
+            }
         }
 
         //Make sure that the sender peer_id and block peer_id are part of the block initial group
@@ -105,7 +157,19 @@ impl BroadcastGroup {
         true
     }
 
-    pub(crate) fn get_group_by_block_hash(&mut self, hash: Hash) -> Option<&HashSet<PeerId>> {
+    /// Returns the most recent snapshot with exactly these members.
+    ///
+    /// Used to check a group which a peer claims, against what the membership provider reported to us.
+    pub(crate) fn find_snapshot(&self, members: &HashSet<PeerId>) -> Option<&GroupSnapshot> {
+        self.snapshots
+            .iter()
+            .filter(|(_, snapshot)| !snapshot.members.is_empty() && snapshot.members == *members)
+            .max_by_key(|(id, _)| **id)
+            .map(|(_, snapshot)| snapshot)
+    }
+
+    /// Returns the group which broadcast the block, with the weights it had at the time.
+    pub(crate) fn get_group_by_block_hash(&mut self, hash: Hash) -> Option<&GroupSnapshot> {
         let membership_id = *self.broadcast_groups.get(&hash)?;
         self.snapshots.get(&membership_id)
     }
@@ -113,8 +177,9 @@ impl BroadcastGroup {
 
 #[cfg(test)]
 mod test {
-    use std::collections::HashSet;
+    use std::collections::{HashMap, HashSet};
 
+    use crate::block::leader;
     use crate::broadcast::group::BroadcastGroup;
     use crate::peer::PeerId;
     use crate::utilities::hash::Hash;
@@ -140,7 +205,7 @@ mod test {
             .map(|(i, sn)| ((i + 1) as u64, sn))
         {
             let gsn = group.snapshots.get(&i).unwrap();
-            assert_eq!(sn, gsn);
+            assert_eq!(sn, &gsn.members);
         }
     }
 
@@ -148,14 +213,21 @@ mod test {
     fn check_membership_empty_group() {
         let mut group = BroadcastGroup::new();
         let hash = Hash::new([0; 32]);
-        assert!(!group.check_membership(hash, &PeerId::random(), &PeerId::random()));
+        assert!(!group.check_membership(hash, 1, 0, 0, &PeerId::random(), &PeerId::random()));
         assert!(!group.broadcast_groups.contains(&hash));
     }
 
     #[test]
     fn check_membership_creator_nor_sender_not_member() {
         let (mut group, _snapshots) = group_with_snapshots(1);
-        assert!(!group.check_membership(Hash::new([0; 32]), &PeerId::random(), &PeerId::random()));
+        assert!(!group.check_membership(
+            Hash::new([0; 32]),
+            1,
+            0,
+            0,
+            &PeerId::random(),
+            &PeerId::random()
+        ));
         assert!(!group.broadcast_groups.contains(&Hash::new([0; 32])));
     }
 
@@ -165,7 +237,7 @@ mod test {
         let sender = snapshots[0].clone().into_iter().next().unwrap();
 
         let hash = Hash::new([0; 32]);
-        assert!(!group.check_membership(hash, &PeerId::random(), &sender));
+        assert!(!group.check_membership(hash, 1, 0, 0, &PeerId::random(), &sender));
         assert!(!group.broadcast_groups.contains(&hash));
     }
 
@@ -174,7 +246,7 @@ mod test {
         let (mut group, snapshots) = group_with_snapshots(1);
         let creator = snapshots[0].clone().into_iter().next().unwrap();
         let hash = Hash::new([0; 32]);
-        assert!(!group.check_membership(hash, &creator, &PeerId::random()));
+        assert!(!group.check_membership(hash, 1, 0, 0, &creator, &PeerId::random()));
         assert!(!group.broadcast_groups.contains(&hash));
     }
 
@@ -184,7 +256,7 @@ mod test {
         let creator = snapshots[0].clone().into_iter().next().unwrap();
         let sender = creator;
         let hash = Hash::new([0; 32]);
-        assert!(group.check_membership(hash, &creator, &sender));
+        assert!(group.check_membership(hash, 1, 0, 0, &creator, &sender));
         assert!(group.broadcast_groups.contains(&hash));
     }
 
@@ -196,14 +268,14 @@ mod test {
         let sender = creator;
 
         let hash = Hash::new([0; 32]);
-        assert!(group.check_membership(hash, &creator, &sender));
+        assert!(group.check_membership(hash, 1, 0, 0, &creator, &sender));
         assert!(group.broadcast_groups.contains(&hash));
 
         //Remove the current snapshot
         group.snapshots.pop(&group.current_id);
 
         //Membership should fail
-        assert!(!group.check_membership(hash, &creator, &sender));
+        assert!(!group.check_membership(hash, 1, 0, 0, &creator, &sender));
     }
 
     #[test]
@@ -215,7 +287,7 @@ mod test {
         let creator = first_snapshot.into_iter().next().unwrap();
         let sender = creator;
         let hash = Hash::new([0; 32]);
-        assert!(group.check_membership(hash, &creator, &sender));
+        assert!(group.check_membership(hash, 1, 0, 0, &creator, &sender));
         assert!(group.broadcast_groups.contains(&hash));
 
         //Add second snapshot
@@ -226,7 +298,55 @@ mod test {
 
         //Membership should still pass
         assert!(group.broadcast_groups.contains(&hash));
-        assert!(group.check_membership(hash, &creator, &sender));
+        assert!(group.check_membership(hash, 1, 0, 0, &creator, &sender));
+    }
+
+    #[test]
+    fn block_group_keeps_weights_of_its_snapshot() {
+        let mut group = BroadcastGroup::new();
+        let member = PeerId::random();
+        group.add_snapshot(HashMap::from([(member, 10)]));
+
+        let hash = Hash::new([0; 32]);
+        assert!(group.check_membership(hash, 1, 0, 0, &member, &member));
+        group.add_snapshot(HashMap::from([(member, 1)]));
+
+        let snapshot = group.get_group_by_block_hash(hash).unwrap();
+        assert_eq!(snapshot.members, HashSet::from([member]));
+        assert_eq!(snapshot.quorum().total_weight, 10);
+    }
+
+    #[test]
+    fn find_snapshot_by_members() {
+        let (group, snapshots) = group_with_snapshots(3);
+        let found = group.find_snapshot(&snapshots[1]).unwrap();
+        assert_eq!(found.members, snapshots[1]);
+
+        assert!(group.find_snapshot(&create_snapshot()).is_none());
+        //Initial empty snapshot is never a group
+        assert!(group.find_snapshot(&HashSet::new()).is_none());
+    }
+
+    #[test]
+    fn check_membership_creator_not_leader() {
+        let mut group = BroadcastGroup::new();
+        let snapshot = (0..3).map(|_| PeerId::random()).collect::<HashSet<_>>();
+        group.add_snapshot(snapshot.clone());
+
+        let first = leader::leader(&snapshot, 1, 0).unwrap();
+        let second = leader::leader(&snapshot, 1, 1).unwrap();
+
+        //Leader of the next view is accepted only after view change
+        let hash = Hash::new([0; 32]);
+        assert!(!group.check_membership(hash, 1, 1, 0, &second, &second));
+        assert!(!group.broadcast_groups.contains(&hash));
+        //Only for the view it leads
+        assert!(!group.check_membership(hash, 1, 0, 1, &second, &second));
+        assert!(group.check_membership(hash, 1, 1, 1, &second, &second));
+
+        let hash = Hash::new([1; 32]);
+        assert!(group.check_membership(hash, 1, 0, 0, &first, &second));
+        assert!(group.broadcast_groups.contains(&hash));
     }
 
     fn group_with_snapshots(count: usize) -> (BroadcastGroup, Vec<HashSet<PeerId>>) {
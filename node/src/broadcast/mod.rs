@@ -1,14 +1,25 @@
-//! Simple reliable broadcast protocol(Bracha) implementation
+//! Reliable broadcast of blocks within the broadcast group.
 //!
-use std::collections::HashSet;
+//! [`BroadcastProtocol`] decides what to do with protocol messages. Every echo and vote is signed over the block hash,
+//! the phase and the group id, implementations sign their own messages and verify the signatures of others before
+//! counting them. Block verification and networking are done by the caller. Available protocols:
+//! - [`bracha::broadcast::Broadcaster`] - Bracha reliable broadcast, the default
+//! - [`signed_echo::SignedEchoBroadcaster`] - signed echo consistent broadcast, for trusted deployments
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Display};
+use std::sync::Arc;
 
+use log::error;
 use serde_derive::{Deserialize, Serialize};
 
-use crate::broadcast::bracha::quorum::Quorum;
+use crate::broadcast::bracha::{broadcast::Broadcaster, quorum::Quorum};
+use crate::broadcast::signed_echo::SignedEchoBroadcaster;
+use crate::broadcast::signing::{Phase, RawPhaseVote};
 use crate::{
     block::types::block::Block,
-    peer::PeerId,
+    config::BroadcastProtocolKind,
+    crypto::Keypair,
+    peer::{PeerId, ToPeerId},
     utilities::{
         crypto::Certificate,
         hash::Hash,
@@ -18,9 +29,58 @@ use crate::{
 };
 
 pub(crate) mod bracha;
+#[cfg(test)]
+mod conformance;
+pub(crate) mod evidence;
+pub(crate) mod fetch;
 pub(crate) mod group;
+pub(crate) mod signed_echo;
 pub(crate) mod signing;
 
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub(crate) enum BroadcastResponse {
+    Broadcast(RawRbMsg),
+    /// Send the message to one member only
+    SendTo(RawRbMsg, PeerId),
+    /// Broadcast the message and deliver its block
+    BroadcastAndDeliver(RawRbMsg),
+    Deliver(Hash),
+    Drop(Hash),
+}
+
+/// Protocol which the broadcast group uses to agree on blocks. All members need to run the same protocol.
+pub(crate) trait BroadcastProtocol: Send {
+    /// Starts the broadcast of a block created by the local node.
+    fn new_broadcast(&mut self, block: Block) -> BroadcastResponse;
+
+    /// Processes a protocol message. Messages from the local node are never passed back.
+    ///
+    /// Messages whose signature doesn't match the sender, the phase or the group of the broadcast are dropped.
+    fn handle(&mut self, rb_msg: &RawRbMsg) -> BroadcastResponse;
+
+    /// True if the block was already delivered, so its messages can be dropped without the block.
+    fn is_delivered(&self, hash: &Hash) -> bool;
+
+    /// Verified signatures of the phase which delivered the block, they prove that the group committed it.
+    fn delivery_certificates(&self, hash: &Hash) -> Option<(Phase, HashSet<Certificate>)>;
+
+    /// Sets the quorum of the current group. Broadcasts already in progress keep the quorum they started with.
+    fn group_updated(&mut self, quorum: Quorum);
+
+    fn is_group_active(&self) -> bool;
+}
+
+pub(crate) fn new_protocol(
+    kind: BroadcastProtocolKind,
+    keypair: Arc<Keypair>,
+) -> Box<dyn BroadcastProtocol> {
+    match kind {
+        BroadcastProtocolKind::Bracha => Box::new(Broadcaster::new(keypair)),
+        BroadcastProtocolKind::SignedEcho => Box::new(SignedEchoBroadcaster::new(keypair)),
+    }
+}
+
 /// Context keeps the broadcast state for a block
 #[derive(Debug, Clone)]
 pub(crate) struct ProtocolContext {
@@ -31,6 +91,8 @@ pub(crate) struct ProtocolContext {
     pub(crate) echo: HashSet<PeerId>,
     /// Peers that sent commit message(this peer included)
     pub(crate) vote: HashSet<PeerId>,
+    /// Verified signatures of the echoes and votes
+    pub(crate) certificates: HashMap<Phase, HashSet<Certificate>>,
     /// Quorum logic for Bracha protocol
     pub(crate) quorum: Quorum,
     /// Flag indicating if the message was delivered to the client
@@ -44,17 +106,68 @@ impl ProtocolContext {
             hash,
             echo: HashSet::new(),
             vote: HashSet::new(),
+            certificates: HashMap::new(),
             quorum,
             delivered: false,
         }
     }
 
-    fn add_echo(&mut self, peer: PeerId) {
+    /// Checks that the message is signed by its sender for its phase, this block and the group of the broadcast.
+    fn verify(&self, rb_msg: &RawRbMsg) -> bool {
+        let vote = RawPhaseVote::new(self.hash, rb_msg.phase(), self.quorum.group_id);
+        match vote.verify(&rb_msg.signature, &rb_msg.original_sender) {
+            Ok(valid) => valid,
+            Err(err) => {
+                error!("Failed to verify {:?} signature: {err:?}", rb_msg.id);
+                false
+            }
+        }
+    }
+
+    /// Signs our echo and counts it.
+    fn echo_reply(&mut self, keypair: &Keypair, rb_msg: &RawRbMsg) -> BroadcastResponse {
+        let reply = rb_msg.echo_reply(keypair, self.hash, self.quorum.group_id);
+        self.count_reply(reply)
+    }
+
+    /// Signs our vote and counts it.
+    fn vote_reply(&mut self, keypair: &Keypair, rb_msg: &RawRbMsg) -> BroadcastResponse {
+        let reply = rb_msg.vote_reply(keypair, self.hash, self.quorum.group_id);
+        self.count_reply(reply)
+    }
+
+    fn count_reply(&mut self, reply: anyhow::Result<RawRbMsg>) -> BroadcastResponse {
+        match reply {
+            Ok(reply) => {
+                let certificate = reply.signature.clone();
+                match reply.phase() {
+                    Phase::Echo => self.add_echo(self.local_peer_id, certificate),
+                    Phase::Vote => self.add_vote(self.local_peer_id, certificate),
+                }
+                BroadcastResponse::Broadcast(reply)
+            }
+            Err(err) => {
+                error!("Failed to sign reply for block {}: {err:?}", self.hash);
+                BroadcastResponse::Drop(self.hash)
+            }
+        }
+    }
+
+    fn add_echo(&mut self, peer: PeerId, certificate: Certificate) {
         self.echo.insert(peer);
+        self.add_certificate(Phase::Echo, certificate);
     }
 
-    fn add_vote(&mut self, peer: PeerId) {
+    fn add_vote(&mut self, peer: PeerId, certificate: Certificate) {
         self.vote.insert(peer);
+        self.add_certificate(Phase::Vote, certificate);
+    }
+
+    fn add_certificate(&mut self, phase: Phase, certificate: Certificate) {
+        self.certificates
+            .entry(phase)
+            .or_default()
+            .insert(certificate);
     }
 
     fn echoed(&self) -> bool {
@@ -76,9 +189,11 @@ pub(crate) struct RbMsg {
     pub(crate) original_sender: PeerId,
     ///When the message was created by the sender.
     pub(crate) timestamp: u64,
-    ///Current phase of the protocol(Echo, Vote)
+    ///Current phase of the protocol(Propose, Echo, Vote)
     pub(crate) phase: MessageType,
-    ///Signature of the message
+    ///Sender's signature of the block hash, phase and group id
+    pub(crate) phase_certificate: Certificate,
+    ///Sender's signature of the block header
     pub(crate) certificate: Certificate,
 }
 
@@ -90,14 +205,18 @@ impl RbMsg {
             original_sender: raw.original_sender,
             timestamp: raw.timestamp,
             phase: raw.message_type,
+            phase_certificate: raw.signature,
             certificate: signature,
         }
     }
 
-    pub(crate) fn block(&self) -> &Block {
-        match &self.phase {
-            MessageType::Echo(block) | MessageType::Vote(block) => block,
-        }
+    /// Returns the block if the message is a proposal. Echo and vote carry only the block hash.
+    pub(crate) fn block(&self) -> Option<&Block> {
+        self.phase.block()
+    }
+
+    pub(crate) fn block_hash(&self) -> Hash {
+        self.phase.block_hash()
     }
 }
 
@@ -108,41 +227,73 @@ pub(crate) struct RawRbMsg {
     pub(crate) original_sender: PeerId,
     pub(crate) timestamp: u64,
     pub(crate) message_type: MessageType,
+    /// Sender's signature of the block hash, phase and group id, see [`RawPhaseVote`]
+    pub(crate) signature: Certificate,
 }
 
 impl RawRbMsg {
-    pub(crate) fn new(block: Block, original_sender: PeerId) -> RawRbMsg {
-        RawRbMsg {
-            id: EphemeraId::generate(),
+    /// Proposal of a new block, signed as the creator's echo in the group.
+    pub(crate) fn new(block: Block, keypair: &Keypair, group_id: Hash) -> anyhow::Result<RawRbMsg> {
+        Self::signed(
+            EphemeraId::generate(),
+            keypair,
+            MessageType::Propose(Box::new(block)),
+            group_id,
+        )
+    }
+
+    fn signed(
+        id: EphemeraId,
+        keypair: &Keypair,
+        message_type: MessageType,
+        group_id: Hash,
+    ) -> anyhow::Result<RawRbMsg> {
+        let signature =
+            RawPhaseVote::new(message_type.block_hash(), message_type.phase(), group_id)
+                .sign(keypair)?;
+        Ok(RawRbMsg {
+            id,
             request_id: EphemeraId::generate(),
-            original_sender,
+            original_sender: keypair.peer_id(),
             timestamp: EphemeraTime::now(),
-            message_type: MessageType::Echo(block),
-        }
+            message_type,
+            signature,
+        })
     }
 
-    pub(crate) fn block(&self) -> Block {
-        match &self.message_type {
-            MessageType::Echo(block) | MessageType::Vote(block) => block.clone(),
-        }
+    pub(crate) fn block_hash(&self) -> Hash {
+        self.message_type.block_hash()
     }
 
-    pub(crate) fn reply(&self, local_id: PeerId, phase: MessageType) -> Self {
-        RawRbMsg {
-            id: self.id.clone(),
-            request_id: EphemeraId::generate(),
-            original_sender: local_id,
-            timestamp: EphemeraTime::now(),
-            message_type: phase,
-        }
+    pub(crate) fn phase(&self) -> Phase {
+        self.message_type.phase()
     }
 
-    pub(crate) fn echo_reply(&self, local_id: PeerId, data: Block) -> Self {
-        self.reply(local_id, MessageType::Echo(data))
+    pub(crate) fn reply(
+        &self,
+        keypair: &Keypair,
+        phase: MessageType,
+        group_id: Hash,
+    ) -> anyhow::Result<Self> {
+        Self::signed(self.id.clone(), keypair, phase, group_id)
     }
 
-    pub(crate) fn vote_reply(&self, local_id: PeerId, data: Block) -> Self {
-        self.reply(local_id, MessageType::Vote(data))
+    pub(crate) fn echo_reply(
+        &self,
+        keypair: &Keypair,
+        hash: Hash,
+        group_id: Hash,
+    ) -> anyhow::Result<Self> {
+        self.reply(keypair, MessageType::Echo(hash), group_id)
+    }
+
+    pub(crate) fn vote_reply(
+        &self,
+        keypair: &Keypair,
+        hash: Hash,
+        group_id: Hash,
+    ) -> anyhow::Result<Self> {
+        self.reply(keypair, MessageType::Vote(hash), group_id)
     }
 }
 
@@ -154,6 +305,7 @@ impl From<RbMsg> for RawRbMsg {
             original_sender: msg.original_sender,
             timestamp: msg.timestamp,
             message_type: msg.phase,
+            signature: msg.phase_certificate,
         }
     }
 }
@@ -165,7 +317,7 @@ impl Display for RbMsg {
             "[id: {}, peer: {}, block: {}, phase: {:?}]",
             self.id,
             self.original_sender,
-            self.block().get_hash(),
+            self.block_hash(),
             self.phase
         )
     }
@@ -178,14 +330,47 @@ impl Debug for RbMsg {
             "[id: {}, peer: {}, block: {}, phase: {:?}]",
             self.id,
             self.original_sender,
-            self.block().get_hash(),
+            self.block_hash(),
             self.phase
         )
     }
 }
 
+/// Only the proposal carries the block, so each block is sent once to every peer.
+/// Peers who get another message before the proposal fetch the block from the sender.
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub(crate) enum MessageType {
-    Echo(Block),
-    Vote(Block),
+    /// Block from its creator. It counts as the creator's echo.
+    Propose(Box<Block>),
+    Echo(Hash),
+    Vote(Hash),
+    /// Echo signatures which the creator collected for its block, signed echo only.
+    Certificate(Hash, Vec<Certificate>),
+}
+
+impl MessageType {
+    pub(crate) fn block(&self) -> Option<&Block> {
+        match self {
+            MessageType::Propose(block) => Some(block),
+            MessageType::Echo(_) | MessageType::Vote(_) | MessageType::Certificate(..) => None,
+        }
+    }
+
+    pub(crate) fn block_hash(&self) -> Hash {
+        match self {
+            MessageType::Propose(block) => block.get_hash(),
+            MessageType::Echo(hash)
+            | MessageType::Vote(hash)
+            | MessageType::Certificate(hash, _) => *hash,
+        }
+    }
+
+    pub(crate) fn phase(&self) -> Phase {
+        match self {
+            MessageType::Propose(_) | MessageType::Echo(_) | MessageType::Certificate(..) => {
+                Phase::Echo
+            }
+            MessageType::Vote(_) => Phase::Vote,
+        }
+    }
 }
@@ -0,0 +1,331 @@
+//! # Signed echo consistent broadcast
+//!
+//! The creator sends its block to the group and every member sends the block hash, signed, back to the creator.
+//! When members with `n - f` of the group weight, the creator included, signed it, the creator delivers the block
+//! and sends the collected signatures to the group as a certificate. Members deliver the block when they get a valid
+//! certificate. The echo signatures form the quorum certificate of the block, Bracha uses the vote signatures instead.
+//!
+//! Only the creator aggregates echoes, so every member sends and receives a constant number of messages
+//! instead of one per member in Bracha's all-to-all rounds.
+//!
+//! Bracha's vote round makes sure that if one honest member delivers a block, all of them do. Signed echo
+//! doesn't have it, if the creator fails halfway some members may deliver the block and others not.
+//! The latter catch up with block sync.
+
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
+use log::{debug, error, trace};
+use lru::LruCache;
+
+use crate::{
+    block::types::block::Block,
+    broadcast::{
+        bracha::quorum::Quorum,
+        signing::{Phase, RawPhaseVote},
+        BroadcastProtocol, BroadcastResponse, MessageType, ProtocolContext, RawRbMsg,
+    },
+    crypto::Keypair,
+    peer::{PeerId, ToPeerId},
+    utilities::{crypto::Certificate, hash::Hash},
+};
+
+pub(crate) struct SignedEchoBroadcaster {
+    /// Local peer id
+    local_peer_id: PeerId,
+    /// Signs our echoes
+    keypair: Arc<Keypair>,
+    /// We keep a context for each block we are processing.
+    contexts: LruCache<Hash, ProtocolContext>,
+    /// Blocks we proposed, we collect their echoes
+    proposed: LruCache<Hash, ()>,
+    /// Quorum of the current group
+    quorum: Quorum,
+}
+
+impl SignedEchoBroadcaster {
+    pub(crate) fn new(keypair: Arc<Keypair>) -> Self {
+        Self {
+            //Same as Bracha, large enough for all blocks in flight
+            contexts: LruCache::new(NonZeroUsize::new(1000).unwrap()),
+            proposed: LruCache::new(NonZeroUsize::new(1000).unwrap()),
+            quorum: Quorum::new(0),
+            local_peer_id: keypair.peer_id(),
+            keypair,
+        }
+    }
+
+    /// Counts the echo of a member. When enough members echoed our block, we deliver it and send the certificate.
+    fn process_echo(
+        ctx: &mut ProtocolContext,
+        keypair: &Keypair,
+        rb_msg: &RawRbMsg,
+    ) -> BroadcastResponse {
+        let hash = ctx.hash;
+        trace!("Adding echo from {:?}", rb_msg.original_sender);
+        ctx.add_echo(rb_msg.original_sender, rb_msg.signature.clone());
+
+        let echoed = ctx.quorum.weight_of(&ctx.echo);
+        let threshold = ctx.quorum.deliver_threshold();
+        if !ctx.quorum.is_reached(&ctx.echo) {
+            trace!(
+                "Deliver threshold not reached: Echoed:{echoed} / Threshold:{threshold} for Block:{hash}"
+            );
+            return BroadcastResponse::Drop(hash);
+        }
+
+        trace!(
+            "Deliver threshold reached: Echoed:{echoed} / Threshold:{threshold} for Block:{hash}"
+        );
+        ctx.delivered = true;
+        let certificates = ctx
+            .certificates
+            .get(&Phase::Echo)
+            .map(|certificates| certificates.iter().cloned().collect())
+            .unwrap_or_default();
+        match rb_msg.reply(
+            keypair,
+            MessageType::Certificate(hash, certificates),
+            ctx.quorum.group_id,
+        ) {
+            Ok(certificate) => BroadcastResponse::BroadcastAndDeliver(certificate),
+            Err(err) => {
+                error!("Failed to sign certificate for block {hash}: {err:?}");
+                BroadcastResponse::Deliver(hash)
+            }
+        }
+    }
+
+    /// Delivers the block if the certificate has valid echo signatures of group members with `n - f` weight.
+    fn process_certificate(
+        ctx: &mut ProtocolContext,
+        rb_msg: &RawRbMsg,
+        certificates: &[Certificate],
+    ) -> BroadcastResponse {
+        let hash = ctx.hash;
+        let echo = RawPhaseVote::new(hash, Phase::Echo, ctx.quorum.group_id);
+        let signed = certificates
+            .iter()
+            .filter_map(|certificate| {
+                let signer = certificate.public_key.peer_id();
+                let valid = ctx.quorum.is_member(&signer)
+                    && echo.verify(certificate, &signer).unwrap_or_else(|err| {
+                        error!("Failed to verify echo signature of {signer}: {err:?}");
+                        false
+                    });
+                valid.then_some((signer, certificate))
+            })
+            .collect::<Vec<_>>();
+
+        let signers = signed
+            .iter()
+            .map(|(signer, _)| signer)
+            .collect::<HashSet<_>>();
+        if !ctx.quorum.is_reached(signers) {
+            debug!(
+                "Dropping {:?} from {}, certificate doesn't have enough valid echo signatures",
+                rb_msg.id, rb_msg.original_sender
+            );
+            return BroadcastResponse::Drop(hash);
+        }
+
+        for (signer, certificate) in signed {
+            ctx.add_echo(signer, certificate.clone());
+        }
+        trace!("Valid certificate for Block:{hash}");
+        ctx.delivered = true;
+        BroadcastResponse::Deliver(hash)
+    }
+}
+
+impl BroadcastProtocol for SignedEchoBroadcaster {
+    fn new_broadcast(&mut self, block: Block) -> BroadcastResponse {
+        let hash = block.get_hash();
+        debug!("Starting broadcast for new block {hash:?}");
+        match RawRbMsg::new(block, &self.keypair, self.quorum.group_id) {
+            Ok(proposal) => self.handle(&proposal),
+            Err(err) => {
+                error!("Failed to sign proposal for block {hash:?}: {err:?}");
+                BroadcastResponse::Drop(hash)
+            }
+        }
+    }
+
+    fn handle(&mut self, rb_msg: &RawRbMsg) -> BroadcastResponse {
+        trace!("Processing new broadcast message: {:?}", rb_msg);
+
+        let hash = rb_msg.block_hash();
+        if let MessageType::Vote(_) = rb_msg.message_type {
+            debug!(
+                "Ignoring VOTE {:?} from {}, signed echo has no vote round",
+                rb_msg.id, rb_msg.original_sender
+            );
+            return BroadcastResponse::Drop(hash);
+        }
+
+        let ctx = self.contexts.get_or_insert_mut(hash, || {
+            ProtocolContext::new(hash, self.local_peer_id, self.quorum.clone())
+        });
+
+        if ctx.delivered {
+            trace!("Block {hash:?} already delivered");
+            return BroadcastResponse::Drop(hash);
+        }
+
+        if !ctx.verify(rb_msg) {
+            debug!(
+                "Dropping {:?} from {}, invalid echo signature",
+                rb_msg.id, rb_msg.original_sender
+            );
+            return BroadcastResponse::Drop(hash);
+        }
+
+        let creator = rb_msg.original_sender;
+        match &rb_msg.message_type {
+            //Our own proposal is our echo
+            MessageType::Propose(_) if self.local_peer_id == creator => {
+                self.proposed.put(hash, ());
+                ctx.add_echo(self.local_peer_id, rb_msg.signature.clone());
+                trace!("Sending proposal for {hash:?}");
+                BroadcastResponse::Broadcast(rb_msg.clone())
+            }
+            MessageType::Propose(_) => {
+                if ctx.echoed() {
+                    return BroadcastResponse::Drop(hash);
+                }
+                trace!("Sending echo reply for {hash:?} to {creator}");
+                match ctx.echo_reply(&self.keypair, rb_msg) {
+                    BroadcastResponse::Broadcast(echo) => BroadcastResponse::SendTo(echo, creator),
+                    response => response,
+                }
+            }
+            MessageType::Echo(_) => {
+                if !self.proposed.contains(&hash) {
+                    debug!(
+                        "Ignoring ECHO {:?} from {}, only the creator collects echoes",
+                        rb_msg.id, rb_msg.original_sender
+                    );
+                    return BroadcastResponse::Drop(hash);
+                }
+                Self::process_echo(ctx, &self.keypair, rb_msg)
+            }
+            MessageType::Certificate(_, certificates) => {
+                Self::process_certificate(ctx, rb_msg, certificates)
+            }
+            MessageType::Vote(_) => BroadcastResponse::Drop(hash),
+        }
+    }
+
+    fn is_delivered(&self, hash: &Hash) -> bool {
+        self.contexts.peek(hash).is_some_and(|ctx| ctx.delivered)
+    }
+
+    fn delivery_certificates(&self, hash: &Hash) -> Option<(Phase, HashSet<Certificate>)> {
+        let ctx = self.contexts.peek(hash).filter(|ctx| ctx.delivered)?;
+        let certificates = ctx.certificates.get(&Phase::Echo)?;
+        Some((Phase::Echo, certificates.clone()))
+    }
+
+    fn group_updated(&mut self, quorum: Quorum) {
+        self.quorum = quorum;
+    }
+
+    fn is_group_active(&self) -> bool {
+        self.quorum.total_weight > 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_matches::assert_matches;
+
+    use crate::crypto::{EphemeraKeypair, Keypair};
+    use crate::utilities::test_utils::{block, keypairs, quorum};
+
+    use super::*;
+
+    #[test]
+    fn test_echoes_are_sent_to_creator() {
+        let peers = keypairs(4);
+        let quorum = quorum(&peers);
+        let group_id = quorum.group_id;
+        let mut creator = SignedEchoBroadcaster::new(peers[0].clone());
+        creator.group_updated(quorum.clone());
+        let mut member = SignedEchoBroadcaster::new(peers[1].clone());
+        member.group_updated(quorum);
+
+        let block = block(&peers[0], 0);
+        let hash = block.get_hash();
+        let BroadcastResponse::Broadcast(proposal) = creator.new_broadcast(block) else {
+            panic!("Creator didn't send its proposal");
+        };
+        let BroadcastResponse::SendTo(echo, to) = member.handle(&proposal) else {
+            panic!("Member didn't echo to the creator");
+        };
+        assert_eq!(to, peers[0].peer_id());
+
+        //Members don't count echoes of other members
+        let other = proposal.echo_reply(&peers[2], hash, group_id).unwrap();
+        assert_matches!(member.handle(&other), BroadcastResponse::Drop(_));
+
+        assert_matches!(creator.handle(&echo), BroadcastResponse::Drop(_));
+        let certificate = match creator.handle(&other) {
+            BroadcastResponse::BroadcastAndDeliver(certificate) => certificate,
+            response => panic!("Creator didn't deliver: {response:?}"),
+        };
+        assert_matches!(&certificate.message_type, MessageType::Certificate(h, signatures) if *h == hash && signatures.len() == 3);
+
+        assert_matches!(member.handle(&certificate), BroadcastResponse::Deliver(h) if h == hash);
+        let (phase, certificates) = member.delivery_certificates(&hash).unwrap();
+        assert_eq!(phase, Phase::Echo);
+        assert_eq!(certificates.len(), 3);
+    }
+
+    #[test]
+    fn test_invalid_certificate_not_delivered() {
+        let peers = keypairs(4);
+        let quorum = quorum(&peers);
+        let group_id = quorum.group_id;
+        let mut member = SignedEchoBroadcaster::new(peers[1].clone());
+        member.group_updated(quorum);
+
+        let block = block(&peers[0], 0);
+        let hash = block.get_hash();
+        let proposal = RawRbMsg::new(block, &peers[0], group_id).unwrap();
+        let echo = |keypair: &Keypair| {
+            RawPhaseVote::new(hash, Phase::Echo, group_id)
+                .sign(keypair)
+                .unwrap()
+        };
+        let certificate = |signatures: Vec<Certificate>| {
+            proposal
+                .reply(
+                    &peers[0],
+                    MessageType::Certificate(hash, signatures),
+                    group_id,
+                )
+                .unwrap()
+        };
+
+        //Not enough signatures
+        let too_few = certificate(vec![echo(&peers[0]), echo(&peers[2])]);
+        assert_matches!(member.handle(&too_few), BroadcastResponse::Drop(_));
+
+        //Signatures of peers outside of the group don't count
+        let outsider = Keypair::generate(None);
+        let outsiders = certificate(vec![echo(&peers[0]), echo(&peers[2]), echo(&outsider)]);
+        assert_matches!(member.handle(&outsiders), BroadcastResponse::Drop(_));
+
+        //Votes don't count as echoes
+        let vote = RawPhaseVote::new(hash, Phase::Vote, group_id)
+            .sign(&peers[3])
+            .unwrap();
+        let replayed = certificate(vec![echo(&peers[0]), echo(&peers[2]), vote]);
+        assert_matches!(member.handle(&replayed), BroadcastResponse::Drop(_));
+        assert!(!member.is_delivered(&hash));
+
+        let valid = certificate(vec![echo(&peers[0]), echo(&peers[2]), echo(&peers[3])]);
+        assert_matches!(member.handle(&valid), BroadcastResponse::Deliver(_));
+    }
+}
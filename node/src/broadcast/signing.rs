@@ -1,19 +1,76 @@
-use std::collections::HashSet;
-use std::num::NonZeroUsize;
 use std::sync::Arc;
 
 use log::trace;
-use lru::LruCache;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    block::types::block::{Block, RawBlock},
+    block::types::block::{Block, RawBlockHeader},
     crypto::Keypair,
-    utilities::{codec::Encode, crypto::Certificate, crypto::EphemeraPublicKey, hash::Hash},
+    peer::{PeerId, ToPeerId},
+    utilities::{
+        codec::{Codec, Encode, EncodingError, EphemeraCodec},
+        crypto::Certificate,
+        crypto::EphemeraPublicKey,
+        hash::Hash,
+    },
 };
 
+/// Broadcast phase a peer agrees to with its signature. The proposal counts as the creator's echo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub(crate) enum Phase {
+    Echo,
+    Vote,
+}
+
+/// What a peer signs when it echoes or votes for a block.
+///
+/// The phase and the group id are part of the signed data, so an echo signature can't be replayed
+/// as a vote and a signature given in one group doesn't count in another one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct RawPhaseVote {
+    pub(crate) block_hash: Hash,
+    pub(crate) phase: Phase,
+    pub(crate) group_id: Hash,
+}
+
+impl RawPhaseVote {
+    pub(crate) fn new(block_hash: Hash, phase: Phase, group_id: Hash) -> Self {
+        Self {
+            block_hash,
+            phase,
+            group_id,
+        }
+    }
+
+    pub(crate) fn sign(&self, keypair: &Keypair) -> anyhow::Result<Certificate> {
+        Certificate::prepare(keypair, self)
+    }
+
+    /// Checks that the certificate signs this vote and comes from the signer.
+    ///
+    /// # Errors
+    /// If the vote can't be encoded.
+    pub(crate) fn verify(
+        &self,
+        certificate: &Certificate,
+        signer: &PeerId,
+    ) -> anyhow::Result<bool> {
+        if certificate.public_key.peer_id() != *signer {
+            return Ok(false);
+        }
+        certificate.verify(self)
+    }
+}
+
+impl Encode for RawPhaseVote {
+    fn encode(&self) -> Result<Vec<u8>, EncodingError> {
+        Codec::encode(&self)
+    }
+}
+
+/// Signs and verifies block headers of broadcast messages. Header signatures identify the sender of a message,
+/// the block is committed with phase signatures.
 pub(crate) struct BlockSigner {
-    /// All signatures of the last blocks that we received from the network(+ our own)
-    verified_signatures: LruCache<Hash, HashSet<Certificate>>,
     /// Our own keypair
     signing_keypair: Arc<Keypair>,
 }
@@ -21,101 +78,58 @@ pub(crate) struct BlockSigner {
 impl BlockSigner {
     pub fn new(keypair: Arc<Keypair>) -> Self {
         Self {
-            verified_signatures: LruCache::new(NonZeroUsize::new(1000).unwrap()),
             signing_keypair: keypair,
         }
     }
 
-    pub(crate) fn get_block_certificates(
-        &mut self,
-        block_id: &Hash,
-    ) -> Option<&HashSet<Certificate>> {
-        self.verified_signatures.get(block_id)
-    }
-
-    pub(crate) fn sign_block(&mut self, block: &Block, hash: &Hash) -> anyhow::Result<Certificate> {
+    pub(crate) fn sign_block(&self, block: &Block) -> anyhow::Result<Certificate> {
         trace!("Signing block: {:?}", block);
-
-        let certificate = block.sign(self.signing_keypair.as_ref())?;
-        self.add_certificate(hash, certificate.clone());
-        Ok(certificate)
+        block.sign(self.signing_keypair.as_ref())
     }
 
     /// This verification is part of reliable broadcast and verifies only the
     /// signature of the sender.
-    pub(crate) fn verify_block(
-        &mut self,
-        block: &Block,
-        certificate: &Certificate,
-    ) -> anyhow::Result<()> {
+    pub(crate) fn verify_block(block: &Block, certificate: &Certificate) -> anyhow::Result<()> {
         trace!("Verifying block: {block:?} against certificate {certificate:?}");
 
-        let raw_block: RawBlock = (*block).clone().into();
-        let raw_block = raw_block.encode()?;
+        let raw_header: RawBlockHeader = block.header.clone().into();
+        let raw_header = raw_header.encode()?;
 
         if certificate
             .public_key
-            .verify(&raw_block, &certificate.signature)
+            .verify(&raw_header, &certificate.signature)
         {
-            self.add_certificate(&block.header.hash, certificate.clone());
             Ok(())
         } else {
             anyhow::bail!("Invalid block certificate");
         }
     }
-
-    fn add_certificate(&mut self, hash: &Hash, certificate: Certificate) {
-        trace!("Adding certificate to block: {}", hash);
-        self.verified_signatures
-            .get_or_insert_mut(*hash, HashSet::new)
-            .insert(certificate);
-    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::block::types::block::RawBlockHeader;
     use crate::block::types::message::{EphemeraMessage, RawEphemeraMessage};
     use crate::crypto::EphemeraKeypair;
     use crate::peer::ToPeerId;
+    use crate::utilities::test_utils::block_with;
 
     use super::*;
 
     #[test]
     fn test_sign_verify_block_ok() {
-        let mut signer = BlockSigner::new(Arc::new(Keypair::generate(None)));
+        let signer = BlockSigner::new(Arc::new(Keypair::generate(None)));
 
         let message_signing_keypair = Keypair::generate(None);
 
         let block = new_block(&message_signing_keypair, "label1");
-        let hash = block.hash_with_default_hasher().unwrap();
-
-        let certificate = signer.sign_block(&block, &hash).unwrap();
-
-        assert!(signer.verify_block(&block, &certificate).is_ok());
-    }
 
-    #[test]
-    fn test_sign_signatures_cached_correctly() {
-        let mut signer = BlockSigner::new(Arc::new(Keypair::generate(None)));
-
-        let block = new_block(&Keypair::generate(None), "label1");
-        let hash = block.hash_with_default_hasher().unwrap();
-
-        //Signed by node 1
-        let certificate1 = block.sign(&Keypair::generate(None)).unwrap();
-        signer.verify_block(&block, &certificate1).unwrap();
-        //Signed by node 2
-        let certificate2 = block.sign(&Keypair::generate(None)).unwrap();
-        signer.verify_block(&block, &certificate2).unwrap();
+        let certificate = signer.sign_block(&block).unwrap();
 
-        let block_certificates = signer.get_block_certificates(&hash).unwrap();
-        assert_eq!(block_certificates.len(), 2);
+        assert!(BlockSigner::verify_block(&block, &certificate).is_ok());
     }
 
     #[test]
     fn test_sign_verify_block_fail() {
-        let mut signer = BlockSigner::new(Arc::new(Keypair::generate(None)));
         let message_signing_keypair = Keypair::generate(None);
 
         let block = new_block(&message_signing_keypair, "label1");
@@ -123,12 +137,35 @@ mod test {
 
         let modified_block = new_block(&message_signing_keypair, "label2");
 
-        assert!(signer.verify_block(&modified_block, &certificate).is_err());
+        assert!(BlockSigner::verify_block(&modified_block, &certificate).is_err());
     }
 
-    fn new_block(keypair: &Keypair, message_label: &str) -> Block {
-        let peer_id = keypair.public_key().peer_id();
+    #[test]
+    fn test_phase_vote_bound_to_phase_and_group() {
+        let keypair = Keypair::generate(None);
+        let hash = new_block(&keypair, "label1").get_hash();
+        let group_id = Hash::new([1; 32]);
+
+        let echo = RawPhaseVote::new(hash, Phase::Echo, group_id);
+        let certificate = echo.sign(&keypair).unwrap();
+        assert!(echo.verify(&certificate, &keypair.peer_id()).unwrap());
+
+        //Echo signature is not a vote
+        let vote = RawPhaseVote::new(hash, Phase::Vote, group_id);
+        assert!(!vote.verify(&certificate, &keypair.peer_id()).unwrap());
+
+        //Nor an echo in another group
+        let other_group = RawPhaseVote::new(hash, Phase::Echo, Hash::new([2; 32]));
+        assert!(!other_group
+            .verify(&certificate, &keypair.peer_id())
+            .unwrap());
+
+        //Signer has to be the sender
+        let other = Keypair::generate(None);
+        assert!(!echo.verify(&certificate, &other.peer_id()).unwrap());
+    }
 
+    fn new_block(keypair: &Keypair, message_label: &str) -> Block {
         let raw_ephemera_message =
             RawEphemeraMessage::new(message_label.to_string(), "payload".as_bytes().to_vec());
 
@@ -138,13 +175,6 @@ mod test {
             message_certificate,
         )];
 
-        let raw_block_header = RawBlockHeader::new(peer_id, 0);
-        let raw_block = RawBlock::new(raw_block_header, messages);
-
-        let block_hash = raw_block
-            .hash_with_default_hasher()
-            .expect("Hashing failed");
-
-        Block::new(raw_block, block_hash)
+        block_with(keypair, 0, messages)
     }
 }
@@ -1,9 +1,10 @@
-use clap::{Args, Parser};
+use clap::{Args, Parser, ValueEnum};
 
 use crate::config::{
-    BlockManagerConfiguration, Configuration, DatabaseConfiguration, HttpConfiguration,
-    Libp2pConfiguration, MembershipKind as ConfigMembershipKind, NodeConfiguration,
-    WebsocketConfiguration,
+    ApplicationConfiguration, BlockManagerConfiguration, BroadcastConfiguration,
+    BroadcastProtocolKind, CommitmentConfiguration, Configuration, DatabaseConfiguration,
+    HttpConfiguration, Libp2pConfiguration, MembershipKind as ConfigMembershipKind,
+    MempoolConfiguration, NodeConfiguration, PruningConfiguration, WebsocketConfiguration,
 };
 use crate::crypto::{EphemeraKeypair, Keypair};
 
@@ -41,7 +42,25 @@ impl From<MembershipKind> for ConfigMembershipKind {
     }
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+pub enum BroadcastProtocol {
+    /// Bracha reliable broadcast
+    Bracha,
+    /// Signed echo consistent broadcast, fewer messages but meant for trusted deployments
+    SignedEcho,
+}
+
+impl From<BroadcastProtocol> for BroadcastProtocolKind {
+    fn from(protocol: BroadcastProtocol) -> Self {
+        match protocol {
+            BroadcastProtocol::Bracha => BroadcastProtocolKind::Bracha,
+            BroadcastProtocol::SignedEcho => BroadcastProtocolKind::SignedEcho,
+        }
+    }
+}
+
 #[derive(Parser)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Cmd {
     /// Name of the node
     #[arg(long, default_value = "default")]
@@ -67,6 +86,66 @@ pub struct Cmd {
     /// When next block is created before preious one is finished, should we repeat it with the same messages
     #[clap(long, default_value_t = false)]
     pub repeat_last_block_messages: bool,
+    /// Maximum number of messages in a block
+    #[clap(long, default_value_t = 1000)]
+    pub max_messages_per_block: usize,
+    /// Maximum total size of block messages in bytes
+    #[clap(long, default_value_t = 512 * 1024)]
+    pub max_block_size_bytes: usize,
+    /// How long a leader has to get its block committed before the next peer becomes the leader
+    #[clap(long, default_value_t = 60)]
+    pub leader_timeout_sec: u64,
+    /// How many times a block is produced again when the previous one is not committed
+    #[clap(long, default_value_t = 100)]
+    pub backoff_max_attempts: u32,
+    /// Delay between block production attempts is multiplied by this rate after each attempt
+    #[clap(long, default_value_t = 2, value_parser = clap::value_parser!(u32).range(1..))]
+    pub backoff_rate: u32,
+    /// Delay before the first block production attempt
+    #[clap(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    pub backoff_initial_delay_sec: u64,
+    /// Produce a block early when mempool has at least this many messages
+    #[clap(long)]
+    pub mempool_trigger_messages: Option<usize>,
+    /// Produce a block early when mempool messages take at least this many bytes
+    #[clap(long)]
+    pub mempool_trigger_bytes: Option<usize>,
+    /// Don't produce blocks without messages
+    #[clap(long, default_value_t = false)]
+    pub skip_empty_blocks: bool,
+    /// How long committed message hashes are kept for replay protection. Forever if not set.
+    #[clap(long)]
+    pub committed_messages_retention_sec: Option<u64>,
+    /// Number of most recent blocks which keep their messages. All if not set
+    #[clap(long)]
+    pub pruning_retain_blocks: Option<u64>,
+    /// How long blocks keep their messages. Forever if not set
+    #[clap(long)]
+    pub pruning_retain_sec: Option<u64>,
+    /// Database size above which messages of the oldest blocks are pruned. Unlimited if not set
+    #[clap(long)]
+    pub pruning_max_size_bytes: Option<u64>,
+    /// Remove headers and certificates of pruned blocks as well
+    #[clap(long, default_value_t = false)]
+    pub pruning_drop_commitments: bool,
+    /// How long to wait for an application call before treating it as failed
+    #[clap(long, default_value_t = 10_000)]
+    pub application_call_timeout_ms: u64,
+    /// Only the creator of a block publishes its commitment to the external chain
+    #[clap(long, default_value_t = false)]
+    pub commitment_leader_only: bool,
+    /// Maximum number of pending messages in the mempool
+    #[clap(long, default_value_t = 10_000)]
+    pub mempool_max_messages: usize,
+    /// Maximum total size of pending messages in the mempool in bytes
+    #[clap(long, default_value_t = 64 * 1024 * 1024)]
+    pub mempool_max_bytes: usize,
+    /// How long a pending message stays valid counting from its timestamp. Zero disables expiry
+    #[clap(long, default_value_t = 60 * 60)]
+    pub mempool_message_ttl_sec: u64,
+    /// Protocol which the group uses to agree on blocks, all members need to use the same
+    #[clap(long, value_enum, default_value_t = BroadcastProtocol::Bracha)]
+    pub broadcast_protocol: BroadcastProtocol,
     /// The interval at which Ephemera requests the list of members
     #[clap(long, default_value_t = 60 * 60)]
     pub members_provider_delay_sec: u64,
@@ -115,6 +194,14 @@ impl Cmd {
                 rocksdb_path: rocksdb_path.as_os_str().to_str().unwrap().to_string(),
                 sqlite_path: sqlite_path.as_os_str().to_str().unwrap().to_string(),
                 create_if_not_exists: true,
+                committed_messages_retention_sec: self.committed_messages_retention_sec,
+                pruning: PruningConfiguration {
+                    retain_blocks: self.pruning_retain_blocks,
+                    retain_sec: self.pruning_retain_sec,
+                    max_size_bytes: self.pruning_max_size_bytes,
+                    keep_commitments: !self.pruning_drop_commitments,
+                    ..Default::default()
+                },
             },
             websocket: WebsocketConfiguration {
                 port: self.websocket_port,
@@ -126,6 +213,32 @@ impl Cmd {
                 producer: self.block_producer,
                 creation_interval_sec: self.block_creation_interval_sec,
                 repeat_last_block_messages: self.repeat_last_block_messages,
+                max_messages_per_block: self.max_messages_per_block,
+                max_block_size_bytes: self.max_block_size_bytes,
+                leader_timeout_sec: self.leader_timeout_sec,
+                backoff_max_attempts: self.backoff_max_attempts,
+                backoff_rate: self.backoff_rate,
+                backoff_initial_delay_sec: self.backoff_initial_delay_sec,
+                mempool_trigger_messages: self.mempool_trigger_messages,
+                mempool_trigger_bytes: self.mempool_trigger_bytes,
+                skip_empty_blocks: self.skip_empty_blocks,
+            },
+            mempool: MempoolConfiguration {
+                max_messages: self.mempool_max_messages,
+                max_bytes: self.mempool_max_bytes,
+                message_ttl_sec: self.mempool_message_ttl_sec,
+                ..Default::default()
+            },
+            application: ApplicationConfiguration {
+                call_timeout_ms: self.application_call_timeout_ms,
+                ..Default::default()
+            },
+            commitment: CommitmentConfiguration {
+                leader_only: self.commitment_leader_only,
+                ..Default::default()
+            },
+            broadcast: BroadcastConfiguration {
+                protocol: self.broadcast_protocol.into(),
             },
         };
 
@@ -56,6 +56,7 @@ impl CreateLocalPeersConfiguration {
                     name: node_name.to_string(),
                     address: format!("/ip4/{}/tcp/{}", node_info.ip, conf.libp2p.port),
                     public_key: keypair.public_key().to_base58(),
+                    weight: None,
                 };
                 peers.push(peer);
 
@@ -0,0 +1,96 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+
+use crate::commitment::{BlockCommitment, CommitmentPublisher, Result};
+
+#[derive(Default)]
+struct MockChainState {
+    /// Published commitments in the order they were accepted
+    commitments: Vec<BlockCommitment>,
+    /// Number of successful publish calls
+    batches: usize,
+    /// Number of following publish calls which fail
+    failures: u32,
+}
+
+/// In-process chain which keeps published commitments in memory.
+///
+/// It's meant for tests and local clusters, where publishing should work without a real chain.
+/// Clones share the same chain.
+#[derive(Clone, Default)]
+pub struct MockChain {
+    state: Arc<Mutex<MockChainState>>,
+}
+
+impl MockChain {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns all published commitments in the order they were accepted.
+    ///
+    /// # Panics
+    /// If the chain lock is poisoned
+    #[must_use]
+    pub fn commitments(&self) -> Vec<BlockCommitment> {
+        self.state.lock().unwrap().commitments.clone()
+    }
+
+    /// Returns published commitment of the block with given hash.
+    ///
+    /// # Panics
+    /// If the chain lock is poisoned
+    #[must_use]
+    pub fn get(&self, hash: &str) -> Option<BlockCommitment> {
+        self.state
+            .lock()
+            .unwrap()
+            .commitments
+            .iter()
+            .find(|commitment| commitment.hash == hash)
+            .cloned()
+    }
+
+    /// Returns the number of successfully published batches.
+    ///
+    /// # Panics
+    /// If the chain lock is poisoned
+    #[must_use]
+    pub fn batches(&self) -> usize {
+        self.state.lock().unwrap().batches
+    }
+
+    /// Makes the next `count` publish calls fail, to exercise retries.
+    ///
+    /// # Panics
+    /// If the chain lock is poisoned
+    pub fn fail_next(&self, count: u32) {
+        self.state.lock().unwrap().failures = count;
+    }
+}
+
+#[async_trait]
+impl CommitmentPublisher for MockChain {
+    async fn publish(&self, commitments: Vec<BlockCommitment>) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.failures > 0 {
+            state.failures -= 1;
+            return Err(anyhow!("Mock chain is unavailable").into());
+        }
+        //Like a contract, the chain ignores commitments it already has
+        for commitment in commitments {
+            if !state.commitments.iter().any(|c| c.hash == commitment.hash) {
+                state.commitments.push(commitment);
+            }
+        }
+        state.batches += 1;
+        Ok(())
+    }
+
+    async fn is_published(&self, commitment: &BlockCommitment) -> Result<bool> {
+        Ok(self.get(&commitment.hash).is_some())
+    }
+}
@@ -0,0 +1,614 @@
+//! # Block commitments
+//!
+//! Ephemera requires a blockchain to anchor its blocks. Messages stay in Ephemera, only a commitment of each
+//! committed block is published to the chain: block hash, height, Merkle root of its messages and the quorum
+//! certificate of the broadcast group members who signed it. Together with [`crate::ephemera_api::ApiMessageProof`] it lets
+//! anyone verify against the chain that a message was included in a block.
+//!
+//! Applications plug in their chain by implementing [`CommitmentPublisher`]. Ephemera calls it after blocks
+//! are committed:
+//! - commitments are published in batches of up to `batch_size`, or after `batch_interval_ms` if the batch isn't full
+//! - a failed batch is retried after `retry_interval_ms` until it succeeds, commitments committed meanwhile wait behind it
+//! - the height up to which all commitments are published is stored, after a restart the rest is loaded from stored blocks
+//! - with `leader_only`, only the creator of a block publishes its commitment, so the group doesn't submit duplicates.
+//!   If the creator doesn't publish it within `takeover_timeout_ms`, other members do unless the chain already has it
+//!
+//! [`MockChain`] keeps commitments in memory, so the flow can be exercised without a chain.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::Mutex;
+use tokio::time::{self, Instant, MissedTickBehavior};
+
+use crate::api::types::ApiQuorumCertificate;
+use crate::block::types::block::{Block, BlockHeader};
+use crate::block::types::quorum_certificate::QuorumCertificate;
+use crate::config::CommitmentConfiguration;
+use crate::core::shutdown::Shutdown;
+use crate::peer::PeerId;
+use crate::storage::EphemeraDatabase;
+
+pub use mock::MockChain;
+
+mod mock;
+
+/// What is published to the chain about a committed block.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BlockCommitment {
+    /// The hash of the block.
+    pub hash: String,
+    /// The height of the block.
+    pub height: u64,
+    /// The Merkle root of the block messages hashes.
+    pub merkle_root: String,
+    /// Aggregated certificate of the block, signed by a quorum of its broadcast group.
+    pub quorum_certificate: ApiQuorumCertificate,
+}
+
+impl BlockCommitment {
+    pub(crate) fn new(block: &Block, quorum_certificate: QuorumCertificate) -> Self {
+        Self::from_header(&block.header, quorum_certificate)
+    }
+
+    pub(crate) fn from_header(header: &BlockHeader, quorum_certificate: QuorumCertificate) -> Self {
+        Self {
+            hash: header.hash.to_string(),
+            height: header.height,
+            merkle_root: header.merkle_root.to_string(),
+            quorum_certificate: quorum_certificate.into(),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("CommitmentPublisherError: {0}")]
+    Publisher(#[from] anyhow::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Anchors block commitments to an external chain.
+#[async_trait]
+pub trait CommitmentPublisher: Send + Sync + 'static {
+    /// Publishes commitments of committed blocks, ordered by height.
+    ///
+    /// If it fails, the commitments are published again later. Commitments which the chain already has
+    /// should be ignored.
+    ///
+    /// # Arguments
+    /// * `commitments` - Commitments of committed blocks
+    ///
+    /// # Errors
+    /// * If the chain didn't accept the commitments
+    async fn publish(&self, commitments: Vec<BlockCommitment>) -> Result<()>;
+
+    /// Returns true if the chain already has the commitment.
+    ///
+    /// With `leader_only`, members ask it before they publish a commitment which the block creator didn't publish
+    /// in time. By default the commitment is published again, and the chain ignores it if it has it.
+    ///
+    /// # Errors
+    /// * If the chain couldn't be queried
+    async fn is_published(&self, _commitment: &BlockCommitment) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+/// Hands commitments of committed blocks over to [`CommitmentService`].
+pub(crate) struct CommitmentSender {
+    commitments_tx: UnboundedSender<(BlockCommitment, PeerId)>,
+}
+
+impl CommitmentSender {
+    /// Queues commitment of a committed block. The service decides who publishes it.
+    pub(crate) fn send(&self, block: &Block, quorum_certificate: QuorumCertificate) {
+        let commitment = BlockCommitment::new(block, quorum_certificate);
+        if self
+            .commitments_tx
+            .send((commitment, block.header.creator))
+            .is_err()
+        {
+            error!(
+                "Commitment service stopped, commitment of block {} is published after restart",
+                block.get_hash()
+            );
+        }
+    }
+}
+
+/// Commitment waiting to be published.
+struct PendingCommitment {
+    commitment: BlockCommitment,
+    /// Creator of the block, with `leader_only` it's responsible for publishing the commitment
+    creator: PeerId,
+    /// When the commitment can be published. Other members wait for the creator before they take over.
+    publish_at: Instant,
+}
+
+pub(crate) struct CommitmentService<P: CommitmentPublisher> {
+    publisher: P,
+    config: CommitmentConfiguration,
+    local_peer_id: PeerId,
+    storage: Arc<Mutex<Box<dyn EphemeraDatabase>>>,
+    commitments_rcv: UnboundedReceiver<(BlockCommitment, PeerId)>,
+    /// Commitments waiting to be published, ordered by height
+    pending: VecDeque<PendingCommitment>,
+    /// Height of the last queued commitment
+    queued_height: u64,
+    /// Height up to which all commitments are published, it's stored so that the backlog survives restarts
+    published_height: u64,
+    /// Number of failed attempts to publish the first batch
+    attempts: u32,
+    /// When the next batch can be published, it's delayed after a failure
+    next_attempt: Instant,
+}
+
+impl<P: CommitmentPublisher> CommitmentService<P> {
+    pub(crate) fn new(
+        publisher: P,
+        config: CommitmentConfiguration,
+        local_peer_id: PeerId,
+        storage: Arc<Mutex<Box<dyn EphemeraDatabase>>>,
+    ) -> (Self, CommitmentSender) {
+        let (commitments_tx, commitments_rcv) = unbounded_channel();
+        let sender = CommitmentSender { commitments_tx };
+        let service = Self {
+            publisher,
+            config,
+            local_peer_id,
+            storage,
+            commitments_rcv,
+            pending: VecDeque::new(),
+            queued_height: 0,
+            published_height: 0,
+            attempts: 0,
+            next_attempt: Instant::now(),
+        };
+        (service, sender)
+    }
+
+    pub(crate) async fn run(mut self, mut shutdown: Shutdown) -> anyhow::Result<()> {
+        info!("Starting commitment publisher: {:?}", self.config);
+        self.load_backlog().await?;
+
+        let period = Duration::from_millis(self.config.batch_interval_ms.max(1));
+        let mut interval = time::interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = shutdown.shutdown_signal_rcv.recv() => {
+                    info!("Shutting down commitment publisher");
+                    break;
+                }
+                Some((commitment, creator)) = self.commitments_rcv.recv() => {
+                    self.queue(commitment, creator);
+                    if self.pending.len() >= self.config.batch_size.max(1) {
+                        self.publish_batch().await;
+                    }
+                }
+                _ = interval.tick() => {
+                    self.publish_batch().await;
+                }
+            }
+        }
+        if !self.pending.is_empty() {
+            info!(
+                "{} commitments are published after restart",
+                self.pending.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Queues commitments of stored blocks which were committed after the last published height.
+    ///
+    /// When nothing was published before, blocks committed until now are not published.
+    async fn load_backlog(&mut self) -> anyhow::Result<()> {
+        let storage = self.storage.clone();
+        let mut storage = storage.lock().await;
+        let last_height = storage
+            .get_last_block()?
+            .map_or(0, |block| block.get_height());
+        let Some(published_height) = storage.get_published_height()? else {
+            info!("Publishing commitments of blocks after height {last_height}");
+            storage.store_published_height(last_height)?;
+            self.published_height = last_height;
+            self.queued_height = last_height;
+            return Ok(());
+        };
+
+        self.published_height = published_height;
+        self.queued_height = published_height;
+        for height in published_height + 1..=last_height {
+            if let Some((commitment, creator)) = Self::stored_commitment(storage.as_ref(), height)?
+            {
+                self.queue(commitment, creator);
+            } else {
+                warn!("Block at height {height} isn't stored anymore, its commitment can't be published");
+                self.queued_height = height;
+            }
+        }
+        info!(
+            "Loaded {} unpublished commitments after height {published_height}",
+            self.pending.len()
+        );
+        Ok(())
+    }
+
+    /// Commitment of the stored block at given height, pruned blocks have it if pruning keeps commitments.
+    fn stored_commitment(
+        storage: &dyn EphemeraDatabase,
+        height: u64,
+    ) -> anyhow::Result<Option<(BlockCommitment, PeerId)>> {
+        let header = match storage.get_block_by_height(height)? {
+            Some(block) => block.header,
+            None => match storage
+                .get_pruned_block_by_height(height)?
+                .and_then(|pruned| pruned.header)
+            {
+                Some(header) => header,
+                None => return Ok(None),
+            },
+        };
+        let Some(quorum_certificate) =
+            storage.get_block_quorum_certificate(&header.hash.to_string())?
+        else {
+            return Ok(None);
+        };
+        let commitment = BlockCommitment::from_header(&header, quorum_certificate);
+        Ok(Some((commitment, header.creator)))
+    }
+
+    fn queue(&mut self, commitment: BlockCommitment, creator: PeerId) {
+        //Commitments loaded from storage can arrive again from the node
+        if commitment.height <= self.queued_height {
+            debug!(
+                "Commitment at height {} is already queued",
+                commitment.height
+            );
+            return;
+        }
+        self.queued_height = commitment.height;
+
+        let mut publish_at = Instant::now();
+        if !self.is_responsible(&creator) {
+            debug!(
+                "Block {} was created by {creator}, waiting for it to publish the commitment",
+                commitment.hash
+            );
+            publish_at += Duration::from_millis(self.config.takeover_timeout_ms);
+        }
+        self.pending.push_back(PendingCommitment {
+            commitment,
+            creator,
+            publish_at,
+        });
+    }
+
+    fn is_responsible(&self, creator: &PeerId) -> bool {
+        !self.config.leader_only || *creator == self.local_peer_id
+    }
+
+    /// Publishes the oldest pending commitments which are due. Commitments are published in height order, so
+    /// a commitment waiting for its creator holds back the later ones.
+    ///
+    /// Commitments of blocks created by other members are published only if the chain doesn't have them yet.
+    async fn publish_batch(&mut self) {
+        let now = Instant::now();
+        if self.pending.is_empty() || now < self.next_attempt {
+            return;
+        }
+        let batch_size = self.config.batch_size.max(1);
+        let mut batch = Vec::new();
+        let mut done = HashSet::new();
+        for pending in self
+            .pending
+            .iter()
+            .take_while(|pending| pending.publish_at <= now)
+        {
+            if batch.len() >= batch_size {
+                break;
+            }
+            if !self.is_responsible(&pending.creator) {
+                match self.publisher.is_published(&pending.commitment).await {
+                    Ok(true) => {
+                        done.insert(pending.commitment.height);
+                        continue;
+                    }
+                    Ok(false) => info!(
+                        "Creator {} didn't publish commitment of block {}, publishing it",
+                        pending.creator, pending.commitment.hash
+                    ),
+                    Err(err) => warn!(
+                        "Failed to check commitment of block {}, publishing it: {err:?}",
+                        pending.commitment.hash
+                    ),
+                }
+            }
+            batch.push(pending.commitment.clone());
+        }
+
+        if !batch.is_empty() {
+            let count = batch.len();
+            let heights = batch.iter().map(|c| c.height).collect::<Vec<_>>();
+            match self.publisher.publish(batch).await {
+                Ok(()) => {
+                    debug!("Published {count} commitments");
+                    done.extend(heights);
+                    self.attempts = 0;
+                }
+                Err(err) => {
+                    self.attempts += 1;
+                    warn!(
+                        "Publishing {count} commitments failed, attempt {}: {err:?}",
+                        self.attempts
+                    );
+                    self.next_attempt =
+                        Instant::now() + Duration::from_millis(self.config.retry_interval_ms);
+                }
+            }
+        }
+
+        if !done.is_empty() {
+            self.pending
+                .retain(|pending| !done.contains(&pending.commitment.height));
+            self.store_published_height().await;
+        }
+    }
+
+    /// Stores the height below the oldest pending commitment.
+    async fn store_published_height(&mut self) {
+        let height = self
+            .pending
+            .front()
+            .map_or(self.queued_height, |pending| pending.commitment.height - 1);
+        if height <= self.published_height {
+            return;
+        }
+        if let Err(err) = self.storage.lock().await.store_published_height(height) {
+            error!("Failed to store published commitments height {height}: {err:?}");
+            return;
+        }
+        self.published_height = height;
+    }
+}
+
+#[cfg(all(test, feature = "sqlite_storage"))]
+mod test {
+    use crate::broadcast::group::GroupSnapshot;
+    use crate::crypto::{EphemeraKeypair, Keypair};
+    use crate::peer::ToPeerId;
+    use crate::utilities::test_utils::{block, sqlite_storage, TempDatabase};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_publish_in_batches() {
+        let chain = MockChain::new();
+        let config = CommitmentConfiguration {
+            batch_size: 2,
+            ..Default::default()
+        };
+        let keypair = Keypair::generate(None);
+        let (storage, _database) = storage();
+        let (mut service, sender) =
+            CommitmentService::new(chain.clone(), config, keypair.peer_id(), storage);
+
+        for height in 1..=3 {
+            sender.send(&block(&keypair, height), QuorumCertificate::default());
+        }
+        while let Ok((commitment, creator)) = service.commitments_rcv.try_recv() {
+            service.queue(commitment, creator);
+        }
+
+        service.publish_batch().await;
+        assert_eq!(chain.batches(), 1);
+        assert_eq!(chain.commitments().len(), 2);
+
+        service.publish_batch().await;
+        assert_eq!(chain.batches(), 2);
+        let heights = chain
+            .commitments()
+            .iter()
+            .map(|c| c.height)
+            .collect::<Vec<_>>();
+        assert_eq!(heights, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_retry_failed_batch() {
+        let chain = MockChain::new();
+        let config = CommitmentConfiguration {
+            retry_interval_ms: 0,
+            ..Default::default()
+        };
+        let keypair = Keypair::generate(None);
+        let (storage, _database) = storage();
+        let (mut service, _) =
+            CommitmentService::new(chain.clone(), config, keypair.peer_id(), storage);
+
+        let commitment = BlockCommitment::new(&block(&keypair, 1), QuorumCertificate::default());
+        service.queue(commitment.clone(), keypair.peer_id());
+
+        chain.fail_next(1);
+        service.publish_batch().await;
+        assert!(chain.commitments().is_empty());
+        assert_eq!(service.pending.len(), 1);
+
+        service.publish_batch().await;
+        assert_eq!(chain.get(&commitment.hash), Some(commitment));
+        assert!(service.pending.is_empty());
+
+        //Not dropped however many times it fails
+        service.queue(
+            BlockCommitment::new(&block(&keypair, 2), QuorumCertificate::default()),
+            keypair.peer_id(),
+        );
+        chain.fail_next(5);
+        for _ in 0..5 {
+            service.publish_batch().await;
+        }
+        assert_eq!(service.pending.len(), 1);
+        service.publish_batch().await;
+        assert!(service.pending.is_empty());
+        assert_eq!(chain.commitments().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_leader_only() {
+        let chain = MockChain::new();
+        let config = CommitmentConfiguration {
+            leader_only: true,
+            ..Default::default()
+        };
+        let local = Keypair::generate(None);
+        let remote = Keypair::generate(None);
+        let (storage, _database) = storage();
+        let (mut service, sender) =
+            CommitmentService::new(chain.clone(), config, local.peer_id(), storage);
+
+        sender.send(&block(&remote, 1), QuorumCertificate::default());
+        sender.send(&block(&local, 2), QuorumCertificate::default());
+        while let Ok((commitment, creator)) = service.commitments_rcv.try_recv() {
+            service.queue(commitment, creator);
+        }
+
+        //The remote creator has time to publish its own commitment, later heights wait for it
+        service.publish_batch().await;
+        assert!(chain.commitments().is_empty());
+        assert_eq!(service.pending.len(), 2);
+
+        //The remote creator published it before the takeover timeout
+        let remote_commitment = service.pending[0].commitment.clone();
+        chain.publish(vec![remote_commitment]).await.unwrap();
+        service.pending[0].publish_at = Instant::now();
+        service.publish_batch().await;
+        let heights = chain
+            .commitments()
+            .iter()
+            .map(|c| c.height)
+            .collect::<Vec<_>>();
+        assert_eq!(heights, vec![1, 2]);
+        assert!(service.pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_take_over_from_creator() {
+        let chain = MockChain::new();
+        let config = CommitmentConfiguration {
+            leader_only: true,
+            takeover_timeout_ms: 0,
+            ..Default::default()
+        };
+        let local = Keypair::generate(None);
+        let remote = Keypair::generate(None);
+        let (storage, _database) = storage();
+        let (mut service, _) =
+            CommitmentService::new(chain.clone(), config, local.peer_id(), storage);
+
+        //The creator went offline
+        let missing = BlockCommitment::new(&block(&remote, 1), QuorumCertificate::default());
+        service.queue(missing.clone(), remote.peer_id());
+        service.publish_batch().await;
+        assert_eq!(chain.get(&missing.hash), Some(missing));
+        assert_eq!(chain.batches(), 1);
+
+        //The creator published it itself
+        let published = BlockCommitment::new(&block(&remote, 2), QuorumCertificate::default());
+        chain.publish(vec![published.clone()]).await.unwrap();
+        service.queue(published, remote.peer_id());
+        service.publish_batch().await;
+        assert_eq!(chain.batches(), 2);
+        assert!(service.pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_publish_backlog_after_restart() {
+        let chain = MockChain::new();
+        let keypair = Keypair::generate(None);
+        let (storage, _database) = storage();
+        for height in 1..=3 {
+            storage
+                .lock()
+                .await
+                .store_block(
+                    &block(&keypair, height),
+                    &[],
+                    &GroupSnapshot::default(),
+                    &QuorumCertificate::default(),
+                )
+                .unwrap();
+        }
+        storage.lock().await.store_published_height(1).unwrap();
+
+        let (mut service, sender) = CommitmentService::new(
+            chain.clone(),
+            CommitmentConfiguration::default(),
+            keypair.peer_id(),
+            storage.clone(),
+        );
+        service.load_backlog().await.unwrap();
+        let heights = service
+            .pending
+            .iter()
+            .map(|p| p.commitment.height)
+            .collect::<Vec<_>>();
+        assert_eq!(heights, vec![2, 3]);
+
+        //Already loaded from storage
+        sender.send(&block(&keypair, 3), QuorumCertificate::default());
+        let (commitment, creator) = service.commitments_rcv.try_recv().unwrap();
+        service.queue(commitment, creator);
+        assert_eq!(service.pending.len(), 2);
+
+        service.publish_batch().await;
+        assert_eq!(chain.commitments().len(), 2);
+        assert_eq!(
+            storage.lock().await.get_published_height().unwrap(),
+            Some(3)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_first_start_skips_committed_blocks() {
+        let keypair = Keypair::generate(None);
+        let (storage, _database) = storage();
+        storage
+            .lock()
+            .await
+            .store_block(
+                &block(&keypair, 1),
+                &[],
+                &GroupSnapshot::default(),
+                &QuorumCertificate::default(),
+            )
+            .unwrap();
+
+        let (mut service, _) = CommitmentService::new(
+            MockChain::new(),
+            CommitmentConfiguration::default(),
+            keypair.peer_id(),
+            storage.clone(),
+        );
+        service.load_backlog().await.unwrap();
+        assert!(service.pending.is_empty());
+        assert_eq!(
+            storage.lock().await.get_published_height().unwrap(),
+            Some(1)
+        );
+    }
+
+    fn storage() -> (Arc<Mutex<Box<dyn EphemeraDatabase>>>, TempDatabase) {
+        let (storage, database) = sqlite_storage(None);
+        let storage: Box<dyn EphemeraDatabase> = Box::new(storage);
+        (Arc::new(Mutex::new(storage)), database)
+    }
+}
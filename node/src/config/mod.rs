@@ -26,6 +26,83 @@ pub struct Configuration {
     pub http: HttpConfiguration,
     /// Configuration related to block creation
     pub block_manager: BlockManagerConfiguration,
+    /// Configuration for pending messages pool
+    #[serde(default)]
+    pub mempool: MempoolConfiguration,
+    /// Configuration for calls to the application
+    #[serde(default)]
+    pub application: ApplicationConfiguration,
+    /// Configuration for publishing block commitments to an external chain
+    #[serde(default)]
+    pub commitment: CommitmentConfiguration,
+    /// Configuration for reliable broadcast of blocks
+    #[serde(default)]
+    pub broadcast: BroadcastConfiguration,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BroadcastConfiguration {
+    /// Protocol which the group uses to agree on blocks. All members need to use the same protocol.
+    pub protocol: BroadcastProtocolKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BroadcastProtocolKind {
+    /// Bracha reliable broadcast, tolerates `f` Byzantine members out of `3f + 1`.
+    /// If an honest member delivers a block, all honest members deliver it. Takes an echo and a vote round.
+    #[default]
+    Bracha,
+    /// Signed echo consistent broadcast. Members echo to the block creator, which delivers the block after `n - f`
+    /// members signed it and forwards their signatures to the group.
+    /// If the creator fails halfway, some members may not deliver the block and have to sync it.
+    /// Meant for trusted deployments where message load matters more.
+    SignedEcho,
+}
+
+/// Tunes how block commitments are published, see [`crate::commitment::CommitmentPublisher`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CommitmentConfiguration {
+    /// If true, only the creator of a block publishes its commitment, so the group doesn't submit duplicates.
+    pub leader_only: bool,
+    /// Maximum number of commitments published at once.
+    pub batch_size: usize,
+    /// How long commitments wait for a full batch before they are published anyway.
+    pub batch_interval_ms: u64,
+    /// Delay before a failed batch is published again. Batches are retried until they succeed.
+    pub retry_interval_ms: u64,
+    /// With `leader_only`, how long other members wait for the block creator before they publish its commitment.
+    pub takeover_timeout_ms: u64,
+}
+
+impl Default for CommitmentConfiguration {
+    fn default() -> Self {
+        Self {
+            leader_only: false,
+            batch_size: 10,
+            batch_interval_ms: 5000,
+            retry_interval_ms: 5000,
+            takeover_timeout_ms: 60_000,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApplicationConfiguration {
+    /// How long Ephemera waits for an application call before treating it as failed.
+    pub call_timeout_ms: u64,
+    /// Delay between attempts to deliver a block which application failed to accept.
+    /// Delivery is retried until it succeeds, later blocks wait for it.
+    pub deliver_retry_interval_ms: u64,
+}
+
+impl Default for ApplicationConfiguration {
+    fn default() -> Self {
+        Self {
+            call_timeout_ms: 10_000,
+            deliver_retry_interval_ms: 1000,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -82,6 +159,47 @@ pub struct DatabaseConfiguration {
     pub sqlite_path: String,
     /// If to create database if it does not exist
     pub create_if_not_exists: bool,
+    /// How long committed message hashes are kept for replay protection, counting from message timestamp.
+    /// Messages older than that are rejected. If not set, hashes are kept forever.
+    #[serde(default)]
+    pub committed_messages_retention_sec: Option<u64>,
+    /// When committed block messages are removed
+    #[serde(default)]
+    pub pruning: PruningConfiguration,
+}
+
+/// Committed blocks keep their messages until any of the configured limits is reached. Then messages of the oldest
+/// blocks are pruned. If no limit is set, nothing is pruned.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PruningConfiguration {
+    /// Number of most recent blocks which keep their messages.
+    pub retain_blocks: Option<u64>,
+    /// How long blocks keep their messages, counting from block timestamp.
+    pub retain_sec: Option<u64>,
+    /// Database size in bytes above which the oldest blocks are pruned.
+    pub max_size_bytes: Option<u64>,
+    /// If true, headers, certificates and broadcast groups of pruned blocks are kept as commitments.
+    pub keep_commitments: bool,
+    /// Interval in seconds between pruning runs.
+    pub interval_sec: u64,
+}
+
+impl PruningConfiguration {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.retain_blocks.is_some() || self.retain_sec.is_some() || self.max_size_bytes.is_some()
+    }
+}
+
+impl Default for PruningConfiguration {
+    fn default() -> Self {
+        Self {
+            retain_blocks: None,
+            retain_sec: None,
+            max_size_bytes: None,
+            keep_commitments: true,
+            interval_sec: 60,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -113,6 +231,61 @@ pub struct BlockManagerConfiguration {
     /// If true, Ephemera will repeat messages from the previous block. Otherwise it will take all messages
     /// from mempool as normally.
     pub repeat_last_block_messages: bool,
+    /// Maximum number of messages in a block. Blocks from other nodes exceeding it are rejected.
+    #[serde(default = "default_max_messages_per_block")]
+    pub max_messages_per_block: usize,
+    /// Maximum total size of block messages in bytes. Blocks from other nodes exceeding it are rejected.
+    ///
+    /// Blocks are broadcast in a single network message limited to 1MB, so it should stay well below that.
+    #[serde(default = "default_max_block_size_bytes")]
+    pub max_block_size_bytes: usize,
+    /// How long the leader of a height has to get its block committed before the next peer becomes the leader.
+    ///
+    /// It should be larger than `creation_interval_sec` plus the time a broadcast takes.
+    #[serde(default = "default_leader_timeout_sec")]
+    pub leader_timeout_sec: u64,
+    /// How many times a block is produced again when the previous one is not committed.
+    #[serde(default = "default_backoff_max_attempts")]
+    pub backoff_max_attempts: u32,
+    /// Delay between attempts is multiplied by this rate after each attempt. Must be at least 1.
+    #[serde(default = "default_backoff_rate")]
+    pub backoff_rate: u32,
+    /// Delay before the first attempt. Must be greater than 0.
+    #[serde(default = "default_backoff_initial_delay_sec")]
+    pub backoff_initial_delay_sec: u64,
+    /// Produce a block without waiting for `creation_interval_sec` when mempool has at least this many messages.
+    #[serde(default)]
+    pub mempool_trigger_messages: Option<usize>,
+    /// Produce a block without waiting for `creation_interval_sec` when mempool messages take at least this many bytes.
+    #[serde(default)]
+    pub mempool_trigger_bytes: Option<usize>,
+    /// If true, no block is produced when there are no messages in the mempool.
+    #[serde(default)]
+    pub skip_empty_blocks: bool,
+}
+
+fn default_max_messages_per_block() -> usize {
+    1000
+}
+
+fn default_max_block_size_bytes() -> usize {
+    512 * 1024
+}
+
+fn default_leader_timeout_sec() -> u64 {
+    60
+}
+
+fn default_backoff_max_attempts() -> u32 {
+    100
+}
+
+fn default_backoff_rate() -> u32 {
+    2
+}
+
+fn default_backoff_initial_delay_sec() -> u64 {
+    10
 }
 
 impl BlockManagerConfiguration {
@@ -121,8 +294,68 @@ impl BlockManagerConfiguration {
             producer,
             creation_interval_sec,
             repeat_last_block_messages: repeat_last_block,
+            max_messages_per_block: default_max_messages_per_block(),
+            max_block_size_bytes: default_max_block_size_bytes(),
+            leader_timeout_sec: default_leader_timeout_sec(),
+            backoff_max_attempts: default_backoff_max_attempts(),
+            backoff_rate: default_backoff_rate(),
+            backoff_initial_delay_sec: default_backoff_initial_delay_sec(),
+            mempool_trigger_messages: None,
+            mempool_trigger_bytes: None,
+            skip_empty_blocks: false,
         }
     }
+
+    fn validate(&self) -> Result<()> {
+        if self.backoff_initial_delay_sec == 0 {
+            return Err(Error::InvalidFormat(
+                "block_manager.backoff_initial_delay_sec must be greater than 0".to_string(),
+            ));
+        }
+        if self.backoff_rate == 0 {
+            return Err(Error::InvalidFormat(
+                "block_manager.backoff_rate must be at least 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MempoolConfiguration {
+    /// Maximum number of pending messages in the pool.
+    pub max_messages: usize,
+    /// Maximum total size of pending messages in bytes.
+    pub max_bytes: usize,
+    /// How long a message stays valid, counting from its timestamp.
+    /// Expired messages are rejected and removed from the pool. Zero disables expiry.
+    pub message_ttl_sec: u64,
+    /// What to do with a new message when the pool is full.
+    pub eviction_policy: EvictionPolicy,
+    /// Maximum number of pending messages with the same label. No limit if not set.
+    pub max_messages_per_label: Option<usize>,
+}
+
+impl Default for MempoolConfiguration {
+    fn default() -> Self {
+        Self {
+            max_messages: 10_000,
+            max_bytes: 64 * 1024 * 1024,
+            message_ttl_sec: 60 * 60,
+            eviction_policy: EvictionPolicy::RejectNew,
+            max_messages_per_label: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionPolicy {
+    /// New messages are rejected until pending messages are included in blocks or expire.
+    RejectNew,
+    /// The oldest pending messages are evicted to make room for newer messages.
+    /// A message older than all pending messages is rejected.
+    OldestFirst,
 }
 
 #[derive(Debug, Error)]
@@ -190,7 +423,9 @@ impl Configuration {
             .build()
             .map_err(Error::from)?;
 
-        config.try_deserialize().map_err(Error::from)
+        let config: Configuration = config.try_deserialize().map_err(Error::from)?;
+        config.block_manager.validate()?;
+        Ok(config)
     }
 
     /// Tries to read Ephemera node configuration from default
@@ -223,7 +458,9 @@ impl Configuration {
             .build()
             .map_err(Error::from)?;
 
-        config.try_deserialize().map_err(Error::from)
+        let config: Configuration = config.try_deserialize().map_err(Error::from)?;
+        config.block_manager.validate()?;
+        Ok(config)
     }
 
     /// Tries to write(create) Ephemera node configuration file (`ephemera.toml`) relative to default
@@ -4,18 +4,29 @@ use log::{debug, error, trace};
 use lru::LruCache;
 use tokio::sync::oneshot::Sender;
 
-use crate::api::types::{ApiBlockBroadcastInfo, ApiBroadcastInfo};
-use crate::api::{DhtKV, DhtKey, DhtValue};
+use crate::api::types::{
+    ApiApplicationHash, ApiBlockBroadcastInfo, ApiBroadcastInfo, ApiEquivocationEvidence,
+    ApiMempoolStats, ApiMessageProof, ApiMessageStatus, ApiPruningStats, ApiSubmitMessageResult,
+    ApiSyncStatus,
+};
+use crate::api::{DhtKV, DhtKey, DhtValue, MAX_BLOCKS_PER_RANGE_QUERY};
+use crate::block::sync::SyncStatus;
 use crate::ephemera_api::ApiEphemeraMessage;
 use crate::peer::ToPeerId;
+use crate::storage::{self, PrunedBlock};
+use crate::utilities::hash::Hash;
 use crate::{
     api::{
         self,
-        application::Application,
-        types::{ApiBlock, ApiCertificate, ApiError},
+        application::{AsyncApplication, Result as ApplicationResult},
+        types::{ApiBlock, ApiBlockCertificate, ApiError, ApiQuorumCertificate},
         ToEphemeraApiCmd,
     },
-    block::{manager::BlockManagerError, types::message},
+    block::{manager::BlockManagerError, message_pool::MessagePoolError, types::message},
+    core::{
+        ephemera::with_timeout,
+        message_check::{SubmitBatchReply, SubmitReply},
+    },
     crypto::EphemeraKeypair,
     ephemera_api::ApiEphemeraConfig,
     network::libp2p::ephemera_sender::EphemeraEvent,
@@ -35,7 +46,7 @@ impl ApiCmdProcessor {
         }
     }
 
-    pub(crate) async fn process_api_requests<A: Application>(
+    pub(crate) async fn process_api_requests<A: AsyncApplication + 'static>(
         ephemera: &mut Ephemera<A>,
         cmd: ToEphemeraApiCmd,
     ) -> api::Result<()> {
@@ -43,7 +54,15 @@ impl ApiCmdProcessor {
         match cmd {
             ToEphemeraApiCmd::SubmitEphemeraMessage(api_msg, reply) => {
                 // Ask application to decide if we should accept this message.
-                Self::submit_message(ephemera, api_msg, reply).await?;
+                ephemera
+                    .message_checker
+                    .check_submitted_message(api_msg, reply);
+            }
+
+            ToEphemeraApiCmd::SubmitEphemeraMessages(api_msgs, reply) => {
+                ephemera
+                    .message_checker
+                    .check_submitted_messages(api_msgs, reply);
             }
 
             ToEphemeraApiCmd::QueryBlockByHash(block_hash, reply) => {
@@ -54,6 +73,10 @@ impl ApiCmdProcessor {
                 Self::query_block_by_height(ephemera, height, reply).await;
             }
 
+            ToEphemeraApiCmd::QueryBlocksByHeightRange(from_height, to_height, reply) => {
+                Self::query_blocks_by_height_range(ephemera, from_height, to_height, reply).await;
+            }
+
             ToEphemeraApiCmd::QueryLastBlock(reply) => {
                 Self::query_last_block(ephemera, reply).await;
             }
@@ -62,6 +85,14 @@ impl ApiCmdProcessor {
                 Self::query_block_certificates(ephemera, &block_id, reply).await;
             }
 
+            ToEphemeraApiCmd::QueryMessageStatus(message_hash, reply) => {
+                Self::query_message_status(ephemera, &message_hash, reply).await;
+            }
+
+            ToEphemeraApiCmd::QueryBlockQuorumCertificate(block_id, reply) => {
+                Self::query_block_quorum_certificate(ephemera, &block_id, reply).await;
+            }
+
             ToEphemeraApiCmd::QueryDht(key, reply) => {
                 Self::query_dht(ephemera, key, reply).await;
             }
@@ -84,11 +115,32 @@ impl ApiCmdProcessor {
                 Self::verify_message_in_block(ephemera, block_hash, message_hash, index, reply)
                     .await;
             }
+            ToEphemeraApiCmd::QueryMessageProof(block_hash, message_hash, reply) => {
+                Self::query_message_proof(ephemera, &block_hash, &message_hash, reply).await;
+            }
+            ToEphemeraApiCmd::QuerySyncStatus(reply) => {
+                Self::sync_status(ephemera, reply).await;
+            }
+            ToEphemeraApiCmd::QueryMempoolStats(reply) => {
+                Self::mempool_stats(ephemera, reply);
+            }
+            ToEphemeraApiCmd::QueryApplicationHash(height, reply) => {
+                Self::query_application_hash(ephemera, height, reply).await;
+            }
+            ToEphemeraApiCmd::QueryApplication(path, data, reply) => {
+                Self::query_application(ephemera, path, data, reply);
+            }
+            ToEphemeraApiCmd::QueryPruningStats(reply) => {
+                Self::pruning_stats(ephemera, reply);
+            }
+            ToEphemeraApiCmd::QueryEvidence(limit, reply) => {
+                Self::query_evidence(ephemera, limit, reply).await;
+            }
         }
         Ok(())
     }
 
-    fn broadcast_group<A: Application>(
+    fn broadcast_group<A: AsyncApplication + 'static>(
         ephemera: &mut Ephemera<A>,
         reply: Sender<api::Result<ApiBroadcastInfo>>,
     ) {
@@ -100,7 +152,148 @@ impl ApiCmdProcessor {
             .expect("Error sending BroadcastGroup response to api");
     }
 
-    fn ephemera_config<A: Application>(
+    async fn sync_status<A: AsyncApplication + 'static>(
+        ephemera: &mut Ephemera<A>,
+        reply: Sender<api::Result<ApiSyncStatus>>,
+    ) {
+        let response = match ephemera.storage.lock().await.get_last_block() {
+            Ok(last_block) => {
+                let (target_height, failed_height) = match ephemera.block_sync.status {
+                    SyncStatus::Syncing { target_height } => (target_height, None),
+                    SyncStatus::Synced => (None, None),
+                    SyncStatus::Failed { height } => (None, Some(height)),
+                };
+                Ok(ApiSyncStatus {
+                    synced: ephemera.block_sync.is_synced(),
+                    last_block_height: last_block.map_or(0, |block| block.get_height()),
+                    target_height,
+                    failed_height,
+                })
+            }
+            Err(err) => {
+                error!("Error querying last block: {:?}", err);
+                Err(ApiError::Internal(
+                    "Failed to query sync status".to_string(),
+                ))
+            }
+        };
+        reply
+            .send(response)
+            .expect("Error sending SyncStatus response to api");
+    }
+
+    fn pruning_stats<A: AsyncApplication + 'static>(
+        ephemera: &mut Ephemera<A>,
+        reply: Sender<api::Result<ApiPruningStats>>,
+    ) {
+        let stats = ephemera.pruning.stats();
+        let response = ApiPruningStats {
+            enabled: ephemera
+                .node_info
+                .initial_config
+                .storage
+                .pruning
+                .is_enabled(),
+            runs: stats.runs,
+            failed_runs: stats.failed_runs,
+            pruned_blocks: stats.pruned_blocks,
+            pruned_height: stats.pruned_height,
+            database_size_bytes: stats.database_size_bytes,
+            last_run_duration_ms: stats.last_run_duration_ms,
+        };
+        reply
+            .send(Ok(response))
+            .expect("Error sending PruningStats response to api");
+    }
+
+    /// Block which is not found might have been pruned, queries get a distinct response for it.
+    fn not_found_or_pruned<T>(
+        pruned: storage::Result<Option<PrunedBlock>>,
+    ) -> api::Result<Option<T>> {
+        match pruned {
+            Ok(Some(block)) => Err(ApiError::BlockPruned(Box::new(block.into()))),
+            Ok(None) => Ok(None),
+            Err(err) => {
+                error!("Error querying pruned block: {:?}", err);
+                Err(ApiError::Internal(
+                    "Failed to query pruned block".to_string(),
+                ))
+            }
+        }
+    }
+
+    fn mempool_stats<A: AsyncApplication + 'static>(
+        ephemera: &mut Ephemera<A>,
+        reply: Sender<api::Result<ApiMempoolStats>>,
+    ) {
+        let message_pool = &ephemera.block_manager.message_pool;
+        let stats = message_pool.stats();
+        let config = message_pool.config();
+        let response = ApiMempoolStats {
+            message_count: stats.message_count,
+            total_bytes: stats.total_bytes,
+            max_messages: config.max_messages,
+            max_bytes: config.max_bytes,
+            evicted_messages: stats.evicted_messages,
+            expired_messages: stats.expired_messages,
+            rejected_messages: stats.rejected_messages,
+        };
+        reply
+            .send(Ok(response))
+            .expect("Error sending MempoolStats response to api");
+    }
+
+    /// Queries the application in its own task, so a slow query doesn't hold up the node.
+    fn query_application<A: AsyncApplication + 'static>(
+        ephemera: &Ephemera<A>,
+        path: String,
+        data: Vec<u8>,
+        reply: Sender<api::Result<Option<Vec<u8>>>>,
+    ) {
+        let timeout = ephemera.application_timeout();
+        let application = ephemera.application.clone();
+        tokio::spawn(async move {
+            let response = with_timeout(timeout, application.query(&path, &data))
+                .await
+                .map_err(|err| {
+                    debug!("Application failed to answer query {path}: {err:?}");
+                    ApiError::Application(err)
+                });
+            //Api request may have timed out meanwhile
+            if reply.send(response).is_err() {
+                debug!("QueryApplication response receiver dropped");
+            }
+        });
+    }
+
+    async fn query_application_hash<A: AsyncApplication + 'static>(
+        ephemera: &mut Ephemera<A>,
+        height: u64,
+        reply: Sender<api::Result<Option<ApiApplicationHash>>>,
+    ) {
+        let storage = ephemera.storage.lock().await;
+        let next_block = height
+            .checked_add(1)
+            .map_or(Ok(None), |next| storage.get_block_by_height(next));
+        let response = match (storage.get_app_hash(height), next_block) {
+            (Ok(app_hash), Ok(next_block)) => Ok(app_hash.map(|app_hash| ApiApplicationHash {
+                height,
+                app_hash: app_hash.to_string(),
+                next_block_app_hash: next_block.map(|block| block.header.app_hash.to_string()),
+            })),
+            (Err(err), _) | (_, Err(err)) => {
+                error!("Error querying application hash: {:?}", err);
+                Err(ApiError::Internal(
+                    "Failed to query application hash".to_string(),
+                ))
+            }
+        };
+        reply
+            .send(response)
+            .expect("Error sending QueryApplicationHash response to api");
+    }
+
+    fn ephemera_config<A: AsyncApplication + 'static>(
         ephemera: &mut Ephemera<A>,
         reply: Sender<api::Result<ApiEphemeraConfig>>,
     ) {
@@ -121,7 +314,7 @@ impl ApiCmdProcessor {
             .expect("Error sending EphemeraConfig response to api");
     }
 
-    async fn store_in_dht<A: Application>(
+    async fn store_in_dht<A: AsyncApplication + 'static>(
         ephemera: &mut Ephemera<A>,
         key: DhtKey,
         value: DhtValue,
@@ -143,7 +336,7 @@ impl ApiCmdProcessor {
             .expect("Error sending StoreInDht response to api");
     }
 
-    async fn query_dht<A: Application>(
+    async fn query_dht<A: AsyncApplication + 'static>(
         ephemera: &mut Ephemera<A>,
         key: DhtKey,
         reply: Sender<api::Result<Option<DhtKV>>>,
@@ -170,25 +363,21 @@ impl ApiCmdProcessor {
         };
     }
 
-    async fn query_block_certificates<A: Application>(
+    async fn query_block_certificates<A: AsyncApplication + 'static>(
         ephemera: &mut Ephemera<A>,
         block_id: &str,
-        reply: Sender<api::Result<Option<Vec<ApiCertificate>>>>,
+        reply: Sender<api::Result<Option<Vec<ApiBlockCertificate>>>>,
     ) {
-        let response = match ephemera
-            .storage
-            .lock()
-            .await
-            .get_block_certificates(block_id)
-        {
-            Ok(signatures) => {
-                let certificates = signatures.map(|s| {
-                    s.into_iter()
-                        .map(Into::into)
-                        .collect::<Vec<ApiCertificate>>()
-                });
-                Ok(certificates)
+        let storage = ephemera.storage.lock().await;
+        let response = match storage.get_block_certificates(block_id) {
+            Ok(Some(signatures)) => {
+                let certificates = signatures
+                    .into_iter()
+                    .map(Into::into)
+                    .collect::<Vec<ApiBlockCertificate>>();
+                Ok(Some(certificates))
             }
+            Ok(None) => Self::not_found_or_pruned(storage.get_pruned_block(block_id)),
             Err(err) => {
                 error!("Error querying block certificates: {:?}", err);
                 Err(ApiError::Internal(
@@ -201,7 +390,45 @@ impl ApiCmdProcessor {
             .expect("Error sending QueryBlockSignatures response to api");
     }
 
-    async fn query_last_block<A: Application>(
+    async fn query_evidence<A: AsyncApplication + 'static>(
+        ephemera: &mut Ephemera<A>,
+        limit: usize,
+        reply: Sender<api::Result<Vec<ApiEquivocationEvidence>>>,
+    ) {
+        let response = match ephemera.storage.lock().await.get_evidence(limit) {
+            Ok(evidence) => Ok(evidence.into_iter().map(Into::into).collect()),
+            Err(err) => {
+                error!("Error querying evidence: {:?}", err);
+                Err(ApiError::Internal("Failed to query evidence".to_string()))
+            }
+        };
+        reply
+            .send(response)
+            .expect("Error sending QueryEvidence response to api");
+    }
+
+    async fn query_block_quorum_certificate<A: AsyncApplication + 'static>(
+        ephemera: &mut Ephemera<A>,
+        block_id: &str,
+        reply: Sender<api::Result<Option<ApiQuorumCertificate>>>,
+    ) {
+        let storage = ephemera.storage.lock().await;
+        let response = match storage.get_block_quorum_certificate(block_id) {
+            Ok(Some(quorum_certificate)) => Ok(Some(quorum_certificate.into())),
+            Ok(None) => Self::not_found_or_pruned(storage.get_pruned_block(block_id)),
+            Err(err) => {
+                error!("Error querying block quorum certificate: {:?}", err);
+                Err(ApiError::Internal(
+                    "Failed to query block quorum certificate".to_string(),
+                ))
+            }
+        };
+        reply
+            .send(response)
+            .expect("Error sending QueryBlockQuorumCertificate response to api");
+    }
+
+    async fn query_last_block<A: AsyncApplication + 'static>(
         ephemera: &mut Ephemera<A>,
         reply: Sender<api::Result<ApiBlock>>,
     ) {
@@ -220,17 +447,18 @@ impl ApiCmdProcessor {
             .expect("Error sending QueryLastBlock response to api");
     }
 
-    async fn query_block_by_height<A: Application>(
+    async fn query_block_by_height<A: AsyncApplication + 'static>(
         ephemera: &mut Ephemera<A>,
         height: u64,
         reply: Sender<api::Result<Option<ApiBlock>>>,
     ) {
-        let response = match ephemera.storage.lock().await.get_block_by_height(height) {
+        let storage = ephemera.storage.lock().await;
+        let response = match storage.get_block_by_height(height) {
             Ok(Some(block)) => {
                 let api_block: ApiBlock = block.into();
                 Ok(api_block.into())
             }
-            Ok(None) => Ok(None),
+            Ok(None) => Self::not_found_or_pruned(storage.get_pruned_block_by_height(height)),
             Err(err) => {
                 error!("Error querying block by height: {:?}", err);
                 Err(ApiError::Internal(
@@ -243,17 +471,58 @@ impl ApiCmdProcessor {
             .expect("Error sending QueryBlockByHeight response to api");
     }
 
-    async fn query_block_by_hash<A: Application>(
+    async fn query_blocks_by_height_range<A: AsyncApplication + 'static>(
+        ephemera: &mut Ephemera<A>,
+        from_height: u64,
+        to_height: u64,
+        reply: Sender<api::Result<Vec<ApiBlock>>>,
+    ) {
+        let to_height = to_height.min(from_height.saturating_add(MAX_BLOCKS_PER_RANGE_QUERY - 1));
+        let storage = ephemera.storage.lock().await;
+
+        let mut blocks = vec![];
+        let mut response = Ok(());
+        for height in from_height..=to_height {
+            match storage.get_block_by_height(height) {
+                Ok(Some(block)) => blocks.push(block.into()),
+                Ok(None) => match storage.get_pruned_height() {
+                    //Pruned blocks are skipped
+                    Ok(Some(pruned_height)) if height <= pruned_height => {}
+                    Ok(_) => break,
+                    Err(err) => {
+                        error!("Error querying pruned height: {:?}", err);
+                        response = Err(ApiError::Internal(
+                            "Failed to query blocks by height range".to_string(),
+                        ));
+                        break;
+                    }
+                },
+                Err(err) => {
+                    error!("Error querying block by height: {:?}", err);
+                    response = Err(ApiError::Internal(
+                        "Failed to query blocks by height range".to_string(),
+                    ));
+                    break;
+                }
+            }
+        }
+        reply
+            .send(response.map(|()| blocks))
+            .expect("Error sending QueryBlocksByHeightRange response to api");
+    }
+
+    async fn query_block_by_hash<A: AsyncApplication + 'static>(
         ephemera: &mut Ephemera<A>,
         block_hash: &str,
         reply: Sender<api::Result<Option<ApiBlock>>>,
     ) {
-        let response = match ephemera.storage.lock().await.get_block_by_hash(block_hash) {
+        let storage = ephemera.storage.lock().await;
+        let response = match storage.get_block_by_hash(block_hash) {
             Ok(Some(block)) => {
                 let api_block: ApiBlock = block.into();
                 Ok(api_block.into())
             }
-            Ok(None) => Ok(None),
+            Ok(None) => Self::not_found_or_pruned(storage.get_pruned_block(block_hash)),
             Err(err) => {
                 error!("Error querying block by id: {:?}", err);
                 Err(ApiError::Internal(
@@ -266,72 +535,151 @@ impl ApiCmdProcessor {
             .expect("Error sending QueryBlockByHash response to api");
     }
 
-    async fn submit_message<A: Application>(
+    /// Message was checked by the application, puts it into mempool and gossips it.
+    pub(crate) async fn on_submitted_message<A: AsyncApplication + 'static>(
         ephemera: &mut Ephemera<A>,
-        api_msg: Box<ApiEphemeraMessage>,
-        reply: Sender<api::Result<()>>,
-    ) -> api::Result<()> {
-        let response = match ephemera.application.check_tx(*api_msg.clone()) {
-            Ok(true) => {
-                trace!("Application accepted ephemera message: {:?}", api_msg);
-
-                // Send to BlockManager to verify it and put into memory pool
-                let ephemera_msg: message::EphemeraMessage = (*api_msg).into();
-                match ephemera.block_manager.on_new_message(ephemera_msg.clone()) {
-                    Ok(_) => {
-                        //Gossip to network for other nodes to receive
-                        match ephemera
-                            .to_network
-                            .send_ephemera_event(EphemeraEvent::EphemeraMessage(
-                                ephemera_msg.into(),
-                            ))
-                            .await
-                        {
-                            Ok(_) => Ok(()),
-                            Err(err) => {
-                                error!("Error sending EphemeraMessage to network: {:?}", err);
-                                Err(ApiError::Internal("Failed to submit message".to_string()))
-                            }
-                        }
+        api_msg: ApiEphemeraMessage,
+        checked: ApplicationResult<bool>,
+        reply: SubmitReply,
+    ) {
+        let response = match Self::accept_message(ephemera, api_msg, checked).await {
+            Ok((ephemera_msg, message_hash)) => {
+                //Gossip to network for other nodes to receive
+                match ephemera
+                    .to_network
+                    .send_ephemera_event(EphemeraEvent::EphemeraMessage(ephemera_msg.into()))
+                    .await
+                {
+                    Ok(()) => Ok(message_hash.to_string()),
+                    Err(err) => {
+                        error!("Error sending EphemeraMessage to network: {:?}", err);
+                        Err(ApiError::Internal("Failed to submit message".to_string()))
                     }
-                    Err(err) => match err {
-                        BlockManagerError::DuplicateMessage(_) => Err(ApiError::DuplicateMessage),
-                        BlockManagerError::BlockManager(err) => {
-                            error!("Error submitting message to block manager: {:?}", err);
-                            Err(ApiError::Internal("Failed to submit message".to_string()))
-                        }
-                    },
                 }
             }
+            Err(err) => Err(err),
+        };
+        reply
+            .send(response)
+            .expect("Error sending SubmitEphemeraMessage response to api");
+    }
+
+    /// Messages were checked by the application, puts accepted ones into mempool and gossips them as one batch.
+    pub(crate) async fn on_submitted_messages<A: AsyncApplication + 'static>(
+        ephemera: &mut Ephemera<A>,
+        checked: Vec<(ApiEphemeraMessage, ApplicationResult<bool>)>,
+        reply: SubmitBatchReply,
+    ) {
+        let mut accepted = vec![];
+        let mut results = Vec::with_capacity(checked.len());
+        for (api_msg, checked) in checked {
+            let message_hash = message::EphemeraMessage::from(api_msg.clone())
+                .hash_with_default_hasher()
+                .map(|hash| hash.to_string())
+                .unwrap_or_default();
+            match Self::accept_message(ephemera, api_msg, checked).await {
+                Ok((ephemera_msg, _)) => {
+                    accepted.push(ephemera_msg);
+                    results.push(ApiSubmitMessageResult::accepted(message_hash));
+                }
+                Err(err) => results.push(ApiSubmitMessageResult::rejected(message_hash, &err)),
+            }
+        }
+
+        //Gossip accepted messages to network as one batch. They are in the mempool already, so if it fails,
+        //the client still gets the result of each message.
+        if !accepted.is_empty() {
+            if let Err(err) = ephemera
+                .to_network
+                .send_ephemera_event(EphemeraEvent::EphemeraMessages(accepted))
+                .await
+            {
+                error!("Error sending EphemeraMessages to network: {:?}", err);
+                results
+                    .iter_mut()
+                    .for_each(ApiSubmitMessageResult::on_gossip_failed);
+            }
+        }
+        reply
+            .send(Ok(results))
+            .expect("Error sending SubmitEphemeraMessages response to api");
+    }
+
+    /// Puts the message into mempool if the application accepted it. Doesn't gossip it.
+    async fn accept_message<A: AsyncApplication + 'static>(
+        ephemera: &mut Ephemera<A>,
+        api_msg: ApiEphemeraMessage,
+        checked: ApplicationResult<bool>,
+    ) -> api::Result<(message::EphemeraMessage, Hash)> {
+        let ephemera_msg: message::EphemeraMessage = api_msg.clone().into();
+        let message_hash = ephemera_msg.hash_with_default_hasher().map_err(|err| {
+            error!("Error hashing ephemera message: {:?}", err);
+            ApiError::Internal("Failed to submit message".to_string())
+        })?;
+
+        match checked {
+            Ok(true) => {
+                trace!("Application accepted ephemera message: {:?}", api_msg);
+            }
             Ok(false) => {
                 debug!("Application rejected ephemera message: {:?}", api_msg);
-                Err(ApiError::ApplicationRejectedMessage)
+                ephemera
+                    .block_manager
+                    .on_message_rejected(message_hash, "Application rejected message");
+                return Err(ApiError::ApplicationRejectedMessage);
             }
             Err(err) => {
                 error!("Application rejected ephemera message: {:?}", err);
-                Err(ApiError::Application(err))
+                return Err(ApiError::Application(err));
             }
+        }
+
+        // Reject replayed messages and send to BlockManager to verify it and put into memory pool
+        let result = match ephemera.check_message_not_committed(&ephemera_msg).await {
+            Ok(()) => ephemera.block_manager.on_new_message(ephemera_msg.clone()),
+            Err(err) => Err(err),
         };
-        reply
-            .send(response)
-            .expect("Error sending SubmitEphemeraMessage response to api");
-        Ok(())
+        match result {
+            Ok(()) => Ok((ephemera_msg, message_hash)),
+            Err(err) => match err {
+                BlockManagerError::DuplicateMessage(_) => Err(ApiError::DuplicateMessage),
+                BlockManagerError::MessagePool(
+                    err @ (MessagePoolError::Full(_) | MessagePoolError::LabelQuotaExceeded(_)),
+                ) => {
+                    debug!("Message pool rejected message: {err}");
+                    Err(ApiError::MempoolFull(err.to_string()))
+                }
+                BlockManagerError::MessagePool(MessagePoolError::Expired(_)) => {
+                    Err(ApiError::MessageExpired)
+                }
+                BlockManagerError::MessagePool(MessagePoolError::Internal(err)) => {
+                    error!("Error submitting message to message pool: {:?}", err);
+                    Err(ApiError::Internal("Failed to submit message".to_string()))
+                }
+                BlockManagerError::BlockManager(err) => {
+                    error!("Error submitting message to block manager: {:?}", err);
+                    Err(ApiError::Internal("Failed to submit message".to_string()))
+                }
+            },
+        }
     }
 
-    async fn query_block_broadcast_info<A: Application>(
+    async fn query_block_broadcast_info<A: AsyncApplication + 'static>(
         ephemera: &mut Ephemera<A>,
         block_id: &str,
         reply: Sender<api::Result<Option<ApiBlockBroadcastInfo>>>,
     ) {
-        let response = match ephemera
-            .storage
-            .lock()
-            .await
+        let storage = ephemera.storage.lock().await;
+        let group = storage
             .get_block_broadcast_group(block_id)
-        {
-            Ok(Some(peers)) => {
+            .and_then(|peers| {
+                let weights = storage.get_block_broadcast_weights(block_id)?;
+                Ok(peers.map(|peers| (peers, weights.unwrap_or_default())))
+            });
+        let response = match group {
+            Ok(Some((peers, weights))) => {
                 let local_peer = ephemera.node_info.keypair.peer_id();
-                Ok(Some(ApiBlockBroadcastInfo::new(local_peer, peers)))
+                Ok(Some(ApiBlockBroadcastInfo::new(local_peer, peers, weights)))
             }
             Ok(None) => Ok(None),
             Err(err) => {
@@ -345,7 +693,7 @@ impl ApiCmdProcessor {
             .send(response)
             .expect("Error sending QueryBlockBroadcastGroup response to api");
     }
-    async fn verify_message_in_block<A: Application>(
+    async fn verify_message_in_block<A: AsyncApplication + 'static>(
         ephemera: &mut Ephemera<A>,
         block_hash: String,
         message_hash: String,
@@ -387,4 +735,101 @@ impl ApiCmdProcessor {
             }
         }
     }
+
+    async fn query_message_proof<A: AsyncApplication + 'static>(
+        ephemera: &mut Ephemera<A>,
+        block_hash: &str,
+        message_hash: &str,
+        reply: Sender<api::Result<Option<ApiMessageProof>>>,
+    ) {
+        let response = match message_hash.parse::<Hash>() {
+            Ok(message_hash) => Self::message_proof(ephemera, block_hash, message_hash).await,
+            Err(_) => Err(ApiError::InvalidHash(
+                "Failed to parse message hash".to_string(),
+            )),
+        };
+        reply
+            .send(response)
+            .expect("Error sending QueryMessageProof response to api");
+    }
+
+    async fn query_message_status<A: AsyncApplication + 'static>(
+        ephemera: &mut Ephemera<A>,
+        message_hash: &str,
+        reply: Sender<api::Result<Option<ApiMessageStatus>>>,
+    ) {
+        let response = match message_hash.parse::<Hash>() {
+            Ok(message_hash) => Self::message_status(ephemera, message_hash).await,
+            Err(_) => Err(ApiError::InvalidHash(
+                "Failed to parse message hash".to_string(),
+            )),
+        };
+        reply
+            .send(response)
+            .expect("Error sending QueryMessageStatus response to api");
+    }
+
+    /// Committed messages are looked up from storage, others from block manager.
+    async fn message_status<A: AsyncApplication + 'static>(
+        ephemera: &mut Ephemera<A>,
+        message_hash: Hash,
+    ) -> api::Result<Option<ApiMessageStatus>> {
+        let storage = ephemera.storage.lock().await;
+        let included = storage
+            .get_message_block_hash(&message_hash.to_string())
+            .and_then(|block_hash| match block_hash {
+                Some(block_hash) => {
+                    let height = match storage.get_block_by_hash(&block_hash)? {
+                        Some(block) => Some(block.get_height()),
+                        None => storage
+                            .get_pruned_block(&block_hash)?
+                            .map(|pruned| pruned.height),
+                    };
+                    Ok(height.map(|height| (block_hash, height)))
+                }
+                None => Ok(None),
+            });
+        match included {
+            Ok(Some((block_hash, height))) => {
+                Ok(Some(ApiMessageStatus::Included { block_hash, height }))
+            }
+            Ok(None) => Ok(ephemera
+                .block_manager
+                .message_status(&message_hash)
+                .map(Into::into)),
+            Err(err) => {
+                error!("Error querying message status: {:?}", err);
+                Err(ApiError::Internal(
+                    "Failed to query message status".to_string(),
+                ))
+            }
+        }
+    }
+
+    async fn message_proof<A: AsyncApplication + 'static>(
+        ephemera: &mut Ephemera<A>,
+        block_hash: &str,
+        message_hash: Hash,
+    ) -> api::Result<Option<ApiMessageProof>> {
+        let storage = ephemera.storage.lock().await;
+        let block = storage.get_block_by_hash(block_hash);
+        let tree = storage.get_block_merkle_tree(block_hash);
+        match (block, tree) {
+            (Ok(Some(block)), Ok(Some(tree))) => {
+                let proof = tree
+                    .leaf_index(&message_hash)
+                    .and_then(|index| tree.proof(index))
+                    .map(|proof| ApiMessageProof::new(block.header, message_hash, &proof));
+                Ok(proof)
+            }
+            (Ok(Some(_)), Ok(None)) => Ok(None),
+            (Ok(None), Ok(_)) => Self::not_found_or_pruned(storage.get_pruned_block(block_hash)),
+            (Err(err), _) | (_, Err(err)) => {
+                error!("Error querying message proof: {:?}", err);
+                Err(ApiError::Internal(
+                    "Failed to query message proof".to_string(),
+                ))
+            }
+        }
+    }
 }
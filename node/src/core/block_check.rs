@@ -0,0 +1,64 @@
+//! # Block checks
+//!
+//! The application checks every block this node proposes with `check_block` before it's broadcast. Checks run in
+//! their own tasks, so a slow application doesn't hold up the node. Results come back to the main loop, which
+//! broadcasts accepted blocks.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::error;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::api::application::{AsyncApplication, CheckBlockResult, Result as ApplicationResult};
+use crate::api::types::ApiBlock;
+use crate::block::types::block::Block;
+use crate::core::ephemera::with_timeout;
+use crate::utilities::crypto::Certificate;
+
+/// New local block together with the result of the application check.
+pub(crate) struct CheckedBlock {
+    pub(crate) block: Block,
+    /// Our signature of the block
+    pub(crate) certificate: Certificate,
+    pub(crate) result: ApplicationResult<CheckBlockResult>,
+}
+
+pub(crate) struct BlockChecker<A: AsyncApplication> {
+    application: Arc<A>,
+    /// How long to wait for an application call
+    timeout: Duration,
+    checked_tx: UnboundedSender<CheckedBlock>,
+    pub(crate) checked_rcv: UnboundedReceiver<CheckedBlock>,
+}
+
+impl<A: AsyncApplication + 'static> BlockChecker<A> {
+    pub(crate) fn new(application: Arc<A>, timeout: Duration) -> Self {
+        let (checked_tx, checked_rcv) = unbounded_channel();
+        Self {
+            application,
+            timeout,
+            checked_tx,
+            checked_rcv,
+        }
+    }
+
+    /// Checks a block produced by this node.
+    pub(crate) fn check_block(&self, block: Block, certificate: Certificate) {
+        let application = self.application.clone();
+        let timeout = self.timeout;
+        let checked_tx = self.checked_tx.clone();
+        tokio::spawn(async move {
+            let api_block: ApiBlock = block.clone().into();
+            let result = with_timeout(timeout, application.check_block(&api_block)).await;
+            let checked = CheckedBlock {
+                block,
+                certificate,
+                result,
+            };
+            if checked_tx.send(checked).is_err() {
+                error!("Node stopped, checked block is lost");
+            }
+        });
+    }
+}
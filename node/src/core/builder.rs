@@ -1,6 +1,7 @@
 use std::fmt::Display;
 use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 
 use futures_util::future::BoxFuture;
 use futures_util::FutureExt;
@@ -13,11 +14,15 @@ use crate::storage::rocksdb::RocksDbStorage;
 #[cfg(feature = "sqlite_storage")]
 use crate::storage::sqlite::SqliteStorage;
 use crate::{
-    api::{application::Application, http, ApiListener, CommandExecutor},
-    block::{builder::BlockManagerBuilder, manager::BlockManager},
-    broadcast::bracha::broadcast::Broadcaster,
+    api::{application::AsyncApplication, http, ApiListener, CommandExecutor},
+    block::{builder::BlockManagerBuilder, manager::BlockManager, sync::BlockSync},
     broadcast::group::BroadcastGroup,
+    broadcast::{self, evidence::EquivocationDetector, fetch::BlockFetcher, BroadcastProtocol},
+    commitment::{CommitmentPublisher, CommitmentSender, CommitmentService},
     config::Configuration,
+    core::block_check::BlockChecker,
+    core::delivery::DeliveryService,
+    core::message_check::MessageChecker,
     core::{
         api_cmd::ApiCmdProcessor,
         shutdown::{Handle, ShutdownManager},
@@ -30,7 +35,10 @@ use crate::{
         swarm_network::SwarmNetwork,
     },
     peer::{PeerId, ToPeerId},
-    storage::EphemeraDatabase,
+    storage::{
+        pruning::{PruningHandle, PruningService},
+        EphemeraDatabase,
+    },
     utilities::crypto::key_manager::KeyManager,
     websocket::ws_manager::{WsManager, WsMessageBroadcaster},
     Ephemera,
@@ -105,7 +113,7 @@ pub struct EphemeraHandle {
 pub struct EphemeraStarterInit {
     config: Configuration,
     node_info: NodeInfo,
-    broadcaster: Broadcaster,
+    broadcaster: Box<dyn BroadcastProtocol>,
     api_listener: ApiListener,
     api: CommandExecutor,
 }
@@ -123,7 +131,8 @@ impl EphemeraStarterInit {
     /// * If the node configuration is invalid
     pub fn new(config: Configuration) -> anyhow::Result<Self> {
         let instance_info = NodeInfo::new(config.clone())?;
-        let broadcaster = Broadcaster::new(instance_info.peer_id);
+        let broadcaster =
+            broadcast::new_protocol(config.broadcast.protocol, instance_info.keypair.clone());
         let (api, api_listener) = CommandExecutor::new();
 
         let builder = EphemeraStarterInit {
@@ -136,7 +145,7 @@ impl EphemeraStarterInit {
         Ok(builder)
     }
 
-    pub fn with_application<A: Application>(
+    pub fn with_application<A: AsyncApplication>(
         self,
         application: A,
     ) -> EphemeraStarterWithApplication<A> {
@@ -154,17 +163,17 @@ struct ServiceInfo {
     to_network: Option<EphemeraToNetworkSender>,
 }
 
-pub struct EphemeraStarterWithApplication<A: Application> {
+pub struct EphemeraStarterWithApplication<A: AsyncApplication> {
     init: EphemeraStarterInit,
     application: A,
 }
 
-impl<A: Application> EphemeraStarterWithApplication<A> {
+impl<A: AsyncApplication> EphemeraStarterWithApplication<A> {
     /// Initialize Ephemera with the given application.
     /// It also tries to open the database connection.
     ///
     /// # Arguments
-    /// * `application` - [Application] to be used
+    /// * `application` - [`AsyncApplication`] to be used, every [`crate::ephemera_api::Application`] is one
     ///
     /// # Returns
     /// [`EphemeraStarterWithApplication`]
@@ -190,23 +199,48 @@ impl<A: Application> EphemeraStarterWithApplication<A> {
         }
 
         let block_manager = self.init_block_manager(&mut storage)?;
+        let storage: Arc<Mutex<Box<dyn EphemeraDatabase>>> =
+            Arc::new(Mutex::new(Box::new(storage)));
 
         let (mut shutdown_manager, shutdown_handle) = ShutdownManager::init();
 
         let mut service_data = ServiceInfo::default();
-        let services = self.init_services(&mut service_data, &mut shutdown_manager, provider)?;
+        let mut services =
+            self.init_services(&mut service_data, &mut shutdown_manager, provider)?;
+        let (pruning, pruning_service) =
+            self.init_pruning(storage.clone(), shutdown_manager.subscribe());
+        services.extend(pruning_service);
 
         Ok(EphemeraStarterWithProvider {
             with_application: self,
             block_manager: Some(block_manager),
             service_data,
             services,
-            storage: Some(Box::new(storage)),
+            storage: Some(storage),
+            pruning,
+            commitments: None,
             shutdown_manager: Some(shutdown_manager),
             shutdown_handle: Some(shutdown_handle),
         })
     }
 
+    /// Pruning service runs only if a retention limit is configured.
+    fn init_pruning(
+        &mut self,
+        storage: Arc<Mutex<Box<dyn EphemeraDatabase>>>,
+        shutdown: Shutdown,
+    ) -> (
+        PruningHandle,
+        Option<BoxFuture<'static, anyhow::Result<()>>>,
+    ) {
+        let config = self.init.config.storage.pruning.clone();
+        if !config.is_enabled() {
+            return (PruningHandle::default(), None);
+        }
+        let service = PruningService::new(storage, config);
+        (service.handle(), Some(service.run(shutdown).boxed()))
+    }
+
     //allocate database connection
     #[cfg(feature = "rocksdb_storage")]
     fn connect_rocksdb(&self) -> anyhow::Result<RocksDbStorage> {
@@ -227,8 +261,10 @@ impl<A: Application> EphemeraStarterWithApplication<A> {
         db: &mut D,
     ) -> anyhow::Result<BlockManager> {
         let block_manager_configuration = self.init.config.block_manager.clone();
+        let mempool_configuration = self.init.config.mempool.clone();
         let keypair = self.init.node_info.keypair.clone();
-        let builder = BlockManagerBuilder::new(block_manager_configuration, keypair);
+        let builder =
+            BlockManagerBuilder::new(block_manager_configuration, mempool_configuration, keypair);
         builder.build(db)
     }
 
@@ -345,12 +381,14 @@ impl<A: Application> EphemeraStarterWithApplication<A> {
 
 pub struct EphemeraStarterWithProvider<A>
 where
-    A: Application + 'static,
+    A: AsyncApplication + 'static,
 {
     with_application: EphemeraStarterWithApplication<A>,
     block_manager: Option<BlockManager>,
     service_data: ServiceInfo,
-    storage: Option<Box<dyn EphemeraDatabase>>,
+    storage: Option<Arc<Mutex<Box<dyn EphemeraDatabase>>>>,
+    pruning: PruningHandle,
+    commitments: Option<CommitmentSender>,
     services: Vec<BoxFuture<'static, anyhow::Result<()>>>,
     shutdown_manager: Option<ShutdownManager>,
     shutdown_handle: Option<Handle>,
@@ -358,8 +396,37 @@ where
 
 impl<A> EphemeraStarterWithProvider<A>
 where
-    A: Application + 'static,
+    A: AsyncApplication + 'static,
 {
+    /// Publish commitments of committed blocks to an external chain.
+    ///
+    /// # Arguments
+    /// * `publisher` - [`CommitmentPublisher`] of the chain, [`crate::commitment::MockChain`] for tests
+    ///
+    /// # Returns
+    /// [`EphemeraStarterWithProvider`]
+    ///
+    /// # Panics
+    /// * If shutdown manager or storage is not initialized, which is a bug
+    #[must_use]
+    pub fn with_commitment_publisher<P: CommitmentPublisher>(mut self, publisher: P) -> Self {
+        let init = &self.with_application.init;
+        let (service, sender) = CommitmentService::new(
+            publisher,
+            init.config.commitment.clone(),
+            init.node_info.peer_id,
+            self.storage.clone().expect("Storage not initialized"),
+        );
+        let shutdown = self
+            .shutdown_manager
+            .as_ref()
+            .expect("Shutdown manager not initialized")
+            .subscribe();
+        self.services.push(service.run(shutdown).boxed());
+        self.commitments = Some(sender);
+        self
+    }
+
     pub fn build(self) -> Ephemera<A> {
         self.ephemera()
     }
@@ -371,9 +438,39 @@ where
         };
 
         let node_info = self.with_application.init.node_info;
-        let application = self.with_application.application;
-        let block_manager = self.block_manager.expect("Block manager not initialized");
+        let application = Arc::new(self.with_application.application);
+        let shutdown_manager = self
+            .shutdown_manager
+            .expect("Shutdown manager not initialized");
+        let storage = self.storage.expect("Storage not initialized");
+
+        let app_config = &node_info.initial_config.application;
+        let (delivery_service, deliveries) = DeliveryService::new(
+            application.clone(),
+            app_config,
+            storage.clone(),
+            self.pruning.clone(),
+        );
+        self.services
+            .push(delivery_service.run(shutdown_manager.subscribe()).boxed());
+        let message_checker = MessageChecker::new(
+            application.clone(),
+            Duration::from_millis(app_config.call_timeout_ms),
+        );
+        let block_checker = BlockChecker::new(
+            application.clone(),
+            Duration::from_millis(app_config.call_timeout_ms),
+        );
+
+        let mut block_manager = self.block_manager.expect("Block manager not initialized");
+        let app = application.clone();
+        block_manager.prepare_proposal =
+            Some(Box::new(move |candidates, max_messages, max_bytes| {
+                app.prepare_proposal(candidates, max_messages, max_bytes)
+            }));
+        let block_sync = BlockSync::new(block_manager.next_block_height() - 1);
         let broadcaster = self.with_application.init.broadcaster;
+        let equivocation_detector = EquivocationDetector::new(node_info.keypair.clone());
         let from_network = self
             .service_data
             .from_network
@@ -382,29 +479,33 @@ where
             .service_data
             .to_network
             .expect("To network not initialized");
-        let storage = self.storage.expect("Storage not initialized");
         let ws_message_broadcast = self
             .service_data
             .ws_message_broadcast
             .expect("WS message broadcast not initialized");
         let api_listener = self.with_application.init.api_listener;
-        let shutdown_manager = self
-            .shutdown_manager
-            .expect("Shutdown manager not initialized");
         let services = self.services;
 
         Ephemera {
             node_info,
             block_manager,
+            block_sync,
             broadcaster,
+            block_fetcher: BlockFetcher::new(),
+            equivocation_detector,
             from_network,
             to_network,
             broadcast_group: BroadcastGroup::new(),
-            storage: Arc::new(Mutex::new(storage)),
+            storage,
+            pruning: self.pruning,
+            commitments: self.commitments,
             ws_message_broadcast,
             api_listener,
             api_cmd_processor: ApiCmdProcessor::new(),
-            application: application.into(),
+            application,
+            deliveries,
+            message_checker,
+            block_checker,
             ephemera_handle,
             shutdown_manager,
             services,
@@ -0,0 +1,514 @@
+//! # Block delivery
+//!
+//! Committed blocks are delivered to the application in height order by [`DeliveryService`], a background task,
+//! so a slow application doesn't hold up the node. If delivery fails or times out, the block is queued and retried
+//! at configured interval until it succeeds. Blocks committed meanwhile are queued behind it, so the application
+//! never receives blocks out of order and never misses a height.
+//!
+//! Delivery is at-least-once. A failed or timed out `deliver_block` call may still have been processed by the
+//! application, so before delivering such a block again the service asks the application for `info`. If the
+//! reported `last_block_height` already includes the block, it's not delivered again, only committed. The
+//! application should still skip blocks at or below its last processed height, for example when it doesn't
+//! report the height.
+//!
+//! After a block is delivered, the application commits its state. If the commit fails, the block stays queued as
+//! delivered and only the commit is retried, the next block is not delivered before that. The application hash
+//! after each block is stored and sent back to the node, the next block needs to include it.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, error, info, warn};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::Mutex;
+use tokio::time::{self, Interval, MissedTickBehavior};
+
+use crate::api::application::AsyncApplication;
+use crate::api::types::{ApiBlock, ApiEquivocationEvidence};
+use crate::config::ApplicationConfiguration;
+use crate::core::ephemera::with_timeout;
+use crate::core::shutdown::Shutdown;
+use crate::storage::pruning::PruningHandle;
+use crate::storage::EphemeraDatabase;
+use crate::utilities::hash::Hash;
+
+struct PendingDelivery {
+    block: ApiBlock,
+    /// Number of failed delivery attempts
+    attempts: u32,
+    /// The application accepted the block, but its state wasn't committed yet
+    delivered: bool,
+}
+
+pub(crate) struct DeliveryQueue {
+    pending: VecDeque<PendingDelivery>,
+    /// Interval between retries
+    pub(crate) retry_interval: Interval,
+}
+
+impl DeliveryQueue {
+    pub(crate) fn new(config: &ApplicationConfiguration) -> Self {
+        let period = Duration::from_millis(config.deliver_retry_interval_ms.max(1));
+        let mut retry_interval = time::interval(period);
+        retry_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Self {
+            pending: VecDeque::new(),
+            retry_interval,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Queues a block whose delivery failed or which has to wait for earlier blocks.
+    pub(crate) fn push(&mut self, block: ApiBlock, attempts: u32) {
+        self.pending.push_back(PendingDelivery {
+            block,
+            attempts,
+            delivered: false,
+        });
+    }
+
+    /// Queues a delivered block whose commit failed.
+    pub(crate) fn push_delivered(&mut self, block: ApiBlock) {
+        self.pending.push_back(PendingDelivery {
+            block,
+            attempts: 1,
+            delivered: true,
+        });
+    }
+
+    /// Next block to deliver or commit.
+    pub(crate) fn front(&self) -> Option<&ApiBlock> {
+        self.pending.front().map(|pending| &pending.block)
+    }
+
+    /// Returns true if delivering the next block was already attempted, the application may have processed it.
+    pub(crate) fn is_front_attempted(&self) -> bool {
+        self.pending
+            .front()
+            .is_some_and(|pending| pending.attempts > 0)
+    }
+
+    /// Returns true if the next block was delivered and only needs to be committed.
+    pub(crate) fn is_front_delivered(&self) -> bool {
+        self.pending
+            .front()
+            .is_some_and(|pending| pending.delivered)
+    }
+
+    /// Next block was delivered, it waits for the commit.
+    pub(crate) fn on_delivered(&mut self) {
+        if let Some(pending) = self.pending.front_mut() {
+            pending.delivered = true;
+        }
+    }
+
+    /// Application state after the next block was committed.
+    pub(crate) fn on_committed(&mut self) {
+        self.pending.pop_front();
+    }
+
+    /// Delivery or commit of the next block failed, it's retried with the next tick.
+    pub(crate) fn on_failed(&mut self) {
+        let Some(pending) = self.pending.front_mut() else {
+            return;
+        };
+        pending.attempts += 1;
+        if pending.attempts % 10 == 0 {
+            error!(
+                "Block {} failed {} delivery attempts, later blocks are waiting for it",
+                pending.block.header.hash, pending.attempts
+            );
+        }
+    }
+}
+
+/// Work for [`DeliveryService`], handled in order.
+#[derive(Debug)]
+pub(crate) enum DeliveryRequest {
+    /// Committed block to deliver and commit
+    Block(ApiBlock),
+    /// Block which the application already has, only its state needs to be committed
+    Commit(ApiBlock),
+    /// Evidence of a peer signing conflicting blocks
+    Evidence(Box<ApiEquivocationEvidence>),
+}
+
+/// Hands committed blocks over to [`DeliveryService`] and receives application hashes after them.
+pub(crate) struct DeliveryHandle {
+    requests_tx: UnboundedSender<DeliveryRequest>,
+    /// Application hash after the block at given height
+    pub(crate) app_hashes_rcv: UnboundedReceiver<(u64, Hash)>,
+}
+
+impl DeliveryHandle {
+    pub(crate) fn send(&self, request: DeliveryRequest) {
+        if let Err(err) = self.requests_tx.send(request) {
+            error!("Delivery service stopped, {:?} is lost", err.0);
+        }
+    }
+}
+
+pub(crate) struct DeliveryService<A: AsyncApplication> {
+    application: Arc<A>,
+    /// How long to wait for an application call
+    timeout: Duration,
+    queue: DeliveryQueue,
+    requests_rcv: UnboundedReceiver<DeliveryRequest>,
+    app_hashes_tx: UnboundedSender<(u64, Hash)>,
+    storage: Arc<Mutex<Box<dyn EphemeraDatabase>>>,
+    pruning: PruningHandle,
+}
+
+impl<A: AsyncApplication> DeliveryService<A> {
+    pub(crate) fn new(
+        application: Arc<A>,
+        config: &ApplicationConfiguration,
+        storage: Arc<Mutex<Box<dyn EphemeraDatabase>>>,
+        pruning: PruningHandle,
+    ) -> (Self, DeliveryHandle) {
+        let (requests_tx, requests_rcv) = unbounded_channel();
+        let (app_hashes_tx, app_hashes_rcv) = unbounded_channel();
+        let service = Self {
+            application,
+            timeout: Duration::from_millis(config.call_timeout_ms),
+            queue: DeliveryQueue::new(config),
+            requests_rcv,
+            app_hashes_tx,
+            storage,
+            pruning,
+        };
+        let handle = DeliveryHandle {
+            requests_tx,
+            app_hashes_rcv,
+        };
+        (service, handle)
+    }
+
+    pub(crate) async fn run(mut self, mut shutdown: Shutdown) -> anyhow::Result<()> {
+        info!("Starting block delivery");
+        loop {
+            tokio::select! {
+                _ = shutdown.shutdown_signal_rcv.recv() => {
+                    info!("Shutting down block delivery");
+                    break;
+                }
+                Some(request) = self.requests_rcv.recv() => {
+                    self.on_request(request).await;
+                }
+                _ = self.queue.retry_interval.tick(), if !self.queue.is_empty() => {
+                    self.retry().await;
+                }
+            }
+        }
+        if !self.queue.is_empty() {
+            warn!(
+                "{} blocks were not delivered to Application before shutdown",
+                self.queue.len()
+            );
+        }
+        Ok(())
+    }
+
+    async fn on_request(&mut self, request: DeliveryRequest) {
+        match request {
+            DeliveryRequest::Block(block) => self.deliver_block(block).await,
+            DeliveryRequest::Commit(block) => {
+                if !self.queue.is_empty() || !self.commit(block.header.height).await {
+                    self.queue.push_delivered(block);
+                }
+            }
+            DeliveryRequest::Evidence(evidence) => {
+                let offender = evidence.offender;
+                if let Err(err) =
+                    with_timeout(self.timeout, self.application.deliver_evidence(*evidence)).await
+                {
+                    error!("Deliver evidence against {offender} to Application failed: {err:?}");
+                }
+            }
+        }
+    }
+
+    /// Delivers committed block to the application. If it fails, the block is retried later.
+    ///
+    /// Blocks are delivered in order, so when earlier blocks are waiting for retry, this one waits as well.
+    async fn deliver_block(&mut self, block: ApiBlock) {
+        if !self.queue.is_empty() {
+            debug!(
+                "Block {} waits for {} earlier blocks to be delivered",
+                block.header.hash,
+                self.queue.len()
+            );
+            self.queue.push(block, 0);
+            return;
+        }
+
+        match with_timeout(self.timeout, self.application.deliver_block(block.clone())).await {
+            Ok(()) => {
+                if !self.commit(block.header.height).await {
+                    self.queue.push_delivered(block);
+                }
+            }
+            Err(err) => {
+                warn!(
+                    "Deliver block {} to Application failed, retrying later: {err:?}",
+                    block.header.hash
+                );
+                self.queue.push(block, 1);
+            }
+        }
+    }
+
+    /// Asks the application for its state hash after the block at given height. The next block needs to include it.
+    ///
+    /// Returns false if the commit failed and needs to be retried.
+    async fn commit(&mut self, height: u64) -> bool {
+        match with_timeout(self.timeout, self.application.commit()).await {
+            Ok(app_hash) => {
+                let app_hash = Hash::new(app_hash);
+                if let Err(err) = self.storage.lock().await.store_app_hash(height, &app_hash) {
+                    error!("Failed to store application hash at height {height}: {err:?}");
+                }
+                //Block can be pruned only after the application committed its state
+                self.pruning.on_delivered(height);
+                if self.app_hashes_tx.send((height, app_hash)).is_err() {
+                    error!("Node stopped, application hash at height {height} is lost");
+                }
+                true
+            }
+            Err(err) => {
+                error!("Application commit at height {height} failed, retrying later: {err:?}");
+                false
+            }
+        }
+    }
+
+    /// Returns true if the application reports that it processed the block at given height.
+    ///
+    /// Blocks are delivered in order, so `last_block_height` is the point up to which delivery is done.
+    async fn is_processed(&self, height: u64) -> bool {
+        match with_timeout(self.timeout, self.application.info()).await {
+            Ok(info) => info.last_block_height.is_some_and(|last| last >= height),
+            Err(err) => {
+                debug!(
+                    "Application info failed, delivering block at height {height} again: {err:?}"
+                );
+                false
+            }
+        }
+    }
+
+    /// Retries blocks which the application failed to accept or commit, in order.
+    async fn retry(&mut self) {
+        while let Some(block) = self.queue.front().cloned() {
+            let hash = block.header.hash.clone();
+            let height = block.header.height;
+            if self.queue.is_front_attempted()
+                && !self.queue.is_front_delivered()
+                && self.is_processed(height).await
+            {
+                debug!("Application already processed block {hash}, not delivering it again");
+                self.queue.on_delivered();
+            }
+            if !self.queue.is_front_delivered() {
+                if let Err(err) =
+                    with_timeout(self.timeout, self.application.deliver_block(block)).await
+                {
+                    warn!("Retrying deliver block {hash} to Application failed: {err:?}");
+                    self.queue.on_failed();
+                    break;
+                }
+                debug!("Block {hash} delivered to Application after retry");
+                self.queue.on_delivered();
+            }
+            if !self.commit(height).await {
+                self.queue.on_failed();
+                break;
+            }
+            self.queue.on_committed();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "sqlite_storage"))]
+mod test {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use anyhow::anyhow;
+    use async_trait::async_trait;
+
+    use crate::api::application::{
+        ApplicationInfo, CheckBlockResult, Error as ApplicationError, Result as ApplicationResult,
+    };
+    use crate::block::types::block::Block;
+    use crate::ephemera_api::ApiEphemeraMessage;
+    use crate::peer::PeerId;
+    use crate::utilities::test_utils::{sqlite_storage, TempDatabase};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_retry_keeps_order() {
+        let mut queue = DeliveryQueue::new(&ApplicationConfiguration::default());
+        let first = block(0);
+        let second = block(0);
+        queue.push(first.clone(), 1);
+        queue.push(second.clone(), 0);
+
+        queue.on_failed();
+        assert_eq!(queue.front(), Some(&first));
+
+        queue.on_delivered();
+        queue.on_committed();
+        assert_eq!(queue.front(), Some(&second));
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_commit_keeps_block() {
+        let mut queue = DeliveryQueue::new(&ApplicationConfiguration::default());
+        let first = block(0);
+        queue.push(first.clone(), 1);
+        queue.push(block(0), 0);
+        assert!(!queue.is_front_delivered());
+
+        //Delivered but commit failed, only the commit is retried
+        queue.on_delivered();
+        queue.on_failed();
+        assert_eq!(queue.front(), Some(&first));
+        assert!(queue.is_front_delivered());
+
+        queue.on_committed();
+        assert!(!queue.is_front_delivered());
+        assert_eq!(queue.len(), 1);
+
+        queue.on_committed();
+        assert!(queue.is_empty());
+
+        //Commit failed right after delivery
+        queue.push_delivered(first.clone());
+        assert_eq!(queue.front(), Some(&first));
+        assert!(queue.is_front_delivered());
+    }
+
+    #[tokio::test]
+    async fn test_failed_block_is_never_skipped() {
+        let (mut service, mut handle, _database) = service(FlakyApplication::default());
+        service.application.failing.store(true, Ordering::Relaxed);
+
+        let first = block(1);
+        service.deliver_block(first.clone()).await;
+        service.deliver_block(block(2)).await;
+        for _ in 0..20 {
+            service.retry().await;
+        }
+        assert_eq!(service.queue.front(), Some(&first));
+        assert_eq!(service.queue.len(), 2);
+        assert!(service.application.delivered.lock().await.is_empty());
+
+        service.application.failing.store(false, Ordering::Relaxed);
+        service.retry().await;
+        assert!(service.queue.is_empty());
+        assert_eq!(*service.application.delivered.lock().await, vec![1, 2]);
+        assert_eq!(handle.app_hashes_rcv.try_recv().unwrap().0, 1);
+        assert_eq!(handle.app_hashes_rcv.try_recv().unwrap().0, 2);
+        assert!(service
+            .storage
+            .lock()
+            .await
+            .get_app_hash(2)
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_processed_block_is_not_delivered_again() {
+        let (mut service, mut handle, _database) = service(FlakyApplication::default());
+        service
+            .application
+            .lost_reply
+            .store(true, Ordering::Relaxed);
+
+        service.deliver_block(block(1)).await;
+        assert_eq!(service.queue.len(), 1);
+        assert_eq!(*service.application.delivered.lock().await, vec![1]);
+
+        service
+            .application
+            .lost_reply
+            .store(false, Ordering::Relaxed);
+        service.retry().await;
+        assert!(service.queue.is_empty());
+        assert_eq!(*service.application.delivered.lock().await, vec![1]);
+        assert_eq!(handle.app_hashes_rcv.try_recv().unwrap().0, 1);
+    }
+
+    /// Fails every call while `failing` is set.
+    #[derive(Default)]
+    struct FlakyApplication {
+        failing: AtomicBool,
+        /// Processes delivered blocks but reports a failure, like a timed out call
+        lost_reply: AtomicBool,
+        delivered: Mutex<Vec<u64>>,
+    }
+
+    #[async_trait]
+    impl AsyncApplication for FlakyApplication {
+        async fn info(&self) -> ApplicationResult<ApplicationInfo> {
+            Ok(ApplicationInfo {
+                last_block_height: self.delivered.lock().await.last().copied(),
+                last_app_hash: [0; 32],
+            })
+        }
+
+        async fn check_tx(&self, _message: ApiEphemeraMessage) -> ApplicationResult<bool> {
+            Ok(true)
+        }
+
+        async fn check_block(&self, _block: &ApiBlock) -> ApplicationResult<CheckBlockResult> {
+            Ok(CheckBlockResult::Accept)
+        }
+
+        async fn deliver_block(&self, block: ApiBlock) -> ApplicationResult<()> {
+            if self.failing.load(Ordering::Relaxed) {
+                return Err(ApplicationError::Application(anyhow!("failing")));
+            }
+            self.delivered.lock().await.push(block.header.height);
+            if self.lost_reply.load(Ordering::Relaxed) {
+                return Err(ApplicationError::Timeout);
+            }
+            Ok(())
+        }
+    }
+
+    fn service(
+        application: FlakyApplication,
+    ) -> (
+        DeliveryService<FlakyApplication>,
+        DeliveryHandle,
+        TempDatabase,
+    ) {
+        let (storage, database) = sqlite_storage(None);
+        let storage: Box<dyn EphemeraDatabase> = Box::new(storage);
+        let (service, handle) = DeliveryService::new(
+            Arc::new(application),
+            &ApplicationConfiguration::default(),
+            Arc::new(Mutex::new(storage)),
+            PruningHandle::default(),
+        );
+        (service, handle, database)
+    }
+
+    fn block(height: u64) -> ApiBlock {
+        let mut block: ApiBlock = Block::new_genesis_block(PeerId::random()).into();
+        block.header.height = height;
+        block
+    }
+}
@@ -1,24 +1,45 @@
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::anyhow;
 use futures_util::future::BoxFuture;
 use futures_util::StreamExt;
-use log::{debug, error, info, trace};
+use log::{debug, error, info, trace, warn};
 use thiserror::Error;
 use tokio::sync::Mutex;
+use tokio::time;
 
 use crate::broadcast::bracha::quorum::Quorum;
 use crate::storage::DatabaseError;
 use crate::{
-    api::{application::Application, application::CheckBlockResult, ApiListener},
-    block::{manager::BlockManager, types::block::Block},
+    api::{
+        application::{
+            AsyncApplication, CheckBlockResult, Error as ApplicationError,
+            Result as ApplicationResult,
+        },
+        ApiListener,
+    },
+    block::{
+        self,
+        manager::{BlockManager, BlockManagerError},
+        message_pool::MessagePoolError,
+        sync::{BlockSync, BlockSyncRequest, BlockSyncResponse, SyncBlock, MAX_BLOCKS_PER_REQUEST},
+        types::{block::Block, message::EphemeraMessage, quorum_certificate::QuorumCertificate},
+    },
     broadcast::{
-        bracha::broadcast::BroadcastResponse, bracha::broadcast::Broadcaster,
-        group::BroadcastGroup, RbMsg,
+        evidence::{EquivocationDetector, Evidence},
+        fetch::BlockFetcher,
+        group::{BroadcastGroup, GroupSnapshot},
+        BroadcastProtocol, BroadcastResponse, RawRbMsg, RbMsg,
     },
+    commitment::CommitmentSender,
     core::{
         api_cmd::ApiCmdProcessor,
+        block_check::{BlockChecker, CheckedBlock},
         builder::{EphemeraHandle, NodeInfo},
+        delivery::{DeliveryHandle, DeliveryRequest},
+        message_check::{CheckedMessages, MessageChecker},
         shutdown::ShutdownManager,
     },
     network::{
@@ -28,8 +49,9 @@ use crate::{
             network_sender::{NetCommunicationReceiver, NetworkEvent},
         },
     },
-    storage::EphemeraDatabase,
-    utilities::crypto::Certificate,
+    peer::PeerId,
+    storage::{pruning::PruningHandle, retention_cutoff, EphemeraDatabase},
+    utilities::{crypto::Certificate, hash::Hash, id::EphemeraId},
     websocket::ws_manager::WsMessageBroadcaster,
 };
 
@@ -44,7 +66,17 @@ enum EphemeraCoreError {
 
 type Result<T> = std::result::Result<T, EphemeraCoreError>;
 
-pub struct Ephemera<A: Application> {
+/// Awaits an application call, it fails if the application doesn't respond in time.
+pub(crate) async fn with_timeout<T>(
+    timeout: Duration,
+    call: impl Future<Output = ApplicationResult<T>>,
+) -> ApplicationResult<T> {
+    time::timeout(timeout, call)
+        .await
+        .unwrap_or(Err(ApplicationError::Timeout))
+}
+
+pub struct Ephemera<A: AsyncApplication> {
     /// Node info
     pub(crate) node_info: NodeInfo,
 
@@ -54,8 +86,19 @@ pub struct Ephemera<A: Application> {
     /// - Message verification sent by clients and gossiped other nodes
     pub(crate) block_manager: BlockManager,
 
+    /// Block sync fetches committed blocks which the node missed while it was offline or lagging.
+    /// Block production is paused until the node has caught up.
+    pub(crate) block_sync: BlockSync,
+
     /// Broadcaster is making sure that blocks are deterministically agreed by all nodes.
-    pub(crate) broadcaster: Broadcaster,
+    /// The protocol is chosen in configuration, see [`crate::config::BroadcastProtocolKind`].
+    pub(crate) broadcaster: Box<dyn BroadcastProtocol>,
+
+    /// Keeps broadcast messages which arrived before their block and the blocks fetched for them.
+    pub(crate) block_fetcher: BlockFetcher,
+
+    /// Detects peers which sign conflicting blocks during broadcast.
+    pub(crate) equivocation_detector: EquivocationDetector,
 
     /// A component which receives messages from network.
     pub(crate) from_network: NetCommunicationReceiver,
@@ -69,6 +112,12 @@ pub struct Ephemera<A: Application> {
     /// A component which has mutable access to database.
     pub(crate) storage: Arc<Mutex<Box<dyn EphemeraDatabase>>>,
 
+    /// Tells the pruning service which blocks the application has received, and provides its stats.
+    pub(crate) pruning: PruningHandle,
+
+    /// Publishes commitments of committed blocks to an external chain, if the application provided a publisher.
+    pub(crate) commitments: Option<CommitmentSender>,
+
     /// A component which broadcasts messages to websocket clients.
     pub(crate) ws_message_broadcast: WsMessageBroadcaster,
 
@@ -81,6 +130,15 @@ pub struct Ephemera<A: Application> {
     /// An implementation of Application trait. Provides callbacks to broadcast.
     pub(crate) application: Arc<A>,
 
+    /// Delivers committed blocks and evidence to the application in a background task.
+    pub(crate) deliveries: DeliveryHandle,
+
+    /// Runs application checks of new messages outside of the main loop.
+    pub(crate) message_checker: MessageChecker<A>,
+
+    /// Runs application checks of blocks we propose outside of the main loop.
+    pub(crate) block_checker: BlockChecker<A>,
+
     ///Interface to external Rust code
     pub(crate) ephemera_handle: EphemeraHandle,
 
@@ -91,7 +149,7 @@ pub struct Ephemera<A: Application> {
     pub(crate) services: Vec<BoxFuture<'static, anyhow::Result<()>>>,
 }
 
-impl<A: Application> Ephemera<A> {
+impl<A: AsyncApplication + 'static> Ephemera<A> {
     ///Provides external api for Rust code to interact with ephemera node.
     #[must_use]
     pub fn handle(&self) -> EphemeraHandle {
@@ -114,14 +172,25 @@ impl<A: Application> Ephemera<A> {
             self.shutdown_manager.add_handle(handle);
         }
 
+        if let Err(err) = self.replay_blocks().await {
+            error!("Error replaying blocks to Application, shutting down ephemera: {err:?}");
+            self.shutdown_manager.stop().await;
+            return;
+        }
+
         info!("Starting ephemera main loop");
 
         loop {
             tokio::select! {
                 // GENERATING NEW BLOCKS
                 Some((new_block, certificate)) = self.block_manager.next() => {
-                    if let Err(err) = self.process_new_local_block(new_block, certificate).await{
-                        error!("Error processing new block: {:?}", err);
+                    self.process_new_local_block(new_block, certificate);
+                }
+
+                // REQUESTING MISSING BLOCKS
+                Some(request) = self.block_sync.next() => {
+                    if let Err(err) = self.to_network.send_ephemera_event(EphemeraEvent::RequestBlocks(request)).await{
+                        error!("Error requesting blocks: {:?}", err);
                     }
                 }
 
@@ -152,6 +221,25 @@ impl<A: Application> Ephemera<A> {
                     }
                 }
 
+                //PROCESSING MESSAGES CHECKED BY APPLICATION
+                Some(checked) = self.message_checker.checked_rcv.recv() => {
+                    if let Err(err) = self.process_checked_messages(checked).await {
+                        error!("Error processing checked messages: {:?}", err);
+                    }
+                }
+
+                //BROADCASTING NEW BLOCKS CHECKED BY APPLICATION
+                Some(checked) = self.block_checker.checked_rcv.recv() => {
+                    if let Err(err) = self.process_checked_block(checked).await {
+                        error!("Error processing checked block: {:?}", err);
+                    }
+                }
+
+                //PROCESSING APPLICATION STATE AFTER DELIVERED BLOCKS
+                Some((height, app_hash)) = self.deliveries.app_hashes_rcv.recv() => {
+                    self.block_manager.on_app_hash(height, app_hash);
+                }
+
                 //PROCESSING SHUTDOWN REQUEST
                 _ = self.shutdown_manager.external_shutdown.recv() => {
                     info!("Shutting down ephemera");
@@ -168,33 +256,35 @@ impl<A: Application> Ephemera<A> {
 
         match net_event {
             NetworkEvent::EphemeraMessage(em) => {
-                let api_msg = (*em.clone()).into();
-                trace!("New ephemera message from network: {:?}", api_msg);
+                trace!("New ephemera message from network: {:?}", em);
 
                 //Only Application checks if messages are valid(possibly message origin).
                 //For messages we don't check if sender belongs to group.
 
-                // Ask application to decide if we should accept this message.
-                match self.application.check_tx(api_msg) {
-                    Ok(true) => {
-                        trace!("Application accepted message: {:?}", em);
-
-                        // Send to BlockManager to store in mempool.
-                        if let Err(err) = self.block_manager.on_new_message(*em) {
-                            error!("Error sending signed message to block manager: {:?}", err);
-                        }
-                    }
-                    Ok(false) => {
-                        trace!("Application rejected message: {:?}", em);
-                    }
-                    Err(err) => {
-                        error!("Application check_tx failed: {:?}", err);
-                    }
+                //Messages which were already committed are not accepted again.
+                if let Err(err) = self.check_message_not_committed(&em).await {
+                    trace!("Rejected message from network: {err}");
+                    return Ok(());
                 }
+
+                // Ask application to decide if we should accept this message.
+                self.message_checker.check_network_message(em);
             }
             NetworkEvent::BroadcastMessage(rb_msg) => {
                 self.process_block_from_network(*rb_msg).await?;
             }
+            NetworkEvent::BroadcastBlockRequest { id, hash } => {
+                self.process_broadcast_block_request(id, hash).await?;
+            }
+            NetworkEvent::BroadcastBlock(block) => match self.block_fetcher.on_block(&block) {
+                Ok(pending) => {
+                    self.process_pending_broadcast_messages(pending, &block)
+                        .await?;
+                }
+                Err(err) => {
+                    warn!("Invalid block {} from fetch: {err:?}", block.get_hash());
+                }
+            },
             NetworkEvent::GroupUpdate(event) => {
                 self.process_group_update(event);
             }
@@ -216,51 +306,166 @@ impl<A: Application> Ephemera<A> {
                     }
                 }
             }
+            NetworkEvent::BlockSyncRequest { id, request } => {
+                self.process_block_sync_request(id, request).await?;
+            }
+            NetworkEvent::BlockSyncResponse(response) => {
+                self.process_block_sync_response(*response).await?;
+            }
+            NetworkEvent::BlockSyncFailed => {
+                self.block_sync.on_request_failed();
+            }
+        }
+        Ok(())
+    }
+
+    /// Puts messages accepted by the application into the mempool.
+    async fn process_checked_messages(&mut self, checked: CheckedMessages) -> Result<()> {
+        match checked {
+            CheckedMessages::Network(em, result) => match result {
+                Ok(true) => {
+                    trace!("Application accepted message: {:?}", em);
+
+                    // Send to BlockManager to store in mempool.
+                    if let Err(err) = self.block_manager.on_new_message(*em) {
+                        error!("Error sending signed message to block manager: {:?}", err);
+                    }
+                }
+                Ok(false) => {
+                    trace!("Application rejected message: {:?}", em);
+                    let hash = em.hash_with_default_hasher()?;
+                    self.block_manager
+                        .on_message_rejected(hash, "Application rejected message");
+                }
+                Err(err) => {
+                    error!("Application check_tx failed: {:?}", err);
+                }
+            },
+            CheckedMessages::Submitted(api_msg, result, reply) => {
+                ApiCmdProcessor::on_submitted_message(self, *api_msg, result, reply).await;
+            }
+            CheckedMessages::SubmittedBatch(checked, reply) => {
+                ApiCmdProcessor::on_submitted_messages(self, checked, reply).await;
+            }
         }
         Ok(())
     }
 
+    /// Replay protection. Rejects messages which were already committed in a block.
+    ///
+    /// Committed message hashes are kept only during the retention window. Messages older than that are
+    /// rejected as expired because we can't tell anymore if they were committed.
+    pub(crate) async fn check_message_not_committed(
+        &mut self,
+        msg: &EphemeraMessage,
+    ) -> block::manager::Result<()> {
+        let hash = msg.hash_with_default_hasher()?;
+        let retention = self
+            .node_info
+            .initial_config
+            .storage
+            .committed_messages_retention_sec;
+        if let Some(retention_sec) = retention {
+            if msg.timestamp < retention_cutoff(retention_sec) {
+                return Err(MessagePoolError::Expired(hash.to_string()).into());
+            }
+        }
+
+        let block_hash = self
+            .storage
+            .lock()
+            .await
+            .get_message_block_hash(&hash.to_string())
+            .map_err(|err| anyhow!(err))?;
+        match block_hash {
+            Some(block_hash) => {
+                debug!("Message {hash} was already committed in block {block_hash}");
+                Err(BlockManagerError::DuplicateMessage(hash.to_string()))
+            }
+            None => Ok(()),
+        }
+    }
+
     fn process_group_update(&mut self, event: GroupChangeEvent) {
         match event {
-            GroupChangeEvent::PeersUpdated(peers) => {
-                info!("New group: {:?}", peers);
-                info!("{}", Quorum::cluster_size_info(peers.len()));
-                self.broadcaster.group_updated(peers.len());
-                self.broadcast_group.add_snapshot(peers);
-                self.block_manager.start();
+            GroupChangeEvent::PeersUpdated(snapshot) => {
+                let peers = &snapshot.members;
+                let quorum = snapshot.quorum();
+                info!("New group: {:?}", snapshot);
+                info!("{}", quorum.info());
+                self.broadcaster.group_updated(quorum);
+                //Nobody to sync from, local node is the source of truth.
+                if peers.len() == 1 && peers.contains(&self.node_info.peer_id) {
+                    self.block_sync.mark_synced();
+                }
+                self.block_manager.on_group_updated(peers);
+                self.broadcast_group.add_snapshot(snapshot);
+                self.block_sync.on_group_updated();
+                if self.block_sync.is_synced() {
+                    self.block_manager.start();
+                }
             }
             GroupChangeEvent::LocalPeerRemoved(peers) | GroupChangeEvent::NotEnoughPeers(peers) => {
                 info!("New group: {:?}", peers);
                 info!("Group update: Local peer removed or not enough peers");
-                self.broadcaster.group_updated(0);
+                self.broadcaster.group_updated(Quorum::new(0));
+                self.block_manager.on_group_updated(&peers);
                 self.broadcast_group.add_snapshot(peers);
                 self.block_manager.stop();
             }
         }
     }
 
-    async fn process_new_local_block(
-        &mut self,
-        new_block: Block,
-        certificate: Certificate,
-    ) -> Result<()> {
+    /// Checks a block produced by block manager and hands it to the application to check.
+    fn process_new_local_block(&mut self, new_block: Block, certificate: Certificate) {
         debug!("New block from block manager: {:?}", new_block.get_hash());
 
         let hash = new_block.header.hash;
+        let height = new_block.get_height();
         let block_creator = &self.node_info.peer_id;
         let sender = &self.node_info.peer_id;
+        let view = new_block.header.view;
+        let max_view = self.block_manager.current_view();
 
         // Check if block matches group membership.
+        if !self.broadcast_group.check_membership(
+            hash,
+            height,
+            view,
+            max_view,
+            block_creator,
+            sender,
+        ) {
+            debug!("Membership check rejected block: {:?}", new_block);
+            return;
+        }
+
         if !self
-            .broadcast_group
-            .check_membership(hash, block_creator, sender)
+            .equivocation_detector
+            .is_first_proposal(&new_block.header)
         {
-            debug!("Membership check rejected block: {:?}", new_block);
+            warn!("Already proposed a block at height {height} view {view}, not proposing {hash}");
+            return;
+        }
+
+        //Ephemera ABCI, the block is broadcast after the application accepts it
+        self.block_checker.check_block(new_block, certificate);
+    }
+
+    /// The application checked a block we produced. If it accepted it, starts the broadcast.
+    async fn process_checked_block(&mut self, checked: CheckedBlock) -> Result<()> {
+        let CheckedBlock {
+            block: new_block,
+            certificate,
+            result,
+        } = checked;
+        let hash = new_block.get_hash();
+        if new_block.get_height() < self.block_manager.next_block_height() {
+            debug!("Block {hash} was checked after its height was committed, not broadcasting it");
             return Ok(());
         }
 
-        //Ephemera ABCI
-        match self.application.check_block(&new_block.clone().into()) {
+        match result {
             Ok(response) => match response {
                 CheckBlockResult::Accept => {
                     debug!("Application accepted new block: {hash:?}",);
@@ -285,135 +490,534 @@ impl<A: Application> Ephemera<A> {
 
         //Block manager generated new block that nobody hasn't seen yet.
         //We start reliable broadcaster protocol to broadcaster it to other nodes.
-        match self.broadcaster.new_broadcast(new_block) {
-            Ok(resp) => {
-                if let BroadcastResponse::Broadcast(msg) = resp {
-                    trace!("Broadcasting new block: {:?}", msg);
+        if let BroadcastResponse::Broadcast(msg) = self.broadcaster.new_broadcast(new_block) {
+            trace!("Broadcasting new block: {:?}", msg);
 
-                    let rb_msg = RbMsg::new(msg, certificate);
+            let rb_msg = RbMsg::new(msg, certificate);
+            self.to_network
+                .send_ephemera_event(EphemeraEvent::ProtocolMessage(rb_msg.into()))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Resolves the block of a broadcast message. Only proposals carry the block, for echo and vote
+    /// we look it up locally or fetch it from the sender.
+    async fn process_block_from_network(&mut self, msg: RbMsg) -> Result<()> {
+        trace!("New broadcast message from network: {:?}", msg);
+
+        if let Some(block) = msg.block().cloned() {
+            let pending = self.block_fetcher.on_block(&block)?;
+            self.process_broadcast_message(msg, &block).await?;
+            return self
+                .process_pending_broadcast_messages(pending, &block)
+                .await;
+        }
+
+        let hash = msg.block_hash();
+        let block = match self.block_fetcher.get(&hash) {
+            Some(block) => Some(block),
+            None => self.block_manager.get_block_by_hash(&hash),
+        };
+        match block {
+            Some(block) => self.process_broadcast_message(msg, &block).await,
+            None if self.broadcaster.is_delivered(&hash) => {
+                trace!("Block {hash} already delivered, ignoring {}", msg.id);
+                Ok(())
+            }
+            None => {
+                let peer = msg.original_sender;
+                //Block we don't know is broadcast in the current group
+                let group = self.broadcast_group.current_snapshot();
+                if self.block_fetcher.on_missing_block(msg, group) {
+                    debug!("Requesting block {hash} from {peer}");
                     self.to_network
-                        .send_ephemera_event(EphemeraEvent::ProtocolMessage(rb_msg.into()))
+                        .send_ephemera_event(EphemeraEvent::RequestBroadcastBlock { hash, peer })
                         .await?;
                 }
+                Ok(())
             }
-            Err(err) => {
-                error!("Error starting new broadcast: {:?}", err);
+        }
+    }
+
+    /// Processes messages which arrived before their block.
+    async fn process_pending_broadcast_messages(
+        &mut self,
+        pending: Vec<RbMsg>,
+        block: &Block,
+    ) -> Result<()> {
+        for msg in pending {
+            if let Err(err) = self.process_broadcast_message(msg, block).await {
+                error!("Error processing pending broadcast message: {:?}", err);
             }
         }
         Ok(())
     }
 
     //TODO: should we accept more blocks(certificates) from peers after its committed?
-    async fn process_block_from_network(&mut self, msg: RbMsg) -> Result<()> {
+    async fn process_broadcast_message(&mut self, msg: RbMsg, block: &Block) -> Result<()> {
         let msg_id = msg.id.clone();
-        let block = msg.block();
         let block_creator = &block.header.creator;
         let sender = &msg.original_sender;
         let hash = block.header.hash;
         let certificate = msg.certificate.clone();
 
-        trace!("New broadcast message from network: {:?}", msg);
+        let height = block.get_height();
+        let view = block.header.view;
+        let max_view = self.max_leader_view(height);
+        if !self.broadcast_group.check_membership(
+            hash,
+            height,
+            view,
+            max_view,
+            block_creator,
+            sender,
+        ) {
+            return Err(anyhow!("Block doesn't match broacast group").into());
+        }
 
-        if !self
-            .broadcast_group
-            .check_membership(hash, block_creator, sender)
+        match self
+            .equivocation_detector
+            .on_signed_header(&block.header, &certificate)
         {
-            return Err(anyhow!("Block doesn't match broacast group").into());
+            Ok(Some(evidence)) => self.on_evidence(evidence).await?,
+            Ok(None) => {}
+            Err(err) => error!("Failed to check block {hash} for equivocation: {err:?}"),
+        }
+
+        //Take part only in the first proposal of the creator at this height and view,
+        //echoing a conflicting one would make us an offender too
+        if !self.equivocation_detector.is_first_proposal(&block.header) {
+            debug!(
+                "Ignoring {msg_id:?}, block {hash} conflicts with an earlier block of {block_creator} at height {height} view {view}"
+            );
+            return Ok(());
+        }
+
+        //Peers are ahead of us, we missed some blocks. Catch up before producing new ones.
+        if block.get_height() > self.block_manager.next_block_height()
+            && self.block_sync.is_synced()
+        {
+            info!(
+                "Received block at height {}, expected {}. Starting block sync",
+                block.get_height(),
+                self.block_manager.next_block_height()
+            );
+            self.block_manager.stop();
+            self.block_sync.start(Some(block.get_height() - 1));
         }
 
         if let Err(err) = self.block_manager.on_block(sender, block, &certificate) {
             return Err(anyhow!("Error sending block to block manager: {:?}", err).into());
         }
+
         let raw_mgs = msg.into();
         match self.broadcaster.handle(&raw_mgs) {
-            Ok(resp) => {
-                match resp {
-                    BroadcastResponse::Broadcast(msg) => {
-                        trace!("Broadcasting block to network: {:?}", msg);
-
-                        match self.block_manager.sign_block(&msg.block()) {
-                            Ok(certificate) => {
-                                let rb_msg = RbMsg::new(msg, certificate);
-                                self.to_network
-                                    .send_ephemera_event(EphemeraEvent::ProtocolMessage(
-                                        rb_msg.into(),
-                                    ))
-                                    .await?;
-                            }
-                            Err(err) => {
-                                return Err(anyhow!("Error signing block: {:?}", err).into());
-                            }
-                        }
-                    }
-                    BroadcastResponse::Deliver(hash) => {
-                        trace!("Block broadcast complete: {hash:?}",);
-                        let block = self.block_manager.get_block_by_hash(&hash);
-                        match block {
-                            Some(block) => {
-                                if block.header.creator == self.node_info.peer_id {
-                                    info!("Block committed, ready to deliver...: {hash:?}",);
-
-                                    //BlockManager
-                                    self.block_manager.on_block_committed(&block).map_err(|e| {
-                                        anyhow!(
-                                            "Error: BlockManager failed to process block: {e:?}",
-                                        )
-                                    })?;
-
-                                    //Save to database
-                                    let certificates = self
-                                        .block_manager
-                                        .get_block_certificates(&block.header.hash)
-                                        .ok_or(anyhow!(
-                                            "Error: Block certificates not found for block: {hash:?}"
-                                        ))?;
-                                    let members = self
-                                        .broadcast_group
-                                        .get_group_by_block_hash(block.get_hash())
-                                        .ok_or(anyhow!(
-                                            "Error: Group not found for block: {hash:?}"
-                                        ))?;
-
-                                    if let Err(e) = self.storage.lock().await.store_block(
-                                        &block,
-                                        certificates.clone(),
-                                        members.clone(),
-                                    ) {
-                                        return Err(EphemeraCoreError::DatabaseFailure(e));
-                                    }
-
-                                    // It is open question how much Application `deliver_block` failure should affect
-                                    // continuing with next block.
-                                    //Application(ABCI)
-                                    self.application
-                                        .deliver_block(Into::into(block.clone()))
-                                        .map_err(|e| {
-                                            anyhow!(
-                                                "Error: Deliver block to Application failed: {e:?}",
-                                            )
-                                        })?;
-
-                                    //WS
-                                    self.ws_message_broadcast.send_block(&block)?;
-                                    info!("Block broadcast complete: {hash:?}",);
-                                }
-                            }
-                            None => {
-                                return Err(
-                                    anyhow!("Error: Block not found in block manager").into()
-                                );
-                            }
-                        }
-                    }
-                    BroadcastResponse::Drop(hash) => {
-                        trace!("Ignoring broadcast message {:?}[block {:?}]", msg_id, hash);
-                        return Ok(());
-                    }
-                }
+            BroadcastResponse::Broadcast(msg) => {
+                trace!("Broadcasting block to network: {:?}", msg);
+                self.send_protocol_message(msg, block, None).await?;
+            }
+            BroadcastResponse::SendTo(msg, peer) => {
+                trace!("Sending broadcast message to {peer}: {:?}", msg);
+                self.send_protocol_message(msg, block, Some(peer)).await?;
+            }
+            BroadcastResponse::BroadcastAndDeliver(msg) => {
+                trace!("Broadcasting block to network and delivering it: {:?}", msg);
+                let hash = msg.block_hash();
+                self.send_protocol_message(msg, block, None).await?;
+                self.process_committed_block(hash).await?;
+            }
+            BroadcastResponse::Deliver(hash) => {
+                trace!("Block broadcast complete: {hash:?}",);
+                self.process_committed_block(hash).await?;
             }
+            BroadcastResponse::Drop(hash) => {
+                trace!("Ignoring broadcast message {:?}[block {:?}]", msg_id, hash);
+            }
+        }
+        Ok(())
+    }
+
+    /// Signs the block header for the protocol message and sends it to the group, or to one peer.
+    async fn send_protocol_message(
+        &mut self,
+        msg: RawRbMsg,
+        block: &Block,
+        peer: Option<PeerId>,
+    ) -> Result<()> {
+        let certificate = self
+            .block_manager
+            .sign_block(block)
+            .map_err(|err| anyhow!("Error signing block: {:?}", err))?;
+        let rb_msg = Box::new(RbMsg::new(msg, certificate));
+        let event = match peer {
+            Some(peer) => EphemeraEvent::ProtocolMessageTo { msg: rb_msg, peer },
+            None => EphemeraEvent::ProtocolMessage(rb_msg),
+        };
+        self.to_network.send_ephemera_event(event).await?;
+        Ok(())
+    }
+
+    /// Highest view whose leader we accept a block from at given height.
+    ///
+    /// Peers track views locally, so we accept leaders one view ahead of ours. We know the view only for the
+    /// height we expect next. For other heights any member is accepted, `on_block` rejects wrong heights later.
+    fn max_leader_view(&self, height: u64) -> u64 {
+        if height == self.block_manager.next_block_height() {
+            self.block_manager.current_view() + 1
+        } else {
+            u64::MAX
+        }
+    }
+
+    /// Every group member who reached deliver threshold for a block persists it and notifies
+    /// its application and websocket clients. This way all nodes serve the same committed blocks.
+    async fn process_committed_block(&mut self, hash: Hash) -> Result<()> {
+        let block = self
+            .block_manager
+            .get_block_by_hash(&hash)
+            .ok_or(anyhow!("Error: Block not found in block manager"))?;
+
+        info!("Block committed, ready to deliver...: {hash:?}",);
+
+        //BlockManager
+        self.block_manager
+            .on_block_committed(&block)
+            .map_err(|e| anyhow!("Error: BlockManager failed to process block: {e:?}",))?;
+
+        let group = self
+            .broadcast_group
+            .get_group_by_block_hash(block.get_hash())
+            .ok_or(anyhow!("Error: Group not found for block: {hash:?}"))?
+            .clone();
+        let (phase, phase_certificates) =
+            self.broadcaster
+                .delivery_certificates(&hash)
+                .ok_or(anyhow!(
+                    "Error: Delivery certificates not found for block: {hash:?}"
+                ))?;
+        let quorum_certificate =
+            QuorumCertificate::for_phase(phase, &phase_certificates, &group.members);
+
+        self.commit_block(&block, &group, quorum_certificate)
+            .await?;
+        info!("Block broadcast complete: {hash:?}",);
+        Ok(())
+    }
+
+    /// Persists committed block and notifies application and websocket clients.
+    async fn commit_block(
+        &mut self,
+        block: &Block,
+        group: &GroupSnapshot,
+        quorum_certificate: QuorumCertificate,
+    ) -> Result<()> {
+        let hash = block.get_hash();
+
+        //Without leader(s) it is possible that more than one block gets committed at the same height.
+        //We keep the first one we see.
+        let existing = self
+            .storage
+            .lock()
+            .await
+            .get_block_by_height(block.get_height())
+            .map_err(EphemeraCoreError::DatabaseFailure)?;
+        if let Some(existing) = existing {
+            warn!(
+                "Block {} already committed at height {}, ignoring block {hash}",
+                existing.get_hash(),
+                block.get_height()
+            );
+            return Ok(());
+        }
+
+        //Save to database, certificates are the phase signatures which delivered the block
+        let members = group.members.iter().copied().collect::<Vec<_>>();
+        let certificates = quorum_certificate.block_certificates(&members)?;
+        if let Err(e) =
+            self.storage
+                .lock()
+                .await
+                .store_block(block, &certificates, group, &quorum_certificate)
+        {
+            return Err(EphemeraCoreError::DatabaseFailure(e));
+        }
+        self.block_sync.on_block_stored(block.get_height());
+
+        //Application(ABCI)
+        self.deliveries
+            .send(DeliveryRequest::Block(block.clone().into()));
+
+        //External chain
+        if let Some(commitments) = &self.commitments {
+            commitments.send(block, quorum_certificate);
+        }
+
+        //WS
+        self.ws_message_broadcast.send_block(block)?;
+        Ok(())
+    }
+
+    /// Stores evidence of equivocation and passes it to the application.
+    async fn on_evidence(&mut self, evidence: Evidence) -> Result<()> {
+        self.storage
+            .lock()
+            .await
+            .store_evidence(&evidence)
+            .map_err(EphemeraCoreError::DatabaseFailure)?;
+        self.deliveries
+            .send(DeliveryRequest::Evidence(Box::new(evidence.into())));
+        Ok(())
+    }
+
+    /// Handshake with the application at startup. Queues committed blocks which the application hasn't processed
+    /// yet for delivery, they are retried in order until the application accepts them.
+    /// Application state hash after the last stored block is needed before the node accepts new blocks.
+    ///
+    /// Fails if the application is ahead of stored blocks, the node can't produce the next block then.
+    async fn replay_blocks(&mut self) -> Result<()> {
+        let last_block = self
+            .storage
+            .lock()
+            .await
+            .get_last_block()
+            .map_err(EphemeraCoreError::DatabaseFailure)?;
+        let last_height = last_block.as_ref().map_or(0, Block::get_height);
+
+        let timeout = self.application_timeout();
+        let app_info = match with_timeout(timeout, self.application.info()).await {
+            Ok(info) => info,
             Err(err) => {
-                error!("Error handling broadcast message: {:?}", err);
+                error!("Application info failed, not replaying blocks: {err:?}");
+                self.commit_application_at_startup(last_block);
+                return Ok(());
             }
+        };
+        let Some(app_height) = app_info.last_block_height else {
+            debug!("Application doesn't report its last block height, not replaying blocks");
+            self.commit_application_at_startup(last_block);
+            return Ok(());
+        };
+
+        if app_height > last_height {
+            return Err(anyhow!(
+                "Application last block height {app_height} is ahead of stored blocks {last_height}"
+            )
+            .into());
+        }
+        self.block_manager
+            .on_app_hash(app_height, Hash::new(app_info.last_app_hash));
+        self.pruning.on_delivered(app_height);
+        if app_height == last_height {
+            info!("Application is up to date at height {app_height}");
+            return Ok(());
+        }
+
+        info!(
+            "Replaying blocks {} to {last_height} to Application",
+            app_height + 1
+        );
+        for height in app_height + 1..=last_height {
+            let block = self
+                .storage
+                .lock()
+                .await
+                .get_block_by_height(height)
+                .map_err(EphemeraCoreError::DatabaseFailure)?
+                .ok_or(anyhow!("Block at height {height} not found"))?;
+            self.deliveries.send(DeliveryRequest::Block(block.into()));
+        }
+        Ok(())
+    }
+
+    /// Blocks can't be replayed, only commits the application state after the last stored block.
+    fn commit_application_at_startup(&mut self, last_block: Option<Block>) {
+        if let Some(block) = last_block {
+            self.deliveries.send(DeliveryRequest::Commit(block.into()));
+        } else {
+            warn!("No stored blocks, Application is not committed");
+        }
+    }
+
+    pub(crate) fn application_timeout(&self) -> Duration {
+        Duration::from_millis(self.node_info.initial_config.application.call_timeout_ms)
+    }
+
+    /// Sends requested committed blocks together with their certificates and broadcast groups.
+    /// A peer got an echo or vote before the proposal and asks for the block.
+    async fn process_broadcast_block_request(&mut self, id: EphemeraId, hash: Hash) -> Result<()> {
+        trace!("Processing broadcast block request: {hash}");
+
+        let mut block = self.block_fetcher.get(&hash);
+        if block.is_none() {
+            block = self.block_manager.get_block_by_hash(&hash);
+        }
+        if block.is_none() {
+            block = self
+                .storage
+                .lock()
+                .await
+                .get_block_by_hash(&hash.to_string())
+                .map_err(EphemeraCoreError::DatabaseFailure)?;
+        }
+
+        self.to_network
+            .send_ephemera_event(EphemeraEvent::BroadcastBlockResponse {
+                id,
+                block: block.map(Box::new),
+            })
+            .await?;
+        Ok(())
+    }
+
+    async fn process_block_sync_request(
+        &mut self,
+        id: EphemeraId,
+        request: BlockSyncRequest,
+    ) -> Result<()> {
+        debug!("Processing block sync request: {request:?}");
+
+        let response = {
+            let storage = self.storage.lock().await;
+            let last_height = storage
+                .get_last_block()
+                .map_err(EphemeraCoreError::DatabaseFailure)?
+                .map_or(0, |block| block.get_height());
+
+            //Genesis block is local to each node, it's never synced
+            let from_height = request.from_height.max(1);
+            let to_height = request
+                .to_height
+                .min(from_height + MAX_BLOCKS_PER_REQUEST - 1)
+                .min(last_height);
+
+            let pruned_height = storage
+                .get_pruned_height()
+                .map_err(EphemeraCoreError::DatabaseFailure)?;
+            if let Some(pruned_height) = pruned_height.filter(|pruned| *pruned >= from_height) {
+                debug!(
+                    "Requested blocks from height {from_height} are pruned up to {pruned_height}"
+                );
+                BlockSyncResponse::pruned(last_height, pruned_height)
+            } else {
+                Self::sync_blocks(&**storage, from_height, to_height, last_height)?
+            }
+        };
+
+        self.to_network
+            .send_ephemera_event(EphemeraEvent::BlockSyncResponse {
+                id,
+                response: response.into(),
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Collects stored blocks with their phase quorum certificates, stops at the first block which can't be synced.
+    fn sync_blocks(
+        storage: &dyn EphemeraDatabase,
+        from_height: u64,
+        to_height: u64,
+        last_height: u64,
+    ) -> Result<BlockSyncResponse> {
+        let mut blocks = vec![];
+        for height in from_height..=to_height {
+            let Some(block) = storage
+                .get_block_by_height(height)
+                .map_err(EphemeraCoreError::DatabaseFailure)?
+            else {
+                break;
+            };
+            let hash = block.get_hash().to_string();
+            //Blocks committed before phase signatures can't prove their delivery
+            let Some(quorum_certificate) = storage
+                .get_block_quorum_certificate(&hash)
+                .map_err(EphemeraCoreError::DatabaseFailure)?
+                .filter(|quorum_certificate| quorum_certificate.phase.is_some())
+            else {
+                debug!("Block at height {height} has no phase quorum certificate");
+                break;
+            };
+            let members = storage
+                .get_block_broadcast_group(&hash)
+                .map_err(EphemeraCoreError::DatabaseFailure)?
+                .unwrap_or_default();
+            blocks.push(SyncBlock::new(block, members, quorum_certificate));
+        }
+        Ok(BlockSyncResponse::new(blocks, last_height))
+    }
+
+    /// Verifies and commits blocks received from a peer.
+    async fn process_block_sync_response(&mut self, response: BlockSyncResponse) -> Result<()> {
+        if let Some(pruned_height) = response.pruned_height {
+            if self.block_sync.on_pruned_response(pruned_height) {
+                return Ok(());
+            }
+        }
+        for sync_block in response.blocks {
+            let height = sync_block.block.get_height();
+            if height < self.block_sync.next_height {
+                trace!("Block at height {height} already stored, skipping");
+                continue;
+            }
+            if height > self.block_sync.next_height {
+                debug!(
+                    "Block sync response has a gap: {height} > {}",
+                    self.block_sync.next_height
+                );
+                break;
+            }
+
+            let (parent, local_group) = {
+                let storage = self.storage.lock().await;
+                let parent = storage
+                    .get_block_by_height(height - 1)
+                    .map_err(EphemeraCoreError::DatabaseFailure)?
+                    .ok_or(anyhow!("Parent block at height {} not found", height - 1))?;
+                //The peer could claim any group, only a group we know ourselves proves the block
+                let local_group = sync_block
+                    .trusted_group(&self.broadcast_group, &parent, storage.as_ref())
+                    .map_err(EphemeraCoreError::DatabaseFailure)?;
+                (parent, local_group)
+            };
+            let Some(local_group) = local_group else {
+                warn!("Received block at height {height} from a broadcast group we don't know");
+                self.block_sync.on_untrusted_group();
+                return Ok(());
+            };
+            if let Err(err) = sync_block.verify(&local_group) {
+                warn!("Received invalid block at height {height}: {err:?}");
+                self.block_sync.on_request_failed();
+                return Ok(());
+            }
+
+            //Block needs to extend our chain
+            if sync_block.block.header.previous_hash != parent.hash_as_parent() {
+                warn!(
+                    "Received block at height {height} doesn't extend local chain: parent {} != {}",
+                    sync_block.block.header.previous_hash,
+                    parent.get_hash()
+                );
+                self.block_sync.on_request_failed();
+                return Ok(());
+            }
+
+            let SyncBlock {
+                block,
+                quorum_certificate,
+                ..
+            } = sync_block;
+            self.block_manager
+                .on_block_committed(&block)
+                .map_err(|e| anyhow!("Error: BlockManager failed to process block: {e:?}",))?;
+            self.commit_block(&block, &local_group, quorum_certificate)
+                .await?;
+            //Block at this height might have been committed via broadcast meanwhile
+            self.block_sync.on_block_stored(height);
+        }
+
+        if self.block_sync.on_response(response.last_height) && self.broadcaster.is_group_active() {
+            self.block_manager.start();
         }
         Ok(())
     }
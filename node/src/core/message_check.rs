@@ -0,0 +1,140 @@
+//! # Message checks
+//!
+//! The application checks every new message with `check_tx` before it's put into the mempool. Checks run in their
+//! own tasks, so a slow application doesn't hold up the node. Results come back to the main loop, which puts
+//! accepted messages into the mempool, gossips them and answers the api.
+//!
+//! At most `MAX_PENDING_CHECKS` checks run at once, messages which arrive meanwhile are rejected.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, error};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::{oneshot, OwnedSemaphorePermit, Semaphore};
+
+use crate::api::application::{AsyncApplication, Result as ApplicationResult};
+use crate::api::types::{ApiEphemeraMessage, ApiSubmitMessageResult};
+use crate::api::{self, types::ApiError};
+use crate::block::types::message::EphemeraMessage;
+use crate::core::ephemera::with_timeout;
+
+/// Maximum number of messages the application checks at once.
+const MAX_PENDING_CHECKS: usize = 1000;
+
+pub(crate) type SubmitReply = oneshot::Sender<api::Result<String>>;
+
+pub(crate) type SubmitBatchReply = oneshot::Sender<api::Result<Vec<ApiSubmitMessageResult>>>;
+
+/// Messages together with the result of the application check.
+pub(crate) enum CheckedMessages {
+    /// Message gossiped by a peer
+    Network(Box<EphemeraMessage>, ApplicationResult<bool>),
+    /// Message submitted over the api
+    Submitted(
+        Box<ApiEphemeraMessage>,
+        ApplicationResult<bool>,
+        SubmitReply,
+    ),
+    /// Messages submitted over the api as one batch, in submitted order
+    SubmittedBatch(
+        Vec<(ApiEphemeraMessage, ApplicationResult<bool>)>,
+        SubmitBatchReply,
+    ),
+}
+
+pub(crate) struct MessageChecker<A: AsyncApplication> {
+    application: Arc<A>,
+    /// How long to wait for an application call
+    timeout: Duration,
+    /// Limits the number of running checks
+    permits: Arc<Semaphore>,
+    checked_tx: UnboundedSender<CheckedMessages>,
+    pub(crate) checked_rcv: UnboundedReceiver<CheckedMessages>,
+}
+
+impl<A: AsyncApplication + 'static> MessageChecker<A> {
+    pub(crate) fn new(application: Arc<A>, timeout: Duration) -> Self {
+        let (checked_tx, checked_rcv) = unbounded_channel();
+        Self {
+            application,
+            timeout,
+            permits: Arc::new(Semaphore::new(MAX_PENDING_CHECKS)),
+            checked_tx,
+            checked_rcv,
+        }
+    }
+
+    /// Checks a message gossiped by a peer. It's dropped if too many checks are running.
+    pub(crate) fn check_network_message(&self, msg: Box<EphemeraMessage>) {
+        let Some(permit) = self.permit() else {
+            debug!("Too many messages waiting for Application check, dropping {msg:?}");
+            return;
+        };
+        let api_msg: ApiEphemeraMessage = (*msg).clone().into();
+        self.spawn(permit, |application, timeout| async move {
+            let result = with_timeout(timeout, application.check_tx(api_msg)).await;
+            CheckedMessages::Network(msg, result)
+        });
+    }
+
+    /// Checks a message submitted over the api.
+    pub(crate) fn check_submitted_message(&self, msg: Box<ApiEphemeraMessage>, reply: SubmitReply) {
+        let Some(permit) = self.permit() else {
+            reply
+                .send(Err(Self::too_many_checks()))
+                .expect("Error sending SubmitEphemeraMessage response to api");
+            return;
+        };
+        self.spawn(permit, |application, timeout| async move {
+            let result = with_timeout(timeout, application.check_tx((*msg).clone())).await;
+            CheckedMessages::Submitted(msg, result, reply)
+        });
+    }
+
+    /// Checks messages submitted over the api as one batch, one after another.
+    pub(crate) fn check_submitted_messages(
+        &self,
+        msgs: Vec<ApiEphemeraMessage>,
+        reply: SubmitBatchReply,
+    ) {
+        let Some(permit) = self.permit() else {
+            reply
+                .send(Err(Self::too_many_checks()))
+                .expect("Error sending SubmitEphemeraMessages response to api");
+            return;
+        };
+        self.spawn(permit, |application, timeout| async move {
+            let mut checked = Vec::with_capacity(msgs.len());
+            for msg in msgs {
+                let result = with_timeout(timeout, application.check_tx(msg.clone())).await;
+                checked.push((msg, result));
+            }
+            CheckedMessages::SubmittedBatch(checked, reply)
+        });
+    }
+
+    fn permit(&self) -> Option<OwnedSemaphorePermit> {
+        self.permits.clone().try_acquire_owned().ok()
+    }
+
+    fn too_many_checks() -> ApiError {
+        ApiError::MempoolFull("Too many messages waiting for Application check".to_string())
+    }
+
+    fn spawn<F, Fut>(&self, permit: OwnedSemaphorePermit, check: F)
+    where
+        F: FnOnce(Arc<A>, Duration) -> Fut,
+        Fut: std::future::Future<Output = CheckedMessages> + Send + 'static,
+    {
+        let checked_tx = self.checked_tx.clone();
+        let check = check(self.application.clone(), self.timeout);
+        tokio::spawn(async move {
+            let checked = check.await;
+            drop(permit);
+            if checked_tx.send(checked).is_err() {
+                error!("Node stopped, checked messages are lost");
+            }
+        });
+    }
+}
@@ -1,4 +1,7 @@
 pub(crate) mod api_cmd;
+pub(crate) mod block_check;
 pub(crate) mod builder;
+pub(crate) mod delivery;
 pub(crate) mod ephemera;
+pub(crate) mod message_check;
 pub(crate) mod shutdown;
@@ -39,15 +39,21 @@ pub use crate::core::shutdown::Handle as ShutdownHandle;
 pub mod ephemera_api {
     pub use crate::api::{
         application::{
-            Application, CheckBlockResult, Dummy, Error as ApplicationError, RemoveMessages,
-            Result as ApplicationResult,
+            Application, ApplicationInfo, AsyncApplication, CheckBlockResult, Dummy,
+            Error as ApplicationError, RemoveMessages, Result as ApplicationResult,
         },
         http::client::{Client, Error as HttpClientError, Result as HttpClientResult},
         types::{
-            ApiBlock, ApiBlockBroadcastInfo, ApiBroadcastInfo, ApiCertificate, ApiDhtQueryRequest,
-            ApiDhtQueryResponse, ApiDhtStoreRequest, ApiEphemeraConfig, ApiEphemeraMessage,
-            ApiError, ApiHealth, ApiVerifyMessageInBlock, RawApiEphemeraMessage,
+            ApiApplicationHash, ApiApplicationQueryRequest, ApiApplicationQueryResponse, ApiBlock,
+            ApiBlockBroadcastInfo, ApiBlockCertificate, ApiBlockHeader, ApiBroadcastInfo,
+            ApiBroadcastPhase, ApiCertificate, ApiDhtQueryRequest, ApiDhtQueryResponse,
+            ApiDhtStoreRequest, ApiEphemeraConfig, ApiEphemeraMessage, ApiEquivocationEvidence,
+            ApiEquivocationKind, ApiError, ApiHealth, ApiMempoolStats, ApiMessageProof,
+            ApiMessageStatus, ApiPrunedBlock, ApiPruningStats, ApiQuorumCertificate,
+            ApiSignedHeader, ApiSubmitMessageResult, ApiSyncStatus, ApiVerifyMessageInBlock,
+            RawApiEphemeraMessage,
         },
+        verifier::{BlockVerdict, BlockVerifier},
         CommandExecutor,
     };
 }
@@ -86,6 +92,9 @@ pub mod configuration {
 /// Ephemera CLI. Helpers for creating configuration, running node, etc.
 pub mod cli;
 
+/// Publishing block commitments to an external chain.
+pub mod commitment;
+
 /// Utilities to set up logging.
 pub mod logging;
 
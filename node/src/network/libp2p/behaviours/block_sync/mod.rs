@@ -0,0 +1,92 @@
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncWrite};
+use libp2p::request_response;
+use log::trace;
+
+use crate::block::sync::{BlockSyncRequest, BlockSyncResponse};
+use crate::utilities::codec::varint_async::{read_length_prefixed, write_length_prefixed};
+
+//Blocks with messages can be large, response size is limited only by this.
+const MAX_RESPONSE_SIZE: u32 = 64 * 1024 * 1024;
+
+const MAX_REQUEST_SIZE: u32 = 1024;
+
+#[derive(Clone)]
+pub(crate) struct BlockSyncCodec;
+
+#[derive(Clone)]
+pub(crate) struct BlockSyncProtocol;
+
+impl request_response::ProtocolName for BlockSyncProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        "/ephemera/block_sync/2.0.0".as_bytes()
+    }
+}
+
+#[async_trait]
+impl request_response::Codec for BlockSyncCodec {
+    type Protocol = BlockSyncProtocol;
+    type Request = BlockSyncRequest;
+    type Response = BlockSyncResponse;
+
+    async fn read_request<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+    ) -> Result<Self::Request, std::io::Error>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let data = read_length_prefixed(io, MAX_REQUEST_SIZE).await?;
+        //FIXME: switch to binary
+        let request = serde_json::from_slice(&data)?;
+        trace!("Received block sync request {:?}", request);
+        Ok(request)
+    }
+
+    async fn read_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+    ) -> std::io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let data = read_length_prefixed(io, MAX_RESPONSE_SIZE).await?;
+        //FIXME: switch to binary
+        let response: BlockSyncResponse = serde_json::from_slice(&data)?;
+        trace!(
+            "Received block sync response with {} blocks",
+            response.blocks.len()
+        );
+        Ok(response)
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        req: Self::Request,
+    ) -> Result<(), std::io::Error>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let data = serde_json::to_vec(&req)?;
+        write_length_prefixed(io, data).await?;
+        Ok(())
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        response: Self::Response,
+    ) -> std::io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let data = serde_json::to_vec(&response)?;
+        write_length_prefixed(io, data).await?;
+        Ok(())
+    }
+}
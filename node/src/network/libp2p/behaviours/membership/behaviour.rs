@@ -182,8 +182,11 @@ where
         self.memberships.current().connected_peers()
     }
 
-    pub(crate) fn active_peer_ids_with_local(&mut self) -> HashSet<PeerId> {
-        self.memberships.current().connected_peer_ids_with_local()
+    /// Returns the peers of current group, local peer included, with their weights.
+    pub(crate) fn active_peer_weights_with_local(&mut self) -> HashMap<PeerId, u64> {
+        self.memberships
+            .current()
+            .connected_peer_weights_with_local()
     }
 
     fn waiting_peers(&mut self, cx: &mut Context) -> Poll<ToSwarm<Event, ToHandler>> {
@@ -2,11 +2,13 @@
 //! Only peers who are returned by [`crate::membership::MembersProviderFut`] are allowed to participate.
 
 use std::collections::{HashMap, HashSet};
+use std::iter;
 use std::num::NonZeroUsize;
 
 use libp2p_identity::PeerId;
 use lru::LruCache;
 
+use crate::broadcast::bracha::quorum::DEFAULT_WEIGHT;
 use crate::network::Peer;
 
 pub(crate) mod behaviour;
@@ -160,10 +162,19 @@ impl Membership {
         self.connected_peers_ids.clone()
     }
 
-    pub(crate) fn connected_peer_ids_with_local(&self) -> HashSet<PeerId> {
-        let mut active_peers = self.connected_peers_ids.clone();
-        active_peers.insert(self.local_peer_id);
-        active_peers
+    /// Connected peers and the local peer, with their weights.
+    pub(crate) fn connected_peer_weights_with_local(&self) -> HashMap<PeerId, u64> {
+        self.connected_peers_ids
+            .iter()
+            .chain(iter::once(&self.local_peer_id))
+            .map(|peer_id| {
+                let weight = self
+                    .all_members
+                    .get(peer_id)
+                    .map_or(DEFAULT_WEIGHT, |peer| peer.weight);
+                (*peer_id, weight)
+            })
+            .collect()
     }
 
     pub(crate) fn connected_peers(&self) -> &HashSet<PeerId> {
@@ -15,15 +15,17 @@ use log::info;
 use crate::membership::PeerInfo;
 use crate::network::libp2p::behaviours::membership::MembershipKind;
 use crate::{
-    broadcast::RbMsg,
+    block::sync::{BlockSyncRequest, BlockSyncResponse},
     crypto::Keypair,
-    network::libp2p::behaviours::request_response::{
-        RbMsgMessagesCodec, RbMsgProtocol, RbMsgResponse,
+    network::libp2p::behaviours::{
+        block_sync::{BlockSyncCodec, BlockSyncProtocol},
+        request_response::{RbMsgMessagesCodec, RbMsgProtocol, RbRequest, RbResponse},
     },
     peer::{PeerId, ToPeerId},
     utilities::hash::{EphemeraHasher, Hasher},
 };
 
+pub(crate) mod block_sync;
 pub(crate) mod membership;
 pub(crate) mod request_response;
 
@@ -36,13 +38,15 @@ where
     pub(crate) members_provider: membership::behaviour::Behaviour<P>,
     pub(crate) gossipsub: gossipsub::Behaviour,
     pub(crate) request_response: libp2p_request_response::Behaviour<RbMsgMessagesCodec>,
+    pub(crate) block_sync: libp2p_request_response::Behaviour<BlockSyncCodec>,
     pub(crate) kademlia: kad::Kademlia<kad::store::MemoryStore>,
 }
 
 #[allow(clippy::large_enum_variant)]
 pub(crate) enum GroupBehaviourEvent {
     Gossipsub(gossipsub::Event),
-    RequestResponse(libp2p_request_response::Event<RbMsg, RbMsgResponse>),
+    RequestResponse(libp2p_request_response::Event<RbRequest, RbResponse>),
+    BlockSync(libp2p_request_response::Event<BlockSyncRequest, BlockSyncResponse>),
     Membership(membership::behaviour::Event),
     Kademlia(kad::KademliaEvent),
 }
@@ -53,12 +57,20 @@ impl From<gossipsub::Event> for GroupBehaviourEvent {
     }
 }
 
-impl From<libp2p_request_response::Event<RbMsg, RbMsgResponse>> for GroupBehaviourEvent {
-    fn from(event: libp2p_request_response::Event<RbMsg, RbMsgResponse>) -> Self {
+impl From<libp2p_request_response::Event<RbRequest, RbResponse>> for GroupBehaviourEvent {
+    fn from(event: libp2p_request_response::Event<RbRequest, RbResponse>) -> Self {
         GroupBehaviourEvent::RequestResponse(event)
     }
 }
 
+impl From<libp2p_request_response::Event<BlockSyncRequest, BlockSyncResponse>>
+    for GroupBehaviourEvent
+{
+    fn from(event: libp2p_request_response::Event<BlockSyncRequest, BlockSyncResponse>) -> Self {
+        GroupBehaviourEvent::BlockSync(event)
+    }
+}
+
 impl From<membership::behaviour::Event> for GroupBehaviourEvent {
     fn from(event: membership::behaviour::Event) -> Self {
         GroupBehaviourEvent::Membership(event)
@@ -74,6 +86,7 @@ impl From<kad::KademliaEvent> for GroupBehaviourEvent {
 //Create combined behaviour.
 //Gossipsub takes care of message delivery semantics
 //Membership takes care of providing peers who are part of the reliable broadcast group
+//Block sync takes care of fetching committed blocks which the node missed
 //Kademlia takes provides closest neighbours and general DHT functionality
 pub(crate) fn create_behaviour<P>(
     keypair: &Arc<Keypair>,
@@ -89,6 +102,7 @@ where
     let local_peer_id = keypair.peer_id();
     let gossipsub = create_gossipsub(keypair, ephemera_msg_topic);
     let request_response = create_request_response();
+    let block_sync = create_block_sync();
     let rendezvous_behaviour = create_membership(
         members_provider,
         members_provider_delay,
@@ -101,6 +115,7 @@ where
         members_provider: rendezvous_behaviour,
         gossipsub,
         request_response,
+        block_sync,
         kademlia,
     }
 }
@@ -138,6 +153,18 @@ pub(crate) fn create_request_response() -> libp2p_request_response::Behaviour<Rb
     )
 }
 
+pub(crate) fn create_block_sync() -> libp2p_request_response::Behaviour<BlockSyncCodec> {
+    let config = libp2p_request_response::Config::default();
+    libp2p_request_response::Behaviour::new(
+        BlockSyncCodec,
+        iter::once((
+            BlockSyncProtocol,
+            libp2p_request_response::ProtocolSupport::Full,
+        )),
+        config,
+    )
+}
+
 pub(crate) fn create_membership<P>(
     members_provider: P,
     members_provider_delay: Duration,
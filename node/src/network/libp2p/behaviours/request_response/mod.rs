@@ -4,8 +4,10 @@ use libp2p::request_response;
 use log::trace;
 use serde::{Deserialize, Serialize};
 
+use crate::block::types::block::Block;
 use crate::broadcast::RbMsg;
 use crate::utilities::codec::varint_async::{read_length_prefixed, write_length_prefixed};
+use crate::utilities::hash::Hash;
 use crate::utilities::id::EphemeraId;
 
 #[derive(Clone)]
@@ -18,26 +20,31 @@ pub(crate) struct RbMsgProtocol;
 
 impl request_response::ProtocolName for RbMsgProtocol {
     fn protocol_name(&self) -> &[u8] {
-        "/ephemera/reliable_broadcast/1.0.0".as_bytes()
+        "/ephemera/reliable_broadcast/3.0.0".as_bytes()
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub(crate) struct RbMsgResponse {
-    pub(crate) id: EphemeraId,
+pub(crate) enum RbRequest {
+    /// Broadcast protocol message
+    Message(Box<RbMsg>),
+    /// Block of a broadcast which the peer knows only by hash
+    Block(Hash),
 }
 
-impl RbMsgResponse {
-    pub(crate) fn new(id: EphemeraId) -> Self {
-        Self { id }
-    }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) enum RbResponse {
+    /// Acknowledges a protocol message
+    Ack(EphemeraId),
+    /// Requested block, if the peer has it
+    Block(Option<Box<Block>>),
 }
 
 #[async_trait]
 impl request_response::Codec for RbMsgMessagesCodec {
     type Protocol = RbMsgProtocol;
-    type Request = RbMsg;
-    type Response = RbMsgResponse;
+    type Request = RbRequest;
+    type Response = RbResponse;
 
     async fn read_request<T>(
         &mut self,
@@ -1,15 +1,47 @@
 use log::trace;
 use tokio::sync::mpsc;
 
-use crate::block::types::message::EphemeraMessage;
+use crate::block::sync::{BlockSyncRequest, BlockSyncResponse};
+use crate::block::types::{block::Block, message::EphemeraMessage};
 use crate::broadcast::RbMsg;
+use crate::peer::PeerId;
+use crate::utilities::{hash::Hash, id::EphemeraId};
 
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) enum EphemeraEvent {
     EphemeraMessage(Box<EphemeraMessage>),
+    /// Messages submitted together, gossiped in as few network messages as possible.
+    EphemeraMessages(Vec<EphemeraMessage>),
     ProtocolMessage(Box<RbMsg>),
-    StoreInDht { key: Vec<u8>, value: Vec<u8> },
-    QueryDht { key: Vec<u8> },
+    /// Protocol message for one group member only.
+    ProtocolMessageTo {
+        msg: Box<RbMsg>,
+        peer: PeerId,
+    },
+    StoreInDht {
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    QueryDht {
+        key: Vec<u8>,
+    },
+    /// Ask blocks from one of the group peers.
+    RequestBlocks(BlockSyncRequest),
+    /// Answer to a peer's `NetworkEvent::BlockSyncRequest`.
+    BlockSyncResponse {
+        id: EphemeraId,
+        response: Box<BlockSyncResponse>,
+    },
+    /// Ask the block of a broadcast message from the peer which sent it.
+    RequestBroadcastBlock {
+        hash: Hash,
+        peer: PeerId,
+    },
+    /// Answer to a peer's `NetworkEvent::BroadcastBlockRequest`.
+    BroadcastBlockResponse {
+        id: EphemeraId,
+        block: Option<Box<Block>>,
+    },
 }
 
 pub(crate) struct EphemeraToNetwork;
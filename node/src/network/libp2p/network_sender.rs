@@ -2,13 +2,16 @@ use log::trace;
 use std::collections::HashSet;
 use tokio::sync::mpsc;
 
-use crate::block::types::message::EphemeraMessage;
-use crate::broadcast::RbMsg;
+use crate::block::sync::{BlockSyncRequest, BlockSyncResponse};
+use crate::block::types::{block::Block, message::EphemeraMessage};
+use crate::broadcast::{group::GroupSnapshot, RbMsg};
 use crate::peer::PeerId;
+use crate::utilities::{hash::Hash, id::EphemeraId};
 
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) enum GroupChangeEvent {
-    PeersUpdated(HashSet<PeerId>),
+    /// Active peers and their weights
+    PeersUpdated(GroupSnapshot),
     LocalPeerRemoved(HashSet<PeerId>),
     NotEnoughPeers(HashSet<PeerId>),
 }
@@ -17,8 +20,26 @@ pub(crate) enum GroupChangeEvent {
 pub(crate) enum NetworkEvent {
     EphemeraMessage(Box<EphemeraMessage>),
     BroadcastMessage(Box<RbMsg>),
+    /// A peer asks for the block of a broadcast message. Response is sent back with the same id.
+    BroadcastBlockRequest {
+        id: EphemeraId,
+        hash: Hash,
+    },
+    /// Block we asked for because we got broadcast messages before it.
+    BroadcastBlock(Box<Block>),
     GroupUpdate(GroupChangeEvent),
-    QueryDhtResponse { key: Vec<u8>, value: Vec<u8> },
+    QueryDhtResponse {
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    /// A peer asks for blocks. Response is sent back with the same id.
+    BlockSyncRequest {
+        id: EphemeraId,
+        request: BlockSyncRequest,
+    },
+    BlockSyncResponse(Box<BlockSyncResponse>),
+    /// Our block request didn't succeed.
+    BlockSyncFailed,
 }
 
 pub(crate) struct EphemeraNetworkCommunication;
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::HashMap;
 use std::future::Future;
 use std::str::FromStr;
 
@@ -6,13 +6,15 @@ use futures::StreamExt;
 use libp2p::kad::{GetClosestPeersResult, GetRecordResult};
 use libp2p::swarm::{NetworkBehaviour, SwarmBuilder};
 use libp2p::{
-    gossipsub, gossipsub::IdentTopic as Topic, kad, request_response, swarm::SwarmEvent, Multiaddr,
-    Swarm,
+    gossipsub, gossipsub::IdentTopic as Topic, kad, request_response,
+    request_response::ResponseChannel, swarm::SwarmEvent, Multiaddr, Swarm,
 };
 use log::{debug, error, info, trace};
 
 use crate::membership::PeerInfo;
+use crate::utilities::id::{EphemeraId, EphemeraIdentifier};
 use crate::{
+    block::sync::{BlockSyncRequest, BlockSyncResponse},
     block::types::message::EphemeraMessage,
     broadcast::RbMsg,
     codec::Encode,
@@ -20,7 +22,8 @@ use crate::{
     network::libp2p::behaviours,
     network::libp2p::{
         behaviours::{
-            create_behaviour, create_transport, request_response::RbMsgResponse,
+            create_behaviour, create_transport,
+            request_response::{RbRequest, RbResponse},
             GroupBehaviourEvent, GroupNetworkBehaviour,
         },
         ephemera_sender::{
@@ -34,6 +37,9 @@ use crate::{
     },
 };
 
+/// Gossipsub rejects messages bigger than 64KiB, batches leave some room for its own envelope.
+const MAX_GOSSIP_BATCH_BYTES: usize = 60 * 1024;
+
 pub(crate) type InitSwarm<P> = (
     SwarmNetwork<P>,
     NetCommunicationReceiver,
@@ -49,6 +55,12 @@ where
     from_ephemera_rcv: EphemeraToNetworkReceiver,
     to_ephemera_tx: NetCommunicationSender,
     ephemera_msg_topic: Topic,
+    /// Inbound block sync requests waiting for response from Ephemera.
+    block_sync_channels: HashMap<EphemeraId, ResponseChannel<BlockSyncResponse>>,
+    /// Inbound broadcast block requests waiting for response from Ephemera.
+    broadcast_block_channels: HashMap<EphemeraId, ResponseChannel<RbResponse>>,
+    /// Used to rotate peers we ask blocks from.
+    block_sync_requests: usize,
 }
 
 impl<P> SwarmNetwork<P>
@@ -88,6 +100,9 @@ where
             from_ephemera_rcv,
             to_ephemera_tx,
             ephemera_msg_topic,
+            block_sync_channels: HashMap::new(),
+            broadcast_block_channels: HashMap::new(),
+            block_sync_requests: 0,
         };
 
         Ok((network, to_ephemera_rcv, from_ephemera_tx))
@@ -118,20 +133,32 @@ where
                     }
                 },
                 Some(event) = self.from_ephemera_rcv.net_event_rcv.recv() => {
-                    self.process_ephemera_events(event);
+                    if let Err(err) = self.process_ephemera_events(event).await{
+                        error!("Error handling ephemera event: {:?}", err);
+                    }
                 }
             }
         }
     }
 
-    fn process_ephemera_events(&mut self, event: EphemeraEvent) {
+    async fn process_ephemera_events(&mut self, event: EphemeraEvent) -> anyhow::Result<()> {
         match event {
             EphemeraEvent::EphemeraMessage(em) => {
                 self.send_ephemera_message(em.as_ref());
             }
+            EphemeraEvent::EphemeraMessages(messages) => {
+                self.send_ephemera_messages(&messages);
+            }
             EphemeraEvent::ProtocolMessage(pm) => {
                 self.send_broadcast_message(pm.as_ref());
             }
+            EphemeraEvent::ProtocolMessageTo { msg, peer } => {
+                trace!("Sending broadcast message: {:?} to peer: {peer:?}", msg.id);
+                self.swarm
+                    .behaviour_mut()
+                    .request_response
+                    .send_request(&peer.into(), RbRequest::Message((*msg).into()));
+            }
             EphemeraEvent::StoreInDht { key, value } => {
                 let record = kad::Record::new(key, value);
                 let quorum = kad::Quorum::One;
@@ -154,7 +181,59 @@ where
                 let query_id = self.swarm.behaviour_mut().kademlia.get_record(kad_key);
                 trace!("QueryDht: {:?}", query_id);
             }
+            EphemeraEvent::RequestBlocks(request) => {
+                if !self.send_block_sync_request(request) {
+                    self.to_ephemera_tx
+                        .send_network_event(NetworkEvent::BlockSyncFailed)
+                        .await?;
+                }
+            }
+            EphemeraEvent::RequestBroadcastBlock { hash, peer } => {
+                trace!("Requesting broadcast block {hash} from peer: {peer:?}");
+                self.swarm
+                    .behaviour_mut()
+                    .request_response
+                    .send_request(&peer.into(), RbRequest::Block(hash));
+            }
+            EphemeraEvent::BroadcastBlockResponse { id, block } => {
+                match self.broadcast_block_channels.remove(&id) {
+                    Some(channel) => {
+                        let response = RbResponse::Block(block);
+                        if self
+                            .swarm
+                            .behaviour_mut()
+                            .request_response
+                            .send_response(channel, response)
+                            .is_err()
+                        {
+                            error!("Error sending broadcast block response, connection closed");
+                        }
+                    }
+                    None => {
+                        error!("No pending broadcast block request for id: {id:?}");
+                    }
+                }
+            }
+            EphemeraEvent::BlockSyncResponse { id, response } => {
+                match self.block_sync_channels.remove(&id) {
+                    Some(channel) => {
+                        if self
+                            .swarm
+                            .behaviour_mut()
+                            .block_sync
+                            .send_response(channel, *response)
+                            .is_err()
+                        {
+                            error!("Error sending block sync response, connection closed");
+                        }
+                    }
+                    None => {
+                        error!("No pending block sync request for id: {id:?}");
+                    }
+                }
+            }
         }
+        Ok(())
     }
 
     async fn handle_incoming_messages<E>(
@@ -186,6 +265,11 @@ where
                     error!("Error processing request response: {:?}", err);
                 }
             }
+            GroupBehaviourEvent::BlockSync(block_sync) => {
+                if let Err(err) = self.process_block_sync(block_sync).await {
+                    error!("Error processing block sync: {:?}", err);
+                }
+            }
 
             GroupBehaviourEvent::Membership(event) => {
                 if let Err(err) = self.process_members_provider_event(event).await {
@@ -208,10 +292,16 @@ where
                 message_id: _,
                 message,
             } => {
-                let msg: EphemeraMessage = serde_json::from_slice(&message.data[..])?;
-                self.to_ephemera_tx
-                    .send_network_event(NetworkEvent::EphemeraMessage(msg.into()))
-                    .await?;
+                //Messages are gossiped one by one or in batches
+                let messages = match serde_json::from_slice::<EphemeraMessage>(&message.data[..]) {
+                    Ok(msg) => vec![msg],
+                    Err(_) => serde_json::from_slice::<Vec<EphemeraMessage>>(&message.data[..])?,
+                };
+                for msg in messages {
+                    self.to_ephemera_tx
+                        .send_network_event(NetworkEvent::EphemeraMessage(msg.into()))
+                        .await?;
+                }
             }
 
             gossipsub::Event::Subscribed { peer_id, topic } => {
@@ -229,29 +319,55 @@ where
 
     async fn process_request_response(
         &mut self,
-        event: request_response::Event<RbMsg, RbMsgResponse>,
+        event: request_response::Event<RbRequest, RbResponse>,
     ) -> anyhow::Result<()> {
         match event {
             request_response::Event::Message { peer, message } => match message {
                 request_response::Message::Request {
                     request_id: _,
-                    request,
+                    request: RbRequest::Message(request),
                     channel,
                 } => {
                     let rb_id = request.id.clone();
                     trace!("Received request {:?}", request);
                     self.to_ephemera_tx
-                        .send_network_event(NetworkEvent::BroadcastMessage(request.into()))
+                        .send_network_event(NetworkEvent::BroadcastMessage(request))
                         .await?;
                     if let Err(err) = self
                         .swarm
                         .behaviour_mut()
                         .request_response
-                        .send_response(channel, RbMsgResponse::new(rb_id))
+                        .send_response(channel, RbResponse::Ack(rb_id))
                     {
                         error!("Error sending response: {:?}", err);
                     }
                 }
+                request_response::Message::Request {
+                    request_id: _,
+                    request: RbRequest::Block(hash),
+                    channel,
+                } => {
+                    trace!("Received broadcast block request {hash} from peer: {peer:?}");
+                    let id = EphemeraId::generate();
+                    self.broadcast_block_channels.insert(id.clone(), channel);
+                    self.to_ephemera_tx
+                        .send_network_event(NetworkEvent::BroadcastBlockRequest { id, hash })
+                        .await?;
+                }
+                request_response::Message::Response {
+                    request_id,
+                    response: RbResponse::Block(block),
+                } => match block {
+                    Some(block) => {
+                        trace!("Received broadcast block {} from peer: {peer:?}, request_id: {request_id:?}", block.get_hash());
+                        self.to_ephemera_tx
+                            .send_network_event(NetworkEvent::BroadcastBlock(block))
+                            .await?;
+                    }
+                    None => {
+                        debug!("Peer {peer:?} doesn't have the requested broadcast block, request_id: {request_id:?}");
+                    }
+                },
                 request_response::Message::Response {
                     request_id,
                     response,
@@ -280,6 +396,61 @@ where
         Ok(())
     }
 
+    async fn process_block_sync(
+        &mut self,
+        event: request_response::Event<BlockSyncRequest, BlockSyncResponse>,
+    ) -> anyhow::Result<()> {
+        match event {
+            request_response::Event::Message { peer, message } => match message {
+                request_response::Message::Request {
+                    request_id: _,
+                    request,
+                    channel,
+                } => {
+                    debug!("Received block sync request {request:?} from peer: {peer:?}");
+                    let id = EphemeraId::generate();
+                    self.block_sync_channels.insert(id.clone(), channel);
+                    self.to_ephemera_tx
+                        .send_network_event(NetworkEvent::BlockSyncRequest { id, request })
+                        .await?;
+                }
+                request_response::Message::Response {
+                    request_id,
+                    response,
+                } => {
+                    debug!(
+                        "Received {} blocks from peer: {peer:?}, request_id: {request_id:?}",
+                        response.blocks.len()
+                    );
+                    self.to_ephemera_tx
+                        .send_network_event(NetworkEvent::BlockSyncResponse(response.into()))
+                        .await?;
+                }
+            },
+            request_response::Event::OutboundFailure {
+                peer,
+                request_id,
+                error,
+            } => {
+                error!("Block sync outbound failure: {error:?}, peer:{peer:?}, request_id:{request_id:?}",);
+                self.to_ephemera_tx
+                    .send_network_event(NetworkEvent::BlockSyncFailed)
+                    .await?;
+            }
+            request_response::Event::InboundFailure {
+                peer,
+                request_id,
+                error,
+            } => {
+                error!("Block sync inbound failure: {error:?}, peer:{peer:?}, request_id:{request_id:?}",);
+            }
+            request_response::Event::ResponseSent { peer, request_id } => {
+                trace!("Block sync response sent to peer: {peer:?}, {request_id:?}",);
+            }
+        }
+        Ok(())
+    }
+
     async fn process_members_provider_event(
         &mut self,
         event: behaviours::membership::behaviour::Event,
@@ -446,13 +617,13 @@ where
                     .swarm
                     .behaviour_mut()
                     .members_provider
-                    .active_peer_ids_with_local();
+                    .active_peer_weights_with_local();
                 let active_peers = active_peers
                     .into_iter()
-                    .map(Into::into)
-                    .collect::<HashSet<_>>();
+                    .map(|(peer_id, weight)| (peer_id.into(), weight))
+                    .collect::<HashMap<_, _>>();
                 let group_update =
-                    NetworkEvent::GroupUpdate(GroupChangeEvent::PeersUpdated(active_peers));
+                    NetworkEvent::GroupUpdate(GroupChangeEvent::PeersUpdated(active_peers.into()));
                 self.to_ephemera_tx.send_network_event(group_update).await?;
             }
             Err(err) => {
@@ -471,10 +642,35 @@ where
             if *peer == local_peer_id {
                 continue;
             }
-            behaviours.request_response.send_request(peer, msg.clone());
+            behaviours
+                .request_response
+                .send_request(peer, RbRequest::Message(msg.clone().into()));
         }
     }
 
+    //Asks blocks from the next group peer. Returns false if there is no one to ask.
+    fn send_block_sync_request(&mut self, request: BlockSyncRequest) -> bool {
+        let local_peer_id = *self.swarm.local_peer_id();
+        let behaviours = self.swarm.behaviour_mut();
+        let peers = behaviours
+            .members_provider
+            .active_peer_ids()
+            .iter()
+            .filter(|peer| **peer != local_peer_id)
+            .copied()
+            .collect::<Vec<_>>();
+        if peers.is_empty() {
+            debug!("No peers to request blocks from");
+            return false;
+        }
+
+        self.block_sync_requests = self.block_sync_requests.wrapping_add(1);
+        let peer = peers[self.block_sync_requests % peers.len()];
+        debug!("Requesting blocks {request:?} from peer: {peer:?}");
+        behaviours.block_sync.send_request(&peer, request);
+        true
+    }
+
     fn send_ephemera_message(&mut self, msg: &EphemeraMessage) {
         trace!("Sending Ephemera message: {:?}", msg);
         match msg.encode() {
@@ -490,6 +686,46 @@ where
         }
     }
 
+    /// Gossips messages in batches which fit into a single gossipsub message.
+    fn send_ephemera_messages(&mut self, messages: &[EphemeraMessage]) {
+        trace!("Sending {} Ephemera messages", messages.len());
+        let mut batch: Vec<&EphemeraMessage> = vec![];
+        let mut batch_size = 0;
+        for msg in messages {
+            let size = match msg.encoded_size() {
+                Ok(size) => size,
+                Err(err) => {
+                    error!("Error serializing message: {}", err);
+                    continue;
+                }
+            };
+            if !batch.is_empty() && batch_size + size + 1 > MAX_GOSSIP_BATCH_BYTES {
+                self.publish_ephemera_messages(&batch);
+                batch.clear();
+                batch_size = 0;
+            }
+            batch.push(msg);
+            batch_size += size + 1;
+        }
+        if !batch.is_empty() {
+            self.publish_ephemera_messages(&batch);
+        }
+    }
+
+    fn publish_ephemera_messages(&mut self, batch: &[&EphemeraMessage]) {
+        match serde_json::to_vec(batch) {
+            Ok(vec) => {
+                let topic = self.ephemera_msg_topic.clone();
+                if let Err(err) = self.swarm.behaviour_mut().gossipsub.publish(topic, vec) {
+                    error!("Error publishing {} messages: {}", batch.len(), err);
+                }
+            }
+            Err(err) => {
+                error!("Error serializing messages: {}", err);
+            }
+        }
+    }
+
     //Just logging
     #[allow(clippy::too_many_lines)]
     fn process_other_swarm_events<E>(swarm_event: SwarmEvent<GroupBehaviourEvent, E>) {
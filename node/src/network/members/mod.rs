@@ -12,6 +12,7 @@ use log::{debug, error};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::broadcast::bracha::quorum::DEFAULT_WEIGHT;
 use crate::crypto::PublicKey;
 use crate::network::{Address, Peer};
 use crate::peer::PeerId;
@@ -29,6 +30,9 @@ pub struct PeerInfo {
     /// The public key of the peer. It uniquely identifies the peer.
     /// Public key is used to derive the peer id.
     pub pub_key: PublicKey,
+    /// The voting weight of the peer, for example its stake. Broadcast quorums are sums of member weights.
+    /// Peers without a weight have weight 1.
+    pub weight: Option<u64>,
 }
 
 impl Display for PeerInfo {
@@ -52,6 +56,7 @@ impl TryFrom<PeerInfo> for Peer {
             address,
             public_key: public_key.clone(),
             peer_id: PeerId::from_public_key(&public_key),
+            weight: value.weight.unwrap_or(DEFAULT_WEIGHT),
         })
     }
 }
@@ -116,6 +121,9 @@ pub struct PeerSetting {
     /// assert_eq!(public_key, public_key_parsed);
     /// ```
     pub public_key: String,
+    /// The voting weight of the peer. See [`PeerInfo`] for more details.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u64>,
 }
 
 impl TryFrom<PeerSetting> for PeerInfo {
@@ -127,6 +135,7 @@ impl TryFrom<PeerSetting> for PeerInfo {
             name: setting.name,
             address: setting.address,
             pub_key,
+            weight: setting.weight,
         })
     }
 }
@@ -144,6 +153,7 @@ impl TryFrom<PeerSetting> for PeerInfo {
 /// name = "node2"
 /// address = "/ip4/127.0.0.1/tcp/3001"
 /// pub_key = "4XTTMFQt2tgNRmwRgEAaGQe2NXygsK6Vr3pkuBfYezhDfoVty"
+/// weight = 10
 /// ```
 pub struct ConfigMembersProvider {
     config_location: PathBuf,
@@ -261,6 +271,9 @@ pub struct JsonPeerInfo {
     /// assert_eq!(public_key, public_key_parsed);
     /// ```
     pub public_key: String,
+    /// The voting weight of the peer. See [`PeerInfo`] for more details.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u64>,
 }
 
 impl JsonPeerInfo {
@@ -270,6 +283,7 @@ impl JsonPeerInfo {
             name,
             address,
             public_key: pub_key,
+            weight: None,
         }
     }
 }
@@ -283,6 +297,7 @@ impl TryFrom<JsonPeerInfo> for PeerInfo {
             name: json_peer_info.name,
             address: json_peer_info.address,
             pub_key,
+            weight: json_peer_info.weight,
         })
     }
 }
@@ -301,7 +316,8 @@ impl TryFrom<JsonPeerInfo> for PeerInfo {
 ///  {
 ///     "name": "node2",
 ///     "address": "/ip4/",
-///     "public_key": "4XTTMFQt2tgNRmwRgEAaGQe2NXygsK6Vr3pkuBfYezhDfoVty"
+///     "public_key": "4XTTMFQt2tgNRmwRgEAaGQe2NXygsK6Vr3pkuBfYezhDfoVty",
+///     "weight": 10
 ///   }
 /// ]
 /// ```
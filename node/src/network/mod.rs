@@ -22,7 +22,7 @@ pub enum PeerIdError {
 }
 
 /// Unique identifier of a peer.
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct PeerId(pub(crate) PeerIdType);
 
 impl PeerId {
@@ -109,6 +109,8 @@ pub(crate) struct Peer {
     pub address: Address,
     /// The peer's name. It can be arbitrary and is just for logging/display purposes.
     pub name: String,
+    /// The peer's voting weight in broadcast quorums.
+    pub weight: u64,
 }
 
 #[derive(Error, Debug)]
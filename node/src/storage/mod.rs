@@ -9,16 +9,25 @@
 //! ## `SqlLite`
 //!
 //! To use `SqlLite`, you need to compile with the `sqlite_storage` feature and with `--no-default-features` flag.
+//!
+//! ## Pruning
+//!
+//! Ephemeral data is discarded once it's not useful anymore. See [`pruning`] for the retention policy.
 
-use std::collections::HashSet;
+use std::collections::HashMap;
 
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::block::types::block::Block;
+use crate::block::types::block::{Block, BlockHeader};
+use crate::block::types::quorum_certificate::{BlockCertificate, QuorumCertificate};
+use crate::broadcast::{evidence::Evidence, group::GroupSnapshot};
 use crate::peer::PeerId;
-use crate::utilities::crypto::Certificate;
+use crate::utilities::hash::Hash;
 use crate::utilities::merkle::MerkleTree;
+use crate::utilities::time::EphemeraTime;
 
+pub(crate) mod pruning;
 #[cfg(feature = "rocksdb_storage")]
 pub(crate) mod rocksdb;
 
@@ -34,6 +43,20 @@ pub(crate) enum DatabaseError {
     DatabaseFailure(#[from] anyhow::Error),
 }
 
+/// Timestamp before which committed message hashes are not kept anymore.
+pub(crate) fn retention_cutoff(retention_sec: u64) -> u64 {
+    EphemeraTime::now().saturating_sub(retention_sec.saturating_mul(1000))
+}
+
+/// What is left of a block after its messages were pruned.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct PrunedBlock {
+    pub(crate) hash: String,
+    pub(crate) height: u64,
+    /// Block header, kept if pruning keeps commitments.
+    pub(crate) header: Option<BlockHeader>,
+}
+
 pub(crate) trait EphemeraDatabase: Send {
     /// Returns block by its id. Block ids are generated by Ephemera
     fn get_block_by_hash(&self, block_hash: &str) -> Result<Option<Block>>;
@@ -47,19 +70,74 @@ pub(crate) trait EphemeraDatabase: Send {
     /// Returns block certificates.
     ///
     /// Certificates were created as part of broadcast protocol and signed by peers who participated.
-    fn get_block_certificates(&self, block_hash: &str) -> Result<Option<Vec<Certificate>>>;
+    /// They sign the broadcast phase which delivered the block, certificates of older blocks sign the header.
+    fn get_block_certificates(&self, block_hash: &str) -> Result<Option<Vec<BlockCertificate>>>;
+
+    /// Returns compact quorum certificate of the block, see [`QuorumCertificate`].
+    fn get_block_quorum_certificate(&self, block_hash: &str) -> Result<Option<QuorumCertificate>>;
 
     /// Returns peers who participated in block broadcast.
     fn get_block_broadcast_group(&self, block_hash: &str) -> Result<Option<Vec<PeerId>>>;
 
-    /// Stores block and its signatures
+    /// Returns weights of the block broadcast group members.
+    ///
+    /// `None` for blocks stored before weights were recorded, their members have the default weight.
+    fn get_block_broadcast_weights(&self, block_hash: &str)
+        -> Result<Option<HashMap<PeerId, u64>>>;
+
+    /// Returns hash of the block which committed the message.
+    ///
+    /// Committed message hashes are kept for replay protection. Hashes of messages older than
+    /// the configured retention window are removed when new blocks are stored.
+    fn get_message_block_hash(&self, message_hash: &str) -> Result<Option<String>>;
+
+    /// Stores block, its signatures and its broadcast group with member weights. Also indexes its message hashes.
     fn store_block(
         &mut self,
         block: &Block,
-        certificates: HashSet<Certificate>,
-        members: HashSet<PeerId>,
+        certificates: &[BlockCertificate],
+        group: &GroupSnapshot,
+        quorum_certificate: &QuorumCertificate,
     ) -> Result<()>;
 
     /// Returns block merkle tree
     fn get_block_merkle_tree(&self, block_hash: &str) -> Result<Option<MerkleTree>>;
+
+    /// Returns what is left of a pruned block. Pruned blocks are not returned by other block queries.
+    fn get_pruned_block(&self, block_hash: &str) -> Result<Option<PrunedBlock>>;
+
+    /// Returns what is left of a pruned block at given height.
+    fn get_pruned_block_by_height(&self, height: u64) -> Result<Option<PrunedBlock>>;
+
+    /// Returns height of the last pruned block. Blocks are pruned in height order.
+    fn get_pruned_height(&self) -> Result<Option<u64>>;
+
+    /// Removes messages and Merkle tree of the block at given height.
+    ///
+    /// If `keep_commitments` is true, block header, certificates, quorum certificate and broadcast group are kept.
+    ///
+    /// # Returns
+    /// `false` if there is no block at given height.
+    fn prune_block(&mut self, height: u64, keep_commitments: bool) -> Result<bool>;
+
+    /// Returns approximate size of stored data in bytes.
+    fn get_database_size(&self) -> Result<u64>;
+
+    /// Stores evidence of a peer equivocating in block broadcast. Evidence is not pruned.
+    fn store_evidence(&mut self, evidence: &Evidence) -> Result<()>;
+
+    /// Returns the most recent equivocation evidence, newest first.
+    fn get_evidence(&self, limit: usize) -> Result<Vec<Evidence>>;
+
+    /// Stores the application state hash after the block at given height was delivered.
+    fn store_app_hash(&mut self, height: u64, app_hash: &Hash) -> Result<()>;
+
+    /// Returns the local application state hash after the block at given height.
+    fn get_app_hash(&self, height: u64) -> Result<Option<Hash>>;
+
+    /// Stores the height up to which commitments of all blocks were published to the external chain.
+    fn store_published_height(&mut self, height: u64) -> Result<()>;
+
+    /// Returns the height up to which commitments of all blocks were published, `None` if nothing was published.
+    fn get_published_height(&self) -> Result<Option<u64>>;
 }
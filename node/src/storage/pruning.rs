@@ -0,0 +1,265 @@
+//! # Block pruning
+//!
+//! Ephemera keeps committed blocks only as long as they are useful. When any of the configured limits is reached,
+//! messages of the oldest blocks are removed:
+//! - `retain_blocks` - number of most recent blocks which keep their messages
+//! - `retain_sec` - how long blocks keep their messages, counting from block timestamp
+//! - `max_size_bytes` - database size above which the oldest blocks are pruned
+//!
+//! Block headers, certificates and broadcast groups can be kept as commitments. Then it's still possible to prove
+//! that a block was committed, but not what it contained. Queries for pruned blocks get a distinct "pruned" response.
+//!
+//! The last block and blocks which the application hasn't committed yet are never pruned.
+//!
+//! Pruning runs as a background service at configured interval.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::{debug, error, info};
+use tokio::sync::Mutex;
+use tokio::time;
+
+use crate::config::PruningConfiguration;
+use crate::core::shutdown::Shutdown;
+use crate::storage::{retention_cutoff, EphemeraDatabase};
+
+/// Maximum number of blocks pruned in one run, so the database isn't locked for long.
+const MAX_BLOCKS_PER_RUN: u64 = 100;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct PruningStats {
+    /// Number of finished pruning runs
+    pub(crate) runs: u64,
+    /// Number of pruning runs which failed
+    pub(crate) failed_runs: u64,
+    /// Number of blocks pruned since start
+    pub(crate) pruned_blocks: u64,
+    /// Height of the last pruned block
+    pub(crate) pruned_height: Option<u64>,
+    /// Database size measured at the end of the last run
+    pub(crate) database_size_bytes: u64,
+    /// Duration of the last run
+    pub(crate) last_run_duration_ms: u64,
+}
+
+/// Lets the node tell the pruning service which blocks the application has received and read its stats.
+#[derive(Clone, Default)]
+pub(crate) struct PruningHandle {
+    /// Height of the last block delivered to the application
+    delivered_height: Arc<AtomicU64>,
+    stats: Arc<std::sync::Mutex<PruningStats>>,
+}
+
+impl PruningHandle {
+    /// Application has processed the block at given height, it can be pruned.
+    pub(crate) fn on_delivered(&self, height: u64) {
+        self.delivered_height.fetch_max(height, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> PruningStats {
+        self.stats
+            .lock()
+            .expect("Pruning stats lock poisoned")
+            .clone()
+    }
+}
+
+pub(crate) struct PruningService {
+    storage: Arc<Mutex<Box<dyn EphemeraDatabase>>>,
+    config: PruningConfiguration,
+    handle: PruningHandle,
+}
+
+impl PruningService {
+    pub(crate) fn new(
+        storage: Arc<Mutex<Box<dyn EphemeraDatabase>>>,
+        config: PruningConfiguration,
+    ) -> Self {
+        Self {
+            storage,
+            config,
+            handle: PruningHandle::default(),
+        }
+    }
+
+    pub(crate) fn handle(&self) -> PruningHandle {
+        self.handle.clone()
+    }
+
+    pub(crate) async fn run(mut self, mut shutdown: Shutdown) -> anyhow::Result<()> {
+        info!("Starting block pruning: {:?}", self.config);
+        let mut interval = time::interval(Duration::from_secs(self.config.interval_sec.max(1)));
+        loop {
+            tokio::select! {
+                _ = shutdown.shutdown_signal_rcv.recv() => {
+                    info!("Shutting down block pruning");
+                    break;
+                }
+                _ = interval.tick() => {
+                    self.run_once().await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn run_once(&mut self) {
+        let started = Instant::now();
+        let result = self.prune().await;
+        let size = self.storage.lock().await.get_database_size();
+
+        let mut stats = self
+            .handle
+            .stats
+            .lock()
+            .expect("Pruning stats lock poisoned");
+        stats.runs += 1;
+        stats.last_run_duration_ms =
+            u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX);
+        match result {
+            Ok((pruned, pruned_height)) => {
+                stats.pruned_blocks += pruned;
+                stats.pruned_height = pruned_height;
+                if pruned > 0 {
+                    info!("Pruned {pruned} blocks, pruned up to height {pruned_height:?}");
+                }
+            }
+            Err(err) => {
+                stats.failed_runs += 1;
+                error!("Block pruning failed: {err:?}");
+            }
+        }
+        match size {
+            Ok(size) => stats.database_size_bytes = size,
+            Err(err) => error!("Failed to get database size: {err:?}"),
+        }
+    }
+
+    /// Prunes the oldest blocks which are out of the retention window.
+    ///
+    /// # Returns
+    /// Number of pruned blocks and the height of the last pruned block.
+    pub(crate) async fn prune(&mut self) -> anyhow::Result<(u64, Option<u64>)> {
+        let (last_height, mut pruned_height) = {
+            let storage = self.storage.lock().await;
+            let last_height = storage.get_last_block()?.map(|block| block.get_height());
+            (last_height, storage.get_pruned_height()?)
+        };
+        let Some(last_height) = last_height else {
+            return Ok((0, pruned_height));
+        };
+
+        //Never prune the last block or blocks the application hasn't received yet
+        let delivered_height = self.handle.delivered_height.load(Ordering::Relaxed);
+        let Some(max_height) = last_height.checked_sub(1) else {
+            return Ok((0, pruned_height));
+        };
+        let max_height = max_height.min(delivered_height);
+
+        let count_limit = self
+            .config
+            .retain_blocks
+            .and_then(|retain| last_height.checked_sub(retain.max(1)));
+        let age_cutoff = self.config.retain_sec.map(retention_cutoff);
+
+        let mut pruned = 0;
+        let mut height = pruned_height.map_or(0, |height| height + 1);
+        while height <= max_height && pruned < MAX_BLOCKS_PER_RUN {
+            let mut storage = self.storage.lock().await;
+            let Some(block) = storage.get_block_by_height(height)? else {
+                debug!("No block at height {height} to prune");
+                height += 1;
+                continue;
+            };
+
+            let by_count = count_limit.is_some_and(|limit| height <= limit);
+            let by_age = age_cutoff.is_some_and(|cutoff| block.header.timestamp < cutoff);
+            let by_size = match self.config.max_size_bytes {
+                Some(max_size) => storage.get_database_size()? > max_size,
+                None => false,
+            };
+            if !(by_count || by_age || by_size) {
+                break;
+            }
+
+            if storage.prune_block(height, self.config.keep_commitments)? {
+                pruned += 1;
+                pruned_height = Some(height);
+            }
+            height += 1;
+        }
+        Ok((pruned, pruned_height))
+    }
+}
+
+#[cfg(all(test, feature = "sqlite_storage"))]
+mod test {
+    use crate::block::types::quorum_certificate::QuorumCertificate;
+    use crate::broadcast::group::GroupSnapshot;
+    use crate::crypto::{EphemeraKeypair, Keypair};
+    use crate::utilities::test_utils::{block, sqlite_storage, TempDatabase};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_prune_by_block_count() {
+        let (mut service, _database) = service(PruningConfiguration {
+            retain_blocks: Some(2),
+            ..Default::default()
+        });
+        store_blocks(&service, 5).await;
+        service.handle.on_delivered(5);
+
+        assert_eq!(service.prune().await.unwrap(), (4, Some(3)));
+        assert_eq!(service.prune().await.unwrap(), (0, Some(3)));
+
+        let storage = service.storage.lock().await;
+        assert!(storage.get_block_by_height(3).unwrap().is_none());
+        assert!(storage.get_pruned_block_by_height(3).unwrap().is_some());
+        assert!(storage.get_block_by_height(4).unwrap().is_some());
+        drop(storage);
+    }
+
+    #[tokio::test]
+    async fn test_prune_only_delivered_blocks() {
+        let (mut service, _database) = service(PruningConfiguration {
+            retain_blocks: Some(1),
+            ..Default::default()
+        });
+        store_blocks(&service, 5).await;
+
+        service.handle.on_delivered(1);
+        assert_eq!(service.prune().await.unwrap(), (2, Some(1)));
+
+        //The last block is never pruned
+        service.handle.on_delivered(5);
+        assert_eq!(service.prune().await.unwrap(), (3, Some(4)));
+    }
+
+    fn service(config: PruningConfiguration) -> (PruningService, TempDatabase) {
+        let (storage, database) = sqlite_storage(None);
+        let storage: Box<dyn EphemeraDatabase> = Box::new(storage);
+        (
+            PruningService::new(Arc::new(Mutex::new(storage)), config),
+            database,
+        )
+    }
+
+    /// Stores blocks with heights `[0, last_height]`.
+    async fn store_blocks(service: &PruningService, last_height: u64) {
+        let keypair = Keypair::generate(None);
+        let mut storage = service.storage.lock().await;
+        for height in 0..=last_height {
+            storage
+                .store_block(
+                    &block(&keypair, height),
+                    &[],
+                    &GroupSnapshot::default(),
+                    &QuorumCertificate::default(),
+                )
+                .unwrap();
+        }
+    }
+}
@@ -1,17 +1,19 @@
-use std::collections::HashSet;
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use log::info;
 use rocksdb::{TransactionDB, TransactionDBOptions};
 
 use crate::block::types::block::Block;
+use crate::block::types::quorum_certificate::{BlockCertificate, QuorumCertificate};
+use crate::broadcast::{evidence::Evidence, group::GroupSnapshot};
 use crate::config::DatabaseConfiguration;
 use crate::peer::PeerId;
 use crate::storage::rocksdb::query::Database;
 use crate::storage::rocksdb::store::DbStore;
-use crate::storage::EphemeraDatabase;
 use crate::storage::Result;
-use crate::utilities::crypto::Certificate;
+use crate::storage::{EphemeraDatabase, PrunedBlock};
+use crate::utilities::hash::Hash;
 use crate::utilities::merkle::MerkleTree;
 
 pub(crate) mod query;
@@ -27,7 +29,17 @@ const PREFIX_BLOCK_HASH: &str = "block_hash";
 const PREFIX_BLOCK_HEIGHT: &str = "block_height";
 const PREFIX_CERTIFICATES: &str = "block_certificates";
 const PREFIX_MEMBERS: &str = "block_members";
+const PREFIX_WEIGHTS: &str = "block_weights";
+const PREFIX_QUORUM_CERTIFICATE: &str = "block_quorum_certificate";
 const MERKLE_TREE: &str = "merkle_tree";
+const PREFIX_COMMITTED_MESSAGE: &str = "committed_message";
+const PREFIX_COMMITTED_MESSAGE_TIME: &str = "committed_message_time";
+const PREFIX_PRUNED_BLOCK: &str = "pruned_block";
+const PREFIX_PRUNED_BLOCK_HEIGHT: &str = "pruned_block_height";
+const PRUNED_HEIGHT_KEY: &str = "pruned_height";
+const PREFIX_EVIDENCE: &str = "equivocation_evidence";
+const PREFIX_APP_HASH: &str = "application_hash";
+const PUBLISHED_HEIGHT_KEY: &str = "published_commitments_height";
 
 impl RocksDbStorage {
     pub fn open(db_conf: &DatabaseConfiguration) -> Result<Self> {
@@ -44,8 +56,8 @@ impl RocksDbStorage {
         .map_err(|err| anyhow::anyhow!(err))?;
 
         let db = Arc::new(db);
-        let db_store = DbStore::new(db.clone());
-        let db_query = Database::new(db);
+        let db_store = DbStore::new(db.clone(), db_conf.committed_messages_retention_sec);
+        let db_query = Database::new(db, db_conf.rocksdb_path.clone());
         let storage = Self { db_store, db_query };
 
         info!("Opened RocksDB database at {}", db_conf.rocksdb_path);
@@ -70,26 +82,48 @@ impl EphemeraDatabase for RocksDbStorage {
             .map_err(Into::into)
     }
 
-    fn get_block_certificates(&self, block_id: &str) -> Result<Option<Vec<Certificate>>> {
+    fn get_block_certificates(&self, block_id: &str) -> Result<Option<Vec<BlockCertificate>>> {
         self.db_query
             .get_block_certificates(block_id)
             .map_err(Into::into)
     }
 
+    fn get_block_quorum_certificate(&self, block_hash: &str) -> Result<Option<QuorumCertificate>> {
+        self.db_query
+            .get_block_quorum_certificate(block_hash)
+            .map_err(Into::into)
+    }
+
     fn get_block_broadcast_group(&self, block_id: &str) -> Result<Option<Vec<PeerId>>> {
         self.db_query
             .get_block_broadcast_group(block_id)
             .map_err(Into::into)
     }
 
+    fn get_block_broadcast_weights(
+        &self,
+        block_hash: &str,
+    ) -> Result<Option<HashMap<PeerId, u64>>> {
+        self.db_query
+            .get_block_broadcast_weights(block_hash)
+            .map_err(Into::into)
+    }
+
+    fn get_message_block_hash(&self, message_hash: &str) -> Result<Option<String>> {
+        self.db_query
+            .get_message_block_hash(message_hash)
+            .map_err(Into::into)
+    }
+
     fn store_block(
         &mut self,
         block: &Block,
-        certificates: HashSet<Certificate>,
-        members: HashSet<PeerId>,
+        certificates: &[BlockCertificate],
+        group: &GroupSnapshot,
+        quorum_certificate: &QuorumCertificate,
     ) -> Result<()> {
         self.db_store
-            .store_block(block, certificates, members)
+            .store_block(block, certificates, group, quorum_certificate)
             .map_err(Into::into)
     }
 
@@ -98,6 +132,60 @@ impl EphemeraDatabase for RocksDbStorage {
             .get_block_merkle_tree(block_hash)
             .map_err(Into::into)
     }
+
+    fn get_pruned_block(&self, block_hash: &str) -> Result<Option<PrunedBlock>> {
+        self.db_query
+            .get_pruned_block(block_hash)
+            .map_err(Into::into)
+    }
+
+    fn get_pruned_block_by_height(&self, height: u64) -> Result<Option<PrunedBlock>> {
+        self.db_query
+            .get_pruned_block_by_height(height)
+            .map_err(Into::into)
+    }
+
+    fn get_pruned_height(&self) -> Result<Option<u64>> {
+        self.db_query.get_pruned_height().map_err(Into::into)
+    }
+
+    fn prune_block(&mut self, height: u64, keep_commitments: bool) -> Result<bool> {
+        self.db_store
+            .prune_block(height, keep_commitments)
+            .map_err(Into::into)
+    }
+
+    fn get_database_size(&self) -> Result<u64> {
+        self.db_query.get_database_size().map_err(Into::into)
+    }
+
+    fn store_evidence(&mut self, evidence: &Evidence) -> Result<()> {
+        self.db_store.store_evidence(evidence).map_err(Into::into)
+    }
+
+    fn get_evidence(&self, limit: usize) -> Result<Vec<Evidence>> {
+        self.db_query.get_evidence(limit).map_err(Into::into)
+    }
+
+    fn store_app_hash(&mut self, height: u64, app_hash: &Hash) -> Result<()> {
+        self.db_store
+            .store_app_hash(height, app_hash)
+            .map_err(Into::into)
+    }
+
+    fn get_app_hash(&self, height: u64) -> Result<Option<Hash>> {
+        self.db_query.get_app_hash(height).map_err(Into::into)
+    }
+
+    fn store_published_height(&mut self, height: u64) -> Result<()> {
+        self.db_store
+            .store_published_height(height)
+            .map_err(Into::into)
+    }
+
+    fn get_published_height(&self) -> Result<Option<u64>> {
+        self.db_query.get_published_height().map_err(Into::into)
+    }
 }
 
 fn block_hash_key(block_hash: &str) -> String {
@@ -120,6 +208,56 @@ fn members_key(block_hash: &str) -> String {
     format!("{PREFIX_MEMBERS}:{block_hash}",)
 }
 
+fn weights_key(block_hash: &str) -> String {
+    format!("{PREFIX_WEIGHTS}:{block_hash}")
+}
+
+fn quorum_certificate_key(block_hash: &str) -> String {
+    format!("{PREFIX_QUORUM_CERTIFICATE}:{block_hash}")
+}
+
 fn merkle_tree_key(block_hash: &str) -> String {
     format!("{MERKLE_TREE}:{block_hash}",)
 }
+
+fn committed_message_key(message_hash: &str) -> String {
+    format!("{PREFIX_COMMITTED_MESSAGE}:{message_hash}")
+}
+
+//Zero padded timestamp keeps keys ordered by time, so expired entries can be removed by iterating from start.
+fn committed_message_time_key(timestamp: u64, message_hash: &str) -> String {
+    format!("{PREFIX_COMMITTED_MESSAGE_TIME}:{timestamp:020}:{message_hash}")
+}
+
+fn committed_message_time_prefix() -> String {
+    format!("{PREFIX_COMMITTED_MESSAGE_TIME}:")
+}
+
+fn pruned_block_key(block_hash: &str) -> String {
+    format!("{PREFIX_PRUNED_BLOCK}:{block_hash}")
+}
+
+fn pruned_block_height_key(height: u64) -> String {
+    format!("{PREFIX_PRUNED_BLOCK_HEIGHT}:{height}")
+}
+
+fn pruned_height_key() -> String {
+    PRUNED_HEIGHT_KEY.to_string()
+}
+
+//Zero padded timestamp keeps evidence ordered by detection time.
+fn evidence_key(detected_at: u64, offender: &PeerId, height: u64) -> String {
+    format!("{PREFIX_EVIDENCE}:{detected_at:020}:{offender}:{height}")
+}
+
+fn evidence_prefix() -> String {
+    format!("{PREFIX_EVIDENCE}:")
+}
+
+fn app_hash_key(height: u64) -> String {
+    format!("{PREFIX_APP_HASH}:{height}")
+}
+
+fn published_height_key() -> String {
+    PUBLISHED_HEIGHT_KEY.to_string()
+}
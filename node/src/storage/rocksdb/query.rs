@@ -1,25 +1,36 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use log::trace;
-use rocksdb::TransactionDB;
+use rocksdb::{Direction, IteratorMode, TransactionDB};
 
 use crate::block::types::block::Block;
+use crate::block::types::quorum_certificate::{BlockCertificate, QuorumCertificate};
+use crate::broadcast::evidence::Evidence;
 use crate::network::PeerId;
 use crate::storage::rocksdb::{
-    block_hash_key, block_height_key, certificates_key, last_block_key, members_key,
-    merkle_tree_key,
+    app_hash_key, block_hash_key, block_height_key, certificates_key, committed_message_key,
+    evidence_prefix, last_block_key, members_key, merkle_tree_key, pruned_block_height_key,
+    pruned_block_key, pruned_height_key, published_height_key, quorum_certificate_key, weights_key,
 };
-use crate::utilities::crypto::Certificate;
+use crate::storage::PrunedBlock;
+use crate::utilities::hash::Hash;
 use crate::utilities::merkle::MerkleTree;
 
 pub struct Database {
     database: Arc<TransactionDB>,
+    /// Database directory, its size is the size of stored data
+    path: PathBuf,
 }
 
 impl Database {
     #[allow(dead_code)]
-    pub fn new(db: Arc<TransactionDB>) -> Database {
-        Database { database: db }
+    pub fn new(db: Arc<TransactionDB>, path: impl Into<PathBuf>) -> Database {
+        Database {
+            database: db,
+            path: path.into(),
+        }
     }
 
     pub(crate) fn get_block_by_hash(&self, block_hash: &str) -> anyhow::Result<Option<Block>> {
@@ -65,13 +76,13 @@ impl Database {
     pub(crate) fn get_block_certificates(
         &self,
         block_hash: &str,
-    ) -> anyhow::Result<Option<Vec<Certificate>>> {
+    ) -> anyhow::Result<Option<Vec<BlockCertificate>>> {
         trace!("Getting block signatures: {}", block_hash);
 
         let certificates_key = certificates_key(block_hash);
 
         if let Some(certificates) = self.database.get(certificates_key)? {
-            let certificates: Vec<Certificate> = serde_json::from_slice(&certificates)?;
+            let certificates: Vec<BlockCertificate> = serde_json::from_slice(&certificates)?;
             trace!("Found certificates: {:?}", certificates);
             Ok(Some(certificates))
         } else {
@@ -80,6 +91,20 @@ impl Database {
         }
     }
 
+    pub(crate) fn get_block_quorum_certificate(
+        &self,
+        block_hash: &str,
+    ) -> anyhow::Result<Option<QuorumCertificate>> {
+        trace!("Getting block quorum certificate: {}", block_hash);
+
+        if let Some(certificate) = self.database.get(quorum_certificate_key(block_hash))? {
+            let certificate: QuorumCertificate = serde_json::from_slice(&certificate)?;
+            Ok(Some(certificate))
+        } else {
+            Ok(None)
+        }
+    }
+
     pub(crate) fn get_block_broadcast_group(
         &self,
         block_hash: &str,
@@ -98,6 +123,21 @@ impl Database {
         }
     }
 
+    pub(crate) fn get_block_broadcast_weights(
+        &self,
+        block_hash: &str,
+    ) -> anyhow::Result<Option<HashMap<PeerId, u64>>> {
+        trace!("Getting block broadcast weights: {}", block_hash);
+
+        if let Some(weights) = self.database.get(weights_key(block_hash))? {
+            let weights: HashMap<PeerId, u64> = serde_json::from_slice(&weights)?;
+            Ok(Some(weights))
+        } else {
+            trace!("Didn't find weights");
+            Ok(None)
+        }
+    }
+
     pub(crate) fn get_block_merkle_tree(
         &self,
         block_hash: &str,
@@ -115,4 +155,104 @@ impl Database {
             Ok(None)
         }
     }
+
+    pub(crate) fn get_message_block_hash(
+        &self,
+        message_hash: &str,
+    ) -> anyhow::Result<Option<String>> {
+        trace!("Getting committed message: {}", message_hash);
+
+        if let Some(block_hash) = self.database.get(committed_message_key(message_hash))? {
+            let block_hash = String::from_utf8(block_hash)?;
+            trace!("Message committed in block: {}", block_hash);
+            Ok(Some(block_hash))
+        } else {
+            trace!("Didn't find committed message");
+            Ok(None)
+        }
+    }
+
+    pub(crate) fn get_pruned_block(&self, block_hash: &str) -> anyhow::Result<Option<PrunedBlock>> {
+        trace!("Getting pruned block: {}", block_hash);
+
+        if let Some(pruned) = self.database.get(pruned_block_key(block_hash))? {
+            let pruned: PrunedBlock = serde_json::from_slice(&pruned)?;
+            Ok(Some(pruned))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub(crate) fn get_pruned_block_by_height(
+        &self,
+        height: u64,
+    ) -> anyhow::Result<Option<PrunedBlock>> {
+        trace!("Getting pruned block by height: {}", height);
+
+        if let Some(block_hash) = self.database.get(pruned_block_height_key(height))? {
+            let block_hash = String::from_utf8(block_hash)?;
+            self.get_pruned_block(&block_hash)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub(crate) fn get_pruned_height(&self) -> anyhow::Result<Option<u64>> {
+        if let Some(height) = self.database.get(pruned_height_key())? {
+            let height = String::from_utf8(height)?.parse()?;
+            Ok(Some(height))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub(crate) fn get_evidence(&self, limit: usize) -> anyhow::Result<Vec<Evidence>> {
+        let prefix = evidence_prefix();
+        //Iterate backwards from the first key after the prefix range, newest evidence first
+        let mut end = prefix.clone().into_bytes();
+        *end.last_mut().expect("Prefix is not empty") += 1;
+        let iter = self
+            .database
+            .iterator(IteratorMode::From(&end, Direction::Reverse));
+
+        let mut evidence = vec![];
+        for item in iter {
+            let (key, value) = item?;
+            if !key.starts_with(prefix.as_bytes()) || evidence.len() >= limit {
+                break;
+            }
+            evidence.push(serde_json::from_slice::<Evidence>(&value)?);
+        }
+        Ok(evidence)
+    }
+
+    pub(crate) fn get_published_height(&self) -> anyhow::Result<Option<u64>> {
+        if let Some(height) = self.database.get(published_height_key())? {
+            let height = String::from_utf8(height)?.parse()?;
+            Ok(Some(height))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub(crate) fn get_app_hash(&self, height: u64) -> anyhow::Result<Option<Hash>> {
+        if let Some(app_hash) = self.database.get(app_hash_key(height))? {
+            let app_hash = String::from_utf8(app_hash)?.parse()?;
+            Ok(Some(app_hash))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Size of database files. Space of removed keys is reclaimed by compaction, so it shrinks with delay.
+    pub(crate) fn get_database_size(&self) -> anyhow::Result<u64> {
+        let mut size = 0;
+        for entry in std::fs::read_dir(&self.path)? {
+            let metadata = entry?.metadata()?;
+            if metadata.is_file() {
+                size += metadata.len();
+            }
+        }
+        Ok(size)
+    }
 }
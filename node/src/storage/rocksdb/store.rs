@@ -1,31 +1,40 @@
-use std::collections::HashSet;
 use std::sync::Arc;
 
 use crate::block::types::block::Block;
+use crate::block::types::quorum_certificate::{BlockCertificate, QuorumCertificate};
+use crate::broadcast::{evidence::Evidence, group::GroupSnapshot};
 use crate::network::PeerId;
 use crate::storage::rocksdb::{
-    block_hash_key, block_height_key, certificates_key, last_block_key, members_key,
-    merkle_tree_key,
+    app_hash_key, block_hash_key, block_height_key, certificates_key, committed_message_key,
+    committed_message_time_key, committed_message_time_prefix, evidence_key, last_block_key,
+    members_key, merkle_tree_key, pruned_block_height_key, pruned_block_key, pruned_height_key,
+    published_height_key, quorum_certificate_key, weights_key,
 };
+use crate::storage::{retention_cutoff, PrunedBlock};
+use crate::utilities::hash::Hash;
 use log::{debug, trace};
-use rocksdb::{TransactionDB, WriteBatchWithTransaction};
-
-use crate::utilities::crypto::Certificate;
+use rocksdb::{Direction, IteratorMode, TransactionDB, WriteBatchWithTransaction};
 
 pub struct DbStore {
     connection: Arc<TransactionDB>,
+    /// How long committed message hashes are kept
+    committed_messages_retention_sec: Option<u64>,
 }
 
 impl DbStore {
-    pub fn new(db: Arc<TransactionDB>) -> DbStore {
-        DbStore { connection: db }
+    pub fn new(db: Arc<TransactionDB>, committed_messages_retention_sec: Option<u64>) -> DbStore {
+        DbStore {
+            connection: db,
+            committed_messages_retention_sec,
+        }
     }
 
     pub(crate) fn store_block(
         &self,
         block: &Block,
-        certificates: HashSet<Certificate>,
-        members: HashSet<PeerId>,
+        certificates: &[BlockCertificate],
+        group: &GroupSnapshot,
+        quorum_certificate: &QuorumCertificate,
     ) -> anyhow::Result<()> {
         debug!("Storing block: {}", block.header);
         trace!("Storing block certificates: {}", certificates.len());
@@ -51,7 +60,7 @@ impl DbStore {
         batch.put(last_block_key(), hash_str.clone());
 
         // Store block height
-        batch.put(height_key.as_bytes(), hash_str);
+        batch.put(height_key.as_bytes(), hash_str.clone());
 
         // Store block(without signature)
         let block_bytes = serde_json::to_vec::<Block>(block)?;
@@ -59,21 +68,146 @@ impl DbStore {
 
         // Store block certificates
         let certificates_bytes =
-            serde_json::to_vec(&certificates.into_iter().collect::<Vec<Certificate>>())
-                .map_err(|e| anyhow::anyhow!(e))?;
+            serde_json::to_vec(certificates).map_err(|e| anyhow::anyhow!(e))?;
         batch.put(certificates_key.as_bytes(), certificates_bytes);
 
-        // Store block members
-        let members_bytes = serde_json::to_vec(&members.into_iter().collect::<Vec<PeerId>>())
+        // Store block members and their weights
+        let members_bytes = serde_json::to_vec(&group.members.iter().collect::<Vec<&PeerId>>())
             .map_err(|e| anyhow::anyhow!(e))?;
         batch.put(members_key.as_bytes(), members_bytes);
+        let weights_bytes = serde_json::to_vec(&group.weights).map_err(|e| anyhow::anyhow!(e))?;
+        batch.put(weights_key(&hash_str).as_bytes(), weights_bytes);
+
+        // Store block quorum certificate
+        let quorum_certificate_bytes =
+            serde_json::to_vec(quorum_certificate).map_err(|e| anyhow::anyhow!(e))?;
+        batch.put(
+            quorum_certificate_key(&hash_str).as_bytes(),
+            quorum_certificate_bytes,
+        );
 
         //Store Merkle Tree
         let merkle_tree = block.merkle_tree()?;
         let merkle_tree_bytes = serde_json::to_vec(&merkle_tree).map_err(|e| anyhow::anyhow!(e))?;
         batch.put(merkle_tree_key.as_bytes(), merkle_tree_bytes);
 
+        //Index committed messages. If a message was already committed, keep the first block.
+        for message in &block.messages {
+            let message_hash = message.hash_with_default_hasher()?.to_string();
+            let message_key = committed_message_key(&message_hash);
+            if self.connection.get(&message_key)?.is_some() {
+                continue;
+            }
+            batch.put(message_key.as_bytes(), hash_str.as_bytes());
+            let time_key = committed_message_time_key(message.timestamp, &message_hash);
+            batch.put(time_key.as_bytes(), message_hash);
+        }
+
+        if let Some(retention_sec) = self.committed_messages_retention_sec {
+            self.remove_expired_committed_messages(&mut batch, retention_cutoff(retention_sec))?;
+        }
+
+        self.connection.write(batch)?;
+        Ok(())
+    }
+
+    pub(crate) fn prune_block(&self, height: u64, keep_commitments: bool) -> anyhow::Result<bool> {
+        let Some(hash) = self.connection.get(block_height_key(&height))? else {
+            return Ok(false);
+        };
+        let hash_str = String::from_utf8(hash)?;
+        let Some(block_bytes) = self.connection.get(block_hash_key(&hash_str))? else {
+            return Ok(false);
+        };
+
+        let header = if keep_commitments {
+            Some(serde_json::from_slice::<Block>(&block_bytes)?.header)
+        } else {
+            None
+        };
+        let pruned = PrunedBlock {
+            hash: hash_str.clone(),
+            height,
+            header,
+        };
+
+        let mut batch = WriteBatchWithTransaction::<true>::default();
+        batch.put(
+            pruned_block_key(&hash_str).as_bytes(),
+            serde_json::to_vec(&pruned)?,
+        );
+        batch.put(
+            pruned_block_height_key(height).as_bytes(),
+            hash_str.as_bytes(),
+        );
+        batch.put(pruned_height_key(), height.to_string());
+
+        batch.delete(block_hash_key(&hash_str).as_bytes());
+        batch.delete(block_height_key(&height).as_bytes());
+        batch.delete(merkle_tree_key(&hash_str).as_bytes());
+        if !keep_commitments {
+            batch.delete(certificates_key(&hash_str).as_bytes());
+            batch.delete(members_key(&hash_str).as_bytes());
+            batch.delete(weights_key(&hash_str).as_bytes());
+            batch.delete(quorum_certificate_key(&hash_str).as_bytes());
+        }
+
         self.connection.write(batch)?;
+        debug!("Pruned block {hash_str} at height {height}");
+        Ok(true)
+    }
+
+    pub(crate) fn store_evidence(&self, evidence: &Evidence) -> anyhow::Result<()> {
+        let key = evidence_key(
+            evidence.evidence.detected_at,
+            &evidence.evidence.offender,
+            evidence.evidence.height,
+        );
+        self.connection.put(key, serde_json::to_vec(evidence)?)?;
+        debug!("Stored evidence against {}", evidence.evidence.offender);
+        Ok(())
+    }
+
+    pub(crate) fn store_app_hash(&self, height: u64, app_hash: &Hash) -> anyhow::Result<()> {
+        self.connection
+            .put(app_hash_key(height), app_hash.to_string())?;
+        debug!("Stored application hash {app_hash} at height {height}");
+        Ok(())
+    }
+
+    pub(crate) fn store_published_height(&self, height: u64) -> anyhow::Result<()> {
+        self.connection
+            .put(published_height_key(), height.to_string())?;
+        debug!("Stored published commitments height {height}");
+        Ok(())
+    }
+
+    fn remove_expired_committed_messages(
+        &self,
+        batch: &mut WriteBatchWithTransaction<true>,
+        cutoff: u64,
+    ) -> anyhow::Result<()> {
+        let prefix = committed_message_time_prefix();
+        let cutoff_key = committed_message_time_key(cutoff, "");
+        let iter = self
+            .connection
+            .iterator(IteratorMode::From(prefix.as_bytes(), Direction::Forward));
+
+        let mut removed = 0;
+        for item in iter {
+            let (key, message_hash) = item?;
+            if !key.starts_with(prefix.as_bytes()) || *key >= *cutoff_key.as_bytes() {
+                break;
+            }
+            let message_hash = String::from_utf8(message_hash.to_vec())?;
+            batch.delete(committed_message_key(&message_hash).as_bytes());
+            batch.delete(key);
+            removed += 1;
+        }
+
+        if removed > 0 {
+            debug!("Removed {removed} committed message hashes older than {cutoff}");
+        }
         Ok(())
     }
 }
@@ -1,15 +1,17 @@
 use log::{error, info};
 use rusqlite::Connection;
-use std::collections::HashSet;
+use std::collections::HashMap;
 
 use crate::block::types::block::Block;
+use crate::block::types::quorum_certificate::{BlockCertificate, QuorumCertificate};
+use crate::broadcast::{evidence::Evidence, group::GroupSnapshot};
 use crate::config::DatabaseConfiguration;
 use crate::peer::PeerId;
 use crate::storage::sqlite::query::DbQuery;
 use crate::storage::sqlite::store::Database;
-use crate::storage::EphemeraDatabase;
 use crate::storage::Result;
-use crate::utilities::crypto::Certificate;
+use crate::storage::{EphemeraDatabase, PrunedBlock};
+use crate::utilities::hash::Hash;
 use crate::utilities::merkle::MerkleTree;
 
 pub(crate) mod query;
@@ -76,26 +78,48 @@ impl EphemeraDatabase for SqliteStorage {
             .map_err(Into::into)
     }
 
-    fn get_block_certificates(&self, block_id: &str) -> Result<Option<Vec<Certificate>>> {
+    fn get_block_certificates(&self, block_id: &str) -> Result<Option<Vec<BlockCertificate>>> {
         self.db_query
             .get_block_certificates(block_id)
             .map_err(Into::into)
     }
 
+    fn get_block_quorum_certificate(&self, block_hash: &str) -> Result<Option<QuorumCertificate>> {
+        self.db_query
+            .get_block_quorum_certificate(block_hash)
+            .map_err(Into::into)
+    }
+
     fn get_block_broadcast_group(&self, block_id: &str) -> Result<Option<Vec<PeerId>>> {
         self.db_query
             .get_block_broadcast_group(block_id)
             .map_err(Into::into)
     }
 
+    fn get_block_broadcast_weights(
+        &self,
+        block_hash: &str,
+    ) -> Result<Option<HashMap<PeerId, u64>>> {
+        self.db_query
+            .get_block_broadcast_weights(block_hash)
+            .map_err(Into::into)
+    }
+
+    fn get_message_block_hash(&self, message_hash: &str) -> Result<Option<String>> {
+        self.db_query
+            .get_message_block_hash(message_hash)
+            .map_err(Into::into)
+    }
+
     fn store_block(
         &mut self,
         block: &Block,
-        certificates: HashSet<Certificate>,
-        members: HashSet<PeerId>,
+        certificates: &[BlockCertificate],
+        group: &GroupSnapshot,
+        quorum_certificate: &QuorumCertificate,
     ) -> Result<()> {
         self.db_store
-            .store_block(block, certificates, members)
+            .store_block(block, certificates, group, quorum_certificate)
             .map_err(Into::into)
     }
 
@@ -104,4 +128,249 @@ impl EphemeraDatabase for SqliteStorage {
             .get_block_merkle_tree(block_hash)
             .map_err(Into::into)
     }
+
+    fn get_pruned_block(&self, block_hash: &str) -> Result<Option<PrunedBlock>> {
+        self.db_query
+            .get_pruned_block(block_hash)
+            .map_err(Into::into)
+    }
+
+    fn get_pruned_block_by_height(&self, height: u64) -> Result<Option<PrunedBlock>> {
+        self.db_query
+            .get_pruned_block_by_height(height)
+            .map_err(Into::into)
+    }
+
+    fn get_pruned_height(&self) -> Result<Option<u64>> {
+        self.db_query.get_pruned_height().map_err(Into::into)
+    }
+
+    fn prune_block(&mut self, height: u64, keep_commitments: bool) -> Result<bool> {
+        self.db_store
+            .prune_block(height, keep_commitments)
+            .map_err(Into::into)
+    }
+
+    fn get_database_size(&self) -> Result<u64> {
+        self.db_query.get_database_size().map_err(Into::into)
+    }
+
+    fn store_evidence(&mut self, evidence: &Evidence) -> Result<()> {
+        self.db_store.store_evidence(evidence).map_err(Into::into)
+    }
+
+    fn get_evidence(&self, limit: usize) -> Result<Vec<Evidence>> {
+        self.db_query.get_evidence(limit).map_err(Into::into)
+    }
+
+    fn store_app_hash(&mut self, height: u64, app_hash: &Hash) -> Result<()> {
+        self.db_store
+            .store_app_hash(height, app_hash)
+            .map_err(Into::into)
+    }
+
+    fn get_app_hash(&self, height: u64) -> Result<Option<Hash>> {
+        self.db_query.get_app_hash(height).map_err(Into::into)
+    }
+
+    fn store_published_height(&mut self, height: u64) -> Result<()> {
+        self.db_store
+            .store_published_height(height)
+            .map_err(Into::into)
+    }
+
+    fn get_published_height(&self) -> Result<Option<u64>> {
+        self.db_query.get_published_height().map_err(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::block::types::message::EphemeraMessage;
+    use crate::broadcast::evidence::EquivocationDetector;
+    use crate::crypto::{EphemeraKeypair, Keypair};
+    use crate::ephemera_api::RawApiEphemeraMessage;
+    use crate::peer::ToPeerId;
+    use crate::utilities::hash::Hash;
+    use crate::utilities::test_utils::{block_with, sqlite_storage};
+    use crate::utilities::time::EphemeraTime;
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_committed_messages_index() {
+        let (mut storage, _database) = sqlite_storage(None);
+
+        let message = message(EphemeraTime::now());
+        let block = block_with(&Keypair::generate(None), 1, vec![message.clone()]);
+        storage
+            .store_block(
+                &block,
+                &[],
+                &GroupSnapshot::default(),
+                &QuorumCertificate::default(),
+            )
+            .unwrap();
+
+        let message_hash = message.hash_with_default_hasher().unwrap().to_string();
+        assert_eq!(
+            storage.get_message_block_hash(&message_hash).unwrap(),
+            Some(block.get_hash().to_string())
+        );
+        let unknown = self::message(EphemeraTime::now());
+        let unknown_hash = unknown.hash_with_default_hasher().unwrap().to_string();
+        assert_eq!(storage.get_message_block_hash(&unknown_hash).unwrap(), None);
+    }
+
+    #[test]
+    fn test_committed_messages_retention() {
+        let (mut storage, _database) = sqlite_storage(Some(60));
+
+        let old = message(EphemeraTime::now() - 120 * 1000);
+        let recent = message(EphemeraTime::now());
+        let block = block_with(
+            &Keypair::generate(None),
+            1,
+            vec![old.clone(), recent.clone()],
+        );
+        storage
+            .store_block(
+                &block,
+                &[],
+                &GroupSnapshot::default(),
+                &QuorumCertificate::default(),
+            )
+            .unwrap();
+
+        let old_hash = old.hash_with_default_hasher().unwrap().to_string();
+        let recent_hash = recent.hash_with_default_hasher().unwrap().to_string();
+        assert_eq!(storage.get_message_block_hash(&old_hash).unwrap(), None);
+        assert!(storage
+            .get_message_block_hash(&recent_hash)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_prune_block() {
+        let (mut storage, _database) = sqlite_storage(None);
+
+        let keypair = Keypair::generate(None);
+        let kept = block_with(&keypair, 1, vec![message(EphemeraTime::now())]);
+        let dropped = block_with(&keypair, 2, vec![message(EphemeraTime::now())]);
+        for block in [&kept, &dropped] {
+            let certificates = [BlockCertificate {
+                phase: None,
+                certificate: block.sign(&keypair).unwrap(),
+            }];
+            let group = GroupSnapshot::from(HashMap::from([(keypair.peer_id(), 10)]));
+            storage
+                .store_block(block, &certificates, &group, &QuorumCertificate::default())
+                .unwrap();
+        }
+
+        assert!(storage.prune_block(1, true).unwrap());
+        assert!(storage.prune_block(2, false).unwrap());
+        assert!(!storage.prune_block(3, true).unwrap());
+        assert_eq!(storage.get_pruned_height().unwrap(), Some(2));
+
+        let kept_hash = kept.get_hash().to_string();
+        assert!(storage.get_block_by_hash(&kept_hash).unwrap().is_none());
+        assert!(storage.get_block_by_height(1).unwrap().is_none());
+        assert!(storage.get_block_merkle_tree(&kept_hash).unwrap().is_none());
+        assert!(storage
+            .get_block_certificates(&kept_hash)
+            .unwrap()
+            .is_some());
+        assert_eq!(
+            storage.get_block_broadcast_weights(&kept_hash).unwrap(),
+            Some(HashMap::from([(keypair.peer_id(), 10)]))
+        );
+        let pruned = storage.get_pruned_block(&kept_hash).unwrap().unwrap();
+        assert_eq!(pruned.height, 1);
+        assert_eq!(pruned.header, Some(kept.header.clone()));
+
+        let dropped_hash = dropped.get_hash().to_string();
+        assert!(storage
+            .get_block_certificates(&dropped_hash)
+            .unwrap()
+            .is_none());
+        assert!(storage
+            .get_block_broadcast_group(&dropped_hash)
+            .unwrap()
+            .is_none());
+        assert!(storage
+            .get_block_broadcast_weights(&dropped_hash)
+            .unwrap()
+            .is_none());
+        assert!(storage
+            .get_block_quorum_certificate(&dropped_hash)
+            .unwrap()
+            .is_none());
+        let pruned = storage.get_pruned_block_by_height(2).unwrap().unwrap();
+        assert_eq!(pruned.hash, dropped_hash);
+        assert_eq!(pruned.header, None);
+    }
+
+    #[test]
+    fn test_evidence() {
+        let (mut storage, _database) = sqlite_storage(None);
+        assert!(storage.get_evidence(10).unwrap().is_empty());
+
+        let creator = Keypair::generate(None);
+        let mut detector = EquivocationDetector::new(Arc::new(Keypair::generate(None)));
+        let mut stored = vec![];
+        for height in 1..=3 {
+            for block in [block_by(&creator, height), block_by(&creator, height)] {
+                let certificate = block.sign(&creator).unwrap();
+                if let Some(evidence) = detector
+                    .on_signed_header(&block.header, &certificate)
+                    .unwrap()
+                {
+                    storage.store_evidence(&evidence).unwrap();
+                    stored.push(evidence);
+                }
+            }
+        }
+        assert_eq!(stored.len(), 3);
+
+        let evidence = storage.get_evidence(2).unwrap();
+        assert_eq!(evidence, vec![stored[2].clone(), stored[1].clone()]);
+    }
+
+    #[test]
+    fn test_published_height() {
+        let (mut storage, _database) = sqlite_storage(None);
+        assert!(storage.get_published_height().unwrap().is_none());
+
+        storage.store_published_height(1).unwrap();
+        storage.store_published_height(3).unwrap();
+        assert_eq!(storage.get_published_height().unwrap(), Some(3));
+    }
+
+    #[test]
+    fn test_app_hash() {
+        let (mut storage, _database) = sqlite_storage(None);
+        assert!(storage.get_app_hash(1).unwrap().is_none());
+
+        storage.store_app_hash(1, &Hash::new([1; 32])).unwrap();
+        //Replayed block commits again
+        storage.store_app_hash(1, &Hash::new([2; 32])).unwrap();
+        assert_eq!(storage.get_app_hash(1).unwrap(), Some(Hash::new([2; 32])));
+        assert!(storage.get_app_hash(2).unwrap().is_none());
+    }
+
+    fn block_by(creator: &Keypair, height: u64) -> Block {
+        //A new message makes blocks at the same height differ
+        block_with(creator, height, vec![message(EphemeraTime::now())])
+    }
+
+    fn message(timestamp: u64) -> EphemeraMessage {
+        let raw = RawApiEphemeraMessage::new("test".to_string(), vec![1, 2, 3]);
+        let signed = raw.sign(&Keypair::generate(None)).unwrap();
+        let mut message: EphemeraMessage = signed.into();
+        message.timestamp = timestamp;
+        message
+    }
 }
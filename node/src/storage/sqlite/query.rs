@@ -1,10 +1,15 @@
+use std::collections::HashMap;
+
 use log::{error, trace};
 use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Row};
 
 use crate::block::types::block::Block;
+use crate::block::types::quorum_certificate::{BlockCertificate, QuorumCertificate};
+use crate::broadcast::evidence::Evidence;
 use crate::config::DatabaseConfiguration;
 use crate::peer::PeerId;
-use crate::utilities::crypto::Certificate;
+use crate::storage::PrunedBlock;
+use crate::utilities::hash::Hash;
 use crate::utilities::merkle::MerkleTree;
 
 pub(crate) struct DbQuery {
@@ -68,10 +73,32 @@ impl DbQuery {
         Ok(block)
     }
 
+    pub(crate) fn get_block_quorum_certificate(
+        &self,
+        block_hash: &str,
+    ) -> anyhow::Result<Option<QuorumCertificate>> {
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT quorum_certificate FROM block_quorum_certificates where block_hash = ?1",
+        )?;
+
+        let certificate = stmt
+            .query_row(params![block_hash], |row| {
+                let certificate: Vec<u8> = row.get(0)?;
+                let certificate = serde_json::from_slice::<QuorumCertificate>(&certificate)
+                    .map_err(|e| {
+                        error!("Error deserializing quorum certificate: {}", e);
+                        rusqlite::Error::InvalidQuery {}
+                    })?;
+                Ok(certificate)
+            })
+            .optional()?;
+        Ok(certificate)
+    }
+
     pub(crate) fn get_block_certificates(
         &self,
         block_hash: &str,
-    ) -> anyhow::Result<Option<Vec<Certificate>>> {
+    ) -> anyhow::Result<Option<Vec<BlockCertificate>>> {
         let mut stmt = self
             .connection
             .prepare_cached("SELECT certificates FROM block_certificates where block_hash = ?1")?;
@@ -79,11 +106,11 @@ impl DbQuery {
         let signatures = stmt
             .query_row(params![block_hash], |row| {
                 let certificates: Vec<u8> = row.get(0)?;
-                let certificates = serde_json::from_slice::<Vec<Certificate>>(&certificates)
+                let certificates = serde_json::from_slice::<Vec<BlockCertificate>>(&certificates)
                     .map_err(|e| {
-                        error!("Error deserializing certificates: {}", e);
-                        rusqlite::Error::InvalidQuery {}
-                    })?;
+                    error!("Error deserializing certificates: {}", e);
+                    rusqlite::Error::InvalidQuery {}
+                })?;
                 Ok(certificates)
             })
             .optional()?;
@@ -125,6 +152,39 @@ impl DbQuery {
         Ok(members)
     }
 
+    pub(crate) fn get_block_broadcast_weights(
+        &self,
+        block_hash: &str,
+    ) -> anyhow::Result<Option<HashMap<PeerId, u64>>> {
+        let mut stmt = self
+            .connection
+            .prepare_cached("SELECT weights FROM block_broadcast_group where block_hash = ?1")?;
+
+        let weights = stmt
+            .query_row(params![block_hash], |row| {
+                //Blocks stored before weights were recorded don't have them
+                let Some(weights) = row.get::<_, Option<Vec<u8>>>(0)? else {
+                    return Ok(None);
+                };
+                let weights =
+                    serde_json::from_slice::<HashMap<PeerId, u64>>(&weights).map_err(|e| {
+                        error!("Error deserializing weights: {}", e);
+                        rusqlite::Error::InvalidQuery {}
+                    })?;
+                Ok(Some(weights))
+            })
+            .optional()?
+            .flatten();
+
+        if weights.is_some() {
+            trace!("Found block {} weights", block_hash);
+        } else {
+            trace!("Weights not found");
+        }
+
+        Ok(weights)
+    }
+
     pub(crate) fn get_block_merkle_tree(
         &self,
         block_hash: &str,
@@ -154,6 +214,124 @@ impl DbQuery {
         Ok(merkle_tree)
     }
 
+    pub(crate) fn get_message_block_hash(
+        &self,
+        message_hash: &str,
+    ) -> anyhow::Result<Option<String>> {
+        let mut stmt = self
+            .connection
+            .prepare_cached("SELECT block_hash FROM committed_messages WHERE message_hash = ?1")?;
+
+        let block_hash = stmt
+            .query_row(params![message_hash], |row| row.get(0))
+            .optional()?;
+
+        if let Some(block_hash) = &block_hash {
+            trace!("Message {} committed in block {}", message_hash, block_hash);
+        } else {
+            trace!("Committed message not found: {}", message_hash);
+        }
+
+        Ok(block_hash)
+    }
+
+    pub(crate) fn get_pruned_block(&self, block_hash: &str) -> anyhow::Result<Option<PrunedBlock>> {
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT block_hash, height, header FROM pruned_blocks WHERE block_hash = ?1",
+        )?;
+        let pruned = stmt
+            .query_row(params![block_hash], Self::map_pruned_block())
+            .optional()?;
+        Ok(pruned)
+    }
+
+    pub(crate) fn get_pruned_block_by_height(
+        &self,
+        height: u64,
+    ) -> anyhow::Result<Option<PrunedBlock>> {
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT block_hash, height, header FROM pruned_blocks WHERE height = ?1",
+        )?;
+        let pruned = stmt
+            .query_row(params![height], Self::map_pruned_block())
+            .optional()?;
+        Ok(pruned)
+    }
+
+    pub(crate) fn get_pruned_height(&self) -> anyhow::Result<Option<u64>> {
+        let mut stmt = self
+            .connection
+            .prepare_cached("SELECT MAX(height) FROM pruned_blocks")?;
+        let height = stmt.query_row(params![], |row| row.get(0))?;
+        Ok(height)
+    }
+
+    pub(crate) fn get_evidence(&self, limit: usize) -> anyhow::Result<Vec<Evidence>> {
+        let mut stmt = self.connection.prepare_cached(
+            "SELECT evidence FROM equivocation_evidence ORDER BY id DESC LIMIT ?1",
+        )?;
+        let evidence = stmt
+            .query_map(params![limit], |row| {
+                let evidence: Vec<u8> = row.get(0)?;
+                serde_json::from_slice::<Evidence>(&evidence).map_err(|e| {
+                    error!("Error deserializing evidence: {}", e);
+                    rusqlite::Error::InvalidQuery {}
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(evidence)
+    }
+
+    pub(crate) fn get_app_hash(&self, height: u64) -> anyhow::Result<Option<Hash>> {
+        let mut stmt = self
+            .connection
+            .prepare_cached("SELECT app_hash FROM application_hashes WHERE height = ?1")?;
+        let app_hash: Option<String> = stmt
+            .query_row(params![height], |row| row.get(0))
+            .optional()?;
+        Ok(app_hash.map(|app_hash| app_hash.parse()).transpose()?)
+    }
+
+    pub(crate) fn get_published_height(&self) -> anyhow::Result<Option<u64>> {
+        let mut stmt = self
+            .connection
+            .prepare_cached("SELECT height FROM published_commitments WHERE id = 0")?;
+        let height = stmt.query_row(params![], |row| row.get(0)).optional()?;
+        Ok(height)
+    }
+
+    /// Size of used database pages. Pages freed by pruning are reused, so the file itself doesn't shrink.
+    pub(crate) fn get_database_size(&self) -> anyhow::Result<u64> {
+        let pragma = |name: &str| -> anyhow::Result<u64> {
+            let value = self
+                .connection
+                .query_row(&format!("PRAGMA {name}"), params![], |row| row.get(0))?;
+            Ok(value)
+        };
+        let used_pages = pragma("page_count")?.saturating_sub(pragma("freelist_count")?);
+        Ok(used_pages * pragma("page_size")?)
+    }
+
+    fn map_pruned_block() -> impl FnOnce(&Row) -> Result<PrunedBlock, rusqlite::Error> {
+        |row| {
+            let hash: String = row.get(0)?;
+            let height: u64 = row.get(1)?;
+            let header: Option<Vec<u8>> = row.get(2)?;
+            let header = header
+                .map(|header| serde_json::from_slice(&header))
+                .transpose()
+                .map_err(|e| {
+                    error!("Error deserializing block header: {}", e);
+                    rusqlite::Error::InvalidQuery {}
+                })?;
+            Ok(PrunedBlock {
+                hash,
+                height,
+                header,
+            })
+        }
+    }
+
     fn map_block() -> impl FnOnce(&Row) -> Result<Block, rusqlite::Error> {
         |row| {
             let body: Vec<u8> = row.get(0)?;
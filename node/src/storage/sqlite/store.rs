@@ -1,28 +1,37 @@
 use crate::block::types::block::Block;
+use crate::block::types::quorum_certificate::{BlockCertificate, QuorumCertificate};
+use crate::broadcast::{evidence::Evidence, group::GroupSnapshot};
 use anyhow::Result;
 use log::debug;
-use rusqlite::{params, Connection, OpenFlags};
-use std::collections::HashSet;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
 
 use crate::config::DatabaseConfiguration;
 use crate::network::PeerId;
-use crate::utilities::crypto::Certificate;
+use crate::storage::retention_cutoff;
+use crate::utilities::hash::Hash;
+use crate::utilities::time::EphemeraTime;
 
 pub struct Database {
     connection: Connection,
+    /// How long committed message hashes are kept
+    committed_messages_retention_sec: Option<u64>,
 }
 
 impl Database {
     pub fn open(db_conf: DatabaseConfiguration, flags: OpenFlags) -> Result<Database> {
         let connection = Connection::open_with_flags(db_conf.sqlite_path, flags)?;
-        Ok(Database { connection })
+        Ok(Database {
+            connection,
+            committed_messages_retention_sec: db_conf.committed_messages_retention_sec,
+        })
     }
 
     pub(crate) fn store_block(
         &mut self,
         block: &Block,
-        certificates: HashSet<Certificate>,
-        members: HashSet<PeerId>,
+        certificates: &[BlockCertificate],
+        group: &GroupSnapshot,
+        quorum_certificate: &QuorumCertificate,
     ) -> Result<()> {
         debug!("Storing block: {}", block.header);
 
@@ -30,10 +39,12 @@ impl Database {
         let height = block.header.height;
         let block_bytes = serde_json::to_vec::<Block>(block).map_err(|e| anyhow::anyhow!(e))?;
         let certificates_bytes =
-            serde_json::to_vec(&certificates.into_iter().collect::<Vec<Certificate>>())
-                .map_err(|e| anyhow::anyhow!(e))?;
-        let members_bytes = serde_json::to_vec(&members.into_iter().collect::<Vec<PeerId>>())
+            serde_json::to_vec(certificates).map_err(|e| anyhow::anyhow!(e))?;
+        let members_bytes = serde_json::to_vec(&group.members.iter().collect::<Vec<&PeerId>>())
             .map_err(|e| anyhow::anyhow!(e))?;
+        let weights_bytes = serde_json::to_vec(&group.weights).map_err(|e| anyhow::anyhow!(e))?;
+        let quorum_certificate_bytes =
+            serde_json::to_vec(quorum_certificate).map_err(|e| anyhow::anyhow!(e))?;
         let merkle_tree = block.merkle_tree()?;
         let merkle_tree_bytes = serde_json::to_vec(&merkle_tree).map_err(|e| anyhow::anyhow!(e))?;
 
@@ -51,10 +62,16 @@ impl Database {
             statement.execute(params![&hash, &certificates_bytes,])?;
 
             let mut statement = tx.prepare_cached(
-                "INSERT INTO block_broadcast_group (block_hash, members) VALUES (?1, ?2)",
+                "INSERT INTO block_broadcast_group (block_hash, members, weights) VALUES (?1, ?2, ?3)",
             )?;
 
-            statement.execute(params![&hash, &members_bytes])?;
+            statement.execute(params![&hash, &members_bytes, &weights_bytes])?;
+
+            let mut statement = tx.prepare_cached(
+                "INSERT INTO block_quorum_certificates (block_hash, quorum_certificate) VALUES (?1, ?2)",
+            )?;
+
+            statement.execute(params![&hash, &quorum_certificate_bytes])?;
 
             //store Merkle Tree
             let mut statement = tx.prepare_cached(
@@ -62,10 +79,118 @@ impl Database {
             )?;
 
             statement.execute(params![&hash, &merkle_tree_bytes])?;
+
+            //Index committed messages. If a message was already committed, keep the first block.
+            let mut statement = tx.prepare_cached(
+                "INSERT OR IGNORE INTO committed_messages (message_hash, block_hash, timestamp) VALUES (?1, ?2, ?3)",
+            )?;
+            for message in &block.messages {
+                let message_hash = message.hash_with_default_hasher()?.to_string();
+                statement.execute(params![&message_hash, &hash, &message.timestamp])?;
+            }
+
+            if let Some(retention_sec) = self.committed_messages_retention_sec {
+                let cutoff = retention_cutoff(retention_sec);
+                let mut statement =
+                    tx.prepare_cached("DELETE FROM committed_messages WHERE timestamp < ?1")?;
+                let removed = statement.execute(params![&cutoff])?;
+                if removed > 0 {
+                    debug!("Removed {removed} committed message hashes older than {cutoff}");
+                }
+            }
         }
 
         tx.commit()?;
 
         Ok(())
     }
+
+    pub(crate) fn prune_block(&mut self, height: u64, keep_commitments: bool) -> Result<bool> {
+        let tx = self.connection.transaction()?;
+        {
+            //Heights are stored as text, compare them as numbers
+            let mut statement = tx.prepare_cached(
+                "SELECT block_hash, block FROM blocks WHERE CAST(height AS INTEGER) = ?1",
+            )?;
+            let row = statement
+                .query_row(params![&height], |row| {
+                    let hash: String = row.get(0)?;
+                    let block: Vec<u8> = row.get(1)?;
+                    Ok((hash, block))
+                })
+                .optional()?;
+            let Some((hash, block_bytes)) = row else {
+                return Ok(false);
+            };
+
+            let header_bytes = if keep_commitments {
+                let block = serde_json::from_slice::<Block>(&block_bytes)?;
+                Some(serde_json::to_vec(&block.header)?)
+            } else {
+                None
+            };
+
+            let mut statement = tx.prepare_cached(
+                "INSERT INTO pruned_blocks (block_hash, height, header, pruned_at) VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            statement.execute(params![&hash, &height, &header_bytes, &EphemeraTime::now()])?;
+
+            tx.execute("DELETE FROM blocks WHERE block_hash = ?1", params![&hash])?;
+            tx.execute(
+                "DELETE FROM block_merkle_tree WHERE block_hash = ?1",
+                params![&hash],
+            )?;
+            if !keep_commitments {
+                tx.execute(
+                    "DELETE FROM block_certificates WHERE block_hash = ?1",
+                    params![&hash],
+                )?;
+                tx.execute(
+                    "DELETE FROM block_broadcast_group WHERE block_hash = ?1",
+                    params![&hash],
+                )?;
+                tx.execute(
+                    "DELETE FROM block_quorum_certificates WHERE block_hash = ?1",
+                    params![&hash],
+                )?;
+            }
+            debug!("Pruned block {hash} at height {height}");
+        }
+        tx.commit()?;
+        Ok(true)
+    }
+
+    pub(crate) fn store_evidence(&mut self, evidence: &Evidence) -> Result<()> {
+        let offender = evidence.evidence.offender.to_string();
+        let evidence_bytes = serde_json::to_vec(evidence)?;
+        let mut statement = self.connection.prepare_cached(
+            "INSERT INTO equivocation_evidence (offender, height, evidence, detected_at) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        statement.execute(params![
+            &offender,
+            &evidence.evidence.height,
+            &evidence_bytes,
+            &evidence.evidence.detected_at
+        ])?;
+        debug!("Stored evidence against {offender}");
+        Ok(())
+    }
+
+    pub(crate) fn store_app_hash(&mut self, height: u64, app_hash: &Hash) -> Result<()> {
+        let mut statement = self.connection.prepare_cached(
+            "INSERT OR REPLACE INTO application_hashes (height, app_hash) VALUES (?1, ?2)",
+        )?;
+        statement.execute(params![&height, &app_hash.to_string()])?;
+        debug!("Stored application hash {app_hash} at height {height}");
+        Ok(())
+    }
+
+    pub(crate) fn store_published_height(&mut self, height: u64) -> Result<()> {
+        let mut statement = self.connection.prepare_cached(
+            "INSERT OR REPLACE INTO published_commitments (id, height) VALUES (0, ?1)",
+        )?;
+        statement.execute(params![&height])?;
+        debug!("Stored published commitments height {height}");
+        Ok(())
+    }
 }
@@ -22,6 +22,18 @@ impl PublicKey {
     pub(crate) fn to_bytes(&self) -> Vec<u8> {
         self.0.encode_protobuf()
     }
+
+    /// Recovers public key from peer id. Ed25519 public keys are short enough to be inlined in peer ids.
+    pub(crate) fn from_peer_id(peer_id: &PeerId) -> Option<Self> {
+        let multihash = peer_id.inner().as_ref();
+        //Identity multihash
+        if multihash.code() != 0 {
+            return None;
+        }
+        libp2p::identity::PublicKey::try_decode_protobuf(multihash.digest())
+            .ok()
+            .map(PublicKey)
+    }
 }
 
 impl Keypair {
@@ -182,5 +194,7 @@ mod test {
 
         let public_key_from_str = PublicKey::from_str(&keypair.to_base58());
         assert_matches!(public_key_from_str, Err(KeyPairError::Decoding(_)));
+
+        assert_eq!(PublicKey::from_peer_id(&peer_id), Some(public_key));
     }
 }
@@ -2,6 +2,11 @@ use serde::{Deserialize, Serialize};
 
 use crate::utilities::hash::{EphemeraHasher, Hash, Hasher};
 
+/// Root of a tree without leaves. It's a hash of empty input, so no leaf can be proven against it.
+fn empty_root() -> Hash {
+    Hasher::digest(&[]).into()
+}
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MerkleTree {
@@ -13,10 +18,8 @@ impl MerkleTree {
     pub(crate) fn build_tree(leaves: &[Hash]) -> Self {
         if leaves.is_empty() {
             return Self {
-                leaf_count: 1,
-                //So every message which matches this hash will be accepted
-                //Not sure if it's a problem
-                nodes: vec![Hash::new([0; 32])],
+                leaf_count: 0,
+                nodes: vec![empty_root()],
             };
         }
 
@@ -54,34 +57,97 @@ impl MerkleTree {
     }
 
     pub(crate) fn verify_leaf_at_index(&self, hash: Hash, leaf_index: usize) -> bool {
+        self.proof(leaf_index)
+            .is_some_and(|proof| proof.verify(hash, self.root_hash()))
+    }
+
+    /// Returns the index of the first leaf with given hash.
+    pub(crate) fn leaf_index(&self, hash: &Hash) -> Option<usize> {
+        self.nodes[..self.leaf_count]
+            .iter()
+            .position(|leaf| leaf == hash)
+    }
+
+    /// Returns sibling hashes on the path from the leaf to the root.
+    ///
+    /// Returns `None` if there is no leaf at given index.
+    pub(crate) fn proof(&self, leaf_index: usize) -> Option<MerkleProof> {
+        if leaf_index >= self.leaf_count {
+            return None;
+        }
+
+        let mut path = vec![];
         let mut level_offset = 0;
         let mut level_len = self.leaf_count;
-        let mut leaf_index = leaf_index;
-        let mut current_hash = hash;
-        while level_offset + level_len < self.nodes.len() {
+        let mut index = leaf_index;
+        while level_len > 1 {
             let level = &self.nodes[level_offset..(level_offset + level_len)];
-            if leaf_index % 2 == 0 {
-                let right_index = if leaf_index + 1 < level_len {
-                    leaf_index + 1
-                } else {
-                    leaf_index
-                };
-                current_hash =
-                    Hasher::digest(&[current_hash.inner(), level[right_index].inner()].concat())
-                        .into();
+            let sibling_index = if index % 2 == 1 {
+                index - 1
+            } else if index + 1 < level_len {
+                index + 1
             } else {
-                current_hash =
-                    Hasher::digest(&[level[leaf_index - 1].inner(), current_hash.inner()].concat())
-                        .into();
-            }
-            leaf_index /= 2;
+                //Last node on odd length level is hashed with itself
+                index
+            };
+            path.push(level[sibling_index]);
+            index /= 2;
             level_offset += level_len;
-            level_len = (level_len + 1) / 2;
+            level_len = level_len.div_ceil(2);
         }
-        current_hash == self.root_hash()
+
+        Some(MerkleProof {
+            leaf_index,
+            leaf_count: self.leaf_count,
+            path,
+        })
     }
 }
 
+/// Merkle inclusion proof of a single leaf.
+///
+/// It allows to check that a leaf is part of a tree knowing only the tree root.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct MerkleProof {
+    pub(crate) leaf_index: usize,
+    pub(crate) leaf_count: usize,
+    /// Sibling hashes from the leaf level up to the level below the root.
+    pub(crate) path: Vec<Hash>,
+}
+
+impl MerkleProof {
+    pub(crate) fn verify(&self, leaf: Hash, root: Hash) -> bool {
+        if self.leaf_index >= self.leaf_count || self.path.len() != tree_depth(self.leaf_count) {
+            return false;
+        }
+
+        let mut index = self.leaf_index;
+        let mut current_hash = leaf;
+        for sibling in &self.path {
+            let concat = if index.is_multiple_of(2) {
+                [current_hash.inner(), sibling.inner()].concat()
+            } else {
+                [sibling.inner(), current_hash.inner()].concat()
+            };
+            current_hash = Hasher::digest(&concat).into();
+            index /= 2;
+        }
+        current_hash == root
+    }
+}
+
+/// Number of levels above the leaves.
+fn tree_depth(leaf_count: usize) -> usize {
+    let mut depth = 0;
+    let mut level_len = leaf_count;
+    while level_len > 1 {
+        level_len = level_len.div_ceil(2);
+        depth += 1;
+    }
+    depth
+}
+
 #[cfg(test)]
 mod tests {
     use std::iter;
@@ -159,4 +225,46 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_proof() {
+        for i in 1..10 {
+            let mut rnd = rand::thread_rng();
+            let leaves = iter::repeat_with(|| {
+                let mut bytes = [0u8; 32];
+                rnd.fill_bytes(&mut bytes);
+                Hash::new(bytes)
+            })
+            .take(i)
+            .collect::<Vec<_>>();
+
+            let tree = MerkleTree::build_tree(&leaves);
+            let root = tree.root_hash();
+
+            for (index, leaf) in leaves.iter().enumerate() {
+                assert_eq!(tree.leaf_index(leaf), Some(index));
+
+                let proof = tree.proof(index).unwrap();
+                assert!(proof.verify(*leaf, root));
+                assert!(!proof.verify(*leaf, Hash::new([0; 32])));
+
+                if !proof.path.is_empty() {
+                    let mut truncated = proof.clone();
+                    truncated.path.pop();
+                    assert!(!truncated.verify(*leaf, root));
+                }
+            }
+            assert!(tree.proof(i).is_none());
+        }
+    }
+
+    #[test]
+    fn test_empty_tree_accepts_nothing() {
+        let tree = MerkleTree::build_tree(&[]);
+        assert_ne!(tree.root_hash(), Hash::new([0; 32]));
+        assert!(!tree.verify_leaf_at_index(Hash::new([0; 32]), 0));
+        assert!(!tree.verify_leaf_at_index(tree.root_hash(), 0));
+        assert!(tree.proof(0).is_none());
+        assert_eq!(tree.leaf_index(&tree.root_hash()), None);
+    }
 }
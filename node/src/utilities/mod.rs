@@ -3,4 +3,6 @@ pub(crate) mod crypto;
 pub(crate) mod hash;
 pub(crate) mod id;
 pub(crate) mod merkle;
+#[cfg(test)]
+pub(crate) mod test_utils;
 pub(crate) mod time;
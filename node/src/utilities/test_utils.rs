@@ -0,0 +1,111 @@
+//! Helpers shared by unit tests.
+
+use std::collections::HashMap;
+use std::iter;
+#[cfg(feature = "sqlite_storage")]
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::block::types::block::{merkle_tree, Block, RawBlock, RawBlockHeader};
+use crate::block::types::message::EphemeraMessage;
+use crate::broadcast::bracha::quorum::Quorum;
+use crate::broadcast::{BroadcastProtocol, BroadcastResponse, RawRbMsg};
+#[cfg(feature = "sqlite_storage")]
+use crate::config::{DatabaseConfiguration, PruningConfiguration};
+use crate::crypto::{EphemeraKeypair, Keypair};
+use crate::peer::{PeerId, ToPeerId};
+#[cfg(feature = "sqlite_storage")]
+use crate::storage::sqlite::SqliteStorage;
+use crate::utilities::hash::Hash;
+
+pub(crate) fn keypairs(n: usize) -> Vec<Arc<Keypair>> {
+    iter::repeat_with(|| Arc::new(Keypair::generate(None)))
+        .take(n)
+        .collect()
+}
+
+/// Quorum of equally weighted peers.
+pub(crate) fn quorum(peers: &[Arc<Keypair>]) -> Quorum {
+    let members = peers.iter().map(|peer| peer.peer_id()).collect();
+    Quorum::weighted(&members, &HashMap::new())
+}
+
+/// Header at given height, its view and all hashes are zero.
+pub(crate) fn raw_header(creator: PeerId, height: u64) -> RawBlockHeader {
+    RawBlockHeader::new(
+        creator,
+        height,
+        0,
+        Hash::new([0; 32]),
+        Hash::new([0; 32]),
+        Hash::new([0; 32]),
+    )
+}
+
+/// Empty block at given height, see [`block_with`].
+pub(crate) fn block(creator: &Keypair, height: u64) -> Block {
+    block_with(creator, height, vec![])
+}
+
+/// Block at given height with zero previous and application hashes.
+pub(crate) fn block_with(creator: &Keypair, height: u64, messages: Vec<EphemeraMessage>) -> Block {
+    let mut header = raw_header(creator.peer_id(), height);
+    header.merkle_root = merkle_tree(&messages).unwrap().root_hash();
+    let raw_block = RawBlock::new(header, messages);
+    let hash = raw_block.hash_with_default_hasher().unwrap();
+    Block::new(raw_block, hash)
+}
+
+/// Handles the message twice, to make sure that duplicate messages don't have impact.
+pub(crate) fn handle_double<P: BroadcastProtocol>(
+    broadcaster: &mut P,
+    rb_msg: &RawRbMsg,
+) -> BroadcastResponse {
+    let response = broadcaster.handle(rb_msg);
+    broadcaster.handle(rb_msg);
+    response
+}
+
+/// Temporary SQLite database file, removed when dropped, so a failing test doesn't leave it behind.
+#[cfg(feature = "sqlite_storage")]
+pub(crate) struct TempDatabase {
+    path: PathBuf,
+}
+
+#[cfg(feature = "sqlite_storage")]
+impl TempDatabase {
+    pub(crate) fn new() -> Self {
+        let path =
+            std::env::temp_dir().join(format!("ephemera-test-{}.sqlite", rand::random::<u64>()));
+        Self { path }
+    }
+
+    /// Configuration which stores into this file.
+    pub(crate) fn config(&self) -> DatabaseConfiguration {
+        DatabaseConfiguration {
+            rocksdb_path: String::new(),
+            sqlite_path: self.path.to_str().unwrap().to_string(),
+            create_if_not_exists: true,
+            committed_messages_retention_sec: None,
+            pruning: PruningConfiguration::default(),
+        }
+    }
+}
+
+#[cfg(feature = "sqlite_storage")]
+impl Drop for TempDatabase {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Opens SQLite storage in a temporary file. The file is removed when the returned guard is dropped.
+#[cfg(feature = "sqlite_storage")]
+pub(crate) fn sqlite_storage(retention_sec: Option<u64>) -> (SqliteStorage, TempDatabase) {
+    let database = TempDatabase::new();
+    let config = DatabaseConfiguration {
+        committed_messages_retention_sec: retention_sec,
+        ..database.config()
+    };
+    (SqliteStorage::open(config).unwrap(), database)
+}